@@ -1,6 +1,13 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-use crate::types::QuestionSource;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use crate::{
+    types::{BinaryResolution, MirrorRef, QuestionSource},
+    util,
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "mirror_bot")]
@@ -10,6 +17,25 @@ pub struct Cli {
     pub command: Commands,
 }
 
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Output format
+    #[arg(long = "format", value_enum, default_value_t = ListFormat::Text, global = true)]
+    pub format: ListFormat,
+    #[command(subcommand)]
+    pub command: ListCommands,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ListFormat {
+    /// One line per row
+    Text,
+    /// Compact columns with long text truncated
+    Table,
+    /// JSON array of rows, for jq and scripts
+    Json,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum ListCommands {
     /// List mirror markets managed by the bot
@@ -17,16 +43,283 @@ pub enum ListCommands {
         /// Show resolved mirrors instead of unresolved
         #[arg(short = 'r', long = "resolved")]
         resolved: bool,
+        /// Only show mirrors of questions from this source
+        #[arg(short = 's', long = "source")]
+        source: Option<QuestionSource>,
+        /// Only show mirrors created on or after this date
+        #[arg(long = "since", value_name = "YYYY-MM-DD")]
+        since: Option<NaiveDate>,
+        /// Only show mirrors created on or before this date
+        #[arg(long = "until", value_name = "YYYY-MM-DD")]
+        until: Option<NaiveDate>,
+        /// Only show mirrors whose question contains this text, ignoring case
+        #[arg(long = "question-contains")]
+        question_contains: Option<String>,
+        /// Only show the mirror of this Manifold market
+        #[arg(long = "contract-id")]
+        contract_id: Option<String>,
     },
     /// List mirrors created by others that we know about
     ThirdParty,
+    /// List resolutions awaiting operator confirmation
+    PendingResolutions {
+        /// Include approved and rejected resolutions
+        #[arg(short = 'a', long = "all")]
+        all: bool,
+    },
+    /// Show resolutions and unresolutions applied to mirrors
+    Resolutions {
+        /// Only show history for the mirror with this row id
+        #[arg(short = 'm', long = "mirror")]
+        mirror_id: Option<i64>,
+    },
+    /// Show recent sync and auto-mirror runs, newest first
+    Runs {
+        /// Number of runs to show
+        #[arg(short = 'n', long = "limit", default_value_t = 20)]
+        limit: usize,
+    },
+    /// List received managrams, newest first
+    Managrams {
+        /// Only show managrams that haven't been processed yet
+        #[arg(long = "unprocessed", conflicts_with = "failed")]
+        unprocessed: bool,
+        /// Only show managrams whose command failed, including refunded ones
+        #[arg(long = "failed")]
+        failed: bool,
+        /// Only show managrams from this user id
+        #[arg(long = "from")]
+        from_id: Option<String>,
+        /// Only show managrams received on or after this date
+        #[arg(long = "since", value_name = "YYYY-MM-DD")]
+        since: Option<NaiveDate>,
+    },
+    /// Show the managram processing audit log, newest first
+    Audit {
+        /// Only show attempts for this managram
+        #[arg(short = 'm', long = "managram")]
+        managram_id: Option<String>,
+    },
+    /// Show auto-mirror candidates rejected by the auto filter, newest first
+    Rejections {
+        /// Only show rejections from this source
+        #[arg(short = 's', long = "source")]
+        source: Option<QuestionSource>,
+        /// Only show rejections within this long ago, e.g. 12h or 7d
+        #[arg(long = "since", value_parser = util::parse_duration)]
+        since: Option<Duration>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportCommands {
+    /// Summarize mana spent by category and source
+    Spend {
+        /// Month to report on, defaults to the current month
+        #[arg(long = "month", value_name = "YYYY-MM")]
+        month: Option<String>,
+    },
+    /// Brier scores and calibration of Manifold closing probabilities for
+    /// resolved mirrors, per source
+    Calibration,
+    /// Estimate upcoming mana needs and compare them to the bot's balance
+    Budget {
+        /// Number of days to plan for
+        #[arg(long = "days", default_value_t = 30)]
+        days: i64,
+    },
+    /// Trading volume and traders on mirrors per source, and the most traded
+    /// mirrors, as of the last `sync --analytics`
+    Activity {
+        /// Number of most traded mirrors to list
+        #[arg(long = "top", default_value_t = 10)]
+        top: usize,
+    },
+    /// Profit and loss on the bot's own bets on mirrors, per source and per month
+    /// of resolution. Open positions are valued at the probability of the last sync
+    Pnl,
+    /// Users who requested the most mirrors by managram, and the mana they paid
+    Requesters {
+        /// Number of requesters to list
+        #[arg(long = "top", default_value_t = 10)]
+        top: usize,
+        /// Only count mirrors created within this long ago, e.g. 30d
+        #[arg(long = "since", value_parser = util::parse_duration)]
+        since: Option<Duration>,
+    },
+    /// Mirrors of each project in `metaculus.projects`
+    Projects,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ExportTable {
+    /// Our mirrors
+    Mirrors,
+    Managrams,
+    /// Mirrors created by others
+    ThirdParty,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommands {
+    /// Re-create mirror records from an export, checking each against Manifold
+    Mirrors {
+        /// File written by `export --table mirrors`
+        path: PathBuf,
+        /// Defaults to csv for .csv files and json otherwise
+        #[arg(long = "format", value_enum)]
+        format: Option<ExportFormat>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective configuration and which source each setting came from
+    Show,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommands {
+    /// Write a consistent snapshot of the database, safe to run while the bot is running
+    Backup {
+        /// File to write the backup to
+        path: PathBuf,
+        /// Compress the backup with gzip, adding a .gz extension
+        #[arg(short = 'z', long = "gzip")]
+        gzip: bool,
+        /// Add the current time to the file name, e.g. backup-20240101T120000Z.db3
+        #[arg(short = 't', long = "timestamp")]
+        timestamp: bool,
+    },
+    /// Check integrity, prune old records, vacuum, and report table sizes
+    Maintenance {
+        /// Report what would be done without pruning or vacuuming
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Default, Args, Deserialize)]
+#[serde(default)]
+pub struct SyncArgs {
+    /// Sync Kalshi resolutions to manifold
+    #[arg(short = 'k', long = "kalshi")]
+    pub kalshi: bool,
+    /// Sync Metaculus resolutions to manifold
+    #[arg(short = 'm', long = "metaculus")]
+    pub metaculus: bool,
+    /// Sync Polymarket resolutions to manifold
+    #[arg(short = 'p', long = "polymarket")]
+    pub polymarket: bool,
+    /// Sync Manifold managrams to db
+    #[arg(short = 'g', long = "managrams")]
+    pub managrams: bool,
+    /// Sync comments mentioning the bot to db
+    #[arg(short = 'c', long = "comments")]
+    pub comments: bool,
+    /// Sync state of our mirror markets from Manifold to db
+    #[arg(short = 's', long = "manifold-self")]
+    pub manifold_self: bool,
+    /// Sync state of third party mirror markets from Manifold to db
+    #[arg(short = 'o', long = "manifold-other")]
+    pub manifold_other: bool,
+    /// Re-render mirror descriptions to keep dynamic footer parts current
+    #[arg(short = 'd', long = "descriptions")]
+    pub descriptions: bool,
+    /// Fetch trading volume and trader counts of mirrors from Manifold to db
+    #[arg(long = "analytics")]
+    pub analytics: bool,
+    /// Add liquidity to mirrors with a lot of activity, see manifold.subsidy config.
+    /// Uses the activity fetched by --analytics
+    #[arg(short = 'l', long = "liquidity")]
+    pub liquidity: bool,
+    /// Send managrams to the top traders on resolved mirrors, see manifold.rewards config
+    #[arg(long = "rewards")]
+    pub rewards: bool,
+    /// Comment on mirrors that are about to close, see manifold.reminders config
+    #[arg(short = 'r', long = "reminders")]
+    pub reminders: bool,
+    /// Comment source forecasts on open mirrors, see manifold.probability_updates config
+    #[arg(short = 'u', long = "probability-updates")]
+    pub probability_updates: bool,
+    /// Answer operator commands sent to the Telegram bot, see notifications.telegram config
+    #[arg(short = 't', long = "telegram")]
+    pub telegram: bool,
+    /// Comment the mirror requester leaderboard when it's due, see manifold.leaderboard config
+    #[arg(long = "leaderboard")]
+    pub leaderboard: bool,
+    /// Sync everything
+    #[arg(short = 'a', long = "all")]
+    pub all: bool,
+    /// Check the source of every unresolved mirror, not only those due according
+    /// to their sync schedule (see sync config)
+    #[arg(long = "ignore-schedule")]
+    pub ignore_schedule: bool,
+    /// Sync resolution, close time and description of this mirror only (row id or
+    /// Manifold url)
+    #[arg(long = "mirror")]
+    #[serde(skip)]
+    pub mirror: Option<MirrorRef>,
+}
+
+impl SyncArgs {
+    /// Whether any sync target was selected
+    pub fn any_target(&self) -> bool {
+        self.kalshi
+            || self.metaculus
+            || self.polymarket
+            || self.managrams
+            || self.comments
+            || self.manifold_self
+            || self.manifold_other
+            || self.descriptions
+            || self.analytics
+            || self.liquidity
+            || self.rewards
+            || self.reminders
+            || self.probability_updates
+            || self.telegram
+            || self.leaderboard
+            || self.all
+            || self.mirror.is_some()
+    }
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// List mirrors, managrams, etc.
+    List(ListArgs),
+    /// Summaries for operators
+    #[command(subcommand)]
+    Report(ReportCommands),
+    /// Database administration
+    #[command(subcommand)]
+    Db(DbCommands),
+    /// Inspect configuration
     #[command(subcommand)]
-    List(ListCommands),
+    Config(ConfigCommands),
+    /// Import records exported from another database
+    #[command(subcommand)]
+    Import(ImportCommands),
+    /// Export rows from the database for analysis elsewhere
+    Export {
+        #[arg(long = "table", value_enum)]
+        table: ExportTable,
+        #[arg(long = "format", value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Only export rows created on or after this date
+        #[arg(long = "since", value_name = "YYYY-MM-DD")]
+        since: Option<NaiveDate>,
+        /// Write to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
     #[command(arg_required_else_help = true)]
     /// Mirror a specific question to Manifold
     Mirror {
@@ -35,35 +328,44 @@ pub enum Commands {
         /// Mirror question even if source has already resolved
         #[arg(short = 'r', long = "allow-resolved")]
         allow_resolved: bool,
+        /// Print the market that would be created instead of creating it
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        /// Create the mirror with this `manifold.accounts` entry instead of the
+        /// one configured for the source
+        #[arg(long = "destination")]
+        destination: Option<String>,
     },
+    /// Mirror every question listed in a file, skipping ones that are already
+    /// mirrored and stopping at the daily clone limits
+    #[command(arg_required_else_help = true)]
+    MirrorBatch {
+        /// File with one source url or `<source> <id>` per line
+        path: PathBuf,
+        /// Create the mirrors with this `manifold.accounts` entry instead of the
+        /// one configured for each source
+        #[arg(long = "destination")]
+        destination: Option<String>,
+    },
+    /// Explain how a question fares against the auto and request filters
+    #[command(arg_required_else_help = true)]
+    Check { source: QuestionSource, id: String },
     /// Sync source resolutions to Manifold
     #[command()]
-    Sync {
-        /// Sync Kalshi resolutions to manifold
-        #[arg(short = 'k', long = "kalshi")]
-        kalshi: bool,
-        /// Sync Metaculus resolutions to manifold
-        #[arg(short = 'm', long = "metaculus")]
-        metaculus: bool,
-        /// Sync Manifold managrams to db
-        #[arg(short = 'g', long = "managrams")]
-        managrams: bool,
-        /// Sync state of our mirror markets from Manifold to db
-        #[arg(short = 's', long = "manifold-self")]
-        manifold_self: bool,
-        /// Sync state of third party mirror markets from Manifold to db
-        #[arg(short = 'o', long = "manifold-other")]
-        manifold_other: bool,
-        /// Sync everything
-        #[arg(short = 'a', long = "all")]
-        all: bool,
-    },
+    Sync(SyncArgs),
     /// Mirror new questions from source platforms to Manifold
     #[command()]
     AutoMirror {
-        source: QuestionSource,
+        /// Defaults to every source that supports auto-mirroring
+        source: Option<QuestionSource>,
+        /// Auto-mirror from every source that supports it, same as omitting the source
+        #[arg(long = "all", conflicts_with = "source")]
+        all: bool,
         #[arg(long = "dry-run")]
         dry_run: bool,
+        /// Show each candidate and ask before mirroring it
+        #[arg(long = "interactive")]
+        interactive: bool,
     },
     /// Send a managram
     #[command()]
@@ -75,7 +377,20 @@ pub enum Commands {
     /// Process managram requests
     #[command()]
     ProcessManagrams,
-    /// Mirror all eligible questions in a Metaculus project (admin only)
+    /// Reset a managram's processing state and process it again
+    #[command(arg_required_else_help = true)]
+    ReprocessManagram {
+        /// Managram transaction id (see `list managrams`)
+        txn_id: String,
+        /// Also reprocess managrams that succeeded or were refunded, running their command again
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Process commands posted as comments on our markets
+    #[command()]
+    ProcessComments,
+    /// Mirror all open questions in a Metaculus project now (admin only). Add it
+    /// to `metaculus.projects` to have `auto-mirror` keep it mirrored instead
     #[command()]
     MirrorMetaculusProject {
         project_id: u64,
@@ -85,4 +400,62 @@ pub enum Commands {
     /// Register unknown markets on our account as manually managed
     #[command()]
     RegisterManualMarkets,
+    /// Apply a resolution that was held for confirmation
+    #[command()]
+    ApproveResolution {
+        /// Pending resolution id (see `list pending-resolutions`)
+        id: i64,
+    },
+    /// Discard a resolution that was held for confirmation
+    #[command()]
+    RejectResolution {
+        /// Pending resolution id (see `list pending-resolutions`)
+        id: i64,
+    },
+    /// Resolve one of our mirrors on Manifold and record it in the database
+    #[command(arg_required_else_help = true)]
+    Resolve {
+        /// Mirror row id or Manifold url
+        mirror: MirrorRef,
+        /// YES, NO, NA or a percentage like p=37
+        resolution: BinaryResolution,
+        /// Required to actually resolve, since resolutions can't be taken back quietly
+        #[arg(long = "i-am-sure")]
+        i_am_sure: bool,
+    },
+    /// Undo a mistaken resolution of one of our mirrors. The next sync resolves
+    /// it again if the source is still resolved.
+    #[command(arg_required_else_help = true)]
+    Unresolve {
+        /// Mirror row id or Manifold url
+        mirror: MirrorRef,
+    },
+    /// Set the close time of one of our mirrors, closing it now by default
+    #[command(arg_required_else_help = true)]
+    Close {
+        /// Mirror row id or Manifold url
+        mirror: MirrorRef,
+        /// New close time, e.g. 2024-06-30T12:00:00Z
+        #[arg(long = "at")]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Resolve one of our mirrors N/A and retire it, e.g. if it was mirrored in error
+    #[command(arg_required_else_help = true)]
+    Abandon {
+        /// Mirror row id or Manifold url
+        mirror: MirrorRef,
+        /// Comment explaining why, posted on the market before resolving
+        #[arg(short = 'c', long = "comment")]
+        comment: Option<String>,
+    },
+    /// Check database and API access, printing a JSON report. Fails if anything is wrong.
+    #[command()]
+    Doctor,
+    /// Serve a dashboard and HTTP API for monitoring the bot and triggering syncs
+    #[command()]
+    Serve {
+        /// Address to listen on, overriding server.bind_address
+        #[arg(short = 'b', long = "bind")]
+        bind_address: Option<String>,
+    },
 }
@@ -1,285 +1,1756 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
 use anyhow::{anyhow, bail, Context, Ok, Result};
-use log::{info, warn};
-use reqwest::blocking::Client;
-use rusqlite::Connection;
-
-use crate::args::{self, Commands, ListCommands};
-use crate::manifold::{self, SendManagramArgs};
-use crate::metaculus::{MetaculusListQuestionsParams, MetaculusQuestion};
-use crate::settings::Settings;
-use crate::types::{Question, QuestionSource};
-use crate::{db, kalshi, log_if_err, managrams, metaculus, mirror};
-
-pub(crate) fn run_command(
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use clap::ValueEnum;
+use flate2::{write::GzEncoder, Compression};
+use futures::TryStreamExt;
+use log::{debug, info, warn};
+use reqwest::Client;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::args::{
+    self, Commands, ConfigCommands, DbCommands, ExportFormat, ExportTable, ImportCommands,
+    ListArgs, ListCommands, ReportCommands, SyncArgs,
+};
+use crate::db::{ManagramState, PendingResolutionState, RunStats, SpendCategory};
+use crate::kalshi::KalshiSource;
+use crate::manifold::{self, CreateMarketArgs, SendManagramArgs};
+use crate::metaculus::{MetaculusSource, ProjectFilter};
+use crate::mirror::{AutoMirrorResult, CandidateDecision, ConfirmCandidate, ImportedMirror};
+use crate::polymarket::PolymarketSource;
+use crate::settings::{MetaculusProject, Settings};
+use crate::source::MirrorSource;
+use crate::types::{
+    BinaryResolution, MirrorRef, MirrorTarget, Question, QuestionSource, RequirementCheck,
+};
+use crate::{
+    comments, db, http, kalshi, listing, log_if_err, managrams, metaculus, mirror, notify,
+    recovery, server, shutdown, telegram, util,
+};
+
+pub(crate) async fn run_command(
     config: Settings,
     args: args::Cli,
 ) -> std::result::Result<(), anyhow::Error> {
     match args.command {
         Commands::List(cmd) => list_markets(&config, cmd),
+        Commands::Report(cmd) => report(&config, cmd).await,
+        Commands::Db(cmd) => db_command(&config, cmd),
+        Commands::Config(cmd) => config_command(cmd),
+        Commands::Export {
+            table,
+            format,
+            since,
+            output,
+        } => export(&config, table, format, since, output),
+        Commands::Import(cmd) => import(&config, cmd).await,
         Commands::Mirror {
             source,
             id,
             allow_resolved,
-        } => mirror_question(&config, source, id, allow_resolved),
-        Commands::Sync {
-            kalshi,
-            metaculus,
-            managrams,
-            manifold_self,
-            manifold_other,
-            all,
-        } => sync(
-            &config,
-            kalshi,
-            metaculus,
-            managrams,
-            manifold_self,
-            manifold_other,
-            all,
-        ),
-        Commands::AutoMirror { source, dry_run } => auto_mirror(&config, source, dry_run),
+            dry_run,
+            destination,
+        } => {
+            mirror_question(
+                &config,
+                source,
+                id,
+                allow_resolved,
+                dry_run,
+                destination.as_deref(),
+            )
+            .await
+        }
+        Commands::MirrorBatch { path, destination } => {
+            mirror_batch(&config, &path, destination.as_deref()).await
+        }
+        Commands::Sync(args) => sync(&config, args).await,
+        Commands::AutoMirror {
+            source,
+            all: _,
+            dry_run,
+            interactive,
+        } => match source {
+            Some(source) => auto_mirror(&config, source, dry_run, interactive).await,
+            None => auto_mirror_all(&config, dry_run, interactive).await,
+        },
         Commands::SendManagram {
             amount,
             to_id,
             message,
-        } => send_managram(&config, amount, to_id, message),
+        } => send_managram(&config, amount, to_id, message).await,
         Commands::MirrorMetaculusProject {
             project_id,
             header,
             group_id,
-        } => mirror_metaculus_project(&config, project_id, header, group_id),
-        Commands::ProcessManagrams => process_managrams(&config),
-        Commands::RegisterManualMarkets => register_manual_markets(&config),
+        } => mirror_metaculus_project(&config, project_id, header, group_id).await,
+        Commands::ProcessManagrams => process_managrams(&config).await,
+        Commands::ReprocessManagram { txn_id, force } => {
+            reprocess_managram(&config, txn_id, force).await
+        }
+        Commands::ProcessComments => process_comments(&config).await,
+        Commands::RegisterManualMarkets => register_manual_markets(&config).await,
+        Commands::ApproveResolution { id } => approve_resolution(&config, id).await,
+        Commands::RejectResolution { id } => reject_resolution(&config, id),
+        Commands::Serve { bind_address } => server::serve(config, bind_address).await,
+        Commands::Resolve {
+            mirror,
+            resolution,
+            i_am_sure,
+        } => resolve_mirror(&config, mirror, resolution, i_am_sure).await,
+        Commands::Unresolve { mirror } => unresolve_mirror(&config, mirror).await,
+        Commands::Close { mirror, at } => close_mirror(&config, mirror, at).await,
+        Commands::Abandon { mirror, comment } => abandon_mirror(&config, mirror, comment).await,
+        Commands::Doctor => doctor(&config).await,
+        Commands::Check { source, id } => check_question(&config, source, id).await,
     }
 }
 
 // TODO: registering individual market
-fn register_manual_markets(config: &Settings) -> Result<()> {
-    let client = Client::new();
+async fn register_manual_markets(config: &Settings) -> Result<()> {
+    let client = http::client(config)?;
     let db = db::open(&config)?;
-    mirror::register_existing_manual_markets(&client, &db, config)?;
+    mirror::register_existing_manual_markets(&client, &db, config).await?;
     Ok(())
 }
 
-pub fn process_managrams(config: &Settings) -> Result<()> {
-    let client = Client::new();
-    let db = db::open(&config)?;
-    log_if_err!(managrams::sync_managrams(&client, &db, config));
-    managrams::process_managrams(&client, &db, config)?;
+async fn approve_resolution(config: &Settings, id: i64) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let mirror = mirror::approve_pending_resolution(&client, &db, config, id).await?;
+    println!("Resolved mirror at {}", mirror.manifold_url);
+    Ok(())
+}
+
+async fn resolve_mirror(
+    config: &Settings,
+    mirror: MirrorRef,
+    resolution: BinaryResolution,
+    i_am_sure: bool,
+) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let mirror = find_mirror(&db, &mirror)?;
+    if mirror.resolved {
+        bail!(
+            "mirror with row id {} is already resolved ({:?})",
+            mirror.id,
+            mirror.resolution
+        );
+    }
+    if !i_am_sure {
+        bail!(
+            "would resolve \"{}\" ({}) to {:?}. Pass --i-am-sure to go ahead.",
+            mirror.question,
+            mirror.manifold_url,
+            resolution
+        );
+    }
+    mirror::apply_resolution(
+        &client,
+        &db,
+        &mirror,
+        resolution.clone(),
+        "manual resolution from the command line",
+        config,
+    )
+    .await?;
+    println!("Resolved {} to {:?}", mirror.manifold_url, resolution);
+    Ok(())
+}
+
+async fn unresolve_mirror(config: &Settings, mirror: MirrorRef) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let mirror = find_mirror(&db, &mirror)?;
+    if !mirror.resolved {
+        bail!("mirror with row id {} is not resolved", mirror.id);
+    }
+    mirror::unresolve_mirror(
+        &client,
+        &db,
+        &mirror,
+        &format!(
+            "manual unresolution from the command line, was {:?}",
+            mirror.resolution
+        ),
+        config,
+    )
+    .await?;
+    println!("Unresolved {}", mirror.manifold_url);
+    Ok(())
+}
+
+async fn close_mirror(
+    config: &Settings,
+    mirror: MirrorRef,
+    at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let mirror = find_mirror(&db, &mirror)?;
+    if mirror.resolved {
+        bail!("mirror with row id {} is already resolved", mirror.id);
+    }
+    let close_time = at.unwrap_or_else(Utc::now);
+    mirror::close_mirror(&client, &db, &mirror, close_time, config).await?;
+    println!(
+        "Set close time of {} to {}",
+        mirror.manifold_url, close_time
+    );
+    Ok(())
+}
+
+async fn abandon_mirror(
+    config: &Settings,
+    mirror: MirrorRef,
+    comment: Option<String>,
+) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let mirror = find_mirror(&db, &mirror)?;
+    if mirror.resolved {
+        bail!("mirror with row id {} is already resolved", mirror.id);
+    }
+    mirror::abandon_mirror(&client, &db, &mirror, comment, config).await?;
+    println!("Abandoned {}", mirror.manifold_url);
+    Ok(())
+}
+
+/// Look up one of our mirrors by row id or Manifold url
+fn find_mirror(db: &Connection, mirror: &MirrorRef) -> Result<db::MirrorRow> {
+    match mirror {
+        MirrorRef::Id(id) => db::get_mirror_by_id(db, *id)?,
+        MirrorRef::Slug(slug) => db::get_mirror_by_slug(db, slug)?,
+    }
+    .ok_or_else(|| anyhow!("no mirror {}", mirror))
+}
+
+fn reject_resolution(config: &Settings, id: i64) -> Result<()> {
+    let db = db::open(config)?;
+    mirror::reject_pending_resolution(&db, id)?;
+    println!("Rejected pending resolution with id {}", id);
     Ok(())
 }
 
-pub fn list_markets(config: &Settings, subcommand: ListCommands) -> Result<()> {
+pub async fn process_managrams(config: &Settings) -> Result<()> {
+    let client = http::client(config)?;
     let db = db::open(&config)?;
+    log_if_err!(managrams::sync_managrams(&client, &db, config).await);
+    managrams::process_managrams(&client, &db, config).await?;
+    log_if_err!(managrams::refund_stale_managrams(&client, &db, config).await);
+    Ok(())
+}
+
+async fn reprocess_managram(config: &Settings, txn_id: String, force: bool) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let state = managrams::reprocess_managram(&client, &db, config, &txn_id, force).await?;
+    println!("Reprocessed managram {}: {:?}", txn_id, state);
+    Ok(())
+}
+
+pub async fn process_comments(config: &Settings) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    log_if_err!(comments::sync_comments(&client, &db, config).await);
+    comments::process_comments(&client, &db, config).await?;
+    Ok(())
+}
+
+pub async fn report(config: &Settings, subcommand: ReportCommands) -> Result<()> {
+    let db = db::open(config)?;
     match subcommand {
-        ListCommands::Mirrors { resolved } => {
-            let mirrors = if resolved {
-                db::get_resolved_mirrors(&db, None)
-            } else {
-                db::get_unresolved_mirrors(&db, None)
+        ReportCommands::Spend { month } => {
+            let month = match month {
+                Some(month) => NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+                    .with_context(|| format!("invalid month \"{}\", expected YYYY-MM", month))?,
+                None => Utc::now().date_naive().with_day(1).unwrap(),
             };
-            for mirror in mirrors? {
-                println!("{:#?}", mirror);
+            let next_month = month
+                .checked_add_months(Months::new(1))
+                .ok_or_else(|| anyhow!("month out of range"))?;
+            let rows = db::get_spend_summary(
+                &db,
+                month.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+                next_month.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+            )?;
+            println!("Spend for {}", month.format("%Y-%m"));
+            println!(
+                "{:<16} {:<12} {:>6} {:>12}",
+                "category", "source", "count", "mana"
+            );
+            for row in rows.iter() {
+                println!(
+                    "{:<16} {:<12} {:>6} {:>12.0}",
+                    row.category.to_string(),
+                    row.source
+                        .as_ref()
+                        .map_or("-".to_string(), |source| source.to_string()),
+                    row.count,
+                    row.total
+                );
             }
+            println!(
+                "{:<16} {:<12} {:>6} {:>12.0}",
+                "total",
+                "",
+                rows.iter().map(|row| row.count).sum::<i64>(),
+                rows.iter().fold(0.0, |total, row| total + row.total)
+            );
         }
-        ListCommands::ThirdParty => {
-            for mirror in db::get_third_party_mirrors(&db)? {
-                println!("{:#?}", mirror);
+        ReportCommands::Calibration => calibration_report(config, &db).await?,
+        ReportCommands::Budget { days } => budget_report(config, &db, days).await?,
+        ReportCommands::Activity { top } => activity_report(&db, top)?,
+        ReportCommands::Pnl => pnl_report(config, &db).await?,
+        ReportCommands::Projects => projects_report(config, &db)?,
+        ReportCommands::Requesters { top, since } => {
+            let client = http::client(config)?;
+            let since = since.map(|since| Utc::now() - since);
+            let requesters = managrams::top_requesters(&client, &db, config, since, top).await?;
+            if requesters.is_empty() {
+                println!("No mirrors were requested by managram");
+                return Ok(());
+            }
+            println!(
+                "{:>4} {:<24} {:>8} {:>10}",
+                "rank", "user", "mirrors", "mana"
+            );
+            for (i, (name, row)) in requesters.iter().enumerate() {
+                println!(
+                    "{:>4} {:<24} {:>8} {:>10.0}",
+                    i + 1,
+                    name,
+                    row.mirrors,
+                    row.spent
+                );
             }
         }
     }
     Ok(())
 }
 
-pub fn mirror_question(
+/// The bot's holdings in one mirror, summed over its bets
+#[derive(Default)]
+struct Position {
+    /// Mana spent, net of sales
+    cost: f64,
+    yes_shares: f64,
+    no_shares: f64,
+}
+
+impl Position {
+    /// Worth of the shares if the market resolves at `p`
+    fn value(&self, p: f64) -> f64 {
+        self.yes_shares * p + self.no_shares * (1.0 - p)
+    }
+}
+
+async fn pnl_report(config: &Settings, db: &Connection) -> Result<()> {
+    let client = http::client(config)?;
+    let bets: Vec<manifold::Bet> = manifold::stream_bets(
+        &client,
+        manifold::GetBetsArgs {
+            user_id: Some(config.manifold.user_id.clone()),
+            ..Default::default()
+        },
+        config,
+    )
+    .try_collect()
+    .await?;
+    let mut positions: BTreeMap<String, Position> = BTreeMap::new();
+    for bet in &bets {
+        let position = positions.entry(bet.contract_id.clone()).or_default();
+        position.cost += bet.amount;
+        match bet.outcome.as_str() {
+            "YES" => position.yes_shares += bet.shares,
+            "NO" => position.no_shares += bet.shares,
+            outcome => warn!("Ignoring shares of bet {} on outcome {}", bet.id, outcome),
+        }
+    }
+    if positions.is_empty() {
+        println!("The bot hasn't bet on any markets");
+        return Ok(());
+    }
+
+    // (mirrors, invested, realized, unrealized) per source
+    let mut sources: BTreeMap<String, (usize, f64, f64, f64)> = BTreeMap::new();
+    // (mirrors, realized) per month of resolution
+    let mut months: BTreeMap<String, (usize, f64)> = BTreeMap::new();
+    let mut unknown = 0;
+    for (contract_id, position) in &positions {
+        let Some(mirror) = db::get_mirror_by_contract_id(db, contract_id)? else {
+            unknown += 1;
+            continue;
+        };
+        let (realized, unrealized) = match (&mirror.resolution, mirror.manifold_probability) {
+            (Some(BinaryResolution::Cancel), _) => (0.0, 0.0),
+            (Some(BinaryResolution::Yes), _) => (position.value(1.0) - position.cost, 0.0),
+            (Some(BinaryResolution::No), _) => (position.value(0.0) - position.cost, 0.0),
+            (Some(BinaryResolution::Percent(p)), _) => (position.value(*p) - position.cost, 0.0),
+            (None, Some(p)) if !mirror.resolved => (0.0, position.value(p) - position.cost),
+            _ => {
+                unknown += 1;
+                continue;
+            }
+        };
+        let entry = sources.entry(mirror.source.to_string()).or_default();
+        entry.0 += 1;
+        entry.1 += position.cost;
+        entry.2 += realized;
+        entry.3 += unrealized;
+        if mirror.resolution.is_some() {
+            let month = mirror.resolved_time.map_or("unknown".to_string(), |time| {
+                time.format("%Y-%m").to_string()
+            });
+            let entry = months.entry(month).or_default();
+            entry.0 += 1;
+            entry.1 += realized;
+        }
+    }
+    if unknown > 0 {
+        println!(
+            "Skipping {} market(s) that aren't mirrors or have an unknown resolution or probability",
+            unknown
+        );
+    }
+    let total = sources.values().fold((0, 0.0, 0.0, 0.0), |a, b| {
+        (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3)
+    });
+    println!(
+        "{:<12} {:>8} {:>10} {:>10} {:>11}",
+        "source", "mirrors", "invested", "realized", "unrealized"
+    );
+    for (source, (count, invested, realized, unrealized)) in
+        sources.iter().chain([(&"all".to_string(), &total)])
+    {
+        println!(
+            "{:<12} {:>8} {:>10.0} {:>10.0} {:>11.0}",
+            source, count, invested, realized, unrealized
+        );
+    }
+    if !months.is_empty() {
+        println!();
+        println!("Realized by month of resolution");
+        println!("{:<8} {:>8} {:>10}", "month", "mirrors", "realized");
+        for (month, (count, realized)) in &months {
+            println!("{:<8} {:>8} {:>10.0}", month, count, realized);
+        }
+    }
+    Ok(())
+}
+
+fn projects_report(config: &Settings, db: &Connection) -> Result<()> {
+    if config.metaculus.projects.is_empty() {
+        println!("No projects configured in metaculus.projects");
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:>8} {:>8} {:>8} {:>8}",
+        "project", "id", "mirrors", "open", "24h"
+    );
+    let projects: BTreeMap<_, _> = config.metaculus.projects.iter().collect();
+    for (name, project) in projects {
+        let (mirrors, open) = db::count_project_mirrors(db, project.id)?;
+        let recent =
+            db::count_project_mirrors_since(db, project.id, Utc::now() - Duration::days(1))?;
+        println!(
+            "{:<20} {:>8} {:>8} {:>8} {:>8}",
+            name, project.id, mirrors, open, recent
+        );
+    }
+    Ok(())
+}
+
+fn activity_report(db: &Connection, top: usize) -> Result<()> {
+    let mut mirrors: Vec<_> = db::get_mirrors(db)?
+        .into_iter()
+        .filter(|mirror| mirror.source != QuestionSource::Manual && mirror.analytics_time.is_some())
+        .collect();
+    if mirrors.is_empty() {
+        println!("No trading activity recorded, run `sync --analytics` first");
+        return Ok(());
+    }
+    // (mirrors, volume, volume in the last 24 hours, traders) per source
+    let mut sources: BTreeMap<String, (usize, f64, f64, i64)> = BTreeMap::new();
+    for mirror in &mirrors {
+        let entry = sources.entry(mirror.source.to_string()).or_default();
+        entry.0 += 1;
+        entry.1 += mirror.volume.unwrap_or(0.0);
+        entry.2 += mirror.volume_24h.unwrap_or(0.0);
+        entry.3 += mirror.unique_bettors.unwrap_or(0);
+    }
+    let total = sources.values().fold((0, 0.0, 0.0, 0), |a, b| {
+        (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3)
+    });
+    println!(
+        "{:<12} {:>8} {:>12} {:>10} {:>8} {:>12}",
+        "source", "mirrors", "volume", "24h", "traders", "traders/mir"
+    );
+    for (source, (count, volume, volume_24h, traders)) in
+        sources.iter().chain([(&"all".to_string(), &total)])
+    {
+        println!(
+            "{:<12} {:>8} {:>12.0} {:>10.0} {:>8} {:>12.1}",
+            source,
+            count,
+            volume,
+            volume_24h,
+            traders,
+            *traders as f64 / *count as f64
+        );
+    }
+    println!();
+    println!("Most traded mirrors");
+    println!(
+        "{:>6} {:>10} {:>8}  question",
+        "row id", "volume", "traders"
+    );
+    mirrors.sort_by(|a, b| b.volume.unwrap_or(0.0).total_cmp(&a.volume.unwrap_or(0.0)));
+    for mirror in mirrors.iter().take(top) {
+        println!(
+            "{:>6} {:>10.0} {:>8}  {}",
+            mirror.id,
+            mirror.volume.unwrap_or(0.0),
+            mirror.unique_bettors.unwrap_or(0),
+            mirror.question
+        );
+    }
+    Ok(())
+}
+
+/// Days of spending history used to estimate costs in the budget report
+const BUDGET_HISTORY_DAYS: i64 = 30;
+
+async fn budget_report(config: &Settings, db: &Connection, days: i64) -> Result<()> {
+    if days <= 0 {
+        bail!("--days must be positive");
+    }
+    let now = Utc::now();
+    let history = db::get_spend_summary(db, now - Duration::days(BUDGET_HISTORY_DAYS), now)?;
+
+    println!("Mana needed over the next {} days", days);
+    println!(
+        "{:<24} {:>10} {:>12} {:>12}",
+        "item", "per day", "unit cost", "mana"
+    );
+    let mut total = 0.0;
+    for (source, clones_per_day) in [
+        (QuestionSource::Kalshi, config.kalshi.max_clones_per_day),
+        (
+            QuestionSource::Metaculus,
+            config.metaculus.max_clones_per_day,
+        ),
+    ] {
+        let (count, spent) = history
+            .iter()
+            .filter(|row| {
+                row.category == SpendCategory::MarketCreation
+                    && row.source.as_ref() == Some(&source)
+            })
+            .fold((0, 0.0), |(count, spent), row| {
+                (count + row.count, spent + row.total)
+            });
+        let unit_cost = if count > 0 {
+            spent / count as f64
+        } else {
+            config.manifold.market_creation_cost
+        };
+        let mana = clones_per_day as f64 * unit_cost * days as f64;
+        total += mana;
+        println!(
+            "{:<24} {:>10} {:>12.0} {:>12.0}",
+            format!("{} auto-mirrors", source),
+            clones_per_day,
+            unit_cost,
+            mana
+        );
+    }
+
+    let subsidy = &config.manifold.subsidy;
+    let subsidies_per_day = if subsidy.enabled {
+        let spent = db::get_total_spend(
+            db,
+            SpendCategory::Subsidy,
+            Some(now - Duration::days(BUDGET_HISTORY_DAYS)),
+            None,
+        )?;
+        (spent / BUDGET_HISTORY_DAYS as f64).min(subsidy.daily_budget)
+    } else {
+        0.0
+    };
+    let mana = subsidies_per_day * days as f64;
+    total += mana;
+    println!("{:<24} {:>10} {:>12} {:>12.0}", "subsidies", "", "", mana);
+
+    let rewards = &config.manifold.rewards;
+    let rewards_per_day = if rewards.enabled {
+        let spent = db::get_total_spend(
+            db,
+            SpendCategory::Reward,
+            Some(now - Duration::days(BUDGET_HISTORY_DAYS)),
+            None,
+        )?;
+        (spent / BUDGET_HISTORY_DAYS as f64).min(rewards.daily_budget)
+    } else {
+        0.0
+    };
+    let mana = rewards_per_day * days as f64;
+    total += mana;
+    println!(
+        "{:<24} {:>10} {:>12} {:>12.0}",
+        "trader rewards", "", "", mana
+    );
+
+    // managrams we haven't finished processing may still have to be refunded
+    let mut exposure = 0.0;
+    for state in [
+        ManagramState::New,
+        ManagramState::Started,
+        ManagramState::Failed,
+    ] {
+        exposure += db::get_managrams_by_state(db, state)?
+            .iter()
+            .map(|managram| managram.amount)
+            .sum::<f64>();
+    }
+    total += exposure;
+    println!(
+        "{:<24} {:>10} {:>12} {:>12.0}",
+        "managram refunds", "", "", exposure
+    );
+    println!("{:<24} {:>10} {:>12} {:>12.0}", "total", "", "", total);
+    println!();
+
+    let balance = manifold::get_me(&http::client(config)?, config)
+        .await
+        .with_context(|| "failed to fetch balance from Manifold")?
+        .balance;
+    println!("Current balance: {:.0}", balance);
+    if balance >= total {
+        println!("Surplus: {:.0}", balance - total);
+    } else {
+        println!("Shortfall: {:.0}", total - balance);
+    }
+    Ok(())
+}
+
+/// Number of equal-width probability buckets in the calibration table
+const CALIBRATION_BUCKETS: usize = 10;
+
+async fn calibration_report(config: &Settings, db: &Connection) -> Result<()> {
+    let client = http::client(config)?;
+    // (closing probability, outcome) per source
+    let mut forecasts: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+    let mut unknown = 0;
+    for mirror in db::get_resolved_mirrors(db, None)? {
+        let outcome = match mirror.resolution {
+            Some(BinaryResolution::Yes) => 1.0,
+            Some(BinaryResolution::No) => 0.0,
+            Some(BinaryResolution::Percent(p)) => p,
+            Some(BinaryResolution::Cancel) => continue,
+            None => {
+                unknown += 1;
+                continue;
+            }
+        };
+        let probability = match mirror.close_probability {
+            Some(p) => Some(p),
+            None => get_close_probability(config, &client, db, &mirror).await?,
+        };
+        match probability {
+            Some(p) => forecasts
+                .entry(mirror.source.to_string())
+                .or_default()
+                .push((p, outcome)),
+            None => unknown += 1,
+        }
+    }
+    if unknown > 0 {
+        println!(
+            "Skipping {} resolved mirror(s) with unknown resolution or closing probability",
+            unknown
+        );
+    }
+    let all: Vec<(f64, f64)> = forecasts.values().flatten().copied().collect();
+    if all.is_empty() {
+        println!("No resolved mirrors to report on");
+        return Ok(());
+    }
+    println!("{:<12} {:>8} {:>8}", "source", "mirrors", "brier");
+    for (source, forecasts) in forecasts.iter().chain([(&"all".to_string(), &all)]) {
+        let brier = forecasts
+            .iter()
+            .map(|(p, outcome)| (p - outcome).powi(2))
+            .sum::<f64>()
+            / forecasts.len() as f64;
+        println!("{:<12} {:>8} {:>8.4}", source, forecasts.len(), brier);
+    }
+    for (source, forecasts) in forecasts.iter() {
+        println!();
+        println!("Calibration for {}", source);
+        println!(
+            "{:<10} {:>8} {:>10} {:>10}",
+            "bucket", "mirrors", "predicted", "observed"
+        );
+        let mut buckets = vec![Vec::new(); CALIBRATION_BUCKETS];
+        for &(p, outcome) in forecasts {
+            let bucket = ((p * CALIBRATION_BUCKETS as f64) as usize).min(CALIBRATION_BUCKETS - 1);
+            buckets[bucket].push((p, outcome));
+        }
+        for (i, bucket) in buckets.iter().enumerate() {
+            if bucket.is_empty() {
+                continue;
+            }
+            let mean = |f: fn(&(f64, f64)) -> f64| {
+                bucket.iter().map(f).sum::<f64>() / bucket.len() as f64 * 100.0
+            };
+            println!(
+                "{:<10} {:>8} {:>9.1}% {:>9.1}%",
+                format!(
+                    "{}-{}%",
+                    i * 100 / CALIBRATION_BUCKETS,
+                    (i + 1) * 100 / CALIBRATION_BUCKETS
+                ),
+                bucket.len(),
+                mean(|(p, _)| *p),
+                mean(|(_, outcome)| *outcome)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Manifold probability when the mirror closed, from the last bet placed before
+/// its close time. Stored so later reports don't need to fetch it again. Falls back
+/// to the probability stored by the last sync if there were no bets, since it
+/// can't have moved after close.
+async fn get_close_probability(
     config: &Settings,
-    source: QuestionSource,
-    id: String,
-    allow_resolved: bool,
-) -> Result<()> {
-    let client = Client::new();
-    let db = db::open(&config)?;
+    client: &Client,
+    db: &Connection,
+    mirror: &db::MirrorRow,
+) -> Result<Option<f64>> {
+    let Some(close_time) = mirror.close_time else {
+        return Ok(mirror.manifold_probability);
+    };
+    let args = manifold::GetBetsArgs {
+        contract_id: Some(mirror.manifold_contract_id.clone()),
+        limit: Some(1),
+        before_time: Some(close_time),
+        ..Default::default()
+    };
+    let probability = match manifold::get_bets(client, &args, config).await {
+        std::result::Result::Ok(bets) => match bets.first() {
+            Some(bet) => {
+                debug!(
+                    "Last bet before close on mirror {} was {} at {}",
+                    mirror.id, bet.id, bet.created_time
+                );
+                Some(bet.prob_after)
+            }
+            None => mirror.manifold_probability,
+        },
+        Err(e) => {
+            warn!(
+                "Failed to fetch bets for mirror {}, using last synced probability: {:#}",
+                mirror.id, e
+            );
+            return Ok(mirror.manifold_probability);
+        }
+    };
+    if let Some(p) = probability {
+        db::set_mirror_close_probability(db, mirror.id, p)?;
+    }
+    Ok(probability)
+}
+
+fn config_command(subcommand: ConfigCommands) -> Result<()> {
+    match subcommand {
+        ConfigCommands::Show => {
+            for (key, value, origin) in Settings::effective_values()? {
+                println!("{} = {}  # {}", key, value, origin);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run the auto and request filters on a question and explain each requirement.
+async fn check_question(config: &Settings, source: QuestionSource, id: String) -> Result<()> {
+    let client = http::client(config)?;
     match source {
+        QuestionSource::Kalshi => check_source_question::<KalshiSource>(&client, config, &id).await,
         QuestionSource::Metaculus => {
-            let metaculus_question = metaculus::get_question(&client, &id, config)
-                .with_context(|| "failed to fetch question from Metaculus")?;
-            if metaculus_question.is_resolved() {
-                if allow_resolved {
-                    warn!("question has already resolved");
+            check_source_question::<MetaculusSource>(&client, config, &id).await
+        }
+        QuestionSource::Polymarket => {
+            check_source_question::<PolymarketSource>(&client, config, &id).await
+        }
+        QuestionSource::Manual => bail!("manual questions have no filters"),
+    }
+}
+
+async fn check_source_question<S: MirrorSource>(
+    client: &Client,
+    config: &Settings,
+    id: &str,
+) -> Result<()> {
+    let question = S::fetch(client, config, id).await?;
+    println!("{} ({})", S::title(&question), S::url(&question));
+    match S::auto_filter(config) {
+        Some(requirements) => {
+            print_requirement_checks("auto_filter", S::check(&question, requirements))
+        }
+        None => println!("auto_filter: not supported for {}", S::SOURCE),
+    }
+    print_requirement_checks(
+        "request_filter",
+        S::check(&question, S::request_filter(config)),
+    );
+    Ok(())
+}
+
+fn print_requirement_checks<E: std::fmt::Display>(filter: &str, checks: Vec<RequirementCheck<E>>) {
+    let failures = checks.iter().filter(|check| check.result.is_err()).count();
+    println!(
+        "{}: {}",
+        filter,
+        if failures == 0 {
+            "PASS".to_string()
+        } else {
+            format!("FAIL ({} requirement(s) not met)", failures)
+        }
+    );
+    for check in checks {
+        let status = match &check.result {
+            std::result::Result::Ok(()) => "pass".to_string(),
+            Err(e) => format!("FAIL: {}", e),
+        };
+        println!(
+            "  {:<40} observed {:<24} threshold {:<16} {}",
+            check.name, check.observed, check.threshold, status
+        );
+    }
+}
+
+/// Outcome of a single `doctor` check
+#[derive(Serialize)]
+struct DoctorCheck {
+    name: &'static str,
+    ok: bool,
+    detail: String,
+}
+
+/// Check that the database and APIs are usable, printing a JSON report.
+/// Fails if any check found a problem.
+async fn doctor(config: &Settings) -> Result<()> {
+    let client = http::client(config)?;
+    let mut checks = Vec::new();
+    let mut check = |name, result: Result<String>| {
+        checks.push(match result {
+            std::result::Result::Ok(detail) => DoctorCheck {
+                name,
+                ok: true,
+                detail,
+            },
+            Err(e) => DoctorCheck {
+                name,
+                ok: false,
+                detail: format!("{:#}", e),
+            },
+        })
+    };
+    check("database", check_database(config));
+    match manifold::get_me(&client, config).await {
+        std::result::Result::Ok(me) => {
+            check(
+                "manifold",
+                if me.id == config.manifold.user_id {
+                    Ok(format!("api key belongs to @{}", me.username))
+                } else {
+                    Err(anyhow!(
+                        "api key belongs to @{} ({}), but manifold.user_id is {}",
+                        me.username,
+                        me.id,
+                        config.manifold.user_id
+                    ))
+                },
+            );
+            check(
+                "balance",
+                if me.balance >= config.manifold.min_balance {
+                    Ok(format!("balance is {:.0}", me.balance))
                 } else {
-                    return Err(anyhow!("question has already resolved"));
+                    Err(anyhow!(
+                        "balance is {:.0}, below manifold.min_balance of {:.0}",
+                        me.balance,
+                        config.manifold.min_balance
+                    ))
+                },
+            );
+        }
+        Err(e) => {
+            check("manifold", Err(e.into()));
+            check("balance", Err(anyhow!("could not fetch the bot's user")));
+        }
+    }
+    check(
+        "metaculus",
+        metaculus::check_auth(&client, config)
+            .await
+            .map(|_| "api key accepted".to_string()),
+    );
+    check(
+        "kalshi",
+        kalshi::check_api(&client, config)
+            .await
+            .map(|_| "api reachable".to_string())
+            .map_err(anyhow::Error::from),
+    );
+    let problems = checks.iter().filter(|check| !check.ok).count();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({ "ok": problems == 0, "checks": checks }))?
+    );
+    if problems > 0 {
+        bail!("doctor found {} problem(s)", problems);
+    }
+    Ok(())
+}
+
+/// Open the database without creating or migrating it, and report its schema version
+fn check_database(config: &Settings) -> Result<String> {
+    let db = Connection::open_with_flags(&config.database.path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .with_context(|| format!("failed to open database at {}", config.database.path))?;
+    let version = db::schema_version(&db)?;
+    let tables: i64 = db.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get(0))?;
+    if version > db::SCHEMA_VERSION {
+        bail!(
+            "schema version {} is newer than this build supports ({})",
+            version,
+            db::SCHEMA_VERSION
+        );
+    }
+    if version < db::SCHEMA_VERSION {
+        return Ok(format!(
+            "schema version {}, will be migrated to {} on next use",
+            version,
+            db::SCHEMA_VERSION
+        ));
+    }
+    Ok(format!(
+        "schema version {}, {} schema objects",
+        version, tables
+    ))
+}
+
+pub fn db_command(config: &Settings, subcommand: DbCommands) -> Result<()> {
+    let db = db::open(config)?;
+    match subcommand {
+        DbCommands::Backup {
+            path,
+            gzip,
+            timestamp,
+        } => {
+            let mut path = path;
+            if timestamp {
+                let stem = path
+                    .file_stem()
+                    .ok_or_else(|| anyhow!("backup path has no file name"))?
+                    .to_string_lossy()
+                    .to_string();
+                let mut file_name = format!("{}-{}", stem, Utc::now().format("%Y%m%dT%H%M%SZ"));
+                if let Some(extension) = path.extension() {
+                    file_name.push_str(&format!(".{}", extension.to_string_lossy()));
                 }
+                path.set_file_name(file_name);
+            }
+            if !gzip {
+                db::backup(&db, &path)?;
+                info!("Backed up database to {}", path.display());
+                return Ok(());
             }
-            // TODO: use function clone_metaculus
-            let question = (&metaculus_question)
-                .try_into()
-                .with_context(|| "failed to convert Metaculus question to common format")?;
-            let row = mirror::mirror_question(&client, &db, &question, config)?;
-            println!("Mirrored question:\n{:#?}", row);
+            // back up to a temporary file first, the backup API can't write to a stream
+            let mut tmp_path = path.clone().into_os_string();
+            tmp_path.push(".tmp");
+            let tmp_path = PathBuf::from(tmp_path);
+            let mut gz_path = path.into_os_string();
+            gz_path.push(".gz");
+            let gz_path = PathBuf::from(gz_path);
+            db::backup(&db, &tmp_path)?;
+            let result = gzip_file(&tmp_path, &gz_path);
+            fs::remove_file(&tmp_path)
+                .with_context(|| format!("failed to remove {}", tmp_path.display()))?;
+            result?;
+            info!("Backed up database to {}", gz_path.display());
         }
-        QuestionSource::Kalshi => {
-            let kalshi_question = kalshi::get_question(&client, &id, config)
-                .with_context(|| "failed to fetch question from Kalshi")?;
-            if kalshi_question.is_resolved() {
-                if allow_resolved {
-                    warn!("question has already resolved");
-                } else {
-                    return Err(anyhow!("question has already resolved"));
+        DbCommands::Maintenance { dry_run } => {
+            let problems = db::integrity_check(&db)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("integrity check: {}", problem);
                 }
+                bail!(
+                    "integrity check found {} problems, not continuing",
+                    problems.len()
+                );
+            }
+            println!("integrity check: ok");
+            let cutoff = Utc::now() - chrono::Duration::days(config.database.retention_days);
+            if dry_run {
+                println!("dry run: not pruning records from before {}", cutoff);
+            } else {
+                for (table, deleted) in db::prune(&db, cutoff)? {
+                    println!("pruned {} rows from {}", deleted, table);
+                }
+                db::vacuum_and_analyze(&db)?;
+                println!("vacuumed and analyzed");
+            }
+            for (table, count) in db::table_row_counts(&db)? {
+                println!("{:<24} {:>8}", table, count);
             }
-            mirror::mirror_kalshi_question(&client, &db, config, &kalshi_question)?;
-        }
-        QuestionSource::Polymarket => {
-            bail!("Polymarket mirroring hasn't been implemented yet");
-        }
-        QuestionSource::Manual => {
-            bail!("Manual markets are not mirrors");
         }
     }
     Ok(())
 }
 
-// NOTE: this implementation is trash, basically a one-off for ACX2024 mirrors
-fn mirror_metaculus_project(
+pub fn export(
     config: &Settings,
-    project_id: u64,
-    header: String,
-    group_id: String,
+    table: ExportTable,
+    format: ExportFormat,
+    since: Option<NaiveDate>,
+    output: Option<PathBuf>,
 ) -> Result<()> {
-    let client = Client::new();
-    let db = db::open(&config)?;
+    let db = db::open(config)?;
+    let (table, time_column) = match table {
+        ExportTable::Mirrors => ("markets", "clone_date"),
+        ExportTable::Managrams => ("managrams", "created_time"),
+        ExportTable::ThirdParty => ("third_party_markets", "created_time"),
+    };
+    let since = since.map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    let (columns, rows) = db::get_raw_rows(&db, table, time_column, since)?;
+    let out: Box<dyn io::Write> = match &output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    match format {
+        ExportFormat::Json => {
+            let objects: Vec<serde_json::Map<String, serde_json::Value>> = rows
+                .into_iter()
+                .map(|row| columns.iter().cloned().zip(row).collect())
+                .collect();
+            serde_json::to_writer_pretty(out, &objects)?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(&columns)?;
+            for row in rows {
+                writer.write_record(row.iter().map(|value| match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                }))?;
+            }
+            writer.flush()?;
+        }
+    }
+    if let Some(path) = output {
+        info!("Exported {} to {}", table, path.display());
+    }
+    Ok(())
+}
 
-    let project_questions = metaculus::get_questions(
-        &client,
-        MetaculusListQuestionsParams {
-            project: Some(project_id.to_string()),
-            r#type: Some(metaculus::QuestionType::Forecast),
-            forecast_type: Some("binary".to_string()),
-            ..Default::default()
-        },
-        config,
-    )
-    .with_context(|| "failed to fetch project questions from Metaculus")?;
+pub async fn import(config: &Settings, subcommand: ImportCommands) -> Result<()> {
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    match subcommand {
+        ImportCommands::Mirrors { path, format } => {
+            let format = format.unwrap_or_else(|| {
+                match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("csv") => ExportFormat::Csv,
+                    _ => ExportFormat::Json,
+                }
+            });
+            let file =
+                File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+            let rows: Vec<ImportedMirror> = match format {
+                ExportFormat::Json => serde_json::from_reader(file)?,
+                ExportFormat::Csv => csv::Reader::from_reader(file)
+                    .deserialize()
+                    .collect::<std::result::Result<_, _>>()?,
+            };
+            let (mut imported, mut skipped, mut failed) = (0, 0, 0);
+            for row in &rows {
+                match mirror::import_mirror(&client, &db, config, row).await {
+                    std::result::Result::Ok(Some(mirror)) => {
+                        info!("Imported mirror: {:#?}", mirror);
+                        imported += 1;
+                    }
+                    std::result::Result::Ok(None) => skipped += 1,
+                    Err(e) => {
+                        warn!(
+                            "Failed to import mirror of market with id {}: {:#}",
+                            row.manifold_contract_id, e
+                        );
+                        failed += 1;
+                    }
+                }
+            }
+            println!(
+                "Imported {} mirrors, skipped {} already in the db, {} failed",
+                imported, skipped, failed
+            );
+        }
+    }
+    Ok(())
+}
+
+fn gzip_file(from: &Path, to: &Path) -> Result<()> {
+    let mut input =
+        File::open(from).with_context(|| format!("failed to open {}", from.display()))?;
+    let output = File::create(to).with_context(|| format!("failed to create {}", to.display()))?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
 
-    for question in project_questions {
-        log_if_err!(mirror_metaculus_project_question(
-            config, &client, &db, &header, &group_id, question
-        ))
+pub fn list_markets(config: &Settings, args: ListArgs) -> Result<()> {
+    let db = db::open(&config)?;
+    let format = args.format;
+    match args.command {
+        ListCommands::Mirrors {
+            resolved,
+            source,
+            since,
+            until,
+            question_contains,
+            contract_id,
+        } => {
+            let start_of_day = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+            let filter = db::MirrorFilter {
+                resolved,
+                source,
+                since: since.map(start_of_day),
+                until: until.and_then(|date| date.succ_opt()).map(start_of_day),
+                question_contains,
+                contract_id,
+            };
+            listing::print_rows(&db::get_filtered_mirrors(&db, &filter)?, format)
+        }
+        ListCommands::ThirdParty => listing::print_rows(&db::get_third_party_mirrors(&db)?, format),
+        ListCommands::PendingResolutions { all } => {
+            let state = if all {
+                None
+            } else {
+                Some(PendingResolutionState::Pending)
+            };
+            listing::print_rows(&db::get_pending_resolutions(&db, state)?, format)
+        }
+        ListCommands::Resolutions { mirror_id } => {
+            listing::print_rows(&db::get_resolution_history(&db, mirror_id)?, format)
+        }
+        ListCommands::Runs { limit } => listing::print_rows(&db::get_runs(&db, limit)?, format),
+        ListCommands::Managrams {
+            unprocessed,
+            failed,
+            from_id,
+            since,
+        } => listing::print_rows(
+            &db::get_filtered_managrams(
+                &db,
+                unprocessed,
+                failed,
+                from_id.as_deref(),
+                since.map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+            )?,
+            format,
+        ),
+        ListCommands::Audit { managram_id } => listing::print_rows(
+            &db::get_managram_audit(&db, managram_id.as_deref())?,
+            format,
+        ),
+        ListCommands::Rejections { source, since } => {
+            let since = since.map(|since| Utc::now() - since);
+            listing::print_rows(&db::get_rejections(&db, source, since)?, format)
+        }
     }
+}
 
+pub async fn mirror_question(
+    config: &Settings,
+    source: QuestionSource,
+    id: String,
+    allow_resolved: bool,
+    dry_run: bool,
+    destination: Option<&str>,
+) -> Result<()> {
+    let client = http::client(config)?;
+    let (question, is_resolved) = mirror::fetch_question(&client, config, source, &id).await?;
+    if is_resolved {
+        if allow_resolved {
+            warn!("question has already resolved");
+        } else {
+            return Err(anyhow!("question has already resolved"));
+        }
+    }
+    if dry_run {
+        // a preview writes nothing, so the database isn't created or migrated
+        let db = db::open_read_only(config)
+            .inspect_err(|e| warn!("Not checking for an existing mirror: {:#}", e))
+            .ok();
+        return print_mirror_preview(config, db.as_ref(), &question);
+    }
+    let db = db::open(config)?;
+    let row = mirror::mirror_question(&client, &db, &question, destination, config).await?;
+    println!("Mirrored question:\n{:#?}", row);
     Ok(())
 }
 
-// garbage code close your eyes
-fn mirror_metaculus_project_question(
+/// Print the market that mirroring the question would create
+fn print_mirror_preview(
     config: &Settings,
-    client: &Client,
-    db: &Connection,
-    header: &String,
-    group_id: &String,
-    question: MetaculusQuestion,
+    db: Option<&Connection>,
+    question: &Question,
 ) -> Result<()> {
-    info!(
-        "mirroring project question with id {} (\"{}\")",
-        question.id, question.title
+    if let Some(db) = db {
+        if let Some(mirror) =
+            db::get_mirror_by_source_id(db, &question.source, &question.source_id)?
+        {
+            println!(
+                "Already mirrored at {} (row id {})",
+                mirror.manifold_url, mirror.id
+            );
+        }
+    }
+    let market = CreateMarketArgs::from_question(config, question);
+    println!(
+        "Title: {} ({} characters)",
+        market.question,
+        market.question.chars().count()
     );
+    println!("Close time: {}", market.close_time);
+    println!("Initial probability: {}%", market.initial_prob);
+    println!(
+        "Groups: {}",
+        if market.group_ids.is_empty() {
+            "none".to_string()
+        } else {
+            market.group_ids.join(", ")
+        }
+    );
+    println!(
+        "Visibility: {:?}, ranked: {}",
+        market.visibility, market.is_ranked
+    );
+    println!(
+        "Request price: {} mana",
+        config.mirror_cost(&question.source, Some(question.end_date))
+    );
+    println!("Description:\n{}", market.description_markdown);
+    Ok(())
+}
 
-    // fetch criteria
-    let question = metaculus::get_question(client, &question.id.to_string(), config)?;
-    let question: Question = (&question)
-        .try_into()
-        .with_context(|| "failed to convert Metaculus question to common format")?;
-
-    if let Some(mirror) = db::get_mirror_by_source_id(&db, &question.source, &question.source_id)? {
-        bail!("Already mirrored: {:?}", mirror);
+/// Mirror every question listed in a file, one Manifold url or `<source> <id>` per
+/// line. Blank lines and lines starting with # are ignored.
+async fn mirror_batch(config: &Settings, path: &Path, destination: Option<&str>) -> Result<()> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let client = http::client(config)?;
+    let db = db::open(config)?;
+    let run_id = db::start_run(&db, "mirror-batch", &path.display().to_string())?;
+    let errors_before = util::errors_logged();
+    let mut stats = RunStats::default();
+    let mut created = Vec::new();
+    let mut skipped = Vec::new();
+    let mut failed = Vec::new();
+    let mut seen = Vec::new();
+    for line in contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        let target = match parse_batch_line(line) {
+            std::result::Result::Ok(target) => target,
+            Err(e) => {
+                failed.push((line, e));
+                continue;
+            }
+        };
+        stats.candidates += 1;
+        if seen.contains(&(target.source.clone(), target.source_id.clone())) {
+            skipped.push((line, "listed twice".to_string()));
+            continue;
+        }
+        seen.push((target.source.clone(), target.source_id.clone()));
+        match mirror_batch_entry(&client, &db, config, &target, destination).await {
+            std::result::Result::Ok(BatchEntryOutcome::Created(url)) => created.push((line, url)),
+            std::result::Result::Ok(BatchEntryOutcome::Skipped(reason)) => {
+                skipped.push((line, reason))
+            }
+            Err(e) => {
+                warn!("failed to mirror {}: {:#}", line, e);
+                failed.push((line, format!("{:#}", e)));
+            }
+        }
+    }
+    stats.mirrors_created = created.len();
+    http::report_open_circuits();
+    stats.errors = util::errors_logged() - errors_before;
+    db::finish_run(&db, run_id, &stats, None)?;
+    for (title, entries) in [
+        ("Created", &created),
+        ("Skipped", &skipped),
+        ("Failed", &failed),
+    ] {
+        println!("{} ({}):", title, entries.len());
+        for (line, detail) in entries {
+            println!("  {}: {}", line, detail);
+        }
     }
+    Ok(())
+}
 
-    let mut market_args = manifold::CreateMarketArgs::from_question(config, &question);
-    market_args.question = market_args
-        .question
-        .replace("[Metaculus]", &format!("[{}]", header));
-    market_args.group_ids.push(group_id.to_string());
+enum BatchEntryOutcome {
+    /// Contains the url of the new mirror
+    Created(String),
+    Skipped(String),
+}
 
-    let market = manifold::create_market(client, market_args, config)?;
-    let mirror_row = db::insert_mirror(db, &market, &question, config)?;
-    info!("Created mirror: {:#?}", mirror_row);
-    Ok(())
+/// Parse a Manifold url, or a source and id separated by whitespace
+fn parse_batch_line(line: &str) -> std::result::Result<MirrorTarget, String> {
+    match line.split_once(char::is_whitespace) {
+        Some((source, id)) => {
+            let source = <QuestionSource as ValueEnum>::from_str(source, true)?;
+            let source_id = source.normalize_id(id);
+            std::result::Result::Ok(MirrorTarget { source, source_id })
+        }
+        None => MirrorTarget::parse_arg(line),
+    }
 }
 
-pub fn sync(
+async fn mirror_batch_entry(
+    client: &Client,
+    db: &Connection,
     config: &Settings,
-    kalshi: bool,
-    metaculus: bool,
-    managrams: bool,
-    manifold_self: bool,
-    manifold_other: bool,
-    all: bool,
+    target: &MirrorTarget,
+    destination: Option<&str>,
+) -> Result<BatchEntryOutcome> {
+    match db::get_any_mirror(db, &target.source, &target.source_id)? {
+        Some(db::AnyMirror::Mirror(mirror)) => {
+            return Ok(BatchEntryOutcome::Skipped(format!(
+                "already mirrored at {}",
+                mirror.manifold_url
+            )))
+        }
+        Some(db::AnyMirror::ThirdPartyMirror(mirror)) => {
+            return Ok(BatchEntryOutcome::Skipped(format!(
+                "already mirrored by someone else at {}",
+                mirror.manifold_url
+            )))
+        }
+        None => {}
+    }
+    let daily_limit = match target.source {
+        QuestionSource::Kalshi => Some(config.kalshi.max_clones_per_day),
+        QuestionSource::Metaculus => Some(config.metaculus.max_clones_per_day),
+        _ => None,
+    };
+    if let Some(limit) = daily_limit {
+        let cloned =
+            db::count_mirrors_cloned_since(db, &target.source, Utc::now() - Duration::days(1))?;
+        if cloned >= limit {
+            return Ok(BatchEntryOutcome::Skipped(format!(
+                "daily limit of {} {} mirrors reached",
+                limit, target.source
+            )));
+        }
+    }
+    if mirror::open_mirror_capacity(db, config, &target.source)? == Some(0) {
+        return Ok(BatchEntryOutcome::Skipped(format!(
+            "max_open_mirrors reached for {}",
+            target.source
+        )));
+    }
+    let (question, is_resolved) =
+        mirror::fetch_question(client, config, target.source.clone(), &target.source_id).await?;
+    if is_resolved {
+        return Ok(BatchEntryOutcome::Skipped(
+            "question has already resolved".to_string(),
+        ));
+    }
+    let mirror = mirror::mirror_question(client, db, &question, destination, config).await?;
+    Ok(BatchEntryOutcome::Created(mirror.manifold_url))
+}
+
+/// Mirror a Metaculus project that isn't configured in `metaculus.projects`,
+/// without filtering its questions
+async fn mirror_metaculus_project(
+    config: &Settings,
+    project_id: u64,
+    header: String,
+    group_id: String,
 ) -> Result<()> {
-    if !(kalshi || metaculus || managrams || manifold_self || manifold_other || all) {
+    let client = http::client(config)?;
+    let db = db::open(&config)?;
+    let project = MetaculusProject {
+        id: project_id,
+        header,
+        group_id,
+        filter: ProjectFilter::Off,
+        max_clones_per_day: 0,
+    };
+    let result = mirror::auto_mirror_project(&client, &db, config, &project, false, None).await?;
+    info!(
+        "Mirrored {} of {} candidates from project {}",
+        result.created.len(),
+        result.candidates,
+        project_id
+    );
+    Ok(())
+}
+
+pub async fn sync(config: &Settings, args: SyncArgs) -> Result<()> {
+    if !args.any_target() {
         bail!("Provide at least one sync target.");
     }
+    let SyncArgs {
+        kalshi,
+        metaculus,
+        polymarket,
+        managrams,
+        comments,
+        manifold_self,
+        manifold_other,
+        descriptions,
+        analytics,
+        liquidity,
+        rewards,
+        reminders,
+        probability_updates,
+        telegram,
+        leaderboard,
+        all,
+        ignore_schedule,
+        mirror: mirror_ref,
+    } = args;
 
-    let client = Client::new();
+    let client = http::client(config)?;
     let db = db::open(&config)?;
+    let target = if all {
+        "all".to_string()
+    } else {
+        [
+            (kalshi, "kalshi"),
+            (metaculus, "metaculus"),
+            (polymarket, "polymarket"),
+            (managrams, "managrams"),
+            (comments, "comments"),
+            (manifold_self, "manifold-self"),
+            (manifold_other, "manifold-other"),
+            (descriptions, "descriptions"),
+            (analytics, "analytics"),
+            (liquidity, "liquidity"),
+            (rewards, "rewards"),
+            (reminders, "reminders"),
+            (probability_updates, "probability-updates"),
+            (telegram, "telegram"),
+            (leaderboard, "leaderboard"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, name)| name.to_string())
+        .chain(
+            mirror_ref
+                .as_ref()
+                .map(|mirror| format!("mirror {}", mirror)),
+        )
+        .collect::<Vec<_>>()
+        .join(",")
+    };
+    let single_mirror = mirror_ref
+        .as_ref()
+        .map(|mirror| find_mirror(&db, mirror))
+        .transpose()?;
+    let run_id = db::start_run(&db, "sync", &target)?;
+    let errors_before = util::errors_logged();
+    let mut stats = RunStats::default();
+    // targets after a shutdown request are skipped
+    let wanted = |target: bool| (target || all) && !shutdown::requested();
+
+    // reconcile side effects of runs that crashed midway before doing anything new
+    log_if_err!(recovery::recover_pending_actions(&client, &db, config).await);
 
-    if manifold_self || all {
-        log_if_err!(mirror::sync_manifold_to_db(&client, &db, config));
+    if wanted(manifold_self) {
+        log_if_err!(mirror::sync_manifold_to_db(&client, &db, config).await);
     }
 
-    if manifold_other || all {
-        log_if_err!(mirror::sync_third_party_mirrors(&client, &db, config));
+    if wanted(manifold_other) {
+        log_if_err!(mirror::sync_third_party_mirrors(&client, &db, config).await);
     }
 
-    if kalshi || all {
+    if let Some(row) = &single_mirror {
+        log_if_err!(mirror::sync_single_mirror(&client, &db, row, config)
+            .await
+            .map(|resolved| stats.resolutions_synced += resolved as usize));
+    }
+
+    if wanted(kalshi) {
         log_if_err!(mirror::sync_resolutions_to_manifold(
             &client,
             &db,
             config,
-            Some(QuestionSource::Kalshi)
-        ));
+            Some(QuestionSource::Kalshi),
+            ignore_schedule
+        )
+        .await
+        .map(|resolved| stats.resolutions_synced += resolved));
     }
 
-    if metaculus || all {
+    if wanted(metaculus) {
         log_if_err!(mirror::sync_resolutions_to_manifold(
             &client,
             &db,
             config,
-            Some(QuestionSource::Metaculus)
-        ));
+            Some(QuestionSource::Metaculus),
+            ignore_schedule
+        )
+        .await
+        .map(|resolved| stats.resolutions_synced += resolved));
+    }
+
+    if wanted(polymarket) {
+        log_if_err!(mirror::sync_resolutions_to_manifold(
+            &client,
+            &db,
+            config,
+            Some(QuestionSource::Polymarket),
+            ignore_schedule
+        )
+        .await
+        .map(|resolved| stats.resolutions_synced += resolved));
+    }
+
+    if wanted(managrams) {
+        log_if_err!(managrams::sync_managrams(&client, &db, config).await);
+    }
+
+    if wanted(comments) {
+        log_if_err!(comments::sync_comments(&client, &db, config).await);
+    }
+
+    if wanted(descriptions) {
+        log_if_err!(mirror::sync_descriptions(&client, &db, config).await);
     }
 
-    if managrams || all {
-        log_if_err!(managrams::sync_managrams(&client, &db, config));
+    if wanted(analytics) {
+        log_if_err!(mirror::sync_analytics(&client, &db, config).await);
     }
 
+    if wanted(liquidity) {
+        log_if_err!(mirror::top_up_liquidity(&client, &db, config).await);
+    }
+
+    if wanted(rewards) {
+        log_if_err!(mirror::reward_top_traders(&client, &db, config).await);
+    }
+
+    if wanted(reminders) {
+        log_if_err!(mirror::post_close_reminders(&client, &db, config).await);
+    }
+
+    if wanted(probability_updates) {
+        log_if_err!(mirror::post_probability_updates(&client, &db, config).await);
+    }
+
+    if wanted(telegram) {
+        log_if_err!(telegram::process_commands(&client, &db, config).await);
+    }
+
+    if wanted(leaderboard) {
+        log_if_err!(managrams::post_requester_leaderboard(&client, &db, config).await);
+    }
+
+    http::report_open_circuits();
+    stats.errors = util::errors_logged() - errors_before;
+    let interrupted = shutdown::requested().then_some("interrupted by shutdown");
+    db::finish_run(&db, run_id, &stats, interrupted)?;
+    if stats.errors > 0 {
+        notify::notify(
+            &client,
+            config,
+            notify::Event::SyncFailed {
+                run_id,
+                errors: stats.errors,
+            },
+        )
+        .await;
+    }
     Ok(())
 }
 
-pub fn auto_mirror(config: &Settings, source: QuestionSource, dry_run: bool) -> Result<()> {
-    let client = Client::new();
+/// Sources with auto-mirroring support
+const AUTO_MIRROR_SOURCES: [QuestionSource; 2] =
+    [QuestionSource::Kalshi, QuestionSource::Metaculus];
+
+/// Auto-mirror from every supported source. Failures are logged per source so one
+/// source being down doesn't stop the others.
+pub async fn auto_mirror_all(config: &Settings, dry_run: bool, interactive: bool) -> Result<()> {
+    for source in AUTO_MIRROR_SOURCES {
+        if shutdown::requested() {
+            break;
+        }
+        log_if_err!(auto_mirror(config, source.clone(), dry_run, interactive)
+            .await
+            .with_context(|| format!("auto-mirror from {} failed", source)));
+    }
+    Ok(())
+}
+
+/// If `interactive` is set, each candidate is shown and only created if the
+/// operator confirms it.
+pub async fn auto_mirror(
+    config: &Settings,
+    source: QuestionSource,
+    dry_run: bool,
+    interactive: bool,
+) -> Result<()> {
+    let client = http::client(config)?;
     let db = db::open(&config)?;
-    match source {
-        QuestionSource::Metaculus => mirror::auto_mirror_metaculus(&client, &db, config, dry_run)?,
-        QuestionSource::Kalshi => mirror::auto_mirror_kalshi(&client, &db, config, dry_run)?,
+    if db::is_paused(&db)? {
+        info!("Bot is paused, skipping auto-mirror");
+        return Ok(());
+    }
+    let run_id = db::start_run(&db, "auto-mirror", &source.to_string())?;
+    let errors_before = util::errors_logged();
+    let mut prompt =
+        |question: &Question, stats: &[String]| prompt_candidate(config, question, stats);
+    let mut confirm: Option<&mut ConfirmCandidate> =
+        if interactive { Some(&mut prompt) } else { None };
+    let result: Result<AutoMirrorResult> = match source {
+        QuestionSource::Metaculus => {
+            let result = mirror::auto_mirror::<MetaculusSource>(
+                &client,
+                &db,
+                config,
+                dry_run,
+                confirm.as_deref_mut(),
+            )
+            .await;
+            let projects =
+                mirror::auto_mirror_projects(&client, &db, config, dry_run, confirm).await;
+            result
+                .map(|mut result| {
+                    result.candidates += projects.candidates;
+                    result.created.extend(projects.created);
+                    result
+                })
+                .map_err(Into::into)
+        }
+        QuestionSource::Kalshi => {
+            mirror::auto_mirror::<KalshiSource>(&client, &db, config, dry_run, confirm)
+                .await
+                .map_err(Into::into)
+        }
         QuestionSource::Polymarket => {
-            todo!()
+            mirror::auto_mirror::<PolymarketSource>(&client, &db, config, dry_run, confirm)
+                .await
+                .map_err(Into::into)
+        }
+        QuestionSource::Manual => Ok(AutoMirrorResult {
+            candidates: 0,
+            created: Vec::new(),
+        }),
+    };
+    let mut stats = RunStats::default();
+    if let std::result::Result::Ok(result) = &result {
+        log_if_err!(
+            managrams::notify_subscribers(&client, &db, config, &source, &result.created).await
+        );
+        stats.candidates = result.candidates;
+        stats.mirrors_created = result.created.len();
+    }
+    let result = result.map(|_| ());
+    http::report_open_circuits();
+    stats.errors = util::errors_logged() - errors_before;
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    db::finish_run(&db, run_id, &stats, error.as_deref())?;
+    result
+}
+
+/// Show an auto-mirror candidate and ask whether to mirror it. End of input
+/// skips all remaining candidates.
+fn prompt_candidate(config: &Settings, question: &Question, stats: &[String]) -> CandidateDecision {
+    println!();
+    println!("{} ({})", question.question, question.source_url);
+    println!("Closes: {}", question.end_date);
+    if let Some(probability) = question.probability {
+        println!("Probability: {:.0}%", probability * 100.0);
+    }
+    for stat in stats {
+        println!("  {}", stat);
+    }
+    println!(
+        "Manifold title: {}",
+        CreateMarketArgs::from_question(config, question).question
+    );
+    loop {
+        print!("Mirror this question? [y]es/[n]o/[s]kip all: ");
+        if let Err(e) = io::stdout().flush() {
+            warn!("failed to flush stdout: {}", e);
+        }
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            std::result::Result::Ok(0) => return CandidateDecision::SkipAll,
+            std::result::Result::Ok(_) => {}
+            Err(e) => {
+                warn!("failed to read answer: {}", e);
+                return CandidateDecision::SkipAll;
+            }
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return CandidateDecision::Mirror,
+            "n" | "no" => return CandidateDecision::Skip,
+            "s" | "skip all" => return CandidateDecision::SkipAll,
+            _ => println!("Please answer y, n or s"),
         }
-        QuestionSource::Manual => {}
     }
-    Ok(())
 }
 
-pub fn send_managram(config: &Settings, amount: f64, to_id: String, message: String) -> Result<()> {
-    let client = Client::new();
+pub async fn send_managram(
+    config: &Settings,
+    amount: f64,
+    to_id: String,
+    message: String,
+) -> Result<()> {
+    let client = http::client(config)?;
     info!("Sending managram to {}", to_id);
     manifold::send_managram(
         &client,
         config,
         &SendManagramArgs {
             amount,
-            to_ids: vec![to_id],
+            to_ids: vec![to_id.clone()],
             message,
         },
+    )
+    .await?;
+    let db = db::open(config)?;
+    db::record_spend(
+        &db,
+        SpendCategory::Other,
+        amount,
+        None,
+        &format!("managram to {}", to_id),
     )?;
     Ok(())
 }
@@ -0,0 +1,333 @@
+use crate::{
+    db::{self, CommentRow, PendingResolutionState},
+    log_if_err,
+    managrams::{self, ManagramProcessingError},
+    manifold::{self, GetCommentsArgs, PostCommentArgs},
+    mirror,
+    settings::Settings,
+    shutdown,
+    types::QuestionSource,
+};
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use clap::{Parser, Subcommand};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info};
+use reqwest::Client;
+
+/// Fetch recent comments on our unresolved markets and save those mentioning the bot for processing.
+pub async fn sync_comments(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    info!("Syncing comments");
+    let mention = mention(config);
+    let cutoff = Utc::now() - Duration::hours(config.manifold.comments.max_age_hours);
+    let mirrors = db::get_unresolved_mirrors(db, None)?;
+    let mut responses = stream::iter(mirrors.into_iter().take_while(|_| !shutdown::requested()))
+        .map(|mirror| async move {
+            let comments = manifold::get_comments(
+                client,
+                &GetCommentsArgs {
+                    contract_id: Some(mirror.manifold_contract_id.clone()),
+                    ..Default::default()
+                },
+                config,
+            )
+            .await;
+            (mirror, comments)
+        })
+        .buffer_unordered(config.http.max_concurrent_requests.get());
+    while let Some((mirror, comments)) = responses.next().await {
+        let comments = match comments {
+            Ok(comments) => comments,
+            Err(e) => {
+                error!(
+                    "failed to fetch comments for market with id {}: {:#}",
+                    mirror.manifold_contract_id, e
+                );
+                continue;
+            }
+        };
+        for comment in comments {
+            if comment.created_time < cutoff || comment.user_id == config.manifold.user_id {
+                continue;
+            }
+            if !comment.text().to_lowercase().contains(&mention) {
+                continue;
+            }
+            if db::insert_comment(db, &comment)? {
+                debug!("Inserted comment into db: {:?}", comment);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fetch unprocessed comments from db and process them.
+pub async fn process_comments(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    if db::is_paused(db)? {
+        info!("Bot is paused, not processing comments");
+        return Ok(());
+    }
+    for comment in db::get_unprocessed_comments(db)? {
+        if shutdown::requested() {
+            break;
+        }
+        log_if_err!(process_comment(client, db, config, &comment)
+            .await
+            .with_context(|| format!(
+                "while processing comment (id: {}, user_id: {})",
+                comment.comment_id, comment.user_id
+            )));
+    }
+    Ok(())
+}
+
+/// Process an unprocessed comment. Does not check processed state.
+async fn process_comment(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    comment: &CommentRow,
+) -> Result<()> {
+    debug!("Processing comment with id {}", comment.comment_id);
+    // Mark processed before replying so we never reply twice to the same comment.
+    db::set_comment_processed(db, &comment.comment_id, true)?;
+    let args = match parse_command(config, &comment.text) {
+        Some(args) => args,
+        None => {
+            // probably just talking about the bot
+            debug!(
+                "Comment with id {} does not contain a known command",
+                comment.comment_id
+            );
+            return Ok(());
+        }
+    };
+    match managrams::check_user_allowed(
+        db,
+        config,
+        &comment.user_id,
+        comment.created_time,
+        &format!("comment {}", comment.comment_id),
+    ) {
+        Ok(()) => {}
+        // not replying, so ignored users can't make the bot spam comments
+        Err(ManagramProcessingError::UserFacing(reason)) => {
+            info!(
+                "Ignoring command in comment with id {}: {}",
+                comment.comment_id, reason
+            );
+            return Ok(());
+        }
+        Err(ManagramProcessingError::Internal(e)) => return Err(e),
+    }
+    info!(
+        "Processing comment command {:?}. Comment id: {}. User id: {}. Market id: {}. Posted: {}.",
+        args.command,
+        comment.comment_id,
+        comment.user_id,
+        comment.contract_id,
+        comment.created_time
+    );
+    let response = match args.command {
+        CommentCommands::Status => status_message(db, comment)?,
+        CommentCommands::Resolve => resolve_market(client, db, config, comment).await?,
+        CommentCommands::Help => help_message(config),
+    };
+    reply_to_comment(client, config, comment, response).await
+}
+
+/// Parse the command following the first mention of the bot, if any. Commands take
+/// no arguments, so only the word after the mention is parsed and the rest of the
+/// comment can be anything.
+fn parse_command(config: &Settings, text: &str) -> Option<CommentArgs> {
+    command_after_mention(&config.manifold.comments.username, text)
+}
+
+fn command_after_mention(username: &str, text: &str) -> Option<CommentArgs> {
+    let rest = after_mention(username, text)?;
+    let command = rest
+        .split_whitespace()
+        .next()?
+        .trim_end_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    CommentArgs::try_parse_from([command]).ok()
+}
+
+/// Text following the first mention of `username`, ignoring longer handles that
+/// start with it
+fn after_mention<'a>(username: &str, text: &'a str) -> Option<&'a str> {
+    let is_handle_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    text.match_indices('@').find_map(|(i, _)| {
+        let end = i + 1 + username.len();
+        let name = text.get(i + 1..end)?;
+        let standalone = !text[..i].chars().next_back().is_some_and(is_handle_char)
+            && !text[end..].chars().next().is_some_and(is_handle_char);
+        (standalone && name.eq_ignore_ascii_case(username)).then(|| &text[end..])
+    })
+}
+
+fn mention(config: &Settings) -> String {
+    format!("@{}", config.manifold.comments.username).to_lowercase()
+}
+
+fn status_message(db: &rusqlite::Connection, comment: &CommentRow) -> Result<String> {
+    let mirror = match db::get_mirror_by_contract_id(db, &comment.contract_id)? {
+        Some(mirror) => mirror,
+        None => return Ok("This market is not in the bot database.".to_string()),
+    };
+    if mirror.source == QuestionSource::Manual {
+        return Ok("This market is not a mirror.".to_string());
+    }
+    let mut status = format!(
+        "This market mirrors [{}]({}) and was created on {}.",
+        mirror.source,
+        mirror.source_url,
+        mirror.clone_date.format("%Y-%m-%d")
+    );
+    if mirror.resolved {
+        status.push_str(" It has been resolved.");
+    } else if let Some(pending) = db::get_latest_pending_resolution(db, mirror.id)?
+        .filter(|r| r.state == PendingResolutionState::Pending)
+    {
+        status.push_str(&format!(
+            " The source has resolved {:?}, which is awaiting confirmation from the bot operator.",
+            pending.resolution
+        ));
+    } else {
+        status.push_str(" The source has not resolved yet, as of the last check.");
+    }
+    Ok(status)
+}
+
+async fn resolve_market(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    comment: &CommentRow,
+) -> Result<String> {
+    let mirror = match db::get_mirror_by_contract_id(db, &comment.contract_id)? {
+        Some(mirror) if mirror.source == QuestionSource::Manual => {
+            return Ok("This market is not a mirror.".to_string())
+        }
+        Some(mirror) if mirror.resolved => {
+            return Ok("This market is already resolved.".to_string())
+        }
+        Some(mirror) => mirror,
+        None => return Ok("This market is not in the bot database.".to_string()),
+    };
+    let resolved = mirror::sync_mirror(client, db, &mirror, config).await?;
+    let awaiting_confirmation = !resolved
+        && db::get_latest_pending_resolution(db, mirror.id)?
+            .is_some_and(|r| r.state == PendingResolutionState::Pending);
+    Ok(if resolved {
+        "Resolved market!"
+    } else if awaiting_confirmation {
+        "Source question has resolved. Resolution is awaiting confirmation from the bot operator."
+    } else {
+        "Source question has not resolved yet."
+    }
+    .to_string())
+}
+
+fn help_message(config: &Settings) -> String {
+    format!(
+        "Comment commands: `@{username} status` shows what this market mirrors and whether \
+        the source has resolved. `@{username} resolve` checks the source and applies its \
+        resolution if it has resolved. Other requests can be sent by managram, send `help` \
+        to @{username} for details.",
+        username = config.manifold.comments.username
+    )
+}
+
+/// Reply in the thread of the comment
+async fn reply_to_comment<M: Into<String>>(
+    client: &Client,
+    config: &Settings,
+    comment: &CommentRow,
+    message: M,
+) -> Result<()> {
+    // Manifold threads are only one level deep, so reply to the thread's root comment
+    let reply_to = comment
+        .reply_to_comment_id
+        .clone()
+        .unwrap_or_else(|| comment.comment_id.clone());
+    manifold::post_comment(
+        client,
+        &PostCommentArgs {
+            contract_id: comment.contract_id.clone(),
+            markdown: message.into(),
+            reply_to_comment_id: Some(reply_to),
+        },
+        config,
+    )
+    .await?;
+    info!(
+        "Replied to comment with id {} from user with id {}.",
+        comment.comment_id, comment.user_id
+    );
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+#[command(disable_help_flag(true))]
+#[command(disable_help_subcommand(true))]
+#[command(no_binary_name(true))]
+struct CommentArgs {
+    #[command(subcommand)]
+    pub command: CommentCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum CommentCommands {
+    /// Show what the market mirrors and whether the source resolved
+    Status,
+    /// Apply source resolution to the market
+    Resolve,
+    /// Show available comment commands
+    Help,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(text: &str) -> Option<CommentCommands> {
+        command_after_mention("mirrorbot", text).map(|args| args.command)
+    }
+
+    #[test]
+    fn commands_may_be_followed_by_text() {
+        assert!(matches!(
+            command("@mirrorbot status thanks!"),
+            Some(CommentCommands::Status)
+        ));
+        assert!(matches!(
+            command("Hey @MirrorBot Resolve, it's over"),
+            Some(CommentCommands::Resolve)
+        ));
+        assert!(command("@mirrorbot is great").is_none());
+    }
+
+    #[test]
+    fn longer_handles_are_not_mentions() {
+        assert!(after_mention("mirrorbot", "@mirrorbotfan status").is_none());
+        assert!(after_mention("mirrorbot", "me@mirrorbot status").is_none());
+        assert_eq!(
+            after_mention("mirrorbot", "@mirrorbotfan and @mirrorbot status"),
+            Some(" status")
+        );
+        // lowercasing İ changes its length, offsets must come from the original text
+        assert_eq!(
+            after_mention("mirrorbot", "İİ @mirrorbot help"),
+            Some(" help")
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::db::{self, MirrorRow, PendingResolutionState};
+
+/// Mirrors closing within this many days are listed as upcoming resolutions
+const UPCOMING_DAYS: i64 = 14;
+/// Number of managrams shown
+const RECENT_MANAGRAMS: usize = 20;
+
+/// Render the HTML dashboard from the database.
+pub fn render(db: &rusqlite::Connection) -> Result<String> {
+    let mut mirrors = db::get_unresolved_mirrors(db, None)?;
+    mirrors.sort_by_key(|m| m.close_time);
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>mirror_bot</title>\
+        <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+        td,th{{border:1px solid #ccc;padding:2px 6px;text-align:left}}\
+        .num{{text-align:right}}</style></head><body><h1>mirror_bot</h1>"
+    )?;
+    render_errors(&mut html, db)?;
+    render_upcoming(&mut html, db, &mirrors)?;
+    render_mirrors(&mut html, &mirrors)?;
+    render_managrams(&mut html, db)?;
+    writeln!(html, "</body></html>")?;
+    Ok(html)
+}
+
+fn render_errors(html: &mut String, db: &rusqlite::Connection) -> Result<()> {
+    writeln!(
+        html,
+        "<h2>Errors</h2><table><tr><th>Period</th><th>Errors logged</th><th>Failed runs</th></tr>"
+    )?;
+    for (label, period) in [
+        ("Last 24 hours", Duration::days(1)),
+        ("Last 7 days", Duration::days(7)),
+    ] {
+        let (errors, failed_runs) = db::get_run_error_counts(db, Utc::now() - period)?;
+        writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            label, errors, failed_runs
+        )?;
+    }
+    writeln!(html, "</table>")?;
+    Ok(())
+}
+
+fn render_upcoming(
+    html: &mut String,
+    db: &rusqlite::Connection,
+    mirrors: &[MirrorRow],
+) -> Result<()> {
+    let pending = db::get_pending_resolutions(db, Some(PendingResolutionState::Pending))?;
+    writeln!(
+        html,
+        "<h2>Upcoming resolutions</h2><p>{} resolution(s) awaiting operator confirmation.</p>\
+        <table><tr><th>Closes</th><th>Question</th><th>Source</th></tr>",
+        pending.len()
+    )?;
+    let horizon = Utc::now() + Duration::days(UPCOMING_DAYS);
+    for mirror in mirrors
+        .iter()
+        .filter(|m| m.close_time.is_some_and(|t| t <= horizon))
+    {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td><a href=\"{}\">{}</a></td><td><a href=\"{}\">{}</a></td></tr>",
+            format_time(mirror.close_time),
+            escape(&mirror.manifold_url),
+            escape(&mirror.question),
+            escape(&mirror.source_url),
+            mirror.source
+        )?;
+    }
+    writeln!(html, "</table>")?;
+    Ok(())
+}
+
+fn render_mirrors(html: &mut String, mirrors: &[MirrorRow]) -> Result<()> {
+    writeln!(
+        html,
+        "<h2>Open mirrors ({})</h2><table><tr><th>Question</th><th>Source</th>\
+        <th>Source prob.</th><th>Manifold prob.</th><th>Difference</th><th>Closes</th>\
+        <th>Last synced</th></tr>",
+        mirrors.len()
+    )?;
+    for mirror in mirrors {
+        let difference = mirror
+            .source_probability
+            .zip(mirror.manifold_probability)
+            .map(|(source, manifold)| format!("{:+.0}", (manifold - source) * 100.0))
+            .unwrap_or_default();
+        writeln!(
+            html,
+            "<tr><td><a href=\"{}\">{}</a></td><td><a href=\"{}\">{}</a></td>\
+            <td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+            <td>{}</td><td>{}</td></tr>",
+            escape(&mirror.manifold_url),
+            escape(&mirror.question),
+            escape(&mirror.source_url),
+            mirror.source,
+            format_probability(mirror.source_probability),
+            format_probability(mirror.manifold_probability),
+            difference,
+            format_time(mirror.close_time),
+            format_time(mirror.last_synced_at)
+        )?;
+    }
+    writeln!(
+        html,
+        "</table><p>Manifold probabilities are as of the last <code>sync --manifold-self</code>, \
+        source probabilities as of the last resolution sync.</p>"
+    )?;
+    Ok(())
+}
+
+fn render_managrams(html: &mut String, db: &rusqlite::Connection) -> Result<()> {
+    writeln!(
+        html,
+        "<h2>Recent managram requests</h2><table><tr><th>Received</th><th>From</th>\
+        <th class=\"num\">Amount</th><th>Message</th><th>State</th><th>Error</th></tr>"
+    )?;
+    for row in db::get_recent_managrams(db, RECENT_MANAGRAMS)? {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td>{}</td>\
+            <td>{:?} ({} attempts)</td><td>{}</td></tr>",
+            format_time(Some(row.managram.created_time)),
+            escape(&row.managram.from_id),
+            row.managram.amount,
+            escape(&row.managram.message),
+            row.state,
+            row.attempts,
+            escape(row.error.as_deref().unwrap_or_default())
+        )?;
+    }
+    writeln!(html, "</table>")?;
+    Ok(())
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn format_probability(probability: Option<f64>) -> String {
+    probability
+        .map(|p| format!("{:.0}%", p * 100.0))
+        .unwrap_or_default()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -1,23 +1,303 @@
+use std::{fmt::Display, path::Path};
+
 use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Utc};
+use log::info;
 use rusqlite::{
-    types::{FromSql, FromSqlError, ToSqlOutput},
-    OptionalExtension, Row, ToSql,
+    types::{FromSql, FromSqlError, ToSqlOutput, ValueRef},
+    DatabaseName, OpenFlags, OptionalExtension, Row, ToSql,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 use crate::{
-    manifold::{LiteMarket, Managram, ManifoldMarket, TokenType},
+    manifold::{Comment, FullMarket, LiteMarket, Managram, ManifoldMarket, TokenType},
+    recovery::CreateMarketPayload,
     settings::Settings,
-    types::{Question, QuestionSource},
+    types::{BinaryResolution, Question, QuestionSource},
 };
 
 pub fn open(config: &Settings) -> Result<rusqlite::Connection> {
     let db = rusqlite::Connection::open(&config.database.path)
         .with_context(|| "failed to connect to database")?;
+    // WAL lets readers and a writer work at the same time, and the busy timeout makes
+    // concurrent writers wait for each other instead of failing with "database is locked"
+    db.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+        .with_context(|| "failed to enable WAL mode")?;
+    db.busy_timeout(std::time::Duration::from_millis(
+        config.database.busy_timeout_ms,
+    ))?;
+    db.pragma_update(None, "foreign_keys", true)?;
+    migrate(&db)?;
     init_tables(&db)?;
     Ok(db)
 }
 
+/// Open an existing database for reading, without creating or migrating it
+pub fn open_read_only(config: &Settings) -> Result<rusqlite::Connection> {
+    let db = rusqlite::Connection::open_with_flags(
+        &config.database.path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .with_context(|| format!("failed to open database at {}", config.database.path))?;
+    db.busy_timeout(std::time::Duration::from_millis(
+        config.database.busy_timeout_ms,
+    ))?;
+    Ok(db)
+}
+
+/// Copy the database to a file using SQLite's online backup API, so the copy is
+/// consistent even if other connections are writing.
+pub fn backup(db: &rusqlite::Connection, path: &Path) -> Result<()> {
+    db.backup(DatabaseName::Main, path, None)
+        .with_context(|| format!("failed to back up database to {}", path.display()))
+}
+
+/// Problems found by SQLite's integrity check. Empty if the database is fine.
+pub fn integrity_check(db: &rusqlite::Connection) -> Result<Vec<String>> {
+    let rows: rusqlite::Result<Vec<String>> = db
+        .prepare("PRAGMA integrity_check")?
+        .query([])?
+        .mapped(|row| row.get(0))
+        .collect();
+    Ok(rows?.into_iter().filter(|row| row != "ok").collect())
+}
+
+/// Rebuild the database file to reclaim space, and refresh query planner statistics
+pub fn vacuum_and_analyze(db: &rusqlite::Connection) -> Result<()> {
+    db.execute_batch("VACUUM; ANALYZE;")?;
+    Ok(())
+}
+
+/// Delete processing records older than the given time. Financial records (ledger,
+/// refunds, donations, the managram audit log) and mirror history are kept. Returns
+/// rows deleted per table.
+pub fn prune(db: &rusqlite::Connection, before: DateTime<Utc>) -> Result<Vec<(String, usize)>> {
+    let statements = [
+        // managrams referenced by refunds, donations or the audit log, and the latest
+        // managram (used to resume syncing), are kept
+        (
+            "managrams",
+            "DELETE FROM managrams
+            WHERE datetime(created_time) < datetime(?1)
+            AND state IN ('SUCCEEDED', 'REFUNDED')
+            AND txn_id NOT IN (SELECT managram_id FROM refunds)
+            AND txn_id NOT IN (SELECT managram_id FROM donations)
+            AND txn_id NOT IN (SELECT managram_id FROM managram_audit)
+            AND id != (SELECT MAX(id) FROM managrams)",
+        ),
+        (
+            "comments",
+            "DELETE FROM comments WHERE processed = TRUE AND datetime(created_time) < datetime(?1)",
+        ),
+        (
+            "runs",
+            "DELETE FROM runs WHERE datetime(start_time) < datetime(?1)",
+        ),
+        (
+            "rejections",
+            "DELETE FROM rejections WHERE datetime(created_time) < datetime(?1)",
+        ),
+        (
+            "metaculus_cache",
+            "DELETE FROM metaculus_cache WHERE datetime(fetched_time) < datetime(?1)",
+        ),
+        (
+            "pending_actions",
+            "DELETE FROM pending_actions
+            WHERE completed_time IS NOT NULL AND datetime(completed_time) < datetime(?1)",
+        ),
+    ];
+    let tx = db.unchecked_transaction()?;
+    let mut deleted = Vec::new();
+    for (table, sql) in statements {
+        deleted.push((table.to_string(), tx.execute(sql, (before,))?));
+    }
+    tx.commit()?;
+    Ok(deleted)
+}
+
+/// All columns of rows in `table` with `time_column` at or after `since`, for exporting.
+/// Returns column names and rows of values in the same order.
+pub fn get_raw_rows(
+    db: &rusqlite::Connection,
+    table: &str,
+    time_column: &str,
+    since: Option<DateTime<Utc>>,
+) -> Result<(Vec<String>, Vec<Vec<JsonValue>>)> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT * FROM \"{table}\"
+        WHERE ?1 IS NULL OR datetime(\"{time_column}\") >= datetime(?1)
+        ORDER BY id"
+    ))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let rows: rusqlite::Result<Vec<Vec<JsonValue>>> = stmt
+        .query((since,))?
+        .mapped(|row| {
+            (0..columns.len())
+                .map(|i| {
+                    Ok(match row.get_ref(i)? {
+                        ValueRef::Null => JsonValue::Null,
+                        ValueRef::Integer(i) => JsonValue::from(i),
+                        ValueRef::Real(f) => JsonValue::from(f),
+                        ValueRef::Text(t) | ValueRef::Blob(t) => {
+                            JsonValue::from(String::from_utf8_lossy(t).into_owned())
+                        }
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    Ok((columns, rows?))
+}
+
+/// Number of rows in each table
+pub fn table_row_counts(db: &rusqlite::Connection) -> Result<Vec<(String, i64)>> {
+    let tables: rusqlite::Result<Vec<String>> = db
+        .prepare(
+            "SELECT name FROM sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+            ORDER BY name",
+        )?
+        .query([])?
+        .mapped(|row| row.get(0))
+        .collect();
+    let mut counts = Vec::new();
+    for table in tables? {
+        let count = db.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+            row.get(0)
+        })?;
+        counts.push((table, count));
+    }
+    Ok(counts)
+}
+
+/// Schema changes to databases created by earlier versions, in order. The database's
+/// `user_version` is the number of migrations that have been applied. New tables don't
+/// need a migration, `init_tables` creates them.
+const MIGRATIONS: &[&str] = &[
+    // 1: managram processing state instead of processed flag. We don't know how
+    // previously processed managrams turned out, so treat them as succeeded.
+    "ALTER TABLE managrams ADD COLUMN state TEXT NOT NULL
+        CHECK( state IN ('NEW', 'STARTED', 'SUCCEEDED', 'FAILED', 'REFUNDED') ) DEFAULT 'NEW';
+    ALTER TABLE managrams ADD COLUMN error TEXT;
+    UPDATE managrams SET state = 'SUCCEEDED' WHERE processed = TRUE;
+    ALTER TABLE managrams DROP COLUMN processed;",
+    // 2: retries for managrams that failed with internal errors
+    "ALTER TABLE managrams ADD COLUMN attempts INT NOT NULL DEFAULT 0;
+    ALTER TABLE managrams ADD COLUMN next_attempt_time TEXT;",
+    // 3: source question as fetched when mirroring, for settling resolution disputes
+    "ALTER TABLE markets ADD COLUMN source_payload TEXT;",
+    // 4: close time and resolution details, kept up to date by sync
+    "ALTER TABLE markets ADD COLUMN close_time TEXT;
+    ALTER TABLE markets ADD COLUMN resolution TEXT;
+    ALTER TABLE markets ADD COLUMN resolved_time TEXT;
+    ALTER TABLE markets ADD COLUMN last_synced_at TEXT;",
+    // 5: latest probabilities, for the dashboard
+    "ALTER TABLE markets ADD COLUMN source_probability REAL;
+    ALTER TABLE markets ADD COLUMN manifold_probability REAL;",
+    // 6: Manifold probability when the mirror closed, for calibration reports
+    "ALTER TABLE markets ADD COLUMN close_probability REAL;",
+    // 7: retired mirrors, abandoned by the operator
+    "ALTER TABLE markets ADD COLUMN status TEXT NOT NULL
+        CHECK( status IN ('ACTIVE', 'RETIRED') ) DEFAULT 'ACTIVE';",
+    // 8: per-mirror resolution sync schedule
+    "ALTER TABLE markets ADD COLUMN next_check_at TEXT;",
+    // 9: Kalshi tickers are case insensitive, keep them uppercase like QuestionSource::normalize_id.
+    // Preceded by check_kalshi_id_collisions.
+    "UPDATE markets SET source_id = UPPER(TRIM(source_id)) WHERE source = 'KALSHI';
+    UPDATE third_party_markets SET source_id = UPPER(TRIM(source_id)) WHERE source = 'KALSHI';",
+    // 10: resolved mirrors whose source opened again
+    "ALTER TABLE markets ADD COLUMN reopened_time TEXT;",
+    // 11: Manifold account the mirror was created with
+    "ALTER TABLE markets ADD COLUMN destination TEXT;",
+    // 12: trading activity on mirrors, for liquidity top-ups and reports
+    "ALTER TABLE markets ADD COLUMN volume REAL;
+    ALTER TABLE markets ADD COLUMN volume_24h REAL;
+    ALTER TABLE markets ADD COLUMN unique_bettors INT;
+    ALTER TABLE markets ADD COLUMN analytics_time TEXT;",
+    // 13: user who paid for the mirror with a managram, for the requester leaderboard
+    "ALTER TABLE markets ADD COLUMN requested_by TEXT;
+    ALTER TABLE markets ADD COLUMN request_price REAL;",
+    // 14: top traders rewarded after resolution
+    "ALTER TABLE markets ADD COLUMN rewarded_time TEXT;",
+];
+
+/// Migration uppercasing Kalshi ids, which can't be applied while two mirrors have
+/// ids differing only in case
+const KALSHI_ID_MIGRATION: usize = 9;
+
+/// Fail with a list of the Kalshi mirrors whose ids differ only in case. They
+/// can't be merged automatically, since each has its own Manifold market.
+fn check_kalshi_id_collisions(conn: &rusqlite::Connection) -> Result<()> {
+    let collisions: rusqlite::Result<Vec<String>> = conn
+        .prepare(
+            "SELECT UPPER(TRIM(source_id)) AS normalized,
+                GROUP_CONCAT('row ' || id || ' (' || source_id || ', ' || manifold_url || ')', ', ')
+            FROM markets
+            WHERE source = 'KALSHI'
+            GROUP BY normalized
+            HAVING COUNT(*) > 1
+            ORDER BY normalized",
+        )?
+        .query([])?
+        .mapped(|row| {
+            Ok(format!(
+                "{}: {}",
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?
+            ))
+        })
+        .collect();
+    let collisions = collisions?;
+    if !collisions.is_empty() {
+        return Err(anyhow!(
+            "Kalshi mirrors with ids differing only in case can't be normalized, \
+            change the source_id of one of each or delete its row before migrating:\n{}",
+            collisions.join("\n")
+        ));
+    }
+    Ok(())
+}
+
+/// Schema version of databases created or migrated by this build
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Schema version of the database, which may be older than [`SCHEMA_VERSION`] if
+/// it was opened without migrating
+pub fn schema_version(conn: &rusqlite::Connection) -> Result<usize> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Bring the schema of an existing database up to date.
+fn migrate(conn: &rusqlite::Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    let is_new: bool = conn.query_row(
+        "SELECT NOT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'markets')",
+        [],
+        |row| row.get(0),
+    )?;
+    if is_new {
+        // init_tables creates the current schema
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        return Ok(());
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("Applying database migration {}", i + 1);
+        if i + 1 == KALSHI_ID_MIGRATION {
+            check_kalshi_id_collisions(conn)
+                .with_context(|| format!("failed to apply database migration {}", i + 1))?;
+        }
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            i + 1
+        ))
+        .with_context(|| format!("failed to apply database migration {}", i + 1))?;
+    }
+    Ok(())
+}
+
 pub fn init_tables(conn: &rusqlite::Connection) -> Result<()> {
     conn.execute_batch(
         "BEGIN;
@@ -32,7 +312,26 @@ pub fn init_tables(conn: &rusqlite::Connection) -> Result<()> {
             source_id               TEXT NOT NULL,
             source_url              TEXT NOT NULL,
             question                TEXT NOT NULL,
-            resolved                INT NOT NULL CHECK( resolved IN (TRUE, FALSE) ) DEFAULT FALSE
+            resolved                INT NOT NULL CHECK( resolved IN (TRUE, FALSE) ) DEFAULT FALSE,
+            source_payload          TEXT, -- JSON, source question when mirrored
+            close_time              TEXT, -- Manifold close time as of the last sync
+            resolution              TEXT, -- YES, NO, CANCEL or MKT:<probability>
+            resolved_time           TEXT,
+            last_synced_at          TEXT, -- last time the source was checked for resolution
+            source_probability      REAL, -- as of last_synced_at
+            manifold_probability    REAL, -- as of the last Manifold sync
+            close_probability       REAL, -- Manifold probability at close_time
+            status                  TEXT NOT NULL CHECK( status IN ('ACTIVE', 'RETIRED') ) DEFAULT 'ACTIVE',
+            next_check_at           TEXT, -- when sync checks the source next, NULL for right away
+            reopened_time           TEXT, -- when the source was found open again after resolving
+            destination             TEXT, -- manifold.accounts entry the mirror was created with, NULL for the main account
+            volume                  REAL, -- Manifold trading volume as of analytics_time
+            volume_24h              REAL, -- volume in the 24 hours before analytics_time
+            unique_bettors          INT, -- traders who have bet on the mirror as of analytics_time
+            analytics_time          TEXT, -- last time trading activity was fetched from Manifold
+            requested_by            TEXT, -- Manifold user id of whoever paid for the mirror with a managram
+            request_price           REAL, -- mana they paid for it
+            rewarded_time           TEXT -- when top traders were rewarded after resolution
         ) STRICT;
         CREATE UNIQUE INDEX IF NOT EXISTS markets_source_key ON markets (source, source_id);
 
@@ -57,9 +356,170 @@ pub fn init_tables(conn: &rusqlite::Connection) -> Result<()> {
             token                   TEXT NOT NULL,
             amount                  REAL NOT NULL,   
             message                 TEXT NOT NULL,
+            state                   TEXT NOT NULL CHECK( state IN ('NEW', 'STARTED', 'SUCCEEDED', 'FAILED', 'REFUNDED') ) DEFAULT 'NEW',
+            error                   TEXT,
+            attempts                INT NOT NULL DEFAULT 0,
+            -- set on failed managrams that should be retried
+            next_attempt_time       TEXT
+        ) STRICT;
+
+        -- resolutions held back until an operator confirms them
+        CREATE TABLE IF NOT EXISTS pending_resolutions (
+            id                      INTEGER PRIMARY KEY,
+            market_id               INTEGER NOT NULL REFERENCES markets(id),
+            resolution              TEXT NOT NULL,
+            volume                  REAL NOT NULL,
+            created_time            TEXT NOT NULL,
+            state                   TEXT NOT NULL CHECK( state IN ('PENDING', 'APPROVED', 'REJECTED') ) DEFAULT 'PENDING'
+        ) STRICT;
+
+        -- every resolution and unresolution applied to a mirror
+        CREATE TABLE IF NOT EXISTS resolutions (
+            id                      INTEGER PRIMARY KEY,
+            market_id               INTEGER NOT NULL REFERENCES markets(id),
+            resolution              TEXT, -- NULL for unresolution
+            source_state            TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- comments on our markets that mention the bot
+        CREATE TABLE IF NOT EXISTS comments (
+            id                      INTEGER PRIMARY KEY,
+            comment_id              TEXT UNIQUE NOT NULL,
+            contract_id             TEXT NOT NULL,
+            user_id                 TEXT NOT NULL,
+            reply_to_comment_id     TEXT,
+            created_time            TEXT NOT NULL,
+            text                    TEXT NOT NULL,
             processed               INT NOT NULL CHECK( processed IN (TRUE, FALSE) ) DEFAULT FALSE
         ) STRICT;
 
+        -- refunds sent for managrams, so we never refund twice
+        CREATE TABLE IF NOT EXISTS refunds (
+            id                      INTEGER PRIMARY KEY,
+            managram_id             TEXT UNIQUE NOT NULL REFERENCES managrams(txn_id),
+            amount                  REAL NOT NULL,
+            reason                  TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- every attempt at executing a managram command, for investigating disputes
+        CREATE TABLE IF NOT EXISTS managram_audit (
+            id                      INTEGER PRIMARY KEY,
+            managram_id             TEXT NOT NULL REFERENCES managrams(txn_id),
+            attempt                 INTEGER NOT NULL,
+            command                 TEXT,
+            outcome                 TEXT NOT NULL,
+            error                   TEXT,
+            response_amount         REAL NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- mana we spent, for budgeting
+        CREATE TABLE IF NOT EXISTS ledger (
+            id                      INTEGER PRIMARY KEY,
+            created_time            TEXT NOT NULL,
+            category                TEXT NOT NULL,
+            amount                  REAL NOT NULL,
+            source                  TEXT,
+            note                    TEXT NOT NULL
+        ) STRICT;
+
+        -- comments the bot posted on its own mirrors, so they aren't repeated
+        CREATE TABLE IF NOT EXISTS mirror_comments (
+            id                      INTEGER PRIMARY KEY,
+            market_id               INTEGER NOT NULL REFERENCES markets(id),
+            kind                    TEXT NOT NULL,
+            comment_id              TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- sync and auto-mirror runs, to check that scheduled jobs are healthy
+        CREATE TABLE IF NOT EXISTS runs (
+            id                      INTEGER PRIMARY KEY,
+            command                 TEXT NOT NULL,
+            target                  TEXT NOT NULL,
+            start_time              TEXT NOT NULL,
+            end_time                TEXT, -- NULL while running, or if the run crashed
+            candidates              INTEGER NOT NULL DEFAULT 0,
+            mirrors_created         INTEGER NOT NULL DEFAULT 0,
+            resolutions_synced      INTEGER NOT NULL DEFAULT 0,
+            errors                  INTEGER NOT NULL DEFAULT 0,
+            error                   TEXT -- set if the run failed as a whole
+        ) STRICT;
+
+        -- auto-mirror candidates that failed the auto filter
+        CREATE TABLE IF NOT EXISTS rejections (
+            id                      INTEGER PRIMARY KEY,
+            source                  TEXT NOT NULL,
+            source_id               TEXT NOT NULL,
+            title                   TEXT NOT NULL,
+            source_url              TEXT NOT NULL,
+            reason                  TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- external side effects, recorded before the API call and completed after.
+        -- An incomplete row means we may have crashed midway, see recovery.rs.
+        CREATE TABLE IF NOT EXISTS pending_actions (
+            id                      INTEGER PRIMARY KEY,
+            kind                    TEXT NOT NULL,
+            subject                 TEXT NOT NULL,
+            payload                 TEXT NOT NULL,
+            created_time            TEXT NOT NULL,
+            completed_time          TEXT
+        ) STRICT;
+
+        -- small bits of runtime state, e.g. whether the bot is paused
+        CREATE TABLE IF NOT EXISTS bot_state (
+            key                     TEXT PRIMARY KEY,
+            value                   TEXT NOT NULL
+        ) STRICT;
+
+        -- managrams without a command, kept as donations
+        CREATE TABLE IF NOT EXISTS donations (
+            id                      INTEGER PRIMARY KEY,
+            managram_id             TEXT UNIQUE NOT NULL REFERENCES managrams(txn_id),
+            from_id                 TEXT NOT NULL,
+            amount                  REAL NOT NULL,
+            message                 TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- users who want a managram when new mirrors are created. NULL source means all sources
+        CREATE TABLE IF NOT EXISTS subscriptions (
+            id                      INTEGER PRIMARY KEY,
+            user_id                 TEXT NOT NULL,
+            source                  TEXT,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+        CREATE UNIQUE INDEX IF NOT EXISTS subscriptions_key ON subscriptions (user_id, IFNULL(source, ''));
+
+        -- announcements of new mirrors on social media, for the daily post cap
+        CREATE TABLE IF NOT EXISTS social_posts (
+            id                      INTEGER PRIMARY KEY,
+            market_id               INTEGER NOT NULL REFERENCES markets(id),
+            platform                TEXT NOT NULL,
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- last response for each Metaculus question, revalidated with conditional GETs
+        CREATE TABLE IF NOT EXISTS metaculus_cache (
+            question_id             INTEGER PRIMARY KEY,
+            etag                    TEXT,
+            last_modified           TEXT,
+            body                    TEXT NOT NULL,
+            fetched_time            TEXT NOT NULL
+        ) STRICT;
+
+        -- mirrors created for configured Metaculus projects (`metaculus.projects`)
+        CREATE TABLE IF NOT EXISTS project_mirrors (
+            id                      INTEGER PRIMARY KEY,
+            project_id              INTEGER NOT NULL,
+            market_id               INTEGER UNIQUE NOT NULL REFERENCES markets(id),
+            created_time            TEXT NOT NULL
+        ) STRICT;
+
         COMMIT;",
     )
     .with_context(|| "failed to initialize database tables")?;
@@ -86,6 +546,63 @@ pub fn insert_managram(db: &rusqlite::Connection, managram: &Managram) -> Result
     )?)
 }
 
+/// Progress of a managram sync that was cut short. Managrams are listed newest
+/// first, so the rest of that sync is everything between the two timestamps.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManagramSyncCheckpoint {
+    /// newest managram in the database when the sync started
+    pub after: Option<DateTime<Utc>>,
+    /// oldest managram fetched so far
+    pub before: DateTime<Utc>,
+}
+
+pub fn get_managram_sync_checkpoint(
+    db: &rusqlite::Connection,
+) -> Result<Option<ManagramSyncCheckpoint>> {
+    db.query_row(
+        "SELECT value FROM bot_state WHERE key = 'managram_sync_checkpoint'",
+        [],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()?
+    .map(|v| {
+        serde_json::from_str(&v).with_context(|| "invalid managram_sync_checkpoint in bot_state")
+    })
+    .transpose()
+}
+
+/// Insert a page of managrams and record where the sync got to in one
+/// transaction, so an interrupted sync resumes right after the last saved page.
+/// A `None` checkpoint marks the sync as finished. Managrams already in the
+/// database are skipped. Returns how many were inserted.
+pub fn insert_managram_page(
+    db: &rusqlite::Connection,
+    managrams: &[Managram],
+    checkpoint: Option<&ManagramSyncCheckpoint>,
+) -> Result<usize> {
+    let tx = db.unchecked_transaction()?;
+    let mut inserted = 0;
+    for managram in managrams {
+        if get_managram(&tx, &managram.id)?.is_none() {
+            insert_managram(&tx, managram)?;
+            inserted += 1;
+        }
+    }
+    match checkpoint {
+        Some(checkpoint) => tx.execute(
+            "INSERT INTO bot_state (key, value) VALUES ('managram_sync_checkpoint', ?1)
+            ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            [serde_json::to_string(checkpoint)?],
+        )?,
+        None => tx.execute(
+            "DELETE FROM bot_state WHERE key = 'managram_sync_checkpoint'",
+            [],
+        )?,
+    };
+    tx.commit()?;
+    Ok(inserted)
+}
+
 pub fn last_managram_timestamp(db: &rusqlite::Connection) -> Result<Option<DateTime<Utc>>> {
     Ok(db
         .query_row(
@@ -97,121 +614,977 @@ pub fn last_managram_timestamp(db: &rusqlite::Connection) -> Result<Option<DateT
         .map(|m| m.created_time))
 }
 
-pub fn get_unprocessed_managrams(db: &rusqlite::Connection) -> Result<Vec<Managram>> {
+pub fn get_managrams_by_state(
+    db: &rusqlite::Connection,
+    state: ManagramState,
+) -> Result<Vec<Managram>> {
     let rows: rusqlite::Result<Vec<Managram>> = db
-        .prepare("SELECT * FROM managrams WHERE processed = FALSE")?
-        .query([])?
+        .prepare("SELECT * FROM managrams WHERE state = ?1")?
+        .query([state])?
+        .mapped(managram_row_helper)
+        .collect();
+    Ok(rows?)
+}
+
+/// Most recently received managrams along with their processing state
+pub fn get_recent_managrams(db: &rusqlite::Connection, limit: usize) -> Result<Vec<ManagramRow>> {
+    let rows: rusqlite::Result<Vec<ManagramRow>> = db
+        .prepare("SELECT * FROM managrams ORDER BY datetime(created_time) DESC LIMIT ?1")?
+        .query((limit,))?
+        .mapped(ManagramRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// Managrams matching all given filters, newest first. Unprocessed managrams are
+/// new or started, failed ones failed or refunded.
+pub fn get_filtered_managrams(
+    db: &rusqlite::Connection,
+    unprocessed: bool,
+    failed: bool,
+    from_id: Option<&str>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<ManagramRow>> {
+    let rows: rusqlite::Result<Vec<ManagramRow>> = db
+        .prepare(
+            "SELECT * FROM managrams
+            WHERE (NOT ?1 OR state IN ('NEW', 'STARTED'))
+            AND (NOT ?2 OR state IN ('FAILED', 'REFUNDED'))
+            AND (?3 IS NULL OR from_id = ?3)
+            AND (?4 IS NULL OR datetime(created_time) >= datetime(?4))
+            ORDER BY datetime(created_time) DESC",
+        )?
+        .query((unprocessed, failed, from_id, since))?
+        .mapped(ManagramRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// New managrams, and failed managrams that are due for a retry
+pub fn get_managrams_to_process(db: &rusqlite::Connection) -> Result<Vec<Managram>> {
+    let rows: rusqlite::Result<Vec<Managram>> = db
+        .prepare(
+            "SELECT * FROM managrams
+            WHERE state = 'NEW'
+            OR (state = 'FAILED' AND datetime(next_attempt_time) <= datetime(?1))",
+        )?
+        .query([Utc::now()])?
         .mapped(managram_row_helper)
         .collect();
     Ok(rows?)
 }
 
-pub fn set_managram_processed(db: &rusqlite::Connection, id: &str, processed: bool) -> Result<()> {
+/// Mark managram as started and count the attempt. Returns the number of attempts so far.
+pub fn start_managram_attempt(db: &rusqlite::Connection, id: &str) -> Result<i64> {
+    Ok(db.query_row(
+        "UPDATE managrams SET state = 'STARTED', attempts = attempts + 1, next_attempt_time = NULL
+        WHERE txn_id = ?1 RETURNING attempts",
+        [id],
+        |row| row.get(0),
+    )?)
+}
+
+/// Mark managram failed, to be tried again at the given time
+pub fn schedule_managram_retry(
+    db: &rusqlite::Connection,
+    id: &str,
+    error: &str,
+    next_attempt_time: DateTime<Utc>,
+) -> Result<()> {
     let changed = db.execute(
-        "UPDATE managrams SET processed = ?2 WHERE txn_id = ?1",
-        (id, &processed),
+        "UPDATE managrams SET state = 'FAILED', error = ?2, next_attempt_time = ?3 WHERE txn_id = ?1",
+        (id, error, next_attempt_time),
     )?;
     if changed == 0 {
         return Err(anyhow!(
-            "set_managram_processed query did not modify any rows"
+            "schedule_managram_retry query did not modify any rows"
         ));
     }
     Ok(())
 }
 
-pub fn insert_mirror(
-    conn: &rusqlite::Connection,
-    manifold_market: &LiteMarket,
-    source_question: &Question,
-    config: &Settings,
-) -> Result<MirrorRow> {
-    let mut statement = conn.prepare(
-        "INSERT INTO markets (clone_date, manifold_contract_id, manifold_url, source, source_id, source_url, question)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) RETURNING *",
+/// Error of managrams whose processing was interrupted. Their command may have taken
+/// effect, so they need manual review and are never refunded automatically.
+pub const INTERRUPTED_MANAGRAM_ERROR: &str = "interrupted during processing";
+
+/// Failed managrams created before the given time that won't be retried and haven't been refunded.
+/// Interrupted managrams are left out, see [`INTERRUPTED_MANAGRAM_ERROR`].
+pub fn get_stale_failed_managrams(
+    db: &rusqlite::Connection,
+    before: DateTime<Utc>,
+) -> Result<Vec<Managram>> {
+    let rows: rusqlite::Result<Vec<Managram>> = db
+        .prepare(
+            "SELECT * FROM managrams
+            WHERE state = 'FAILED'
+            AND next_attempt_time IS NULL
+            AND error IS NOT ?2
+            AND datetime(created_time) < datetime(?1)
+            AND txn_id NOT IN (SELECT managram_id FROM refunds)",
+        )?
+        .query((before, INTERRUPTED_MANAGRAM_ERROR))?
+        .mapped(managram_row_helper)
+        .collect();
+    Ok(rows?)
+}
+
+/// Record a refund before sending it. Returns false if managram was already refunded,
+/// in which case the refund must not be sent.
+pub fn insert_refund(
+    db: &rusqlite::Connection,
+    managram_id: &str,
+    amount: f64,
+    reason: &str,
+) -> Result<bool> {
+    let changed = db.execute(
+        "INSERT OR IGNORE INTO refunds (managram_id, amount, reason, created_time)
+        VALUES (?1, ?2, ?3, ?4)",
+        (managram_id, amount, reason, Utc::now()),
     )?;
-    Ok(statement.query_row(
+    Ok(changed > 0)
+}
+
+/// Record the outcome of an attempt at executing a managram command
+pub fn insert_managram_audit(
+    db: &rusqlite::Connection,
+    managram_id: &str,
+    attempt: i64,
+    command: Option<&str>,
+    outcome: ManagramState,
+    error: Option<&str>,
+    response_amount: f64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO managram_audit
+        (managram_id, attempt, command, outcome, error, response_amount, created_time)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         (
+            managram_id,
+            attempt,
+            command,
+            outcome,
+            error,
+            response_amount,
             Utc::now(),
-            &manifold_market.id,
-            manifold_market.url(config),
-            &source_question.source,
-            &source_question.source_id,
-            &source_question.source_url,
-            &source_question.question,
         ),
-        MirrorRow::from_row,
-    )?)
+    )?;
+    Ok(())
 }
 
-pub fn insert_third_party_mirror(
-    conn: &rusqlite::Connection,
-    manifold_market: &LiteMarket,
-    source: &QuestionSource,
-    source_id: &str,
-    config: &Settings,
-) -> Result<ThirdPartyMirrorRow> {
-    let mut statement = conn.prepare(
-        "INSERT INTO third_party_markets (manifold_contract_id, manifold_url, source, source_id, created_time)
-        VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
-    )?;
-    Ok(statement.query_row(
+/// Audit log, newest first, optionally for a single managram
+pub fn get_managram_audit(
+    db: &rusqlite::Connection,
+    managram_id: Option<&str>,
+) -> Result<Vec<ManagramAuditRow>> {
+    let rows: rusqlite::Result<Vec<ManagramAuditRow>> = db
+        .prepare(
+            "SELECT * FROM managram_audit
+            WHERE ?1 IS NULL OR managram_id = ?1
+            ORDER BY id DESC",
+        )?
+        .query((managram_id,))?
+        .mapped(ManagramAuditRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// Mana sent back to the sender of a managram since the given time, as refund or response
+pub fn get_managram_response_total(
+    db: &rusqlite::Connection,
+    managram_id: &str,
+    since: DateTime<Utc>,
+) -> Result<f64> {
+    Ok(db.query_row(
+        "SELECT COALESCE(SUM(amount), 0.0) FROM ledger
+        WHERE note = ?1
+        AND category IN (?2, ?3)
+        AND datetime(created_time) >= datetime(?4)",
         (
-            &manifold_market.id,
-            &manifold_market.url(config),
-            source,
-            source_id,
-            manifold_market.created_time,
+            managram_id,
+            SpendCategory::Refund,
+            SpendCategory::Response,
+            since,
         ),
-        ThirdPartyMirrorRow::from_row,
+        |row| row.get(0),
     )?)
 }
 
-pub fn get_third_party_mirror_by_source_id(
-    conn: &rusqlite::Connection,
-    source: &QuestionSource,
-    source_id: &str,
-) -> Result<Option<ThirdPartyMirrorRow>> {
-    Ok(conn
-        .query_row(
-            "SELECT * FROM third_party_markets WHERE source = ?1 AND source_id = ?2",
-            (&source, &source_id),
-            ThirdPartyMirrorRow::from_row,
-        )
-        .optional()?)
+/// Number of managrams the user sent in the given time range (exclusive)
+pub fn count_managrams_from_user(
+    db: &rusqlite::Connection,
+    user_id: &str,
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+) -> Result<usize> {
+    Ok(db.query_row(
+        "SELECT COUNT(*) FROM managrams
+        WHERE from_id = ?1
+        AND datetime(created_time) > datetime(?2)
+        AND datetime(created_time) < datetime(?3)",
+        (user_id, after, before),
+        |row| row.get(0),
+    )?)
 }
 
-pub fn get_third_party_mirror_by_contract_id(
-    conn: &rusqlite::Connection,
-    contract_id: &str,
-) -> Result<Option<ThirdPartyMirrorRow>> {
-    Ok(conn
+/// Number of comments mentioning the bot the user posted in the given time range (exclusive)
+pub fn count_comments_from_user(
+    db: &rusqlite::Connection,
+    user_id: &str,
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+) -> Result<usize> {
+    Ok(db.query_row(
+        "SELECT COUNT(*) FROM comments
+        WHERE user_id = ?1
+        AND datetime(created_time) > datetime(?2)
+        AND datetime(created_time) < datetime(?3)",
+        (user_id, after, before),
+        |row| row.get(0),
+    )?)
+}
+
+/// Managram with the given transaction id, if it has been synced
+pub fn get_managram(db: &rusqlite::Connection, id: &str) -> Result<Option<ManagramRow>> {
+    Ok(db
         .query_row(
-            "SELECT * FROM third_party_markets WHERE manifold_contract_id = ?1",
-            (&contract_id,),
-            ThirdPartyMirrorRow::from_row,
+            "SELECT * FROM managrams WHERE txn_id = ?1",
+            (id,),
+            ManagramRow::from_row,
         )
         .optional()?)
 }
 
-pub fn get_unresolved_mirrors(
-    conn: &rusqlite::Connection,
-    source: Option<QuestionSource>,
-) -> Result<Vec<MirrorRow>> {
-    let rows: rusqlite::Result<Vec<MirrorRow>> = if let Some(source) = source {
-        conn.prepare("SELECT * FROM markets WHERE source = ?1 AND resolved = FALSE")?
-            .query((&source,))?
-            .mapped(MirrorRow::from_row)
-            .collect()
-    } else {
-        conn.prepare("SELECT * FROM markets WHERE resolved = FALSE")?
-            .query([])?
-            .mapped(MirrorRow::from_row)
-            .collect()
-    };
-    Ok(rows.with_context(|| "failed to fetch unresolved markets from db")?)
-}
-
-pub fn get_resolved_mirrors(
+/// Put managram back in the queue as if it was just received, clearing errors and retries
+pub fn reset_managram(db: &rusqlite::Connection, id: &str) -> Result<()> {
+    let changed = db.execute(
+        "UPDATE managrams SET state = 'NEW', error = NULL, next_attempt_time = NULL
+        WHERE txn_id = ?1",
+        (id,),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!("reset_managram query did not modify any rows"));
+    }
+    Ok(())
+}
+
+/// Update processing state of managram and cancel any scheduled retry. Error is
+/// recorded for failures, and kept otherwise so refunded managrams still show what
+/// went wrong.
+pub fn set_managram_state(
+    db: &rusqlite::Connection,
+    id: &str,
+    state: ManagramState,
+    error: Option<&str>,
+) -> Result<()> {
+    let changed = db.execute(
+        "UPDATE managrams SET state = ?2, error = COALESCE(?3, error), next_attempt_time = NULL
+        WHERE txn_id = ?1",
+        (id, &state, error),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!("set_managram_state query did not modify any rows"));
+    }
+    Ok(())
+}
+
+/// Save comment for processing. Returns false if we've seen it before.
+pub fn insert_comment(db: &rusqlite::Connection, comment: &Comment) -> Result<bool> {
+    let changed = db.execute(
+        "INSERT OR IGNORE INTO comments (comment_id, contract_id, user_id, reply_to_comment_id, created_time, text)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (
+            &comment.id,
+            &comment.contract_id,
+            &comment.user_id,
+            &comment.reply_to_comment_id,
+            &comment.created_time,
+            &comment.text(),
+        ),
+    )?;
+    Ok(changed > 0)
+}
+
+pub fn get_unprocessed_comments(db: &rusqlite::Connection) -> Result<Vec<CommentRow>> {
+    let rows: rusqlite::Result<Vec<CommentRow>> = db
+        .prepare("SELECT * FROM comments WHERE processed = FALSE ORDER BY datetime(created_time)")?
+        .query([])?
+        .mapped(CommentRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+pub fn set_comment_processed(db: &rusqlite::Connection, id: &str, processed: bool) -> Result<()> {
+    let changed = db.execute(
+        "UPDATE comments SET processed = ?2 WHERE comment_id = ?1",
+        (id, &processed),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!(
+            "set_comment_processed query did not modify any rows"
+        ));
+    }
+    Ok(())
+}
+
+/// Record mana leaving the bot account. For spend on a specific market, the note is
+/// its Manifold contract id.
+pub fn record_spend(
+    db: &rusqlite::Connection,
+    category: SpendCategory,
+    amount: f64,
+    source: Option<&QuestionSource>,
+    note: &str,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO ledger (created_time, category, amount, source, note) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (Utc::now(), category, amount, source, note),
+    )?;
+    Ok(())
+}
+
+/// Record the start of a sync or auto-mirror run. Returns the run id.
+pub fn start_run(db: &rusqlite::Connection, command: &str, target: &str) -> Result<i64> {
+    Ok(db.query_row(
+        "INSERT INTO runs (command, target, start_time) VALUES (?1, ?2, ?3) RETURNING id",
+        (command, target, Utc::now()),
+        |row| row.get(0),
+    )?)
+}
+
+/// Record the end of a run along with its statistics
+pub fn finish_run(
+    db: &rusqlite::Connection,
+    id: i64,
+    stats: &RunStats,
+    error: Option<&str>,
+) -> Result<()> {
+    db.execute(
+        "UPDATE runs SET end_time = ?2, candidates = ?3, mirrors_created = ?4,
+        resolutions_synced = ?5, errors = ?6, error = ?7
+        WHERE id = ?1",
+        (
+            id,
+            Utc::now(),
+            stats.candidates,
+            stats.mirrors_created,
+            stats.resolutions_synced,
+            stats.errors,
+            error,
+        ),
+    )?;
+    Ok(())
+}
+
+/// Most recent runs, newest first
+pub fn get_runs(db: &rusqlite::Connection, limit: usize) -> Result<Vec<RunRow>> {
+    let rows: rusqlite::Result<Vec<RunRow>> = db
+        .prepare("SELECT * FROM runs ORDER BY id DESC LIMIT ?1")?
+        .query((limit,))?
+        .mapped(RunRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// Record auto-mirror candidates that were rejected by the auto filter
+pub fn insert_rejections(
+    db: &rusqlite::Connection,
+    source: QuestionSource,
+    rejections: &[NewRejection],
+) -> Result<()> {
+    let tx = db.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO rejections (source, source_id, title, source_url, reason, created_time)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let now = Utc::now();
+        for rejection in rejections {
+            stmt.execute((
+                &source,
+                source.normalize_id(&rejection.source_id),
+                &rejection.title,
+                &rejection.source_url,
+                &rejection.reason,
+                now,
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Rejected auto-mirror candidates, newest first
+pub fn get_rejections(
+    db: &rusqlite::Connection,
+    source: Option<QuestionSource>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<RejectionRow>> {
+    let rows: rusqlite::Result<Vec<RejectionRow>> = db
+        .prepare(
+            "SELECT * FROM rejections
+            WHERE (?1 IS NULL OR source = ?1)
+            AND (?2 IS NULL OR datetime(created_time) >= datetime(?2))
+            ORDER BY id DESC",
+        )?
+        .query((source, since))?
+        .mapped(RejectionRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// Errors logged by runs started since the given time, and how many runs failed outright
+pub fn get_run_error_counts(db: &rusqlite::Connection, since: DateTime<Utc>) -> Result<(i64, i64)> {
+    Ok(db.query_row(
+        "SELECT COALESCE(SUM(errors), 0), COUNT(error) FROM runs
+        WHERE datetime(start_time) >= datetime(?1)",
+        (since,),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+/// Record a comment the bot posted on one of its mirrors
+pub fn insert_mirror_comment(
+    db: &rusqlite::Connection,
+    market_id: i64,
+    kind: MirrorCommentKind,
+    comment_id: &str,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO mirror_comments (market_id, kind, comment_id, created_time)
+        VALUES (?1, ?2, ?3, ?4)",
+        (market_id, kind, comment_id, Utc::now()),
+    )?;
+    Ok(())
+}
+
+/// When the bot last posted a comment of this kind on the mirror, if ever
+pub fn get_last_mirror_comment_time(
+    db: &rusqlite::Connection,
+    market_id: i64,
+    kind: MirrorCommentKind,
+) -> Result<Option<DateTime<Utc>>> {
+    Ok(db
+        .query_row(
+            "SELECT created_time FROM mirror_comments
+            WHERE market_id = ?1 AND kind = ?2
+            ORDER BY datetime(created_time) DESC
+            LIMIT 1",
+            (market_id, kind),
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Total spend in category since the given time, optionally only for one market
+pub fn get_total_spend(
+    db: &rusqlite::Connection,
+    category: SpendCategory,
+    since: Option<DateTime<Utc>>,
+    manifold_contract_id: Option<&str>,
+) -> Result<f64> {
+    Ok(db.query_row(
+        "SELECT COALESCE(SUM(amount), 0.0) FROM ledger
+        WHERE category = ?1
+        AND (?2 IS NULL OR datetime(created_time) >= datetime(?2))
+        AND (?3 IS NULL OR note = ?3)",
+        (category, since, manifold_contract_id),
+        |row| row.get(0),
+    )?)
+}
+
+/// Spend between the given times, grouped by category and source
+pub fn get_spend_summary(
+    db: &rusqlite::Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<SpendSummaryRow>> {
+    let rows: rusqlite::Result<Vec<SpendSummaryRow>> = db
+        .prepare(
+            "SELECT category, source, COUNT(*) AS count, SUM(amount) AS total FROM ledger
+            WHERE datetime(created_time) >= datetime(?1) AND datetime(created_time) < datetime(?2)
+            GROUP BY category, source
+            ORDER BY category, source",
+        )?
+        .query((from, to))?
+        .mapped(SpendSummaryRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+/// Whether operator paused the bot. While paused, we don't auto-mirror or process
+/// commands from regular users.
+pub fn is_paused(db: &rusqlite::Connection) -> Result<bool> {
+    Ok(db
+        .query_row(
+            "SELECT value FROM bot_state WHERE key = 'paused'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .is_some_and(|v| v == "true"))
+}
+
+pub fn set_paused(db: &rusqlite::Connection, paused: bool) -> Result<()> {
+    db.execute(
+        "INSERT INTO bot_state (key, value) VALUES ('paused', ?1)
+        ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        [paused.to_string()],
+    )?;
+    Ok(())
+}
+
+pub fn insert_social_post(db: &rusqlite::Connection, market_id: i64, platform: &str) -> Result<()> {
+    db.execute(
+        "INSERT INTO social_posts (market_id, platform, created_time) VALUES (?1, ?2, ?3)",
+        (market_id, platform, Utc::now()),
+    )?;
+    Ok(())
+}
+
+pub fn count_social_posts_since(
+    db: &rusqlite::Connection,
+    platform: &str,
+    since: DateTime<Utc>,
+) -> Result<usize> {
+    Ok(db.query_row(
+        "SELECT COUNT(*) FROM social_posts
+        WHERE platform = ?1 AND datetime(created_time) >= datetime(?2)",
+        (platform, since),
+        |row| row.get(0),
+    )?)
+}
+
+pub fn insert_project_mirror(
+    db: &rusqlite::Connection,
+    project_id: u64,
+    market_id: i64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO project_mirrors (project_id, market_id, created_time) VALUES (?1, ?2, ?3)",
+        (project_id, market_id, Utc::now()),
+    )?;
+    Ok(())
+}
+
+pub fn count_project_mirrors_since(
+    db: &rusqlite::Connection,
+    project_id: u64,
+    since: DateTime<Utc>,
+) -> Result<usize> {
+    Ok(db.query_row(
+        "SELECT COUNT(*) FROM project_mirrors
+        WHERE project_id = ?1 AND datetime(created_time) >= datetime(?2)",
+        (project_id, since),
+        |row| row.get(0),
+    )?)
+}
+
+/// Mirrors of a Metaculus project, and how many of them are unresolved
+pub fn count_project_mirrors(db: &rusqlite::Connection, project_id: u64) -> Result<(usize, usize)> {
+    Ok(db.query_row(
+        "SELECT COUNT(*), COUNT(*) FILTER (WHERE m.resolved = FALSE)
+        FROM project_mirrors p JOIN markets m ON m.id = p.market_id
+        WHERE p.project_id = ?1",
+        [project_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+/// When the requester leaderboard was last posted to Manifold
+pub fn get_leaderboard_posted_time(db: &rusqlite::Connection) -> Result<Option<DateTime<Utc>>> {
+    db.query_row(
+        "SELECT value FROM bot_state WHERE key = 'leaderboard_posted_time'",
+        [],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()?
+    .map(|v| {
+        v.parse()
+            .with_context(|| "invalid leaderboard_posted_time in bot_state")
+    })
+    .transpose()
+}
+
+pub fn set_leaderboard_posted_time(db: &rusqlite::Connection, time: DateTime<Utc>) -> Result<()> {
+    db.execute(
+        "INSERT INTO bot_state (key, value) VALUES ('leaderboard_posted_time', ?1)
+        ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        [time.to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Id of the first Telegram update we haven't processed yet
+pub fn get_telegram_offset(db: &rusqlite::Connection) -> Result<Option<i64>> {
+    db.query_row(
+        "SELECT value FROM bot_state WHERE key = 'telegram_offset'",
+        [],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()?
+    .map(|v| {
+        v.parse()
+            .with_context(|| "invalid telegram_offset in bot_state")
+    })
+    .transpose()
+}
+
+pub fn set_telegram_offset(db: &rusqlite::Connection, offset: i64) -> Result<()> {
+    db.execute(
+        "INSERT INTO bot_state (key, value) VALUES ('telegram_offset', ?1)
+        ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        [offset.to_string()],
+    )?;
+    Ok(())
+}
+
+/// A cached Metaculus question response
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+    pub fetched_time: DateTime<Utc>,
+}
+
+pub fn get_metaculus_cache(
+    db: &rusqlite::Connection,
+    question_id: u64,
+) -> Result<Option<CachedResponse>> {
+    Ok(db
+        .query_row(
+            "SELECT etag, last_modified, body, fetched_time FROM metaculus_cache
+            WHERE question_id = ?1",
+            [question_id],
+            |row| {
+                Ok(CachedResponse {
+                    etag: row.get(0)?,
+                    last_modified: row.get(1)?,
+                    body: row.get(2)?,
+                    fetched_time: row.get(3)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+pub fn set_metaculus_cache(
+    db: &rusqlite::Connection,
+    question_id: u64,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+    body: &str,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO metaculus_cache (question_id, etag, last_modified, body, fetched_time)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (question_id) DO UPDATE SET etag = excluded.etag,
+            last_modified = excluded.last_modified, body = excluded.body,
+            fetched_time = excluded.fetched_time",
+        (question_id, etag, last_modified, body, Utc::now()),
+    )?;
+    Ok(())
+}
+
+/// Mark a cached response as still current
+pub fn touch_metaculus_cache(db: &rusqlite::Connection, question_id: u64) -> Result<()> {
+    db.execute(
+        "UPDATE metaculus_cache SET fetched_time = ?2 WHERE question_id = ?1",
+        (question_id, Utc::now()),
+    )?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    pub mirrors: i64,
+    pub unresolved_mirrors: i64,
+    pub pending_resolutions: i64,
+    pub managrams_last_day: i64,
+    pub failed_managrams: i64,
+    pub donations: f64,
+    /// Trading volume and traders summed over mirrors, as of their last analytics sync
+    pub volume: f64,
+    pub traders: i64,
+}
+
+pub fn get_stats(db: &rusqlite::Connection) -> Result<Stats> {
+    let count = |query: &str| -> Result<i64> { Ok(db.query_row(query, [], |row| row.get(0))?) };
+    Ok(Stats {
+        mirrors: count("SELECT COUNT(*) FROM markets WHERE source != 'MANUAL'")?,
+        unresolved_mirrors: count(
+            "SELECT COUNT(*) FROM markets WHERE source != 'MANUAL' AND resolved = FALSE",
+        )?,
+        pending_resolutions: count(
+            "SELECT COUNT(*) FROM pending_resolutions WHERE state = 'PENDING'",
+        )?,
+        managrams_last_day: count(
+            "SELECT COUNT(*) FROM managrams WHERE datetime(created_time) > datetime('now', '-1 day')",
+        )?,
+        failed_managrams: count("SELECT COUNT(*) FROM managrams WHERE state = 'FAILED'")?,
+        donations: db.query_row("SELECT COALESCE(SUM(amount), 0.0) FROM donations", [], |row| {
+            row.get(0)
+        })?,
+        volume: db.query_row(
+            "SELECT COALESCE(SUM(volume), 0.0) FROM markets WHERE source != 'MANUAL'",
+            [],
+            |row| row.get(0),
+        )?,
+        traders: count("SELECT COALESCE(SUM(unique_bettors), 0) FROM markets WHERE source != 'MANUAL'")?,
+    })
+}
+
+pub fn insert_donation(db: &rusqlite::Connection, managram: &Managram) -> Result<()> {
+    db.execute(
+        "INSERT INTO donations (managram_id, from_id, amount, message, created_time)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        (
+            &managram.id,
+            &managram.from_id,
+            &managram.amount,
+            &managram.message,
+            &managram.created_time,
+        ),
+    )?;
+    Ok(())
+}
+
+/// Returns false if the user was already subscribed
+pub fn insert_subscription(
+    db: &rusqlite::Connection,
+    user_id: &str,
+    source: Option<&QuestionSource>,
+) -> Result<bool> {
+    let changed = db.execute(
+        "INSERT OR IGNORE INTO subscriptions (user_id, source, created_time) VALUES (?1, ?2, ?3)",
+        (user_id, source, Utc::now()),
+    )?;
+    Ok(changed > 0)
+}
+
+/// Remove all subscriptions for user. Returns number of subscriptions removed.
+pub fn delete_subscriptions(db: &rusqlite::Connection, user_id: &str) -> Result<usize> {
+    Ok(db.execute("DELETE FROM subscriptions WHERE user_id = ?1", [user_id])?)
+}
+
+/// Ids of users subscribed to new mirrors from source
+pub fn get_subscribers(db: &rusqlite::Connection, source: &QuestionSource) -> Result<Vec<String>> {
+    let rows: rusqlite::Result<Vec<String>> = db
+        .prepare("SELECT DISTINCT user_id FROM subscriptions WHERE source IS NULL OR source = ?1")?
+        .query([source])?
+        .mapped(|row| row.get(0))
+        .collect();
+    Ok(rows?)
+}
+
+/// Insert a mirror, completing the action creating it if there is one
+pub fn insert_mirror(
+    conn: &rusqlite::Connection,
+    manifold_market: &LiteMarket,
+    source_question: &Question,
+    clone_date: DateTime<Utc>,
+    destination: Option<&str>,
+    config: &Settings,
+) -> Result<MirrorRow> {
+    let tx = conn.unchecked_transaction()?;
+    let mirror = tx
+        .prepare(
+            "INSERT INTO markets (clone_date, manifold_contract_id, manifold_url, source, source_id, source_url, question, source_payload, close_time, destination)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) RETURNING *",
+        )?
+        .query_row(
+        (
+            clone_date,
+            &manifold_market.id,
+            manifold_market.url(config),
+            &source_question.source,
+            source_question.source.normalize_id(&source_question.source_id),
+            &source_question.source_url,
+            &source_question.question,
+            source_question
+                .source_payload
+                .as_ref()
+                .map(|payload| payload.to_string()),
+            manifold_market.close_time,
+            destination,
+        ),
+        MirrorRow::from_row,
+    )?;
+    tx.execute(
+        "UPDATE pending_actions SET completed_time = ?1
+        WHERE kind = ?2 AND subject = ?3 AND completed_time IS NULL",
+        (
+            Utc::now(),
+            ActionKind::CreateMarket,
+            mirror_subject(&source_question.source, &source_question.source_id),
+        ),
+    )?;
+    tx.commit()?;
+    Ok(mirror)
+}
+
+/// Subject of the action creating a mirror of a question
+fn mirror_subject(source: &QuestionSource, source_id: &str) -> String {
+    format!("{}:{}", source, source.normalize_id(source_id))
+}
+
+/// Record that a mirror of the question is about to be created, returning when
+/// an earlier attempt that didn't finish started, if there was one
+pub fn start_mirror_creation(
+    conn: &rusqlite::Connection,
+    payload: &CreateMarketPayload,
+) -> Result<Option<DateTime<Utc>>> {
+    let question = &payload.question;
+    let subject = mirror_subject(&question.source, &question.source_id);
+    let previous = get_incomplete_action(conn, ActionKind::CreateMarket, &subject)?;
+    if previous.is_none() {
+        start_action(conn, ActionKind::CreateMarket, &subject, payload)?;
+    }
+    Ok(previous.map(|action| action.created_time))
+}
+
+/// Record an external side effect before attempting it. Returns the action's id,
+/// to complete it once done.
+pub fn start_action<P: Serialize>(
+    conn: &rusqlite::Connection,
+    kind: ActionKind,
+    subject: &str,
+    payload: &P,
+) -> Result<i64> {
+    let payload = serde_json::to_string(payload).with_context(|| "failed to serialize payload")?;
+    conn.execute(
+        "INSERT INTO pending_actions (kind, subject, payload, created_time) VALUES (?1, ?2, ?3, ?4)",
+        (kind, subject, payload, Utc::now()),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn complete_action(conn: &rusqlite::Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE pending_actions SET completed_time = ?1 WHERE id = ?2",
+        (Utc::now(), id),
+    )?;
+    Ok(())
+}
+
+pub fn get_incomplete_action(
+    conn: &rusqlite::Connection,
+    kind: ActionKind,
+    subject: &str,
+) -> Result<Option<PendingActionRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM pending_actions
+            WHERE kind = ?1 AND subject = ?2 AND completed_time IS NULL
+            ORDER BY id LIMIT 1",
+            (kind, subject),
+            PendingActionRow::from_row,
+        )
+        .optional()?)
+}
+
+/// Actions started before `before` that were never completed, oldest first
+pub fn get_incomplete_actions(
+    conn: &rusqlite::Connection,
+    before: DateTime<Utc>,
+) -> Result<Vec<PendingActionRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM pending_actions
+        WHERE completed_time IS NULL AND datetime(created_time) < datetime(?1)
+        ORDER BY id",
+    )?;
+    let rows = stmt.query_map((before,), PendingActionRow::from_row)?;
+    rows.collect::<rusqlite::Result<_>>()
+        .with_context(|| "failed to fetch incomplete actions")
+}
+
+pub fn insert_third_party_mirror(
+    conn: &rusqlite::Connection,
+    manifold_market: &LiteMarket,
+    source: &QuestionSource,
+    source_id: &str,
+    config: &Settings,
+) -> Result<ThirdPartyMirrorRow> {
+    let mut statement = conn.prepare(
+        "INSERT INTO third_party_markets (manifold_contract_id, manifold_url, source, source_id, created_time)
+        VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
+    )?;
+    Ok(statement.query_row(
+        (
+            &manifold_market.id,
+            &manifold_market.url(config),
+            source,
+            source.normalize_id(source_id),
+            manifold_market.created_time,
+        ),
+        ThirdPartyMirrorRow::from_row,
+    )?)
+}
+
+pub fn get_third_party_mirror_by_source_id(
+    conn: &rusqlite::Connection,
+    source: &QuestionSource,
+    source_id: &str,
+) -> Result<Option<ThirdPartyMirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM third_party_markets WHERE source = ?1 AND source_id = ?2",
+            (source, source.normalize_id(source_id)),
+            ThirdPartyMirrorRow::from_row,
+        )
+        .optional()?)
+}
+
+pub fn get_third_party_mirror_by_contract_id(
+    conn: &rusqlite::Connection,
+    contract_id: &str,
+) -> Result<Option<ThirdPartyMirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM third_party_markets WHERE manifold_contract_id = ?1",
+            (&contract_id,),
+            ThirdPartyMirrorRow::from_row,
+        )
+        .optional()?)
+}
+
+pub fn get_unresolved_mirrors(
+    conn: &rusqlite::Connection,
+    source: Option<QuestionSource>,
+) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = if let Some(source) = source {
+        conn.prepare("SELECT * FROM markets WHERE source = ?1 AND resolved = FALSE")?
+            .query((&source,))?
+            .mapped(MirrorRow::from_row)
+            .collect()
+    } else {
+        conn.prepare("SELECT * FROM markets WHERE resolved = FALSE")?
+            .query([])?
+            .mapped(MirrorRow::from_row)
+            .collect()
+    };
+    Ok(rows.with_context(|| "failed to fetch unresolved markets from db")?)
+}
+
+/// Unresolved mirrors whose scheduled resolution check is due
+pub fn get_due_mirrors(
+    conn: &rusqlite::Connection,
+    source: Option<QuestionSource>,
+) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
+        .prepare(
+            "SELECT * FROM markets
+            WHERE resolved = FALSE
+                AND (?1 IS NULL OR source = ?1)
+                AND (next_check_at IS NULL OR next_check_at <= ?2)",
+        )?
+        .query((&source, Utc::now()))?
+        .mapped(MirrorRow::from_row)
+        .collect();
+    rows.with_context(|| "failed to fetch due markets from db")
+}
+
+pub fn get_resolved_mirrors(
     conn: &rusqlite::Connection,
     source: Option<QuestionSource>,
 ) -> Result<Vec<MirrorRow>> {
@@ -229,58 +1602,524 @@ pub fn get_resolved_mirrors(
     Ok(rows.with_context(|| "failed to fetch unresolved markets from db")?)
 }
 
-pub fn get_mirrors(conn: &rusqlite::Connection) -> Result<Vec<MirrorRow>> {
-    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
-        .prepare("SELECT * FROM markets")?
-        .query([])?
-        .mapped(MirrorRow::from_row)
-        .collect();
-    Ok(rows?)
+/// Active mirrors resolved at or after `since` that haven't been flagged as
+/// reopened yet, to check whether their source opened again
+pub fn get_recently_resolved_mirrors(
+    conn: &rusqlite::Connection,
+    source: &QuestionSource,
+    since: DateTime<Utc>,
+) -> Result<Vec<MirrorRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM markets
+        WHERE source = ?1 AND resolved = TRUE AND status = 'ACTIVE' AND reopened_time IS NULL
+        AND datetime(resolved_time) >= datetime(?2)",
+    )?;
+    let rows = stmt.query_map((source, since), MirrorRow::from_row)?;
+    rows.collect::<rusqlite::Result<_>>()
+        .with_context(|| "failed to fetch recently resolved markets from db")
+}
+
+/// Flag a resolved mirror whose source is open again
+pub fn set_mirror_reopened(conn: &rusqlite::Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET reopened_time = ?2 WHERE id = ?1",
+        (id, Utc::now()),
+    )?;
+    Ok(())
+}
+
+/// Filters for [`get_filtered_mirrors`]. Unset filters match everything.
+#[derive(Debug, Default)]
+pub struct MirrorFilter {
+    pub resolved: bool,
+    pub source: Option<QuestionSource>,
+    /// Cloned at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Cloned before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Case insensitive substring of the question
+    pub question_contains: Option<String>,
+    pub contract_id: Option<String>,
+}
+
+/// Mirrors matching all filters, oldest first
+pub fn get_filtered_mirrors(
+    conn: &rusqlite::Connection,
+    filter: &MirrorFilter,
+) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
+        .prepare(
+            "SELECT * FROM markets
+            WHERE resolved = ?1
+            AND (?2 IS NULL OR source = ?2)
+            AND (?3 IS NULL OR datetime(clone_date) >= datetime(?3))
+            AND (?4 IS NULL OR datetime(clone_date) < datetime(?4))
+            AND (?5 IS NULL OR instr(lower(question), lower(?5)) > 0)
+            AND (?6 IS NULL OR manifold_contract_id = ?6)
+            ORDER BY id",
+        )?
+        .query((
+            filter.resolved,
+            &filter.source,
+            filter.since,
+            filter.until,
+            &filter.question_contains,
+            &filter.contract_id,
+        ))?
+        .mapped(MirrorRow::from_row)
+        .collect();
+    rows.with_context(|| "failed to fetch filtered markets from db")
+}
+
+/// Number of mirrors of questions from the source cloned at or after the given time
+pub fn count_mirrors_cloned_since(
+    conn: &rusqlite::Connection,
+    source: &QuestionSource,
+    since: DateTime<Utc>,
+) -> Result<usize> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM markets WHERE source = ?1 AND datetime(clone_date) >= datetime(?2)",
+        (source, since),
+        |row| row.get(0),
+    )?)
+}
+
+/// Number of unresolved, active mirrors, of one source or all of them.
+/// Manually registered markets don't count.
+pub fn count_open_mirrors(
+    conn: &rusqlite::Connection,
+    source: Option<&QuestionSource>,
+) -> Result<usize> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM markets
+        WHERE resolved = FALSE AND status = 'ACTIVE' AND source != 'MANUAL'
+        AND (?1 IS NULL OR source = ?1)",
+        (source,),
+        |row| row.get(0),
+    )?)
+}
+
+pub fn get_mirrors(conn: &rusqlite::Connection) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
+        .prepare("SELECT * FROM markets")?
+        .query([])?
+        .mapped(MirrorRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+pub fn get_third_party_mirrors(conn: &rusqlite::Connection) -> Result<Vec<ThirdPartyMirrorRow>> {
+    let rows: rusqlite::Result<Vec<ThirdPartyMirrorRow>> = conn
+        .prepare("SELECT * FROM third_party_markets")?
+        .query([])?
+        .mapped(ThirdPartyMirrorRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+pub fn get_mirror_by_source_id(
+    conn: &rusqlite::Connection,
+    source: &QuestionSource,
+    source_id: &str,
+) -> Result<Option<MirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM markets WHERE source = ?1 AND source_id = ?2",
+            (source, source.normalize_id(source_id)),
+            MirrorRow::from_row,
+        )
+        .optional()?)
+}
+
+/// Find mirror by the last path segment of its Manifold url
+pub fn get_mirror_by_slug(conn: &rusqlite::Connection, slug: &str) -> Result<Option<MirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM markets WHERE manifold_url LIKE '%/' || ?1",
+            (&slug,),
+            MirrorRow::from_row,
+        )
+        .optional()?)
+}
+
+pub fn get_mirror_by_contract_id(
+    conn: &rusqlite::Connection,
+    contract_id: &str,
+) -> Result<Option<MirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM markets WHERE manifold_contract_id = ?1",
+            (&contract_id,),
+            MirrorRow::from_row,
+        )
+        .optional()?)
+}
+
+/// Mark mirror resolved by the bot
+pub fn set_mirror_resolution(
+    conn: &rusqlite::Connection,
+    id: i64,
+    resolution: &BinaryResolution,
+) -> Result<()> {
+    let changed = conn.execute(
+        "UPDATE markets SET resolved = TRUE, resolution = ?2, resolved_time = ?3,
+        reopened_time = NULL WHERE id = ?1",
+        (id, resolution, Utc::now()),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!(
+            "set_mirror_resolution query did not modify any rows"
+        ));
+    }
+    Ok(())
+}
+
+/// Record a close time set by the bot
+pub fn set_mirror_close_time(
+    conn: &rusqlite::Connection,
+    id: i64,
+    close_time: DateTime<Utc>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET close_time = ?2 WHERE id = ?1",
+        (id, close_time),
+    )?;
+    Ok(())
+}
+
+/// Mark mirror as abandoned by the operator
+pub fn set_mirror_retired(conn: &rusqlite::Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET status = ?2 WHERE id = ?1",
+        (id, MirrorStatus::Retired),
+    )?;
+    Ok(())
+}
+
+/// Mark mirror unresolved again after its resolution was undone
+pub fn set_mirror_unresolved(conn: &rusqlite::Connection, id: i64) -> Result<()> {
+    let changed = conn.execute(
+        "UPDATE markets SET resolved = FALSE, resolution = NULL, resolved_time = NULL,
+        status = 'ACTIVE' WHERE id = ?1",
+        (id,),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!(
+            "set_mirror_unresolved query did not modify any rows"
+        ));
+    }
+    Ok(())
+}
+
+/// Copy close time, probability and resolution state from the mirror's Manifold market
+pub fn update_mirror_from_market(
+    conn: &rusqlite::Connection,
+    id: i64,
+    market: &LiteMarket,
+) -> Result<()> {
+    let changed = conn.execute(
+        "UPDATE markets SET resolved = ?2, resolution = ?3, resolved_time = ?4, close_time = ?5,
+        manifold_probability = ?6
+        WHERE id = ?1",
+        (
+            id,
+            market.is_resolved,
+            market.binary_resolution(),
+            market.resolution_time,
+            market.close_time,
+            market.probability,
+        ),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!(
+            "update_mirror_from_market query did not modify any rows"
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors resolved between `after` and `before` whose top traders haven't been
+/// rewarded yet. Cancelled mirrors have no profits to reward.
+pub fn get_mirrors_to_reward(
+    conn: &rusqlite::Connection,
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
+        .prepare(
+            "SELECT * FROM markets
+            WHERE source != 'MANUAL' AND resolved = TRUE AND rewarded_time IS NULL
+            AND resolution IS NOT NULL AND resolution != 'CANCEL'
+            AND datetime(resolved_time) >= datetime(?1) AND datetime(resolved_time) < datetime(?2)",
+        )?
+        .query((after, before))?
+        .mapped(MirrorRow::from_row)
+        .collect();
+    rows.with_context(|| "failed to fetch mirrors to reward from db")
+}
+
+pub fn set_mirror_rewarded(conn: &rusqlite::Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET rewarded_time = ?2 WHERE id = ?1",
+        (id, Utc::now()),
+    )?;
+    Ok(())
+}
+
+/// Record the user who paid for the mirror
+pub fn set_mirror_requester(
+    conn: &rusqlite::Connection,
+    id: i64,
+    user_id: &str,
+    price: f64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET requested_by = ?2, request_price = ?3 WHERE id = ?1",
+        (id, user_id, price),
+    )?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct RequesterRow {
+    pub user_id: String,
+    pub mirrors: i64,
+    /// Mana paid for mirrors
+    pub spent: f64,
+}
+
+/// Users who requested the most mirrors since `since`, most mana spent first among ties
+pub fn get_top_requesters(
+    conn: &rusqlite::Connection,
+    since: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<RequesterRow>> {
+    let rows: rusqlite::Result<Vec<RequesterRow>> = conn
+        .prepare(
+            "SELECT requested_by, COUNT(*) AS mirrors, COALESCE(SUM(request_price), 0.0) AS spent
+            FROM markets
+            WHERE requested_by IS NOT NULL AND (?1 IS NULL OR datetime(clone_date) >= datetime(?1))
+            GROUP BY requested_by
+            ORDER BY mirrors DESC, spent DESC
+            LIMIT ?2",
+        )?
+        .query((since, limit))?
+        .mapped(|row| {
+            Ok(RequesterRow {
+                user_id: row.get("requested_by")?,
+                mirrors: row.get("mirrors")?,
+                spent: row.get("spent")?,
+            })
+        })
+        .collect();
+    Ok(rows?)
+}
+
+/// Record trading activity on the mirror's Manifold market
+pub fn set_mirror_analytics(
+    conn: &rusqlite::Connection,
+    id: i64,
+    market: &FullMarket,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET volume = ?2, volume_24h = ?3, unique_bettors = ?4, analytics_time = ?5
+        WHERE id = ?1",
+        (
+            id,
+            market.volume,
+            market.volume_24_hours,
+            market.unique_bettor_count,
+            Utc::now(),
+        ),
+    )?;
+    Ok(())
+}
+
+/// Mirrors whose trading activity may have changed since it was last fetched:
+/// unresolved ones, and resolved ones last fetched before they resolved
+pub fn get_mirrors_for_analytics(conn: &rusqlite::Connection) -> Result<Vec<MirrorRow>> {
+    let rows: rusqlite::Result<Vec<MirrorRow>> = conn
+        .prepare(
+            "SELECT * FROM markets WHERE source != 'MANUAL' AND (resolved = FALSE
+            OR analytics_time IS NULL OR datetime(analytics_time) < datetime(resolved_time))",
+        )?
+        .query([])?
+        .mapped(MirrorRow::from_row)
+        .collect();
+    rows.with_context(|| "failed to fetch mirrors for analytics from db")
+}
+
+/// Record that the mirror's source was just checked for resolution
+pub fn set_mirror_synced(
+    conn: &rusqlite::Connection,
+    id: i64,
+    source_probability: Option<f64>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET last_synced_at = ?2, source_probability = ?3 WHERE id = ?1",
+        (id, Utc::now(), source_probability),
+    )?;
+    Ok(())
+}
+
+pub fn set_mirror_next_check(
+    conn: &rusqlite::Connection,
+    id: i64,
+    next_check_at: DateTime<Utc>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET next_check_at = ?2 WHERE id = ?1",
+        (id, next_check_at),
+    )?;
+    Ok(())
+}
+
+/// Record the Manifold probability at the time the mirror closed
+pub fn set_mirror_close_probability(
+    conn: &rusqlite::Connection,
+    id: i64,
+    probability: f64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE markets SET close_probability = ?2 WHERE id = ?1",
+        (id, probability),
+    )?;
+    Ok(())
+}
+
+pub fn get_mirror_by_id(conn: &rusqlite::Connection, id: i64) -> Result<Option<MirrorRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM markets WHERE id = ?1",
+            (id,),
+            MirrorRow::from_row,
+        )
+        .optional()?)
 }
 
-pub fn get_third_party_mirrors(conn: &rusqlite::Connection) -> Result<Vec<ThirdPartyMirrorRow>> {
-    let rows: rusqlite::Result<Vec<ThirdPartyMirrorRow>> = conn
-        .prepare("SELECT * FROM third_party_markets")?
-        .query([])?
-        .mapped(ThirdPartyMirrorRow::from_row)
-        .collect();
-    Ok(rows?)
+pub fn insert_pending_resolution(
+    conn: &rusqlite::Connection,
+    market_id: i64,
+    resolution: &BinaryResolution,
+    volume: f64,
+) -> Result<PendingResolutionRow> {
+    let mut statement = conn.prepare(
+        "INSERT INTO pending_resolutions (market_id, resolution, volume, created_time)
+        VALUES (?1, ?2, ?3, ?4) RETURNING *",
+    )?;
+    Ok(statement.query_row(
+        (market_id, resolution, volume, Utc::now()),
+        PendingResolutionRow::from_row,
+    )?)
 }
 
-pub fn get_mirror_by_source_id(
+pub fn get_pending_resolution(
     conn: &rusqlite::Connection,
-    source: &QuestionSource,
-    source_id: &str,
-) -> Result<Option<MirrorRow>> {
+    id: i64,
+) -> Result<Option<PendingResolutionRow>> {
     Ok(conn
         .query_row(
-            "SELECT * FROM markets WHERE source = ?1 AND source_id = ?2",
-            (&source, &source_id),
-            MirrorRow::from_row,
+            "SELECT * FROM pending_resolutions WHERE id = ?1",
+            (id,),
+            PendingResolutionRow::from_row,
         )
         .optional()?)
 }
 
-pub fn get_mirror_by_contract_id(
+/// Most recent held resolution for a mirror, in any state
+pub fn get_latest_pending_resolution(
     conn: &rusqlite::Connection,
-    contract_id: &str,
-) -> Result<Option<MirrorRow>> {
+    market_id: i64,
+) -> Result<Option<PendingResolutionRow>> {
     Ok(conn
         .query_row(
-            "SELECT * FROM markets WHERE manifold_contract_id = ?1",
-            (&contract_id,),
-            MirrorRow::from_row,
+            "SELECT * FROM pending_resolutions WHERE market_id = ?1 ORDER BY id DESC LIMIT 1",
+            (market_id,),
+            PendingResolutionRow::from_row,
         )
         .optional()?)
 }
 
-pub fn set_mirror_resolved(conn: &rusqlite::Connection, id: i64, resolved: bool) -> Result<()> {
+pub fn get_pending_resolutions(
+    conn: &rusqlite::Connection,
+    state: Option<PendingResolutionState>,
+) -> Result<Vec<PendingResolutionRow>> {
+    let rows: rusqlite::Result<Vec<PendingResolutionRow>> = if let Some(state) = state {
+        conn.prepare("SELECT * FROM pending_resolutions WHERE state = ?1")?
+            .query((&state,))?
+            .mapped(PendingResolutionRow::from_row)
+            .collect()
+    } else {
+        conn.prepare("SELECT * FROM pending_resolutions")?
+            .query([])?
+            .mapped(PendingResolutionRow::from_row)
+            .collect()
+    };
+    Ok(rows?)
+}
+
+/// Append to the resolution history of a mirror. A resolution of None records an unresolution.
+pub fn insert_resolution_event(
+    db: &rusqlite::Connection,
+    market_id: i64,
+    resolution: Option<&BinaryResolution>,
+    source_state: &str,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO resolutions (market_id, resolution, source_state, created_time)
+        VALUES (?1, ?2, ?3, ?4)",
+        (market_id, resolution, source_state, Utc::now()),
+    )?;
+    Ok(())
+}
+
+/// Resolution history, oldest first, optionally for a single mirror
+pub fn get_resolution_history(
+    db: &rusqlite::Connection,
+    market_id: Option<i64>,
+) -> Result<Vec<ResolutionEventRow>> {
+    let rows: rusqlite::Result<Vec<ResolutionEventRow>> = db
+        .prepare(
+            "SELECT * FROM resolutions
+            WHERE ?1 IS NULL OR market_id = ?1
+            ORDER BY id",
+        )?
+        .query((market_id,))?
+        .mapped(ResolutionEventRow::from_row)
+        .collect();
+    Ok(rows?)
+}
+
+pub fn set_pending_resolution_state(
+    conn: &rusqlite::Connection,
+    id: i64,
+    state: PendingResolutionState,
+) -> Result<()> {
+    let changed = conn.execute(
+        "UPDATE pending_resolutions SET state = ?2 WHERE id = ?1",
+        (id, &state),
+    )?;
+    if changed == 0 {
+        return Err(anyhow!(
+            "set_pending_resolution_state query did not modify any rows"
+        ));
+    }
+    Ok(())
+}
+
+pub fn set_pending_resolution_value(
+    conn: &rusqlite::Connection,
+    id: i64,
+    resolution: &BinaryResolution,
+) -> Result<()> {
     let changed = conn.execute(
-        "UPDATE markets SET resolved = ?2 WHERE id = ?1",
-        (id, &resolved),
+        "UPDATE pending_resolutions SET resolution = ?2 WHERE id = ?1",
+        (id, resolution),
     )?;
     if changed == 0 {
-        return Err(anyhow!("set_market_resolved query did not modify any rows"));
+        return Err(anyhow!(
+            "set_pending_resolution_value query did not modify any rows"
+        ));
     }
     Ok(())
 }
@@ -291,7 +2130,7 @@ pub fn get_any_mirror(
     source_id: &str,
 ) -> Result<Option<AnyMirror>> {
     if let Some(mirror) = get_mirror_by_source_id(&db, source, source_id)? {
-        return Ok(Some(AnyMirror::Mirror(mirror)));
+        return Ok(Some(AnyMirror::Mirror(Box::new(mirror))));
     }
     if let Some(mirror) = get_third_party_mirror_by_source_id(&db, source, source_id)? {
         return Ok(Some(AnyMirror::ThirdPartyMirror(mirror)));
@@ -301,11 +2140,11 @@ pub fn get_any_mirror(
 
 #[derive(Debug)]
 pub enum AnyMirror {
-    Mirror(MirrorRow),
+    Mirror(Box<MirrorRow>),
     ThirdPartyMirror(ThirdPartyMirrorRow),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct MirrorRow {
     pub id: i64,
     pub clone_date: DateTime<Utc>,
@@ -316,6 +2155,29 @@ pub struct MirrorRow {
     pub source_url: String,
     pub question: String,
     pub resolved: bool,
+    pub close_time: Option<DateTime<Utc>>,
+    pub resolution: Option<BinaryResolution>,
+    pub resolved_time: Option<DateTime<Utc>>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub source_probability: Option<f64>,
+    pub manifold_probability: Option<f64>,
+    pub close_probability: Option<f64>,
+    pub status: MirrorStatus,
+    pub next_check_at: Option<DateTime<Utc>>,
+    /// Set when the source was found open again while the mirror is resolved
+    pub reopened_time: Option<DateTime<Utc>>,
+    /// `manifold.accounts` entry the mirror was created with, None for the main account
+    pub destination: Option<String>,
+    /// Trading activity as of `analytics_time`, None until first fetched
+    pub volume: Option<f64>,
+    pub volume_24h: Option<f64>,
+    pub unique_bettors: Option<i64>,
+    pub analytics_time: Option<DateTime<Utc>>,
+    /// User who requested the mirror by managram, and the mana they paid
+    pub requested_by: Option<String>,
+    pub request_price: Option<f64>,
+    /// When top traders were rewarded, see `manifold.rewards`
+    pub rewarded_time: Option<DateTime<Utc>>,
 }
 
 impl MirrorRow {
@@ -330,11 +2192,29 @@ impl MirrorRow {
             source_url: row.get("source_url")?,
             question: row.get("question")?,
             resolved: row.get("resolved")?,
+            close_time: row.get("close_time")?,
+            resolution: row.get("resolution")?,
+            resolved_time: row.get("resolved_time")?,
+            last_synced_at: row.get("last_synced_at")?,
+            source_probability: row.get("source_probability")?,
+            manifold_probability: row.get("manifold_probability")?,
+            close_probability: row.get("close_probability")?,
+            status: row.get("status")?,
+            next_check_at: row.get("next_check_at")?,
+            reopened_time: row.get("reopened_time")?,
+            destination: row.get("destination")?,
+            volume: row.get("volume")?,
+            volume_24h: row.get("volume_24h")?,
+            unique_bettors: row.get("unique_bettors")?,
+            analytics_time: row.get("analytics_time")?,
+            requested_by: row.get("requested_by")?,
+            request_price: row.get("request_price")?,
+            rewarded_time: row.get("rewarded_time")?,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ThirdPartyMirrorRow {
     pub id: i64,
     pub manifold_contract_id: String,
@@ -357,6 +2237,331 @@ impl ThirdPartyMirrorRow {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct PendingResolutionRow {
+    pub id: i64,
+    pub market_id: i64,
+    pub resolution: BinaryResolution,
+    pub volume: f64,
+    pub created_time: DateTime<Utc>,
+    pub state: PendingResolutionState,
+}
+
+impl PendingResolutionRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<PendingResolutionRow> {
+        Ok(PendingResolutionRow {
+            id: row.get("id")?,
+            market_id: row.get("market_id")?,
+            resolution: row.get("resolution")?,
+            volume: row.get("volume")?,
+            created_time: row.get("created_time")?,
+            state: row.get("state")?,
+        })
+    }
+}
+
+/// Statistics collected during a run
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    pub candidates: usize,
+    pub mirrors_created: usize,
+    pub resolutions_synced: usize,
+    pub errors: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunRow {
+    pub id: i64,
+    pub command: String,
+    pub target: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub stats: RunStats,
+    pub error: Option<String>,
+}
+
+impl RunRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<RunRow> {
+        Ok(RunRow {
+            id: row.get("id")?,
+            command: row.get("command")?,
+            target: row.get("target")?,
+            start_time: row.get("start_time")?,
+            end_time: row.get("end_time")?,
+            stats: RunStats {
+                candidates: row.get("candidates")?,
+                mirrors_created: row.get("mirrors_created")?,
+                resolutions_synced: row.get("resolutions_synced")?,
+                errors: row.get("errors")?,
+            },
+            error: row.get("error")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingActionRow {
+    pub id: i64,
+    pub kind: ActionKind,
+    /// What the action is on: source question, contract id or managram id
+    pub subject: String,
+    /// JSON, whatever is needed to reconcile the action
+    pub payload: String,
+    pub created_time: DateTime<Utc>,
+}
+
+impl PendingActionRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<PendingActionRow> {
+        Ok(PendingActionRow {
+            id: row.get("id")?,
+            kind: row.get("kind")?,
+            subject: row.get("subject")?,
+            payload: row.get("payload")?,
+            created_time: row.get("created_time")?,
+        })
+    }
+}
+
+/// Auto-mirror candidate rejected by the auto filter, to be recorded
+pub struct NewRejection {
+    pub source_id: String,
+    pub title: String,
+    pub source_url: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RejectionRow {
+    pub id: i64,
+    pub source: QuestionSource,
+    pub source_id: String,
+    pub title: String,
+    pub source_url: String,
+    pub reason: String,
+    pub created_time: DateTime<Utc>,
+}
+
+impl RejectionRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<RejectionRow> {
+        Ok(RejectionRow {
+            id: row.get("id")?,
+            source: row.get("source")?,
+            source_id: row.get("source_id")?,
+            title: row.get("title")?,
+            source_url: row.get("source_url")?,
+            reason: row.get("reason")?,
+            created_time: row.get("created_time")?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolutionEventRow {
+    pub id: i64,
+    pub market_id: i64,
+    /// None if the mirror was unresolved
+    pub resolution: Option<BinaryResolution>,
+    pub source_state: String,
+    pub created_time: DateTime<Utc>,
+}
+
+impl ResolutionEventRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<ResolutionEventRow> {
+        Ok(ResolutionEventRow {
+            id: row.get("id")?,
+            market_id: row.get("market_id")?,
+            resolution: row.get("resolution")?,
+            source_state: row.get("source_state")?,
+            created_time: row.get("created_time")?,
+        })
+    }
+}
+
+/// Managram along with how processing it went
+#[derive(Debug, Serialize)]
+pub struct ManagramRow {
+    pub managram: Managram,
+    pub state: ManagramState,
+    pub attempts: i64,
+    pub error: Option<String>,
+}
+
+impl ManagramRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<ManagramRow> {
+        Ok(ManagramRow {
+            managram: managram_row_helper(row)?,
+            state: row.get("state")?,
+            attempts: row.get("attempts")?,
+            error: row.get("error")?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManagramAuditRow {
+    pub id: i64,
+    pub managram_id: String,
+    pub attempt: i64,
+    pub command: Option<String>,
+    pub outcome: ManagramState,
+    pub error: Option<String>,
+    pub response_amount: f64,
+    pub created_time: DateTime<Utc>,
+}
+
+impl ManagramAuditRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<ManagramAuditRow> {
+        Ok(ManagramAuditRow {
+            id: row.get("id")?,
+            managram_id: row.get("managram_id")?,
+            attempt: row.get("attempt")?,
+            command: row.get("command")?,
+            outcome: row.get("outcome")?,
+            error: row.get("error")?,
+            response_amount: row.get("response_amount")?,
+            created_time: row.get("created_time")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SpendSummaryRow {
+    pub category: SpendCategory,
+    pub source: Option<QuestionSource>,
+    pub count: i64,
+    pub total: f64,
+}
+
+impl SpendSummaryRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<SpendSummaryRow> {
+        Ok(SpendSummaryRow {
+            category: row.get("category")?,
+            source: row.get("source")?,
+            count: row.get("count")?,
+            total: row.get("total")?,
+        })
+    }
+}
+
+/// What mana was spent on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SpendCategory {
+    /// Ante for a new market
+    MarketCreation,
+    /// Managram refunding a failed request
+    Refund,
+    /// Managram responding to a request, e.g. returning the minimum amount
+    Response,
+    /// Managram notifying subscribers
+    Notification,
+    /// Liquidity added to existing markets
+    Subsidy,
+    /// Managrams rewarding top traders on resolved mirrors
+    Reward,
+    /// Liquidity added with mana users sent in `subsidize` requests
+    UserSubsidy,
+    /// Anything sent manually by the operator
+    Other,
+}
+
+impl Display for SpendCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::MarketCreation => "MARKET_CREATION",
+            Self::Refund => "REFUND",
+            Self::Response => "RESPONSE",
+            Self::Notification => "NOTIFICATION",
+            Self::Subsidy => "SUBSIDY",
+            Self::Reward => "REWARD",
+            Self::UserSubsidy => "USER_SUBSIDY",
+            Self::Other => "OTHER",
+        })
+    }
+}
+
+/// Lifecycle status of a mirror, separate from its resolution
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum MirrorStatus {
+    Active,
+    /// Resolved N/A and abandoned, e.g. because it was mirrored in error
+    Retired,
+}
+
+/// Kinds of comments the bot posts on its own mirrors
+#[derive(Debug, Clone, PartialEq)]
+pub enum MirrorCommentKind {
+    /// Heads-up that the mirror is about to close
+    CloseReminder,
+    /// Periodic source forecast versus Manifold probability
+    ProbabilityUpdate,
+}
+
+/// Processing state of a managram
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ManagramState {
+    /// Not looked at yet
+    New,
+    /// Command is being executed. Seeing this outside of processing means we crashed midway.
+    Started,
+    Succeeded,
+    /// Command failed. No refund has been sent (yet). Retried later if a retry is scheduled.
+    Failed,
+    /// Command failed and the sender got their mana back
+    Refunded,
+}
+
+/// External side effects recorded in pending_actions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionKind {
+    /// Creating a mirror. Subject is `<source>:<source id>`, payload the question.
+    CreateMarket,
+    /// Resolving a mirror. Subject is the contract id.
+    ResolveMarket,
+    /// Responding to a managram. Subject is the id of the managram responded to.
+    SendManagram,
+}
+
+impl Display for ActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::CreateMarket => "CREATE_MARKET",
+            Self::ResolveMarket => "RESOLVE_MARKET",
+            Self::SendManagram => "SEND_MANAGRAM",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum PendingResolutionState {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug)]
+pub struct CommentRow {
+    pub comment_id: String,
+    pub contract_id: String,
+    pub user_id: String,
+    pub reply_to_comment_id: Option<String>,
+    pub created_time: DateTime<Utc>,
+    pub text: String,
+}
+
+impl CommentRow {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<CommentRow> {
+        Ok(CommentRow {
+            comment_id: row.get("comment_id")?,
+            contract_id: row.get("contract_id")?,
+            user_id: row.get("user_id")?,
+            reply_to_comment_id: row.get("reply_to_comment_id")?,
+            created_time: row.get("created_time")?,
+            text: row.get("text")?,
+        })
+    }
+}
+
 fn managram_row_helper(row: &Row<'_>) -> rusqlite::Result<Managram> {
     Ok(Managram {
         id: row.get("txn_id")?,
@@ -402,3 +2607,188 @@ impl FromSql for TokenType {
         })
     }
 }
+
+impl ToSql for SpendCategory {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for SpendCategory {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "MARKET_CREATION" => Self::MarketCreation,
+            "REFUND" => Self::Refund,
+            "RESPONSE" => Self::Response,
+            "NOTIFICATION" => Self::Notification,
+            "SUBSIDY" => Self::Subsidy,
+            "REWARD" => Self::Reward,
+            "USER_SUBSIDY" => Self::UserSubsidy,
+            "OTHER" => Self::Other,
+            _ => return Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+impl ToSql for MirrorStatus {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Self::Active => "ACTIVE",
+            Self::Retired => "RETIRED",
+        }))
+    }
+}
+
+impl FromSql for MirrorStatus {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "ACTIVE" => Self::Active,
+            "RETIRED" => Self::Retired,
+            _ => return Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+impl ToSql for MirrorCommentKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Self::CloseReminder => "CLOSE_REMINDER",
+            Self::ProbabilityUpdate => "PROBABILITY_UPDATE",
+        }))
+    }
+}
+
+impl ToSql for ActionKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for ActionKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "CREATE_MARKET" => Self::CreateMarket,
+            "RESOLVE_MARKET" => Self::ResolveMarket,
+            "SEND_MANAGRAM" => Self::SendManagram,
+            _ => return Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+impl ToSql for ManagramState {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Self::New => "NEW",
+            Self::Started => "STARTED",
+            Self::Succeeded => "SUCCEEDED",
+            Self::Failed => "FAILED",
+            Self::Refunded => "REFUNDED",
+        }))
+    }
+}
+
+impl FromSql for ManagramState {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "NEW" => Self::New,
+            "STARTED" => Self::Started,
+            "SUCCEEDED" => Self::Succeeded,
+            "FAILED" => Self::Failed,
+            "REFUNDED" => Self::Refunded,
+            _ => return Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+impl ToSql for PendingResolutionState {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Self::Pending => "PENDING",
+            Self::Approved => "APPROVED",
+            Self::Rejected => "REJECTED",
+        }))
+    }
+}
+
+impl FromSql for PendingResolutionState {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "PENDING" => Self::Pending,
+            "APPROVED" => Self::Approved,
+            "REJECTED" => Self::Rejected,
+            _ => return Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+/// Stored as YES, NO, CANCEL, or MKT:<probability>
+impl ToSql for BinaryResolution {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Self::Yes => "YES".to_string(),
+            Self::No => "NO".to_string(),
+            Self::Cancel => "CANCEL".to_string(),
+            Self::Percent(p) => format!("MKT:{}", p),
+        }))
+    }
+}
+
+impl FromSql for BinaryResolution {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "YES" => Self::Yes,
+            "NO" => Self::No,
+            "CANCEL" => Self::Cancel,
+            other => match other.strip_prefix("MKT:").map(str::parse::<f64>) {
+                Some(Ok(p)) => Self::Percent(p),
+                _ => return Err(FromSqlError::InvalidType),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kalshi_ids_differing_in_case_fail_the_migration() {
+        // the parts of the schema before migration 9 that the later migrations touch
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE markets (
+                id INTEGER PRIMARY KEY,
+                manifold_url TEXT NOT NULL,
+                source TEXT NOT NULL,
+                source_id TEXT NOT NULL
+            );
+            CREATE UNIQUE INDEX markets_source_key ON markets (source, source_id);
+            CREATE TABLE third_party_markets (source TEXT NOT NULL, source_id TEXT NOT NULL);
+            INSERT INTO markets (manifold_url, source, source_id) VALUES
+                ('https://manifold.markets/bot/m1', 'KALSHI', 'ABC-1'),
+                ('https://manifold.markets/bot/m2', 'KALSHI', 'abc-1'),
+                ('https://manifold.markets/bot/m3', 'KALSHI', 'def-2');
+            PRAGMA user_version = 8;",
+        )
+        .unwrap();
+
+        let error = format!("{:#}", migrate(&conn).unwrap_err());
+        assert!(error.contains("row 1 (ABC-1"), "{}", error);
+        assert!(error.contains("row 2 (abc-1"), "{}", error);
+        assert!(!error.contains("def-2"), "{}", error);
+        assert_eq!(schema_version(&conn).unwrap(), 8);
+
+        conn.execute("DELETE FROM markets WHERE id = 2", ())
+            .unwrap();
+        migrate(&conn).unwrap();
+        let ids: Vec<String> = conn
+            .prepare("SELECT source_id FROM markets ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ids, ["ABC-1", "DEF-2"]);
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+}
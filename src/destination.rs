@@ -0,0 +1,60 @@
+use reqwest::Client;
+
+use crate::{
+    manifold::{
+        Comment, CreateMarketArgs, LiteMarket, ManifoldDestination, PostCommentArgs,
+        UpdateMarketArgs,
+    },
+    mirror::MirrorError,
+    settings::Settings,
+    types::BinaryResolution,
+};
+
+/// Site mirrors are created on
+pub type Destination = ManifoldDestination;
+
+/// A site we create mirrors on. Mirror logic in `mirror.rs` makes its changes to
+/// mirrored markets through this, so other destinations can be swapped in for
+/// [`Destination`]. `destination` names the account to act as, as recorded on
+/// the mirror, or None for the main account.
+pub trait MirrorDestination {
+    /// Create a market, returning it as created
+    async fn create_market(
+        client: &Client,
+        destination: Option<&str>,
+        args: CreateMarketArgs,
+        config: &Settings,
+    ) -> Result<LiteMarket, MirrorError>;
+
+    async fn resolve_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        resolution: &BinaryResolution,
+        config: &Settings,
+    ) -> Result<(), MirrorError>;
+
+    /// Undo the resolution of a market
+    async fn unresolve_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        config: &Settings,
+    ) -> Result<(), MirrorError>;
+
+    /// Update description or close time of a market
+    async fn update_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        args: &UpdateMarketArgs,
+        config: &Settings,
+    ) -> Result<(), MirrorError>;
+
+    async fn post_comment(
+        client: &Client,
+        destination: Option<&str>,
+        args: &PostCommentArgs,
+        config: &Settings,
+    ) -> Result<Comment, MirrorError>;
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use log::{debug, error, info, warn};
+use reqwest::{header::RETRY_AFTER, Client, Method, Proxy, RequestBuilder, Response, StatusCode};
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+    recording::{self, RecordedRequest},
+    settings::{RateLimit, Settings},
+};
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("skipped request to {0}, which keeps failing (circuit breaker open)")]
+    CircuitOpen(String),
+    #[error("replaying http.replay_dir: {0}")]
+    Replay(String),
+    /// Still answered 429 Too Many Requests after `http.max_retries` retries
+    #[error(
+        "rate limited by {host}{}",
+        .retry_after.map(|wait| format!(", retry after {}s", wait.as_secs())).unwrap_or_default()
+    )]
+    RateLimited {
+        host: String,
+        retry_after: Option<Duration>,
+    },
+}
+
+impl HttpError {
+    /// Strip the url from request errors, for urls containing secrets
+    pub fn without_url(self) -> Self {
+        match self {
+            HttpError::Request(e) => HttpError::Request(e.without_url()),
+            e => e,
+        }
+    }
+}
+
+/// Build the client for talking to external APIs, configured from `[http]`
+pub fn client(config: &Settings) -> Result<Client> {
+    let http = &config.http;
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(http.connect_timeout_secs))
+        .timeout(Duration::from_secs(http.timeout_secs))
+        .user_agent(&http.user_agent);
+    if !http.proxy.is_empty() {
+        builder = builder.proxy(Proxy::all(&http.proxy).with_context(|| "invalid http.proxy")?);
+    }
+    builder
+        .build()
+        .with_context(|| "failed to build HTTP client")
+}
+
+/// Token buckets by host, shared by all requests of the process
+static BUCKETS: OnceLock<Mutex<HashMap<String, Bucket>>> = OnceLock::new();
+
+struct Bucket {
+    /// May go negative when requests reserve tokens ahead of time
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Take a token and return how long to wait before it may be used
+    fn reserve(&mut self, limit: &RateLimit) -> Duration {
+        let now = Instant::now();
+        let refill = (now - self.updated).as_secs_f64() * limit.requests_per_second;
+        self.tokens = (self.tokens + refill).min(limit.burst as f64);
+        self.updated = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / limit.requests_per_second)
+        }
+    }
+}
+
+/// Wait until a request to `host` is allowed by `http.rate_limits`. Hosts without
+/// a configured limit aren't limited.
+async fn wait_for_rate_limit(config: &Settings, host: &str) {
+    let Some(limit) = config.http.rate_limits.iter().find(|l| l.host == host) else {
+        return;
+    };
+    let wait = BUCKETS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(host.to_string())
+        .or_insert_with(|| Bucket {
+            tokens: limit.burst as f64,
+            updated: Instant::now(),
+        })
+        .reserve(limit);
+    if !wait.is_zero() {
+        debug!(
+            "Rate limited, waiting {:?} before requesting {}",
+            wait, host
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Whether a failed attempt is worth repeating. A POST that failed with a 5xx or
+/// timed out may have been applied anyway, so those are only retried when
+/// rejected by rate limiting.
+fn is_transient(method: &Method, result: &reqwest::Result<Response>) -> bool {
+    let rate_limited = matches!(result, Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS);
+    let failed = match result {
+        Ok(resp) => resp.status().is_server_error(),
+        Err(e) => e.is_timeout(),
+    };
+    rate_limited || (failed && method != Method::POST)
+}
+
+/// Exponential backoff, or the server's `Retry-After` (in seconds) if it's longer
+fn retry_delay(config: &Settings, attempt: u32, result: &reqwest::Result<Response>) -> Duration {
+    let backoff = config
+        .http
+        .retry_base_delay_ms
+        .saturating_mul(1 << attempt.min(16))
+        .min(config.http.retry_max_delay_ms);
+    let retry_after = result
+        .as_ref()
+        .ok()
+        .and_then(retry_after)
+        .map_or(0, |wait| wait.as_millis() as u64);
+    Duration::from_millis(backoff.max(retry_after.min(config.http.retry_max_delay_ms)))
+}
+
+/// `Retry-After` of a response, given either in seconds or as an HTTP date
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+/// Turn a 429 response that outlasted the retries into [`HttpError::RateLimited`],
+/// so callers can wait it out instead of failing on the body
+fn check_rate_limited(host: &str, resp: Response) -> Result<Response, HttpError> {
+    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(HttpError::RateLimited {
+            host: host.to_string(),
+            retry_after: retry_after(&resp),
+        });
+    }
+    Ok(resp)
+}
+
+/// Consecutive failures by host. Hosts that keep failing are skipped for
+/// `http.circuit_breaker_cooldown_secs` instead of being hammered.
+static BREAKERS: OnceLock<Mutex<HashMap<String, Breaker>>> = OnceLock::new();
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    opened: Option<Instant>,
+    /// requests skipped since the last report
+    skipped: usize,
+}
+
+fn breakers() -> std::sync::MutexGuard<'static, HashMap<String, Breaker>> {
+    BREAKERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Whether requests to `host` should be skipped. Once the cooldown has passed a
+/// request is let through to see whether the host has recovered.
+fn circuit_open(config: &Settings, host: &str) -> bool {
+    let mut breakers = breakers();
+    let Some(breaker) = breakers.get_mut(host) else {
+        return false;
+    };
+    let cooldown = Duration::from_secs(config.http.circuit_breaker_cooldown_secs);
+    match breaker.opened {
+        Some(opened) if opened.elapsed() < cooldown => {
+            breaker.skipped += 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn record_outcome(config: &Settings, host: &str, failed: bool) {
+    let threshold = config.http.circuit_breaker_threshold;
+    let mut breakers = breakers();
+    let breaker = breakers.entry(host.to_string()).or_default();
+    if !failed {
+        if breaker.opened.take().is_some() {
+            info!("{} is responding again, closing its circuit breaker", host);
+        }
+        breaker.failures = 0;
+        return;
+    }
+    breaker.failures += 1;
+    if threshold > 0 && breaker.failures >= threshold {
+        if breaker.opened.is_none() {
+            error!(
+                "{} failed {} times in a row, skipping requests to it for {}s",
+                host, breaker.failures, config.http.circuit_breaker_cooldown_secs
+            );
+        }
+        breaker.opened = Some(Instant::now());
+    }
+}
+
+/// Log an error for every host that requests were skipped for since the last
+/// report, so outages show up in run stats and notifications.
+pub fn report_open_circuits() {
+    for (host, breaker) in breakers().iter_mut() {
+        if breaker.skipped > 0 {
+            error!(
+                "{} is down: {} request(s) to it were skipped after {} consecutive failures",
+                host, breaker.skipped, breaker.failures
+            );
+            breaker.skipped = 0;
+        }
+    }
+}
+
+/// Send a request, respecting the rate limit of its host and retrying transient
+/// failures up to `http.max_retries` times. Requests to a host that keeps failing
+/// are skipped. Every request to an external API should go through here.
+/// Requests and responses are written to `http.record_dir` if set, and with
+/// `http.replay_dir` set requests are answered from there instead.
+pub async fn send(config: &Settings, request: RequestBuilder) -> Result<Response, HttpError> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let host = request.url().host_str().unwrap_or_default().to_string();
+    if !config.http.replay_dir.is_empty() {
+        let resp = recording::replay(&config.http.replay_dir, &request)?;
+        return check_rate_limited(&host, resp);
+    }
+    let recorded = (!config.http.record_dir.is_empty()).then(|| RecordedRequest::new(&request));
+    if circuit_open(config, &host) {
+        return Err(HttpError::CircuitOpen(host));
+    }
+    let mut attempt = 0;
+    let result = loop {
+        wait_for_rate_limit(config, &host).await;
+        // streaming bodies can't be cloned, those requests get a single attempt
+        let retry = (attempt < config.http.max_retries)
+            .then(|| request.try_clone())
+            .flatten();
+        let Some(retry) = retry else {
+            break client.execute(request).await;
+        };
+        let result = client.execute(retry).await;
+        if !is_transient(request.method(), &result) {
+            break result;
+        }
+        let delay = retry_delay(config, attempt, &result);
+        // not logging the url, some contain secrets (the Telegram bot token)
+        warn!(
+            "{} request to {} failed ({}), retrying in {:?}",
+            request.method(),
+            host,
+            match &result {
+                Ok(resp) => resp.status().to_string(),
+                Err(_) => "timed out".to_string(),
+            },
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    };
+    // a throttling host is up, being rate limited is waited out instead
+    let failed = match &result {
+        Ok(resp) => resp.status().is_server_error(),
+        Err(e) => e.is_timeout() || e.is_connect(),
+    };
+    record_outcome(config, &host, failed);
+    let resp = match recorded {
+        Some(recorded) => recording::record(&config.http.record_dir, recorded, result?).await?,
+        None => result?,
+    };
+    check_rate_limited(&host, resp)
+}
+
+/// Stream the items of a paginated listing. `fetch` gets the cursor of a page and
+/// returns its items along with the cursor of the next page, if there is one.
+/// Stops at an empty page or after `http.max_paginated_items` items, so a runaway
+/// feed can neither exhaust memory nor be followed forever.
+pub fn paginate<'a, T, C, E, F, Fut>(
+    config: &'a Settings,
+    first: C,
+    fetch: F,
+) -> impl Stream<Item = Result<T, E>> + 'a
+where
+    T: 'a,
+    C: 'a,
+    E: 'a,
+    F: FnMut(C) -> Fut + 'a,
+    Fut: Future<Output = Result<(Vec<T>, Option<C>), E>> + 'a,
+{
+    let max_items = config.http.max_paginated_items;
+    stream::try_unfold((Some(first), fetch), |(cursor, mut fetch)| async move {
+        let Some(cursor) = cursor else {
+            return Ok(None);
+        };
+        let (items, next) = fetch(cursor).await?;
+        let next = if items.is_empty() { None } else { next };
+        Ok(Some((stream::iter(items).map(Ok), (next, fetch))))
+    })
+    .try_flatten()
+    .enumerate()
+    .map(move |(i, item)| {
+        if i + 1 == max_items {
+            warn!(
+                "Reached http.max_paginated_items ({}), ignoring the rest of the listing",
+                max_items
+            );
+        }
+        item
+    })
+    .take(max_items)
+}
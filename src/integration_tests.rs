@@ -0,0 +1,453 @@
+//! End to end tests of auto-mirroring, resolution sync and managram processing
+//! against fake APIs. Run with `cargo test --features test-support`.
+
+use futures::TryStreamExt;
+use reqwest::Client;
+
+use crate::{
+    db::{self, ManagramState},
+    http,
+    kalshi::KalshiSource,
+    managrams,
+    metaculus::{self, ProjectFilter},
+    mirror,
+    settings::{MetaculusProject, Settings},
+    test_support::{self, FakeApis},
+    types::{BinaryResolution, Question, QuestionSource},
+};
+
+fn client(config: &Settings) -> Client {
+    http::client(config).unwrap()
+}
+
+#[tokio::test]
+async fn auto_mirror_creates_kalshi_mirror() {
+    let apis = FakeApis::start().await;
+    apis.kalshi_market(test_support::kalshi_market("TICK"))
+        .await;
+    apis.manifold_creates(test_support::manifold_market(
+        "m1",
+        "[Kalshi] Will it happen?",
+    ))
+    .await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+
+    let result = mirror::auto_mirror::<KalshiSource>(&client(&config), &db, &config, false, None)
+        .await
+        .unwrap();
+
+    assert_eq!(result.candidates, 1);
+    assert_eq!(result.created.len(), 1);
+    let mirror = db::get_mirror_by_source_id(&db, &QuestionSource::Kalshi, "TICK")
+        .unwrap()
+        .expect("mirror should be recorded");
+    assert_eq!(mirror.manifold_contract_id, "m1");
+    let created = apis.manifold_requests("/v0/market/").await;
+    assert_eq!(created.len(), 1);
+    assert_eq!(created[0]["question"], "[Kalshi] Will it happen?");
+    assert_eq!(created[0]["initialProb"], 42);
+}
+
+#[tokio::test]
+async fn auto_mirror_skips_mirrored_questions() {
+    let apis = FakeApis::start().await;
+    apis.kalshi_market(test_support::kalshi_market("TICK"))
+        .await;
+    apis.manifold_creates(test_support::manifold_market(
+        "m1",
+        "[Kalshi] Will it happen?",
+    ))
+    .await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+    mirror::auto_mirror::<KalshiSource>(&client, &db, &config, false, None)
+        .await
+        .unwrap();
+
+    let result = mirror::auto_mirror::<KalshiSource>(&client, &db, &config, false, None)
+        .await
+        .unwrap();
+
+    assert_eq!(result.candidates, 0);
+    assert_eq!(apis.manifold_requests("/v0/market/").await.len(), 1);
+}
+
+#[tokio::test]
+async fn project_auto_mirror_uses_header_group_and_daily_cap() {
+    let apis = FakeApis::start().await;
+    apis.metaculus_listing(vec![
+        test_support::metaculus_post(1),
+        test_support::metaculus_post(2),
+    ])
+    .await;
+    apis.manifold_creates(test_support::manifold_market("m1", "[ACX] Will X happen?"))
+        .await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+    let project = MetaculusProject {
+        id: 77,
+        header: "ACX".to_string(),
+        group_id: "acx-group".to_string(),
+        filter: ProjectFilter::Off,
+        max_clones_per_day: 1,
+    };
+    mirror::auto_mirror_project(&client, &db, &config, &project, false, None)
+        .await
+        .unwrap();
+
+    let result = mirror::auto_mirror_project(&client, &db, &config, &project, false, None)
+        .await
+        .unwrap();
+
+    assert_eq!(result.candidates, 1);
+    assert!(result.created.is_empty());
+    assert_eq!(db::count_project_mirrors(&db, 77).unwrap(), (1, 1));
+    let created = apis.manifold_requests("/v0/market/").await;
+    assert_eq!(created.len(), 1);
+    assert_eq!(created[0]["question"], "[ACX] Will X happen?");
+    assert!(created[0]["groupIds"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("acx-group")));
+}
+
+#[tokio::test]
+async fn metaculus_listing_waits_out_rate_limits() {
+    let apis = FakeApis::start().await;
+    apis.metaculus_throttles(1).await;
+    apis.metaculus_listing(vec![test_support::metaculus_post(1)])
+        .await;
+    let mut config = apis.settings();
+    config.http.max_retries = 0;
+    let client = client(&config);
+    let params = || metaculus::MetaculusListQuestionsParams::default();
+
+    config.metaculus.max_rate_limit_wait_secs = 0;
+    let throttled: anyhow::Result<Vec<_>> = metaculus::stream_questions(&client, params(), &config)
+        .try_collect()
+        .await;
+    config.metaculus.max_rate_limit_wait_secs = 10;
+    apis.metaculus_throttles(1).await;
+    let resumed: Vec<_> = metaculus::stream_questions(&client, params(), &config)
+        .try_collect()
+        .await
+        .unwrap();
+
+    assert!(throttled.is_err());
+    assert_eq!(resumed.len(), 1);
+}
+
+#[tokio::test]
+async fn polymarket_question_is_fetched_from_configured_api() {
+    let apis = FakeApis::start().await;
+    apis.polymarket_market(test_support::polymarket_market("will-it-happen"))
+        .await;
+    let config = apis.settings();
+
+    let (question, is_resolved) = mirror::fetch_question(
+        &client(&config),
+        &config,
+        QuestionSource::Polymarket,
+        "will-it-happen",
+    )
+    .await
+    .unwrap();
+
+    assert!(!is_resolved);
+    assert_eq!(question.source_id, "will-it-happen");
+    assert_eq!(question.question, "Will it happen?");
+}
+
+#[tokio::test]
+async fn kalshi_member_session_is_renewed_and_shows_orderbook() {
+    let apis = FakeApis::start().await;
+    apis.kalshi_market(test_support::kalshi_market("TICK"))
+        .await;
+    apis.kalshi_member_api(serde_json::json!({"yes": [[40, 100], [39, 50]], "no": null}))
+        .await;
+    apis.kalshi_rejects_once("/trade-api/v2/events/TICK").await;
+    let mut config = apis.settings();
+    config.kalshi.email = "bot@example.com".to_string();
+    config.kalshi.fetch_orderbooks = true;
+
+    let (question, _) =
+        mirror::fetch_question(&client(&config), &config, QuestionSource::Kalshi, "TICK")
+            .await
+            .unwrap();
+
+    assert_eq!(
+        question.market_summary.unwrap().resting_contracts,
+        Some((150, 0))
+    );
+    assert_eq!(apis.kalshi_request_count("/trade-api/v2/login").await, 2);
+}
+
+#[tokio::test]
+async fn sync_resolves_mirror_of_settled_kalshi_market() {
+    let apis = FakeApis::start().await;
+    apis.kalshi_market(test_support::settled_kalshi_market("TICK", "yes"))
+        .await;
+    apis.manifold_market(test_support::manifold_market(
+        "m1",
+        "[Kalshi] Will it happen?",
+    ))
+    .await;
+    apis.manifold_resolves().await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let market =
+        serde_json::from_value(test_support::manifold_market("m1", "Will it happen?")).unwrap();
+    let question = Question {
+        source: QuestionSource::Kalshi,
+        source_url: "https://kalshi.com/markets/SER#TICK".to_string(),
+        source_id: "TICK".to_string(),
+        question: "Will it happen?".to_string(),
+        criteria: None,
+        background: None,
+        end_date: chrono::Utc::now(),
+        close_time: None,
+        probability: None,
+        source_payload: None,
+        market_summary: None,
+        forecast_history: Vec::new(),
+    };
+    let row =
+        db::insert_mirror(&db, &market, &question, chrono::Utc::now(), None, &config).unwrap();
+
+    let resolved = mirror::sync_resolutions_to_manifold(
+        &client(&config),
+        &db,
+        &config,
+        Some(QuestionSource::Kalshi),
+        true,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(resolved, 1);
+    let row = db::get_mirror_by_id(&db, row.id).unwrap().unwrap();
+    assert_eq!(row.resolution, Some(BinaryResolution::Yes));
+    let resolutions = apis.manifold_requests("/v0/market/m1/resolve/").await;
+    assert_eq!(resolutions.len(), 1);
+    assert_eq!(resolutions[0]["outcome"], "YES");
+}
+
+#[tokio::test]
+async fn managram_mirror_request_creates_mirror_and_returns_change() {
+    let apis = FakeApis::start().await;
+    apis.metaculus_post(test_support::metaculus_post(123)).await;
+    apis.manifold_creates(test_support::manifold_market(
+        "m1",
+        "[Metaculus] Will X happen?",
+    ))
+    .await;
+    apis.manifold_managrams(serde_json::json!([test_support::managram(
+        "g1",
+        "user-1",
+        1100.0,
+        "mirror https://www.metaculus.com/questions/123/"
+    )]))
+    .await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+
+    managrams::sync_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+    managrams::process_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+
+    let mirror = db::get_mirror_by_source_id(&db, &QuestionSource::Metaculus, "123")
+        .unwrap()
+        .expect("mirror should be recorded");
+    assert_eq!(mirror.manifold_contract_id, "m1");
+    assert_eq!(mirror.requested_by.as_deref(), Some("user-1"));
+    let created = apis.manifold_requests("/v0/market/").await;
+    let description = created[0]["descriptionMarkdown"].as_str().unwrap();
+    assert!(description.contains("**Background**\n\nX has been discussed for years."));
+    assert!(description.contains("` 30% → 37%"));
+    assert!(db::get_managrams_by_state(&db, ManagramState::Succeeded)
+        .unwrap()
+        .iter()
+        .any(|managram| managram.id == "g1"));
+    let sent = apis.manifold_requests("/v0/managram/").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["toIds"], serde_json::json!(["user-1"]));
+    assert_eq!(
+        sent[0]["amount"],
+        1100.0 - config.manifold.managrams.mirror_cost
+    );
+}
+
+#[tokio::test]
+async fn failed_response_after_mirror_is_created_is_not_retried() {
+    let apis = FakeApis::start().await;
+    apis.metaculus_post(test_support::metaculus_post(123)).await;
+    apis.manifold_creates(test_support::manifold_market(
+        "m1",
+        "[Metaculus] Will X happen?",
+    ))
+    .await;
+    apis.manifold_managrams(serde_json::json!([test_support::managram(
+        "g1",
+        "user-1",
+        1100.0,
+        "mirror https://www.metaculus.com/questions/123/"
+    )]))
+    .await;
+    apis.manifold_managram_fails_once().await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+
+    managrams::sync_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+    managrams::process_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+
+    let row = db::get_managram(&db, "g1").unwrap().unwrap();
+    assert_eq!(row.state, ManagramState::Succeeded);
+    assert!(row.error.is_some());
+    assert!(db::get_managrams_to_process(&db).unwrap().is_empty());
+    assert_eq!(apis.manifold_requests("/v0/market/").await.len(), 1);
+}
+
+#[tokio::test]
+async fn failed_response_is_left_to_recovery() {
+    let apis = FakeApis::start().await;
+    apis.manifold_managrams(serde_json::json!([test_support::managram(
+        "g1", "user-1", 10.0, "ping"
+    )]))
+    .await;
+    apis.manifold_managram_fails_once().await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+
+    managrams::sync_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+    managrams::process_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        db::get_managram(&db, "g1").unwrap().unwrap().state,
+        ManagramState::Succeeded
+    );
+    assert!(db::get_managrams_to_process(&db).unwrap().is_empty());
+    assert!(
+        db::get_incomplete_action(&db, db::ActionKind::SendManagram, "g1")
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn managram_with_too_little_mana_is_refunded() {
+    let apis = FakeApis::start().await;
+    apis.manifold_managrams(serde_json::json!([test_support::managram(
+        "g1",
+        "user-1",
+        50.0,
+        "mirror https://www.metaculus.com/questions/123/"
+    )]))
+    .await;
+    let config = apis.settings();
+    let db = test_support::memory_db();
+    let client = client(&config);
+
+    managrams::sync_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+    managrams::process_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+
+    assert!(
+        db::get_mirror_by_source_id(&db, &QuestionSource::Metaculus, "123")
+            .unwrap()
+            .is_none()
+    );
+    let sent = apis.manifold_requests("/v0/managram/").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["amount"], 50.0);
+}
+
+#[tokio::test]
+async fn interrupted_managrams_are_not_refunded_as_stale() {
+    let apis = FakeApis::start().await;
+    let week_ago = (chrono::Utc::now() - chrono::Duration::days(7)).timestamp_millis();
+    let mut page = serde_json::json!([
+        test_support::managram("g1", "user-1", 100.0, "ping"),
+        test_support::managram("g2", "user-2", 100.0, "ping"),
+    ]);
+    for managram in page.as_array_mut().unwrap() {
+        managram["createdTime"] = week_ago.into();
+    }
+    apis.manifold_managrams(page).await;
+    let mut config = apis.settings();
+    config.manifold.managrams.stale_refund_days = 1;
+    let db = test_support::memory_db();
+    let client = client(&config);
+    managrams::sync_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+    let interrupted = Some(db::INTERRUPTED_MANAGRAM_ERROR);
+    db::set_managram_state(&db, "g1", ManagramState::Failed, interrupted).unwrap();
+    db::set_managram_state(&db, "g2", ManagramState::Failed, Some("timed out")).unwrap();
+
+    managrams::refund_stale_managrams(&client, &db, &config)
+        .await
+        .unwrap();
+
+    let sent = apis.manifold_requests("/v0/managram/").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["toIds"], serde_json::json!(["user-2"]));
+}
+
+#[tokio::test]
+async fn recorded_responses_replay_without_the_apis() {
+    let dir = std::env::temp_dir().join(format!(
+        "mirror_bot-recording-{}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    ));
+    let apis = FakeApis::start().await;
+    apis.kalshi_market(test_support::kalshi_market("TICK"))
+        .await;
+    let mut config = apis.settings();
+    config.http.record_dir = dir.display().to_string();
+    let recorded = mirror::auto_mirror::<KalshiSource>(
+        &client(&config),
+        &test_support::memory_db(),
+        &config,
+        true,
+        None,
+    )
+    .await
+    .unwrap();
+    drop(apis);
+    config.http.record_dir = String::new();
+    config.http.replay_dir = dir.display().to_string();
+
+    let replayed = mirror::auto_mirror::<KalshiSource>(
+        &client(&config),
+        &test_support::memory_db(),
+        &config,
+        true,
+        None,
+    )
+    .await
+    .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(recorded.candidates, 1);
+    assert_eq!(replayed.candidates, recorded.candidates);
+}
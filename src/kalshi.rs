@@ -1,34 +1,235 @@
-use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use chrono::{DateTime, Duration, Utc};
-use log::{debug, info};
-use reqwest::blocking::{Client, Response};
-use reqwest::StatusCode;
+use futures::{stream, StreamExt, TryStreamExt};
+use log::{debug, info, warn};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::pss::BlindedSigningKey;
+use rsa::rand_core::OsRng;
+use rsa::sha2::Sha256;
+use rsa::signature::{RandomizedSigner, SignatureEncoding};
+use rsa::RsaPrivateKey;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
 
+use crate::http::{self, HttpError};
+use crate::mirror::MirrorError;
 use crate::settings::{KalshiQuestionRequirements, Settings};
-use crate::types::{BinaryResolution, Question, QuestionSource};
+use crate::source::MirrorSource;
+use crate::types::{BinaryResolution, MarketSummary, Question, QuestionSource, RequirementCheck};
+
+/// Session from logging in with `kalshi.email`, shared by all requests of the
+/// process. Replaced when it gets old or Kalshi stops accepting it.
+static SESSION: Mutex<Option<Session>> = Mutex::const_new(None);
+
+struct Session {
+    /// value of the Authorization header
+    token: String,
+    logged_in: Instant,
+}
+
+/// No query parameters, for [`send`]
+const NO_QUERY: &[(&str, &str)] = &[];
+
+/// Key for signing requests, loaded from `kalshi.private_key_path` on first use
+static SIGNING_KEY: OnceLock<BlindedSigningKey<Sha256>> = OnceLock::new();
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    member_id: String,
+    token: String,
+}
+
+async fn login(client: &Client, config: &Settings) -> Result<String, KalshiError> {
+    info!("Logging in to Kalshi as {}", config.kalshi.email);
+    let resp = http::send(
+        config,
+        client
+            .post(format!("{}login", config.kalshi.api_url))
+            .json(&json!({
+                "email": config.kalshi.email,
+                "password": config.kalshi.password,
+            })),
+    )
+    .await?;
+    let resp: LoginResponse = parse_response(resp).await?;
+    Ok(format!("{} {}", resp.member_id, resp.token))
+}
+
+/// Token of the current session, logging in if there is none, it's older than
+/// `kalshi.session_refresh_minutes`, or `rejected` is the token Kalshi refused
+async fn session_token(
+    client: &Client,
+    config: &Settings,
+    rejected: Option<&str>,
+) -> Result<String, KalshiError> {
+    let max_age = std::time::Duration::from_secs(config.kalshi.session_refresh_minutes * 60);
+    let mut session = SESSION.lock().await;
+    let valid = session.as_ref().filter(|session| {
+        session.logged_in.elapsed() < max_age && Some(session.token.as_str()) != rejected
+    });
+    if let Some(session) = valid {
+        return Ok(session.token.clone());
+    }
+    let token = login(client, config).await?;
+    *session = Some(Session {
+        token: token.clone(),
+        logged_in: Instant::now(),
+    });
+    Ok(token)
+}
+
+/// Whether requests are made with a member login or API key
+pub fn is_authenticated(config: &Settings) -> bool {
+    !config.kalshi.api_key_id.is_empty() || !config.kalshi.email.is_empty()
+}
+
+fn signing_key(config: &Settings) -> Result<&'static BlindedSigningKey<Sha256>, KalshiError> {
+    if let Some(key) = SIGNING_KEY.get() {
+        return Ok(key);
+    }
+    let path = &config.kalshi.private_key_path;
+    let pem = std::fs::read_to_string(path)
+        .map_err(|e| KalshiError::InvalidPrivateKey(format!("failed to read {}: {}", path, e)))?;
+    // Kalshi hands out PKCS#1 keys, but accept PKCS#8 as well
+    let key = RsaPrivateKey::from_pkcs1_pem(&pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs8_pem(&pem))
+        .map_err(|e| KalshiError::InvalidPrivateKey(format!("failed to parse {}: {}", path, e)))?;
+    Ok(SIGNING_KEY.get_or_init(|| BlindedSigningKey::new(key)))
+}
 
-fn list_questions(
+/// Send a GET request to `path` (relative to `kalshi.api_url`), authenticated
+/// with the API key or member login if one is configured. A session Kalshi no
+/// longer accepts is replaced by logging in again, once.
+async fn send(
     client: &Client,
-    params: &KalshiListQuestionsParams,
+    config: &Settings,
+    path: &str,
+    query: &(impl Serialize + ?Sized),
+) -> Result<Response, KalshiError> {
+    let (builder, token) = request(client, config, Method::GET, path, None).await?;
+    let resp = http::send(config, builder.query(query)).await?;
+    let Some(token) = token.filter(|_| resp.status() == StatusCode::UNAUTHORIZED) else {
+        return Ok(resp);
+    };
+    warn!("Kalshi rejected the session token, logging in again");
+    let (builder, _) = request(client, config, Method::GET, path, Some(&token)).await?;
+    Ok(http::send(config, builder.query(query)).await?)
+}
+
+/// Build a request to `path`, along with the session token it carries if it's
+/// authenticated with the member login. `rejected` forces a new session.
+async fn request(
+    client: &Client,
+    config: &Settings,
+    method: Method,
+    path: &str,
+    rejected: Option<&str>,
+) -> Result<(RequestBuilder, Option<String>), KalshiError> {
+    let kalshi = &config.kalshi;
+    let url = format!("{}{}", kalshi.api_url, path);
+    let mut builder = client.request(method.clone(), &url);
+    if !kalshi.api_key_id.is_empty() {
+        // the signature covers the timestamp, method and path without the query
+        let path = Url::parse(&url)
+            .map_err(|_| KalshiError::InvalidUrl(url.clone()))?
+            .path()
+            .to_string();
+        let timestamp = Utc::now().timestamp_millis().to_string();
+        let signature = signing_key(config)?.sign_with_rng(
+            &mut OsRng,
+            format!("{}{}{}", timestamp, method, path).as_bytes(),
+        );
+        builder = builder
+            .header("KALSHI-ACCESS-KEY", &kalshi.api_key_id)
+            .header("KALSHI-ACCESS-TIMESTAMP", timestamp)
+            .header(
+                "KALSHI-ACCESS-SIGNATURE",
+                BASE64.encode(signature.to_bytes()),
+            );
+    } else if !kalshi.email.is_empty() {
+        let token = session_token(client, config, rejected).await?;
+        builder = builder.header(AUTHORIZATION, &token);
+        return Ok((builder, Some(token)));
+    }
+    Ok((builder, None))
+}
+
+async fn list_events(
+    client: &Client,
+    params: &KalshiListEventsParams,
+    config: &Settings,
 ) -> Result<KalshiEventListResponse, KalshiError> {
     debug!(
-        "kalshi::list_questions called (page {})",
-        params.page_number.unwrap_or(1)
+        "kalshi::list_events called (cursor {:?})",
+        params.cursor.as_deref().unwrap_or_default()
     );
-    let resp = client
-        .get("https://trading-api.kalshi.com/v1/events/")
-        .query(&params)
-        .send()?;
-    parse_response(resp)
+    let resp = send(client, config, "events", params).await?;
+    parse_response(resp).await
 }
 
-pub fn get_question(
+/// Full orderbook of a market. Anonymous requests only get part of it, if any.
+pub async fn get_orderbook(
+    client: &Client,
+    ticker: &str,
+    config: &Settings,
+) -> Result<Orderbook, KalshiError> {
+    let resp = send(
+        client,
+        config,
+        &format!("markets/{}/orderbook", ticker),
+        NO_QUERY,
+    )
+    .await?;
+    let resp: KalshiOrderbookResponse = parse_response(resp).await?;
+    Ok(resp.orderbook)
+}
+
+/// Make a minimal request to check that the API is reachable
+pub async fn check_api(client: &Client, config: &Settings) -> Result<(), KalshiError> {
+    list_events(
+        client,
+        &KalshiListEventsParams {
+            limit: Some(1),
+            ..Default::default()
+        },
+        config,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Fetch the settlement sources of a series, which v2 no longer includes with events
+async fn get_settlement_sources(
+    client: &Client,
+    series_ticker: &str,
+    config: &Settings,
+) -> Result<Vec<SettlementSource>, KalshiError> {
+    let resp = send(
+        client,
+        config,
+        &format!("series/{}", series_ticker),
+        NO_QUERY,
+    )
+    .await?;
+    let resp: KalshiSeriesResponse = parse_response(resp).await?;
+    Ok(resp.series.settlement_sources)
+}
+
+pub async fn get_question(
     client: &Client,
     input_ticker: &str,
-    _config: &Settings,
+    config: &Settings,
 ) -> Result<KalshiMarket, KalshiError> {
     // As input validation, ensure only alphanumeric and "-" and "." are used
     if !input_ticker
@@ -43,153 +244,241 @@ pub fn get_question(
     // the JSON. Their URLs use lowercase by default, so user input is likely
     // to need the uppercase conversion.
     let uppercase_ticker = input_ticker.to_uppercase();
-    let resp = client
-        .get(format!(
-            "https://trading-api.kalshi.com/v1/events/{}/",
-            uppercase_ticker
-        ))
-        .send()?;
-    let resp: KalshiEventResponse = parse_response(resp)?;
-    return (&resp.event).try_into();
+    let resp = send(
+        client,
+        config,
+        &format!("events/{}", uppercase_ticker),
+        NO_QUERY,
+    )
+    .await?;
+    let resp: KalshiEventResponse = parse_response(resp).await?;
+    let event = Event {
+        markets: resp.markets,
+        ..resp.event
+    };
+    let mut market: KalshiMarket = (&event).try_into()?;
+    market.settlement_sources =
+        get_settlement_sources(client, &event.series_ticker, config).await?;
+    Ok(market)
 }
 
-pub fn get_mirror_candidates(client: &Client, config: &Settings) -> Result<Vec<KalshiMarket>> {
+/// Questions that passed the auto filter, and those rejected along with the reason
+pub type MirrorCandidates = (Vec<KalshiMarket>, Vec<(KalshiMarket, KalshiCheckFailure)>);
+
+/// Fetch markets for auto-mirroring. Returns markets passing the auto filter,
+/// and those that were rejected along with the reason.
+pub async fn get_mirror_candidates(client: &Client, config: &Settings) -> Result<MirrorCandidates> {
     info!("Fetching mirror candidates from Kalshi");
     let requirements = &config.kalshi.auto_filter;
-    let mut params = KalshiListQuestionsParams {
-        single_event_per_series: Some(requirements.single_event_per_series),
-        page_size: Some(200),
-        page_number: Some(1),
+    let params = KalshiListEventsParams {
+        limit: Some(200),
+        with_nested_markets: Some(true),
+        // TODO: use enum?
+        status: requirements.require_open.then(|| "open".to_string()),
         ..Default::default()
     };
-    if requirements.require_open {
-        params.status = Some("open".to_string()); // TODO: use enum?
-    }
-    let mut events = Vec::new();
-    loop {
-        let resp = list_questions(client, &params)?;
-        // single_event_per_series, and perhaps other filtering parameters, are
-        // applied after the server limits to page_size, such that fewer events
-        // than page_size may be returned. Strictly speaking, checking for len()
-        // == 0 is not sufficient to know there are no more events on later
-        // pages, but it's a good enough heuristic.
-        if resp.events.len() == 0 {
-            break;
+    let events: Vec<Event> = http::paginate(config, None, |cursor| {
+        let params = KalshiListEventsParams {
+            cursor,
+            ..params.clone()
+        };
+        async move {
+            let resp = list_events(client, &params, config).await?;
+            let next = resp.cursor.filter(|c| !c.is_empty());
+            Ok::<_, KalshiError>((resp.events, next.map(Some)))
         }
-        events.extend(resp.events.into_iter());
-        *params.page_number.as_mut().unwrap() += 1;
-    }
+    })
+    .try_collect()
+    .await?;
     info!("{} events listed via Kalshi API", events.len());
-    let markets = events
-        .into_iter()
+    // v2 has no single_event_per_series parameter, keep the first listed event
+    // of each series instead
+    let mut seen_series = HashSet::new();
+    let events = events.into_iter().filter(|event| {
+        !requirements.single_event_per_series || seen_series.insert(event.series_ticker.clone())
+    });
+    let mut markets = Vec::new();
+    let mut rejected = Vec::new();
+    for market in events
         .filter(|e| !e.is_multimarket())
-        .filter_map(|event| (&event).try_into().ok())
-        .filter(|q| check_market_requirements(q, requirements).is_ok())
-        .collect::<Vec<KalshiMarket>>();
-
-    Ok(markets)
+        .filter_map(|event| -> Option<KalshiMarket> { (&event).try_into().ok() })
+    {
+        match check_market_requirements(&market, requirements) {
+            Ok(()) => markets.push(market),
+            Err(reason) => rejected.push((market, reason)),
+        }
+    }
+    // only look up settlement sources for markets that may get mirrored
+    let markets = stream::iter(markets)
+        .map(|mut market| async move {
+            market.settlement_sources =
+                get_settlement_sources(client, &market.series_ticker, config).await?;
+            Ok::<_, KalshiError>(market)
+        })
+        .buffered(config.http.max_concurrent_requests.get())
+        .try_collect()
+        .await?;
+    Ok((markets, rejected))
 }
 
 pub fn check_market_requirements(
     market: &KalshiMarket,
     requirements: &KalshiQuestionRequirements,
 ) -> Result<(), KalshiCheckFailure> {
-    // config requirements
-    if requirements.require_open && !market.is_active() {
-        return Err(KalshiCheckFailure::NotActive);
-    }
-    if requirements.exclude_resolved && market.is_resolved() {
-        return Err(KalshiCheckFailure::Resolved);
-    }
-    // Min liquidity
-    if market.liquidity < requirements.min_liquidity {
-        return Err(KalshiCheckFailure::NotEnoughLiquidity {
-            liquidity: market.liquidity,
-            threshold: requirements.min_liquidity,
-        });
-    }
-    // Min volume
-    if market.volume < requirements.min_volume {
-        return Err(KalshiCheckFailure::NotEnoughVolume {
-            volume: market.volume,
-            threshold: requirements.min_volume,
-        });
-    }
-    // Min recent volume
-    if market.recent_volume < requirements.min_recent_volume {
-        return Err(KalshiCheckFailure::NotEnoughRecentVolume {
-            recent_volume: market.recent_volume,
-            threshold: requirements.min_recent_volume,
-        });
-    }
-    // Min open interest
-    if market.open_interest < requirements.min_open_interest {
-        return Err(KalshiCheckFailure::NotEnoughOpenInterest {
-            open_interest: market.open_interest,
-            threshold: requirements.min_open_interest,
-        });
-    }
-    // min dollar volume
-    if market.dollar_volume < requirements.min_dollar_volume {
-        return Err(KalshiCheckFailure::NotEnoughDollarVolume {
-            dollar_volume: market.dollar_volume,
-            threshold: requirements.min_dollar_volume,
-        });
-    }
-    // min dollar recent volume
-    if market.dollar_recent_volume < requirements.min_dollar_recent_volume {
-        return Err(KalshiCheckFailure::NotEnoughDollarRecentVolume {
-            dollar_recent_volume: market.dollar_recent_volume,
-            threshold: requirements.min_dollar_recent_volume,
-        });
-    }
-    // min dollar open interest
-    if market.dollar_open_interest < requirements.min_dollar_open_interest {
-        return Err(KalshiCheckFailure::NotEnoughDollarOpenInterest {
-            dollar_open_interest: market.dollar_open_interest,
-            threshold: requirements.min_dollar_open_interest,
-        });
-    }
-
-    if market.time_to_resolution() < Duration::days(requirements.min_days_to_resolution) {
-        return Err(KalshiCheckFailure::ResolvesTooSoon {
-            days_remaining: market.time_to_resolution().num_days(),
-            threshold: requirements.min_days_to_resolution,
-        });
-    }
-    if market.time_to_resolution() > Duration::days(requirements.max_days_to_resolution) {
-        return Err(KalshiCheckFailure::ResolvesTooLate {
-            days_remaining: market.time_to_resolution().num_days(),
-            threshold: requirements.max_days_to_resolution,
-        });
-    }
-    if market.age() > Duration::days(requirements.max_age_days) {
-        return Err(KalshiCheckFailure::TooOld {
-            age_days: market.age().num_days(),
-            threshold: requirements.max_age_days,
-        });
-    }
-    if (100 - market.yes_ask) as f64 > requirements.max_confidence * 100.0
-        || market.yes_bid as f64 > requirements.max_confidence * 100.0
-    {
-        return Err(KalshiCheckFailure::TooExtreme {
-            yes_ask: market.yes_ask,
-            yes_bid: market.yes_bid,
-            threshold: requirements.max_confidence,
-        });
-    }
-    if requirements.exclude_ids.contains(market.id()) {
-        return Err(KalshiCheckFailure::Banned);
-    }
+    RequirementCheck::first_failure(evaluate_market_requirements(market, requirements))
+}
 
-    Ok(())
+/// Evaluate every requirement, in the order [`check_market_requirements`] applies them
+pub fn evaluate_market_requirements(
+    market: &KalshiMarket,
+    requirements: &KalshiQuestionRequirements,
+) -> Vec<RequirementCheck<KalshiCheckFailure>> {
+    use KalshiCheckFailure::*;
+    let days_to_resolution = market.time_to_resolution().num_days();
+    vec![
+        // config requirements
+        RequirementCheck::new(
+            "require_open",
+            format!("{:?}", market.status),
+            requirements.require_open,
+            !requirements.require_open || market.is_active(),
+            || NotActive,
+        ),
+        RequirementCheck::new(
+            "exclude_resolved",
+            format!("{:?}", market.status),
+            requirements.exclude_resolved,
+            !requirements.exclude_resolved || !market.is_resolved(),
+            || Resolved,
+        ),
+        RequirementCheck::new(
+            "min_liquidity",
+            market.liquidity,
+            requirements.min_liquidity,
+            market.liquidity >= requirements.min_liquidity,
+            || NotEnoughLiquidity {
+                liquidity: market.liquidity,
+                threshold: requirements.min_liquidity,
+            },
+        ),
+        RequirementCheck::new(
+            "min_volume",
+            market.volume,
+            requirements.min_volume,
+            market.volume >= requirements.min_volume,
+            || NotEnoughVolume {
+                volume: market.volume,
+                threshold: requirements.min_volume,
+            },
+        ),
+        RequirementCheck::new(
+            "min_recent_volume",
+            market.recent_volume,
+            requirements.min_recent_volume,
+            market.recent_volume >= requirements.min_recent_volume,
+            || NotEnoughRecentVolume {
+                recent_volume: market.recent_volume,
+                threshold: requirements.min_recent_volume,
+            },
+        ),
+        RequirementCheck::new(
+            "min_open_interest",
+            market.open_interest,
+            requirements.min_open_interest,
+            market.open_interest >= requirements.min_open_interest,
+            || NotEnoughOpenInterest {
+                open_interest: market.open_interest,
+                threshold: requirements.min_open_interest,
+            },
+        ),
+        RequirementCheck::new(
+            "min_dollar_volume",
+            market.dollar_volume,
+            requirements.min_dollar_volume,
+            market.dollar_volume >= requirements.min_dollar_volume,
+            || NotEnoughDollarVolume {
+                dollar_volume: market.dollar_volume,
+                threshold: requirements.min_dollar_volume,
+            },
+        ),
+        RequirementCheck::new(
+            "min_dollar_recent_volume",
+            market.dollar_recent_volume,
+            requirements.min_dollar_recent_volume,
+            market.dollar_recent_volume >= requirements.min_dollar_recent_volume,
+            || NotEnoughDollarRecentVolume {
+                dollar_recent_volume: market.dollar_recent_volume,
+                threshold: requirements.min_dollar_recent_volume,
+            },
+        ),
+        RequirementCheck::new(
+            "min_dollar_open_interest",
+            market.dollar_open_interest,
+            requirements.min_dollar_open_interest,
+            market.dollar_open_interest >= requirements.min_dollar_open_interest,
+            || NotEnoughDollarOpenInterest {
+                dollar_open_interest: market.dollar_open_interest,
+                threshold: requirements.min_dollar_open_interest,
+            },
+        ),
+        RequirementCheck::new(
+            "min_days_to_resolution",
+            days_to_resolution,
+            requirements.min_days_to_resolution,
+            market.time_to_resolution() >= Duration::days(requirements.min_days_to_resolution),
+            || ResolvesTooSoon {
+                days_remaining: days_to_resolution,
+                threshold: requirements.min_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_days_to_resolution",
+            days_to_resolution,
+            requirements.max_days_to_resolution,
+            market.time_to_resolution() <= Duration::days(requirements.max_days_to_resolution),
+            || ResolvesTooLate {
+                days_remaining: days_to_resolution,
+                threshold: requirements.max_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_age_days",
+            market.age().num_days(),
+            requirements.max_age_days,
+            market.age() <= Duration::days(requirements.max_age_days),
+            || TooOld {
+                age_days: market.age().num_days(),
+                threshold: requirements.max_age_days,
+            },
+        ),
+        RequirementCheck::new(
+            "max_confidence",
+            format!("bid {}¢ / ask {}¢", market.yes_bid, market.yes_ask),
+            requirements.max_confidence,
+            (100 - market.yes_ask) as f64 <= requirements.max_confidence * 100.0
+                && market.yes_bid as f64 <= requirements.max_confidence * 100.0,
+            || TooExtreme {
+                yes_ask: market.yes_ask,
+                yes_bid: market.yes_bid,
+                threshold: requirements.max_confidence,
+            },
+        ),
+        RequirementCheck::new(
+            "exclude_ids",
+            market.id(),
+            format!("{} excluded", requirements.exclude_ids.len()),
+            !requirements.exclude_ids.contains(market.id()),
+            || Banned,
+        ),
+    ]
 }
 
 /// helper function for parsing both success and error responses
-fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, KalshiError> {
+async fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, KalshiError> {
     if resp.status().is_success() {
         let body = resp
             .text()
+            .await
             .map_err(|_| KalshiError::UnexpectedResponseType)?;
         match serde_json::from_str(&body) {
             Ok(r) => Ok(r),
@@ -203,6 +492,7 @@ fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, KalshiError>
         let status = resp.status();
         let error_resp: KalshiErrorResponse = resp
             .json()
+            .await
             .map_err(|_| KalshiError::UnexpectedErrorType(status))?;
         match error_resp.code {
             KalshiErrorCode::NotFound => Err(KalshiError::NotFound(status, error_resp)),
@@ -221,7 +511,7 @@ impl KalshiMarket {
     }
 
     pub fn is_resolved(&self) -> bool {
-        self.status == Status::Finalized
+        matches!(self.status, Status::Determined | Status::Settled)
     }
 
     pub fn is_active(&self) -> bool {
@@ -241,33 +531,21 @@ impl KalshiMarket {
         )
     }
 
-    pub fn title(&self) -> String {
-        self.title.clone()
+    /// Midpoint of the yes bid and ask
+    pub fn probability(&self) -> f64 {
+        (self.yes_bid + self.yes_ask) as f64 / 200.0
     }
 
-    pub fn get_criteria_and_sources(&self) -> String {
-        format!(
-            "{}{}",
-            self.format_underlying_rulebook_variables(),
-            self.get_resolution_sources_markdown()
-        )
+    pub fn series_url(&self) -> String {
+        format!("https://kalshi.com/markets/{}", self.series_ticker)
     }
 
-    pub fn format_underlying_rulebook_variables(&self) -> String {
-        let mut return_string = self.underlying.clone();
-        // For each variable in market.rulebook_variables, substitute ||variable|| with the value of the variable
-        let rulebook = self.rulebook_variables.clone();
-        // For each key in rulebook
-        for key in rulebook.as_object().unwrap().keys() {
-            // Remove the surrounding "" marks
-            let mut replacement_value = rulebook[key].to_string();
-            replacement_value = replacement_value[1..replacement_value.len() - 1].to_string();
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
 
-            // We've seen instances with and without spaces
-            return_string = return_string.replace(&format!("||{}||", key), &replacement_value);
-            return_string = return_string.replace(&format!("|| {} ||", key), &replacement_value);
-        }
-        return return_string;
+    pub fn get_criteria_and_sources(&self) -> String {
+        format!("{}{}", self.rules, self.get_resolution_sources_markdown())
     }
 
     pub fn get_resolution_sources_markdown(&self) -> String {
@@ -292,6 +570,7 @@ impl KalshiMarket {
                 Some(KalshiResult::StillOpen) => {
                     bail!("Kalshi market is resolved but has no result")
                 }
+                Some(KalshiResult::Void) => bail!("Kalshi market was voided"),
                 None => bail!("Kalshi market is resolved but with an unexpected result"),
             }
         } else {
@@ -304,7 +583,7 @@ impl Event {
     pub fn is_multimarket(&self) -> bool {
         // If the market's ticker doesn't match the event's, then Kalshi might
         // add new markets to the event going forward, which is unsupported
-        return self.markets.len() != 1 || self.markets[0].ticker_name != self.ticker;
+        self.markets.len() != 1 || self.markets[0].ticker != self.event_ticker
     }
 }
 
@@ -317,15 +596,46 @@ impl TryInto<KalshiMarket> for &Event {
         if self.is_multimarket() {
             return Err(KalshiError::OnlySingleMarketsSupported(self.markets.len()));
         }
-        let mut market = self.markets[0].clone();
-
+        let mut market: KalshiMarket = self.markets[0].clone().into();
         market.series_ticker = self.series_ticker.clone();
-        market.underlying = self.underlying.clone();
-        market.settlement_sources = self.settlement_sources.clone();
         return Ok(market);
     }
 }
 
+impl From<Market> for KalshiMarket {
+    fn from(market: Market) -> Self {
+        // v2 only reports contract counts, value them at the last traded price
+        // (in cents, each contract pays out $1) to get dollar amounts
+        let dollars = |contracts: i64| contracts * market.last_price / 100;
+        let rules = if market.rules_secondary.is_empty() {
+            market.rules_primary.clone()
+        } else {
+            format!("{}\n\n{}", market.rules_primary, market.rules_secondary)
+        };
+        KalshiMarket {
+            dollar_volume: dollars(market.volume),
+            dollar_recent_volume: dollars(market.volume_24h),
+            dollar_open_interest: dollars(market.open_interest),
+            title: market.title,
+            ticker_name: market.ticker,
+            status: market.status,
+            open_date: market.open_time,
+            result: market.result,
+            yes_bid: market.yes_bid,
+            yes_ask: market.yes_ask,
+            expiration_date: market.expiration_time,
+            volume: market.volume,
+            recent_volume: market.volume_24h,
+            open_interest: market.open_interest,
+            liquidity: market.liquidity,
+            rules,
+            series_ticker: String::new(),
+            settlement_sources: Vec::new(),
+            resting_contracts: None,
+        }
+    }
+}
+
 impl TryInto<Question> for &KalshiMarket {
     type Error = anyhow::Error;
 
@@ -336,37 +646,208 @@ impl TryInto<Question> for &KalshiMarket {
             source_id: self.id().to_string(),
             question: self.title.clone(),
             criteria: Some(self.get_criteria_and_sources()),
+            background: None,
             end_date: self.expiration_date,
+            close_time: None,
+            probability: Some(self.probability()),
+            source_payload: serde_json::to_value(self).ok(),
+            market_summary: Some(MarketSummary {
+                price: None,
+                bid_ask: Some((self.yes_bid as f64 / 100.0, self.yes_ask as f64 / 100.0)),
+                volume: Some(self.dollar_volume as f64),
+                related_url: Some(self.series_url()),
+                resting_contracts: self.resting_contracts,
+            }),
+            forecast_history: Vec::new(),
         })
     }
 }
 
+/// [`MirrorSource`] for Kalshi markets
+pub struct KalshiSource;
+
+impl MirrorSource for KalshiSource {
+    type Question = KalshiMarket;
+    type Requirements = KalshiQuestionRequirements;
+    type CheckFailure = KalshiCheckFailure;
+
+    const SOURCE: QuestionSource = QuestionSource::Kalshi;
+
+    async fn fetch(
+        client: &Client,
+        config: &Settings,
+        id: &str,
+    ) -> Result<KalshiMarket, MirrorError> {
+        Ok(get_question(client, id, config).await?)
+    }
+
+    async fn candidates(
+        client: &Client,
+        config: &Settings,
+    ) -> Result<MirrorCandidates, MirrorError> {
+        Ok(get_mirror_candidates(client, config).await?)
+    }
+
+    /// Fetch the full orderbook for the description if configured to. Mirroring
+    /// goes on without it if that fails.
+    async fn prepare(
+        client: &Client,
+        config: &Settings,
+        market: &KalshiMarket,
+    ) -> Result<KalshiMarket, MirrorError> {
+        let mut market = market.clone();
+        if config.kalshi.fetch_orderbooks && is_authenticated(config) {
+            match get_orderbook(client, market.id(), config).await {
+                Ok(orderbook) => market.resting_contracts = Some(orderbook.resting_contracts()),
+                Err(e) => warn!("Failed to fetch the orderbook of {}: {:#}", market.id(), e),
+            }
+        }
+        Ok(market)
+    }
+
+    fn check(
+        market: &KalshiMarket,
+        requirements: &KalshiQuestionRequirements,
+    ) -> Vec<RequirementCheck<KalshiCheckFailure>> {
+        evaluate_market_requirements(market, requirements)
+    }
+
+    fn auto_filter(config: &Settings) -> Option<&KalshiQuestionRequirements> {
+        Some(&config.kalshi.auto_filter)
+    }
+
+    fn request_filter(config: &Settings) -> &KalshiQuestionRequirements {
+        &config.kalshi.request_filter
+    }
+
+    fn max_clones_per_day(config: &Settings) -> usize {
+        config.kalshi.max_clones_per_day
+    }
+
+    fn is_resolved(market: &KalshiMarket) -> bool {
+        market.is_resolved()
+    }
+
+    fn resolution(market: &KalshiMarket) -> Result<Option<BinaryResolution>> {
+        market.get_binary_resolution()
+    }
+
+    fn resolution_state(market: &KalshiMarket) -> String {
+        format!(
+            "Kalshi status {:?}, result {:?}",
+            market.status, market.result
+        )
+    }
+
+    fn probability(market: &KalshiMarket) -> Option<f64> {
+        Some(market.probability())
+    }
+
+    fn id(market: &KalshiMarket) -> String {
+        market.id().to_string()
+    }
+
+    fn title(market: &KalshiMarket) -> String {
+        market.title()
+    }
+
+    fn url(market: &KalshiMarket) -> String {
+        market.full_url()
+    }
+
+    fn to_question(market: &KalshiMarket) -> Result<Question> {
+        market
+            .try_into()
+            .with_context(|| "failed to convert Kalshi question to common format")
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct KalshiEventResponse {
     pub event: Event,
+    pub markets: Vec<Market>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct KalshiEventListResponse {
     pub events: Vec<Event>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KalshiOrderbookResponse {
+    orderbook: Orderbook,
+}
+
+/// Resting bids on each side as (price in cents, contracts), null when empty
+#[derive(Deserialize, Debug)]
+pub struct Orderbook {
+    #[serde(default)]
+    pub yes: Option<Vec<(i64, i64)>>,
+    #[serde(default)]
+    pub no: Option<Vec<(i64, i64)>>,
+}
+
+impl Orderbook {
+    /// Contracts bid on yes and on no
+    pub fn resting_contracts(&self) -> (i64, i64) {
+        let total = |bids: &Option<Vec<(i64, i64)>>| {
+            bids.iter().flatten().map(|(_, contracts)| contracts).sum()
+        };
+        (total(&self.yes), total(&self.no))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct KalshiSeriesResponse {
+    pub series: Series,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Event {
     pub series_ticker: String,
-    pub ticker: String,
-    pub markets: Vec<KalshiMarket>,
-    pub settlement_sources: Vec<SettlementSource>,
-    pub underlying: String,
+    pub event_ticker: String,
+    /// only included when listing with `with_nested_markets`
+    #[serde(default)]
+    pub markets: Vec<Market>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
+pub struct Series {
+    #[serde(default)]
+    pub settlement_sources: Vec<SettlementSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SettlementSource {
     pub name: String,
     pub url: String,
 }
 
+/// Market as returned by the v2 API, see [`KalshiMarket`] for the one we work with
 #[derive(Deserialize, Debug, Clone)]
+pub struct Market {
+    pub ticker: String,
+    pub title: String,
+    pub status: Status,
+    pub open_time: DateTime<Utc>,
+    pub expiration_time: DateTime<Utc>,
+    #[serde(default)]
+    pub result: Option<KalshiResult>,
+    pub yes_bid: i64,
+    pub yes_ask: i64,
+    pub last_price: i64,
+    pub volume: i64,
+    pub volume_24h: i64,
+    pub open_interest: i64,
+    pub liquidity: i64,
+    #[serde(default)]
+    pub rules_primary: String,
+    #[serde(default)]
+    pub rules_secondary: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct KalshiMarket {
     pub title: String,
     pub ticker_name: String,
@@ -377,45 +858,54 @@ pub struct KalshiMarket {
     pub yes_ask: i64,
     pub expiration_date: DateTime<Utc>, // Unsure if we should use close_date, which is earlier
     pub volume: i64,
+    /// volume of the last 24 hours
     pub recent_volume: i64,
     pub open_interest: i64,
+    /// the dollar amounts are estimates, see `From<Market>`
     pub dollar_volume: i64,
     pub dollar_recent_volume: i64,
     pub dollar_open_interest: i64,
     pub liquidity: i64,
-    pub rulebook_variables: serde_json::Value,
+    pub rules: String,
     #[serde(skip)]
     pub series_ticker: String,
     #[serde(skip)]
-    pub underlying: String,
-    #[serde(skip)]
     pub settlement_sources: Vec<SettlementSource>,
+    /// contracts resting on yes and on no bids, if the orderbook was fetched
+    #[serde(skip)]
+    pub resting_contracts: Option<(i64, i64)>,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Status {
-    //todo all options
+    #[serde(alias = "unopened")]
+    Initialized,
+    #[serde(alias = "open")]
     Active,
     Closed,
-    Finalized, // In GET parameters, use status=settled instead, even though "settled" never shows up in the json response
+    /// the result is known, but payouts haven't been made yet
+    Determined,
+    #[serde(alias = "finalized")]
+    Settled,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum KalshiResult {
     Yes,
     No,
+    Void,
     #[serde(rename = "")]
     StillOpen,
 }
 
-#[derive(Serialize, Debug, Default)]
-pub struct KalshiListQuestionsParams {
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct KalshiListEventsParams {
     pub status: Option<String>,
-    pub single_event_per_series: Option<bool>,
-    pub page_size: Option<i64>,
-    pub page_number: Option<i64>,
+    pub with_nested_markets: Option<bool>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -479,6 +969,12 @@ pub enum KalshiError {
     OnlySingleMarketsSupported(usize),
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[error(transparent)]
+    HttpError(#[from] HttpError),
+    #[error("invalid Kalshi private key: {}", .0)]
+    InvalidPrivateKey(String),
+    #[error("invalid Kalshi API url: {}", .0)]
+    InvalidUrl(String),
     #[error("Only alphanumeric, \"-\", and \".\" are allowed in ticker names (\"{}\" given)", .0)]
     IllegalTickerCharacters(String),
     // #[error(transparent)]
@@ -521,6 +1017,7 @@ impl<'de> Deserialize<'de> for KalshiErrorResponse {
             #[serde(default)]
             code: KalshiErrorCode,
             message: String,
+            #[serde(default)]
             service: String,
         }
         let tmp = Outer::deserialize(deserializer)?.error;
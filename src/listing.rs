@@ -0,0 +1,370 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::args::ListFormat;
+use crate::db::{
+    ManagramAuditRow, ManagramRow, MirrorRow, MirrorStatus, PendingResolutionRow, RejectionRow,
+    ResolutionEventRow, RunRow, ThirdPartyMirrorRow,
+};
+use crate::util;
+
+/// Longest question or title shown in a table column
+const MAX_TITLE_CHARS: usize = 60;
+/// Longest free-form text, e.g. errors, shown in a table column
+const MAX_TEXT_CHARS: usize = 40;
+
+/// Row printed by the `list` commands
+pub trait ListRow: Serialize {
+    /// Column headers for table output
+    const COLUMNS: &'static [&'static str];
+
+    /// Full description of the row for text output
+    fn text(&self) -> String;
+
+    /// Cells for table output, one per column
+    fn cells(&self) -> Vec<String>;
+}
+
+/// Print rows in the requested format.
+pub fn print_rows<T: ListRow>(rows: &[T], format: ListFormat) -> Result<()> {
+    match format {
+        ListFormat::Text => {
+            for row in rows {
+                println!("{}", row.text());
+            }
+        }
+        ListFormat::Table => print!(
+            "{}",
+            render_table(T::COLUMNS, rows.iter().map(T::cells).collect())
+        ),
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(rows)?),
+    }
+    Ok(())
+}
+
+/// Render left-aligned columns separated by two spaces, with a header line.
+fn render_table(columns: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header = columns.iter().map(|c| c.to_string()).collect();
+    let mut table = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn format_date(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+impl ListRow for MirrorRow {
+    const COLUMNS: &'static [&'static str] = &["ID", "SOURCE", "QUESTION", "CLOSES", "RESOLVED"];
+
+    fn text(&self) -> String {
+        let status = match (&self.resolution, self.resolved) {
+            _ if self.status == MirrorStatus::Retired => {
+                format!("retired at {}", format_time(self.resolved_time))
+            }
+            (Some(resolution), _) => format!(
+                "resolved {:?} at {}{}",
+                resolution,
+                format_time(self.resolved_time),
+                self.reopened_time
+                    .map(|t| format!(", source reopened at {}", format_time(Some(t))))
+                    .unwrap_or_default()
+            ),
+            (None, true) => "resolved".to_string(),
+            (None, false) => format!(
+                "unresolved, next check {}",
+                self.next_check_at
+                    .map_or_else(|| "due".to_string(), |t| format_time(Some(t)))
+            ),
+        };
+        format!(
+            "#{} {} {} \"{}\" ({}): cloned {}, closes {}, {}, last synced {}{}",
+            self.id,
+            self.source,
+            self.source_id,
+            self.question,
+            self.manifold_url,
+            self.clone_date.format("%Y-%m-%d"),
+            format_time(self.close_time),
+            status,
+            self.last_synced_at
+                .map_or_else(|| "never".to_string(), |t| format_time(Some(t))),
+            self.destination
+                .as_ref()
+                .map(|d| format!(", on account {}", d))
+                .unwrap_or_default()
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.source.to_string(),
+            util::truncate(&self.question, MAX_TITLE_CHARS),
+            format_date(self.close_time),
+            match (&self.resolution, self.resolved) {
+                (Some(resolution), _) => format!("{:?}", resolution),
+                (None, true) => "yes".to_string(),
+                (None, false) => "no".to_string(),
+            },
+        ]
+    }
+}
+
+impl ListRow for ThirdPartyMirrorRow {
+    const COLUMNS: &'static [&'static str] = &["ID", "SOURCE", "SOURCE ID", "CREATED", "URL"];
+
+    fn text(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.source.to_string(),
+            self.source_id.clone(),
+            format_date(Some(self.created_time)),
+            self.manifold_url.clone(),
+        ]
+    }
+}
+
+impl ListRow for PendingResolutionRow {
+    const COLUMNS: &'static [&'static str] =
+        &["ID", "MIRROR", "RESOLUTION", "VOLUME", "CREATED", "STATE"];
+
+    fn text(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.market_id.to_string(),
+            format!("{:?}", self.resolution),
+            format!("{:.0}", self.volume),
+            format_time(Some(self.created_time)),
+            format!("{:?}", self.state),
+        ]
+    }
+}
+
+impl ListRow for ResolutionEventRow {
+    const COLUMNS: &'static [&'static str] =
+        &["ID", "TIME", "MIRROR", "RESOLUTION", "SOURCE STATE"];
+
+    fn text(&self) -> String {
+        format!(
+            "#{} {} mirror {}: {} ({})",
+            self.id,
+            self.created_time.format("%Y-%m-%d %H:%M:%S"),
+            self.market_id,
+            self.resolution
+                .as_ref()
+                .map(|r| format!("resolved {:?}", r))
+                .unwrap_or_else(|| "unresolved".to_string()),
+            self.source_state
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            format_time(Some(self.created_time)),
+            self.market_id.to_string(),
+            self.resolution
+                .as_ref()
+                .map(|r| format!("{:?}", r))
+                .unwrap_or_else(|| "unresolved".to_string()),
+            util::truncate(&self.source_state, MAX_TEXT_CHARS),
+        ]
+    }
+}
+
+impl RunRow {
+    fn status(&self) -> String {
+        match (&self.error, self.end_time) {
+            (Some(error), _) => format!("FAILED: {}", error),
+            (None, Some(end_time)) => {
+                format!(
+                    "finished in {}s",
+                    (end_time - self.start_time).num_seconds()
+                )
+            }
+            (None, None) => "did not finish".to_string(),
+        }
+    }
+}
+
+impl ListRow for RunRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "ID",
+        "STARTED",
+        "COMMAND",
+        "TARGET",
+        "CANDIDATES",
+        "CREATED",
+        "RESOLVED",
+        "ERRORS",
+        "STATUS",
+    ];
+
+    fn text(&self) -> String {
+        format!(
+            "#{} {} {} {}: {} candidates, {} created, {} resolved, {} errors, {}",
+            self.id,
+            self.start_time.format("%Y-%m-%d %H:%M:%S"),
+            self.command,
+            self.target,
+            self.stats.candidates,
+            self.stats.mirrors_created,
+            self.stats.resolutions_synced,
+            self.stats.errors,
+            self.status()
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            format_time(Some(self.start_time)),
+            self.command.clone(),
+            self.target.clone(),
+            self.stats.candidates.to_string(),
+            self.stats.mirrors_created.to_string(),
+            self.stats.resolutions_synced.to_string(),
+            self.stats.errors.to_string(),
+            util::truncate(&self.status(), MAX_TEXT_CHARS),
+        ]
+    }
+}
+
+impl ListRow for ManagramRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "ID", "RECEIVED", "FROM", "AMOUNT", "MESSAGE", "STATE", "ATTEMPTS", "ERROR",
+    ];
+
+    fn text(&self) -> String {
+        format!(
+            "{} {} from {}, {} mana: \"{}\" -> {:?} after {} attempt(s){}",
+            self.managram.id,
+            self.managram.created_time.format("%Y-%m-%d %H:%M:%S"),
+            self.managram.from_id,
+            self.managram.amount,
+            self.managram.message,
+            self.state,
+            self.attempts,
+            self.error
+                .as_ref()
+                .map(|e| format!(" (error: {})", e))
+                .unwrap_or_default()
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.managram.id.clone(),
+            format_time(Some(self.managram.created_time)),
+            self.managram.from_id.clone(),
+            self.managram.amount.to_string(),
+            util::truncate(&self.managram.message, MAX_TEXT_CHARS),
+            format!("{:?}", self.state),
+            self.attempts.to_string(),
+            util::truncate(self.error.as_deref().unwrap_or_default(), MAX_TEXT_CHARS),
+        ]
+    }
+}
+
+impl ListRow for ManagramAuditRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "ID",
+        "TIME",
+        "MANAGRAM",
+        "ATTEMPT",
+        "COMMAND",
+        "OUTCOME",
+        "SENT BACK",
+        "ERROR",
+    ];
+
+    fn text(&self) -> String {
+        format!(
+            "#{} {} managram {} attempt {}: {} -> {:?}, sent back {}{}",
+            self.id,
+            self.created_time.format("%Y-%m-%d %H:%M:%S"),
+            self.managram_id,
+            self.attempt,
+            self.command.as_deref().unwrap_or("(no command)"),
+            self.outcome,
+            self.response_amount,
+            self.error
+                .as_ref()
+                .map(|e| format!(" (error: {})", e))
+                .unwrap_or_default()
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            format_time(Some(self.created_time)),
+            self.managram_id.clone(),
+            self.attempt.to_string(),
+            util::truncate(self.command.as_deref().unwrap_or_default(), MAX_TEXT_CHARS),
+            format!("{:?}", self.outcome),
+            self.response_amount.to_string(),
+            util::truncate(self.error.as_deref().unwrap_or_default(), MAX_TEXT_CHARS),
+        ]
+    }
+}
+
+impl ListRow for RejectionRow {
+    const COLUMNS: &'static [&'static str] =
+        &["ID", "TIME", "SOURCE", "SOURCE ID", "TITLE", "REASON"];
+
+    fn text(&self) -> String {
+        format!(
+            "#{} {} {} {} ({}): {} [{}]",
+            self.id,
+            self.created_time.format("%Y-%m-%d %H:%M:%S"),
+            self.source,
+            self.source_id,
+            self.title,
+            self.reason,
+            self.source_url
+        )
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            format_time(Some(self.created_time)),
+            self.source.to_string(),
+            self.source_id.clone(),
+            util::truncate(&self.title, MAX_TITLE_CHARS),
+            util::truncate(&self.reason, MAX_TEXT_CHARS),
+        ]
+    }
+}
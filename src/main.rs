@@ -6,26 +6,48 @@ use tracing_subscriber::{fmt, EnvFilter};
 
 mod args;
 mod commands;
+mod comments;
+mod dashboard;
 mod db;
+mod destination;
+mod http;
+#[cfg(all(test, feature = "test-support"))]
+mod integration_tests;
 mod kalshi;
+mod listing;
 mod managrams;
 mod manifold;
+mod markdown;
 mod metaculus;
 mod mirror;
+mod notify;
+mod polymarket;
+mod recording;
+mod recovery;
+mod server;
 mod settings;
+mod shutdown;
+mod social;
+mod source;
+mod telegram;
+#[cfg(all(test, feature = "test-support"))]
+mod test_support;
 mod types;
 mod util;
 
-fn main() -> Result<(), anyhow::Error> {
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
     dotenvy::dotenv().ok();
 
     tracing_subscriber::registry()
         .with(EnvFilter::from_default_env())
         .with(fmt::layer().json().flatten_event(true))
+        .with(util::ErrorCounter)
         .init();
 
     let config = settings::Settings::new()?;
     let args = Cli::parse();
+    shutdown::listen();
 
-    commands::run_command(config, args)
+    commands::run_command(config, args).await
 }
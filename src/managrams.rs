@@ -1,87 +1,367 @@
 use crate::{
-    db::{self, AnyMirror, MirrorRow},
+    db::{
+        self, ActionKind, AnyMirror, ManagramState, MirrorRow, PendingResolutionState,
+        SpendCategory,
+    },
+    destination::{Destination, MirrorDestination},
+    kalshi::{self, KalshiError, KalshiSource},
     log_if_err,
-    manifold::{self, GetManagramsArgs, Managram, ManifoldError, SendManagramArgs},
-    metaculus, mirror,
-    settings::Settings,
-    types::QuestionSource,
+    manifold::{
+        self, GetManagramsArgs, Managram, ManifoldError, PostCommentArgs, SendManagramArgs,
+    },
+    metaculus::{self, MetaculusSource},
+    mirror::{self, ExtendOutcome},
+    notify::{self, Event},
+    polymarket::{self, PolymarketError, PolymarketSource},
+    recovery::ManagramPayload,
+    settings::{Managrams, MirrorPricing, Settings},
+    shutdown,
+    types::{BinaryResolution, MirrorTarget, Question, QuestionSource},
+    util,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
 use clap::{Parser, Subcommand};
-use log::{debug, info, warn};
-use reqwest::{blocking::Client, StatusCode, Url};
+use log::{debug, error, info, warn};
+use reqwest::{Client, StatusCode, Url};
+
+/// Managrams requested per page, the server side maximum
+const MANAGRAM_PAGE_SIZE: usize = 100;
 
 /// Fetch managrams from manifold and save to db for processing.
-pub fn sync_managrams(client: &Client, db: &rusqlite::Connection, config: &Settings) -> Result<()> {
+pub async fn sync_managrams(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
     info!("Syncing managrams");
-    let last_managram_timestamp = db::last_managram_timestamp(db)?;
-    for managram in manifold::get_managrams_depaginated(
-        client,
-        GetManagramsArgs {
+    // Once a page is saved the newest managram in the database is newer than the
+    // ones still to fetch, so an unfinished sync has to continue from its
+    // checkpoint rather than from there.
+    let (after, mut before) = match db::get_managram_sync_checkpoint(db)? {
+        Some(checkpoint) => {
+            info!(
+                "Resuming managram sync from before {} (after {:?})",
+                checkpoint.before, checkpoint.after
+            );
+            (checkpoint.after, Some(checkpoint.before))
+        }
+        None => (db::last_managram_timestamp(db)?, None),
+    };
+    let mut fetched = 0;
+    loop {
+        if shutdown::requested() {
+            break;
+        }
+        let args = GetManagramsArgs {
             to_id: Some(config.manifold.user_id.to_owned()),
-            after: last_managram_timestamp,
+            limit: Some(MANAGRAM_PAGE_SIZE),
+            after,
+            before,
             ..Default::default()
-        },
-        config,
-    )? {
-        debug!("Inserting managram into db: {:?}", managram);
-        db::insert_managram(db, &managram)?;
+        };
+        let page = manifold::get_managrams(client, &args, config).await?;
+        fetched += page.len();
+        let checkpoint = match page.last() {
+            Some(oldest) if page.len() >= MANAGRAM_PAGE_SIZE => Some(db::ManagramSyncCheckpoint {
+                after,
+                before: oldest.created_time,
+            }),
+            _ => None,
+        };
+        let inserted = db::insert_managram_page(db, &page, checkpoint.as_ref())?;
+        debug!("Inserted {} of {} fetched managrams", inserted, page.len());
+        let Some(checkpoint) = checkpoint else {
+            break;
+        };
+        if fetched >= config.http.max_paginated_items {
+            warn!(
+                "Fetched http.max_paginated_items ({}) managrams, continuing on the next sync",
+                config.http.max_paginated_items
+            );
+            break;
+        }
+        before = Some(checkpoint.before);
     }
-
     Ok(())
 }
 
 /// Fetch unprocessed managrams from db and process them.
-pub fn process_managrams(
+pub async fn process_managrams(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
 ) -> Result<()> {
-    for managram in db::get_unprocessed_managrams(db)? {
-        log_if_err!(
-            process_managram(client, db, config, &managram).with_context(|| format!(
+    // We don't know which side effects of an interrupted command went through, so
+    // retrying or refunding automatically could pay out or mirror twice.
+    for managram in db::get_managrams_by_state(db, ManagramState::Started)? {
+        error!(
+            "Processing of managram (id: {}, user_id: {}) was interrupted. Marking failed, needs manual review.",
+            managram.id, managram.from_id
+        );
+        db::set_managram_state(
+            db,
+            &managram.id,
+            ManagramState::Failed,
+            Some(db::INTERRUPTED_MANAGRAM_ERROR),
+        )?;
+    }
+    let paused = db::is_paused(db)?;
+    if paused {
+        info!("Bot is paused, only processing managrams from admins");
+    }
+    for managram in db::get_managrams_to_process(db)? {
+        if shutdown::requested() {
+            break;
+        }
+        if paused && !is_admin(config, &managram.from_id) {
+            continue;
+        }
+        log_if_err!(process_managram(client, db, config, &managram)
+            .await
+            .with_context(|| format!(
                 "while processing managram (id: {}, user_id: {})",
                 managram.id, managram.from_id
-            ))
-        );
+            )));
     }
     Ok(())
 }
 
-/// Process an unprocessed managram. Does not check processed state.
-fn process_managram(
+/// Reset a managram and process it again, e.g. after a transient failure.
+/// Managrams that succeeded or were refunded are only reprocessed with `force`,
+/// since that runs their command a second time. Returns the new state.
+pub async fn reprocess_managram(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    id: &str,
+    force: bool,
+) -> Result<ManagramState> {
+    let row = db::get_managram(db, id)?.ok_or_else(|| anyhow!("no managram with id {}", id))?;
+    if matches!(
+        row.state,
+        ManagramState::Succeeded | ManagramState::Refunded
+    ) && !force
+    {
+        bail!(
+            "managram with id {} is already {:?}, its command would run again",
+            id,
+            row.state
+        );
+    }
+    info!(
+        "Reprocessing managram with id {} (was {:?} after {} attempts)",
+        id, row.state, row.attempts
+    );
+    db::reset_managram(db, id)?;
+    process_managram(client, db, config, &row.managram).await?;
+    Ok(db::get_managram(db, id)?
+        .ok_or_else(|| anyhow!("managram with id {} disappeared", id))?
+        .state)
+}
+
+/// Process a new managram. Does not check processing state.
+async fn process_managram(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
     managram: &Managram,
 ) -> Result<()> {
     debug!("Processing managram with txn_id {}", managram.id);
+    let started = Utc::now();
+    let attempts = db::start_managram_attempt(db, &managram.id)?;
     let result = process_managram_command(client, db, config, managram);
-    match result {
+    let (outcome, error) = match result.await {
         Ok(()) => {
-            db::set_managram_processed(db, &managram.id, true)?;
+            db::set_managram_state(db, &managram.id, ManagramState::Succeeded, None)?;
+            (ManagramState::Succeeded, None)
         }
         Err(ManagramProcessingError::UserFacing(msg)) => {
             warn!(
                 "Command from managram with id {} failed (message: {}). Refunding.",
                 managram.id, msg
             );
-            // Mark processed before refunding so we don't keep sending the refund if we get an error response.
-            // TODO: encode failure state in db somehow
-            // maybe instead of "processed", have a state that can be new/complete/started/failed
-            db::set_managram_processed(db, &managram.id, true)?;
-            respond_to_managram(client, config, managram, ResponseAmount::Refund, msg)?;
+            // Mark failed and record refund before refunding so we don't keep sending the refund
+            // if we get an error response.
+            db::set_managram_state(db, &managram.id, ManagramState::Failed, Some(&msg))?;
+            if db::insert_refund(db, &managram.id, managram.amount, &msg)? {
+                respond_to_managram(
+                    client,
+                    db,
+                    config,
+                    managram,
+                    ResponseAmount::Refund,
+                    msg.clone(),
+                )
+                .await?;
+            }
+            db::set_managram_state(db, &managram.id, ManagramState::Refunded, None)?;
+            notify::notify(
+                client,
+                config,
+                Event::ManagramFailed {
+                    managram,
+                    error: &msg,
+                },
+            )
+            .await;
+            (ManagramState::Refunded, Some(msg))
+        }
+        Err(ManagramProcessingError::Internal(e)) if command_took_effect(db, &managram.id)? => {
+            let error = format!("{:#}", e);
+            error!(
+                "Command from managram with id {} took effect but failed afterwards. Not retrying: {}",
+                managram.id, error
+            );
+            db::set_managram_state(db, &managram.id, ManagramState::Succeeded, Some(&error))?;
+            (ManagramState::Succeeded, Some(error))
         }
         Err(ManagramProcessingError::Internal(e)) => {
-            // TODO: append error instead of failing silently
-            db::set_managram_processed(db, &managram.id, true).ok();
+            let cfg = &config.manifold.managrams;
+            let error = format!("{:#}", e);
+            if attempts < cfg.max_attempts {
+                // 1x, 2x, 4x, ... base delay, capped so large attempt counts can't overflow
+                let delay = Duration::minutes(cfg.retry_base_delay_minutes)
+                    * 2i32.pow((attempts as u32 - 1).min(16));
+                warn!(
+                    "Attempt {} of {} for managram with id {} failed. Retrying in {} minutes.",
+                    attempts,
+                    cfg.max_attempts,
+                    managram.id,
+                    delay.num_minutes()
+                );
+                db::schedule_managram_retry(db, &managram.id, &error, Utc::now() + delay)?;
+            } else {
+                db::set_managram_state(db, &managram.id, ManagramState::Failed, Some(&error))?;
+                notify::notify(
+                    client,
+                    config,
+                    Event::ManagramFailed {
+                        managram,
+                        error: &error,
+                    },
+                )
+                .await;
+            }
+            notify::notify(
+                client,
+                config,
+                Event::ManagramProcessed {
+                    managram,
+                    attempt: attempts,
+                    outcome: &ManagramState::Failed,
+                    error: Some(&error),
+                },
+            )
+            .await;
+            audit_managram(
+                db,
+                managram,
+                started,
+                attempts,
+                ManagramState::Failed,
+                Some(error),
+            );
             return Err(e);
         }
+    };
+    notify::notify(
+        client,
+        config,
+        Event::ManagramProcessed {
+            managram,
+            attempt: attempts,
+            outcome: &outcome,
+            error: error.as_deref(),
+        },
+    )
+    .await;
+    audit_managram(db, managram, started, attempts, outcome, error);
+    Ok(())
+}
+
+/// Whether a failed command got far enough that running it again would repeat its
+/// effects. Commands mark their managram succeeded as soon as that's the case. A
+/// response that failed to send is left to recovery, retrying could send it twice.
+fn command_took_effect(db: &rusqlite::Connection, id: &str) -> Result<bool> {
+    let succeeded =
+        db::get_managram(db, id)?.is_some_and(|row| row.state == ManagramState::Succeeded);
+    Ok(succeeded || db::get_incomplete_action(db, ActionKind::SendManagram, id)?.is_some())
+}
+
+/// Record an attempt in the audit log. Failing to do so is logged, but doesn't fail processing.
+fn audit_managram(
+    db: &rusqlite::Connection,
+    managram: &Managram,
+    started: DateTime<Utc>,
+    attempt: i64,
+    outcome: ManagramState,
+    error: Option<String>,
+) {
+    let command = util::tokenize(&managram.message)
+        .ok()
+        .and_then(|tokens| ManagramArgs::try_parse_from(tokens).ok())
+        .map(|args| format!("{:?}", args.command));
+    log_if_err!(
+        db::get_managram_response_total(db, &managram.id, started).and_then(|amount| {
+            db::insert_managram_audit(
+                db,
+                &managram.id,
+                attempt,
+                command.as_deref(),
+                outcome,
+                error.as_deref(),
+                amount,
+            )
+        })
+    );
+}
+
+/// Refund managrams that failed without being refunded, once they're old enough
+/// that the operator has had a chance to look at them.
+pub async fn refund_stale_managrams(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    let cutoff = Utc::now() - Duration::days(config.manifold.managrams.stale_refund_days);
+    for managram in db::get_stale_failed_managrams(db, cutoff)? {
+        if shutdown::requested() {
+            break;
+        }
+        let reason = "stale failed managram";
+        if !db::insert_refund(db, &managram.id, managram.amount, reason)? {
+            continue;
+        }
+        warn!(
+            "Refunding stale failed managram (id: {}, user_id: {}, amount: {})",
+            managram.id, managram.from_id, managram.amount
+        );
+        log_if_err!(respond_to_managram(
+            client,
+            db,
+            config,
+            &managram,
+            ResponseAmount::Refund,
+            format!(
+                "Sorry, something went wrong while processing your managram from {} (\"{}\"). \
+                Refunding.",
+                managram.created_time.format("%Y-%m-%d"),
+                managram.message
+            ),
+        )
+        .await
+        .and_then(|_| db::set_managram_state(
+            db,
+            &managram.id,
+            ManagramState::Refunded,
+            None
+        )));
     }
     Ok(())
 }
 
-enum ManagramProcessingError {
+pub enum ManagramProcessingError {
     /// Errors expected during normal operation. These should lead to an error response for the user.
     UserFacing(String),
     /// Errors that indicate something went wrong in a way that leaves us in an unclear state.
@@ -90,46 +370,557 @@ enum ManagramProcessingError {
 }
 
 /// Try to parse a command from a managram and execute it.
-fn process_managram_command(
+async fn process_managram_command(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
     managram: &Managram,
 ) -> Result<(), ManagramProcessingError> {
+    check_user_allowed(
+        db,
+        config,
+        &managram.from_id,
+        managram.created_time,
+        &format!("managram {}", managram.id),
+    )?;
     // clap expects args in the form of a list of strings, since normally the shell
-    // handles tokenization etc. For now this just splits on whitespace. If we want
-    // quoted arguments in the future we'll have to do something fancier than this.
-    let args = ManagramArgs::try_parse_from(managram.message.split_whitespace())
+    // handles tokenization etc.
+    let tokens = match util::tokenize(&managram.message) {
+        Ok(tokens) if !tokens.is_empty() => tokens,
+        // free-form text can have unbalanced quotes, so don't hold that against donations
+        _ if is_donation(config, managram) => {
+            return process_donation(client, db, config, managram).await;
+        }
+        Ok(tokens) => tokens,
+        Err(e) => return Err(ManagramProcessingError::UserFacing(e)),
+    };
+    let args = ManagramArgs::try_parse_from(tokens)
         .map_err(|e| ManagramProcessingError::UserFacing(e.to_string()))?;
     match args.command {
         ManagramCommands::Mirror(args) => {
-            process_managram_mirror_command(client, db, config, managram, args)
+            process_managram_mirror_command(client, db, config, managram, args).await
         }
         ManagramCommands::Resolve(args) => {
-            process_managram_resolve_command(client, db, config, managram, args)
+            process_managram_resolve_command(client, db, config, managram, args).await
+        }
+        ManagramCommands::Refresh(args) => {
+            process_managram_refresh_command(client, db, config, managram, args).await
+        }
+        ManagramCommands::Extend(args) => {
+            process_managram_extend_command(client, db, config, managram, args).await
+        }
+        ManagramCommands::Subsidize(args) => {
+            process_managram_subsidize_command(client, db, config, managram, args).await
+        }
+        ManagramCommands::Subscribe(args) => {
+            process_managram_subscribe_command(client, db, config, managram, args).await
+        }
+        ManagramCommands::Unsubscribe => {
+            process_managram_unsubscribe_command(client, db, config, managram).await
+        }
+        ManagramCommands::Admin(args) => {
+            process_managram_admin_command(client, db, config, managram, args).await
         }
         ManagramCommands::Ping => {
             info!(
                 "Managram ping received (id: {}, user id: {})",
                 managram.id, managram.from_id
             );
-            respond_to_managram(client, config, managram, ResponseAmount::Refund, "Pong!")
-                .map_err(|e| ManagramProcessingError::Internal(e))?;
-            db::set_managram_processed(db, &managram.id, true)
-                .map_err(|e| ManagramProcessingError::Internal(e))
+            respond_to_managram(
+                client,
+                db,
+                config,
+                managram,
+                ResponseAmount::Refund,
+                "Pong!",
+            )
+            .await
+            .map_err(ManagramProcessingError::Internal)
+        }
+        ManagramCommands::Help => {
+            info!(
+                "Managram help request received (id: {}, user id: {})",
+                managram.id, managram.from_id
+            );
+            respond_to_managram(
+                client,
+                db,
+                config,
+                managram,
+                ResponseAmount::Refund,
+                help_message(config),
+            )
+            .await
+            .map_err(ManagramProcessingError::Internal)
+        }
+        ManagramCommands::None(_) if is_donation(config, managram) => {
+            process_donation(client, db, config, managram).await
         }
         ManagramCommands::None(_) => {
             info!(
-                "Managram with id {} from {} does not contain a known command. Ignoring.",
+                "Managram with id {} from {} does not contain a known command. Responding with usage.",
                 managram.id, managram.from_id
             );
-            db::set_managram_processed(db, &managram.id, true)
-                .map_err(|e| ManagramProcessingError::Internal(e))
+            Err(ManagramProcessingError::UserFacing(
+                "Unknown command. Available commands: mirror, resolve, refresh, extend, subsidize, subscribe, unsubscribe, ping, help. \
+                Send `help` for details."
+                    .to_string(),
+            ))
         }
     }
 }
 
-fn process_managram_resolve_command(
+async fn process_managram_subscribe_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    SubscribeArgs { source }: SubscribeArgs,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Processing managram subscribe command. \
+        Managram id: {}. From id: {}. Source: {:?}.",
+        managram.id, managram.from_id, source
+    );
+    if source == Some(QuestionSource::Manual) {
+        return Err(ManagramProcessingError::UserFacing(
+            "Can only subscribe to kalshi, metaculus or polymarket mirrors".to_string(),
+        ));
+    }
+    let added = db::insert_subscription(db, &managram.from_id, source.as_ref())
+        .map_err(ManagramProcessingError::Internal)?;
+    let sources = match &source {
+        Some(source) => format!("{} mirrors", source),
+        None => "mirrors from all sources".to_string(),
+    };
+    let response = if added {
+        format!(
+            "Subscribed to {}. Send `unsubscribe` to stop notifications.",
+            sources
+        )
+    } else {
+        format!("Already subscribed to {}.", sources)
+    };
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Refund,
+        response,
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)
+}
+
+async fn process_managram_unsubscribe_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Processing managram unsubscribe command. Managram id: {}. From id: {}.",
+        managram.id, managram.from_id
+    );
+    let removed = db::delete_subscriptions(db, &managram.from_id)
+        .map_err(ManagramProcessingError::Internal)?;
+    let response = if removed > 0 {
+        "Unsubscribed from all new mirror notifications."
+    } else {
+        "You had no subscriptions."
+    };
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Refund,
+        response,
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)
+}
+
+/// Send one managram to everyone subscribed to source, listing the new mirrors.
+pub async fn notify_subscribers(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    source: &QuestionSource,
+    mirrors: &[MirrorRow],
+) -> Result<()> {
+    if mirrors.is_empty() {
+        return Ok(());
+    }
+    let subscribers = db::get_subscribers(db, source)?;
+    if subscribers.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "Notifying {} subscribers of {} new {} mirrors",
+        subscribers.len(),
+        mirrors.len(),
+        source
+    );
+    let urls: Vec<&str> = mirrors.iter().map(|m| m.manifold_url.as_str()).collect();
+    manifold::send_managram(
+        client,
+        config,
+        &SendManagramArgs {
+            amount: config.manifold.managrams.min_amount,
+            to_ids: subscribers.clone(),
+            message: format!(
+                "New {} mirrors: {}. Send `unsubscribe` to stop these notifications.",
+                source,
+                urls.join(" ")
+            ),
+        },
+    )
+    .await?;
+    log_if_err!(db::record_spend(
+        db,
+        SpendCategory::Notification,
+        config.manifold.managrams.min_amount * subscribers.len() as f64,
+        Some(source),
+        &format!("{} subscribers", subscribers.len()),
+    ));
+    Ok(())
+}
+
+/// Enforce ban list and per-user rate limit for a command `user_id` sent at `time`,
+/// by managram or comment. Both count towards the same limit. `request` names the
+/// command for logs.
+pub fn check_user_allowed(
+    db: &rusqlite::Connection,
+    config: &Settings,
+    user_id: &str,
+    time: DateTime<Utc>,
+    request: &str,
+) -> Result<(), ManagramProcessingError> {
+    let cfg = &config.manifold.managrams;
+    if is_admin(config, user_id) {
+        return Ok(());
+    }
+    if cfg.banned_user_ids.contains(user_id) {
+        info!("{} is from banned user {}", request, user_id);
+        return Err(ManagramProcessingError::UserFacing(
+            "You are not allowed to send commands to this bot.".to_string(),
+        ));
+    }
+    // count relative to the request rather than now, so results don't depend on processing delay
+    let after = time - Duration::days(1);
+    let previous_count = db::count_managrams_from_user(db, user_id, after, time)
+        .map_err(ManagramProcessingError::Internal)?
+        + db::count_comments_from_user(db, user_id, after, time)
+            .map_err(ManagramProcessingError::Internal)?;
+    if previous_count >= cfg.max_requests_per_user_per_day {
+        info!(
+            "User {} exceeded rate limit with {} ({} requests in previous 24 hours)",
+            user_id, request, previous_count
+        );
+        return Err(ManagramProcessingError::UserFacing(format!(
+            "You can send at most {} requests per day. Please try again later.",
+            cfg.max_requests_per_user_per_day
+        )));
+    }
+    Ok(())
+}
+
+fn is_admin(config: &Settings, user_id: &str) -> bool {
+    config
+        .manifold
+        .managrams
+        .admin_user_ids
+        .iter()
+        .any(|id| id == user_id)
+}
+
+async fn process_managram_admin_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    AdminArgs { command }: AdminArgs,
+) -> Result<(), ManagramProcessingError> {
+    if !is_admin(config, &managram.from_id) {
+        warn!(
+            "Admin command from non-admin user {} (managram id: {})",
+            managram.from_id, managram.id
+        );
+        return Err(ManagramProcessingError::UserFacing(
+            "Admin commands are restricted to the bot operators.".to_string(),
+        ));
+    }
+    info!(
+        "Processing admin command {:?}. Managram id: {}. From id: {}.",
+        command, managram.id, managram.from_id
+    );
+    let response = run_admin_command(
+        client,
+        db,
+        config,
+        command,
+        &format!("admin managram {} from {}", managram.id, managram.from_id),
+    )
+    .await?;
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Refund,
+        response,
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)
+}
+
+/// Parse an operator command from a message, e.g. `pause` or `mirror <url>`
+pub fn parse_admin_command(message: &str) -> Result<AdminCommands, String> {
+    let tokens = util::tokenize(message)?;
+    AdminArgs::try_parse_from(std::iter::once("admin".to_string()).chain(tokens))
+        .map(|args| args.command)
+        .map_err(|e| e.to_string())
+}
+
+/// Execute an operator command and return the response. Callers are responsible
+/// for checking the sender is an operator. `origin` describes where the command
+/// came from, for the resolution history.
+pub async fn run_admin_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    command: AdminCommands,
+    origin: &str,
+) -> Result<String, ManagramProcessingError> {
+    Ok(match command {
+        AdminCommands::Pause => {
+            db::set_paused(db, true).map_err(ManagramProcessingError::Internal)?;
+            "Paused. Auto-mirroring and commands from regular users are on hold.".to_string()
+        }
+        AdminCommands::Resume => {
+            db::set_paused(db, false).map_err(ManagramProcessingError::Internal)?;
+            "Resumed.".to_string()
+        }
+        AdminCommands::Resolve { target, resolution } => {
+            let market_row = get_unresolved_mirror(client, db, config, target).await?;
+            mirror::apply_resolution(client, db, &market_row, resolution.clone(), origin, config)
+                .await
+                .map_err(|e| ManagramProcessingError::Internal(e.into()))?;
+            format!("Resolved {} to {:?}.", market_row.manifold_url, resolution)
+        }
+        AdminCommands::Mirror {
+            target: MirrorTarget { source, source_id },
+        } => {
+            if let Some(mirror) = db::get_mirror_by_source_id(db, &source, &source_id)
+                .map_err(ManagramProcessingError::Internal)?
+            {
+                return Err(ManagramProcessingError::UserFacing(format!(
+                    "Mirror already exists: {}",
+                    mirror.manifold_url
+                )));
+            }
+            let (question, is_resolved) =
+                mirror::fetch_question(client, config, source, &source_id)
+                    .await
+                    .map_err(ManagramProcessingError::Internal)?;
+            if is_resolved {
+                return Err(ManagramProcessingError::UserFacing(
+                    "Question is already resolved".to_string(),
+                ));
+            }
+            let mirror = mirror::mirror_question(client, db, &question, None, config)
+                .await
+                .map_err(|e| ManagramProcessingError::Internal(e.into()))?;
+            format!("Mirrored at {}", mirror.manifold_url)
+        }
+        AdminCommands::Stats => {
+            let stats = db::get_stats(db).map_err(ManagramProcessingError::Internal)?;
+            format!(
+                "Mirrors: {} ({} unresolved). Volume: {:.0} mana from {} traders. \
+                Pending resolutions: {}. Managrams in last 24h: {}. Failed managrams: {}. \
+                Donations: {} mana. Paused: {}.",
+                stats.mirrors,
+                stats.unresolved_mirrors,
+                stats.volume,
+                stats.traders,
+                stats.pending_resolutions,
+                stats.managrams_last_day,
+                stats.failed_managrams,
+                stats.donations,
+                db::is_paused(db).map_err(ManagramProcessingError::Internal)?,
+            )
+        }
+    })
+}
+
+/// Users who requested the most mirrors created since `since`, with their
+/// Manifold username, or their user id if it can't be fetched
+pub async fn top_requesters(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    since: Option<DateTime<Utc>>,
+    top: usize,
+) -> Result<Vec<(String, db::RequesterRow)>> {
+    let mut requesters = Vec::new();
+    for row in db::get_top_requesters(db, since, top)? {
+        let name = match manifold::get_user(client, &row.user_id, config).await {
+            Ok(user) => user.username,
+            Err(e) => {
+                warn!("Failed to fetch user {}: {:#}", row.user_id, e);
+                row.user_id.clone()
+            }
+        };
+        requesters.push((name, row));
+    }
+    Ok(requesters)
+}
+
+/// Comment the requester leaderboard on `manifold.leaderboard.contract_id` once
+/// every `interval_days`
+pub async fn post_requester_leaderboard(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    let cfg = &config.manifold.leaderboard;
+    if !cfg.enabled {
+        debug!("Requester leaderboard is disabled");
+        return Ok(());
+    }
+    if cfg.contract_id.is_empty() {
+        bail!("manifold.leaderboard.contract_id must be set to post the leaderboard");
+    }
+    let now = Utc::now();
+    let since = now - Duration::days(cfg.interval_days);
+    let last_posted = db::get_leaderboard_posted_time(db)?;
+    if last_posted.is_some_and(|posted| posted > since) {
+        return Ok(());
+    }
+    let requesters =
+        top_requesters(client, db, config, last_posted.or(Some(since)), cfg.top).await?;
+    if requesters.is_empty() {
+        info!("No mirrors were requested since the last leaderboard, not posting one");
+        db::set_leaderboard_posted_time(db, now)?;
+        return Ok(());
+    }
+    let mut markdown = format!(
+        "Top mirror requesters since {}:\n\n",
+        last_posted.unwrap_or(since).format("%Y-%m-%d")
+    );
+    for (i, (name, row)) in requesters.iter().enumerate() {
+        markdown.push_str(&format!(
+            "{}. @{}: {} mirror{} for {:.0} mana\n",
+            i + 1,
+            name,
+            row.mirrors,
+            if row.mirrors == 1 { "" } else { "s" },
+            row.spent
+        ));
+    }
+    markdown.push_str("\nThanks for your requests!");
+    info!("Posting requester leaderboard on {}", cfg.contract_id);
+    Destination::post_comment(
+        client,
+        None,
+        &PostCommentArgs {
+            contract_id: cfg.contract_id.clone(),
+            markdown,
+            reply_to_comment_id: None,
+        },
+        config,
+    )
+    .await?;
+    db::set_leaderboard_posted_time(db, now)?;
+    Ok(())
+}
+
+/// Managrams without a command are donations if they're large enough.
+/// Smaller ones are more likely to be mistyped commands.
+fn is_donation(config: &Settings, managram: &Managram) -> bool {
+    managram.amount > config.manifold.managrams.donation_threshold
+}
+
+async fn process_donation(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Managram with id {} from {} is a donation of {} mana",
+        managram.id, managram.from_id, managram.amount
+    );
+    db::insert_donation(db, managram).map_err(ManagramProcessingError::Internal)?;
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Minimum,
+        format!(
+            "Thank you for your donation of {} mana! It will go towards mirroring more questions.",
+            managram.amount
+        ),
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)
+}
+
+/// How the mirror price grows with time to resolution, if it does
+fn pricing_note(pricing: Option<&MirrorPricing>, cfg: &Managrams) -> String {
+    match pricing {
+        Some(pricing) => format!(
+            ", plus {} per month until the question resolves, up to {} total",
+            pricing.per_month,
+            pricing.max + cfg.min_amount
+        ),
+        None => String::new(),
+    }
+}
+
+/// Usage and pricing for all managram commands
+fn help_message(config: &Settings) -> String {
+    let cfg = &config.manifold.managrams;
+    format!(
+        "Commands: \
+        `mirror <url>` mirrors a question to Manifold. Send at least {mirror_amount} mana{pricing_note} \
+        ({polymarket_amount} for Polymarket{polymarket_pricing_note}), anything above the price \
+        is returned. \
+        Supports Metaculus \
+        (https://www.metaculus.com/questions/12345/), Kalshi \
+        (https://kalshi.com/markets/inxd#inxd-24jun03) and Polymarket \
+        (https://polymarket.com/event/some-event). \
+        `resolve <url>` applies the source resolution to one of our mirrors \
+        (https://manifold.markets/mirrorbot/some-market). Send at least {resolve_amount} mana, \
+        all of which is returned. \
+        `refresh <url>` updates one of our mirrors' close time and description from the \
+        source. Send at least {refresh_amount} mana, {refresh_cost} is kept. \
+        `extend <url>` pushes the close time of one of our mirrors forward if the source \
+        hasn't resolved yet. Send at least {extend_amount} mana, {extend_cost} is kept. \
+        `subsidize <url>` adds the mana you send to one of our mirrors' liquidity, \
+        minus a {subsidize_fee} mana fee. \
+        `subscribe [kalshi|metaculus|polymarket]` sends you a managram listing new mirrors \
+        whenever we create some, and `unsubscribe` stops that. Both return your mana. \
+        `ping` returns your mana. \
+        `help` shows this message.",
+        mirror_amount = config.mirror_cost(&QuestionSource::Metaculus, None) + cfg.min_amount,
+        polymarket_amount = config.mirror_cost(&QuestionSource::Polymarket, None) + cfg.min_amount,
+        pricing_note = pricing_note(config.mirror_pricing(&QuestionSource::Metaculus), cfg),
+        polymarket_pricing_note =
+            pricing_note(config.mirror_pricing(&QuestionSource::Polymarket), cfg),
+        resolve_amount = cfg.resolve_cost + cfg.min_amount,
+        refresh_amount = cfg.refresh_cost + cfg.min_amount,
+        refresh_cost = cfg.refresh_cost,
+        extend_amount = cfg.extend_cost + cfg.min_amount,
+        extend_cost = cfg.extend_cost,
+        subsidize_fee = cfg.subsidize_fee + cfg.min_amount,
+    )
+}
+
+async fn process_managram_resolve_command(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
@@ -149,54 +940,248 @@ fn process_managram_resolve_command(
             required_amount
         )));
     }
+    let market_row = get_unresolved_mirror(client, db, config, target).await?;
+    let resolved = match mirror::sync_mirror(client, db, &market_row, config).await {
+        Ok(resolved) => resolved,
+        Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
+    };
+    let awaiting_confirmation = !resolved
+        && db::get_latest_pending_resolution(db, market_row.id)
+            .map_err(ManagramProcessingError::Internal)?
+            .is_some_and(|r| r.state == PendingResolutionState::Pending);
+    let response = if resolved {
+        "Resolved market!"
+    } else if awaiting_confirmation {
+        "Source question has resolved. Resolution is awaiting confirmation from the bot operator."
+    } else {
+        "Source question has not resolved yet"
+    };
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Refund,
+        response,
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)?;
+    Ok(())
+}
+
+/// Look up an unresolved mirror created by this bot.
+async fn get_unresolved_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    target: MarketIdentifier,
+) -> Result<MirrorRow, ManagramProcessingError> {
     let market_id = match target {
         MarketIdentifier::Id(id) => id,
-        MarketIdentifier::Slug(slug) => match manifold::get_market_by_slug(client, &slug, config) {
-            Ok(market) => {
-                if market.creator_id != config.manifold.user_id {
-                    return Err(ManagramProcessingError::UserFacing(
-                        "Market was not created by this bot".to_string(),
-                    ));
+        MarketIdentifier::Slug(slug) => {
+            match manifold::get_market_by_slug(client, &slug, config).await {
+                Ok(market) => {
+                    if !config.own_user_ids().any(|id| id == market.creator_id) {
+                        return Err(ManagramProcessingError::UserFacing(
+                            "Market was not created by this bot".to_string(),
+                        ));
+                    }
+                    if market.is_resolved {
+                        return Err(ManagramProcessingError::UserFacing(
+                            "Market is already resolved".to_string(),
+                        ));
+                    }
+                    market.id
                 }
-                if market.is_resolved {
+                Err(ManifoldError::ErrorResponse(StatusCode::NOT_FOUND, _)) => {
                     return Err(ManagramProcessingError::UserFacing(
-                        "Market is already resolved".to_string(),
-                    ));
+                        "Market not found".to_string(),
+                    ))
                 }
-                market.id
+                Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
             }
-            Err(ManifoldError::ErrorResponse(StatusCode::NOT_FOUND, _)) => {
-                return Err(ManagramProcessingError::UserFacing(
-                    "Market not found".to_string(),
-                ))
-            }
-            Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
-        },
-    };
-    let market_row = match db::get_mirror_by_contract_id(db, &market_id) {
-        Ok(Some(market)) => market,
-        Ok(None) => {
-            return Err(ManagramProcessingError::UserFacing(
-                "Market not in bot database".to_string(),
-            ))
         }
-        Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
     };
-    let resolved = match mirror::sync_mirror(client, db, &market_row, config) {
-        Ok(resolved) => resolved,
+    match db::get_mirror_by_contract_id(db, &market_id) {
+        Ok(Some(market)) => Ok(market),
+        Ok(None) => Err(ManagramProcessingError::UserFacing(
+            "Market not in bot database".to_string(),
+        )),
+        Err(error) => Err(ManagramProcessingError::Internal(error)),
+    }
+}
+
+async fn process_managram_refresh_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    RefreshArgs { target }: RefreshArgs,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Processing managram refresh command. \
+        Managram id: {}. From id: {}. Target: {:?}.",
+        managram.id, managram.from_id, target
+    );
+    let cfg = &config.manifold.managrams;
+    let required_amount = cfg.refresh_cost + cfg.min_amount;
+    if managram.amount < required_amount {
+        return Err(ManagramProcessingError::UserFacing(format!(
+            "Refresh requests should include at least {} mana.",
+            required_amount
+        )));
+    }
+    let market_row = get_unresolved_mirror(client, db, config, target).await?;
+    if market_row.source == QuestionSource::Manual {
+        return Err(ManagramProcessingError::UserFacing(
+            "Market is not a mirror".to_string(),
+        ));
+    }
+    let response = match mirror::refresh_mirror(client, db, &market_row, config).await {
+        Ok(Some(close_time)) => format!(
+            "Refreshed mirror. Close time changed to {}.",
+            close_time.format("%Y-%m-%d %H:%M UTC")
+        ),
+        Ok(None) => "Refreshed mirror. Close time is unchanged.".to_string(),
         Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
     };
-    let response = if resolved {
-        "Resolved market!"
-    } else {
-        "Source question has not resolved yet"
-    };
-    respond_to_managram(client, config, managram, ResponseAmount::Refund, response)
-        .map_err(|e| ManagramProcessingError::Internal(e))?;
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Amount(managram.amount - cfg.refresh_cost),
+        response,
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)?;
+    Ok(())
+}
+
+async fn process_managram_extend_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    ExtendArgs { target }: ExtendArgs,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Processing managram extend command. \
+        Managram id: {}. From id: {}. Target: {:?}.",
+        managram.id, managram.from_id, target
+    );
+    let cfg = &config.manifold.managrams;
+    let required_amount = cfg.extend_cost + cfg.min_amount;
+    if managram.amount < required_amount {
+        return Err(ManagramProcessingError::UserFacing(format!(
+            "Extension requests should include at least {} mana.",
+            required_amount
+        )));
+    }
+    let market_row = get_unresolved_mirror(client, db, config, target).await?;
+    if market_row.source == QuestionSource::Manual {
+        return Err(ManagramProcessingError::UserFacing(
+            "Market is not a mirror".to_string(),
+        ));
+    }
+    let close_time =
+        match mirror::extend_mirror(client, db, &market_row, config, cfg.extend_days).await {
+            Ok(ExtendOutcome::Extended(close_time)) => close_time,
+            Ok(ExtendOutcome::AlreadyOpen(close_time)) => {
+                return Err(ManagramProcessingError::UserFacing(format!(
+                    "Market is already open until {}.",
+                    close_time.format("%Y-%m-%d %H:%M UTC")
+                )))
+            }
+            Ok(ExtendOutcome::SourceResolved(resolution)) => {
+                return Err(ManagramProcessingError::UserFacing(format!(
+                    "Source question has resolved ({:?}). \
+                Send `resolve <url>` to resolve the mirror instead.",
+                    resolution
+                )))
+            }
+            Err(error) => return Err(ManagramProcessingError::Internal(error.into())),
+        };
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Amount(managram.amount - cfg.extend_cost),
+        format!(
+            "Extended mirror. It now closes at {}.",
+            close_time.format("%Y-%m-%d %H:%M UTC")
+        ),
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)?;
+    Ok(())
+}
+
+async fn process_managram_subsidize_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    SubsidizeArgs { target }: SubsidizeArgs,
+) -> Result<(), ManagramProcessingError> {
+    info!(
+        "Processing managram subsidize command. \
+        Managram id: {}. From id: {}. Target: {:?}.",
+        managram.id, managram.from_id, target
+    );
+    let cfg = &config.manifold.managrams;
+    // the reply costs min_amount too
+    let amount = managram.amount - cfg.subsidize_fee - cfg.min_amount;
+    if amount <= 0.0 {
+        return Err(ManagramProcessingError::UserFacing(format!(
+            "Subsidy requests should include more than {} mana.",
+            cfg.subsidize_fee + cfg.min_amount
+        )));
+    }
+    let mirror = get_unresolved_mirror(client, db, config, target).await?;
+    if mirror
+        .close_time
+        .is_some_and(|close_time| close_time < Utc::now())
+    {
+        return Err(ManagramProcessingError::UserFacing(
+            "Market is closed".to_string(),
+        ));
+    }
+    manifold::add_liquidity(client, &mirror.manifold_contract_id, amount, config)
+        .await
+        .map_err(|e| ManagramProcessingError::Internal(e.into()))?;
+    // Liquidity was added, so make sure a failed response doesn't lead to a refund or retry
+    db::set_managram_state(db, &managram.id, ManagramState::Succeeded, None)
+        .map_err(ManagramProcessingError::Internal)?;
+    info!(
+        "Added {} liquidity to mirror with row id {} (\"{}\") for user {}",
+        amount, mirror.id, mirror.question, managram.from_id
+    );
+    log_if_err!(db::record_spend(
+        db,
+        SpendCategory::UserSubsidy,
+        amount,
+        Some(&mirror.source),
+        &managram.id
+    ));
+    respond_to_managram(
+        client,
+        db,
+        config,
+        managram,
+        ResponseAmount::Minimum,
+        format!(
+            "Added {} mana of liquidity to {}. Thanks!",
+            amount, mirror.manifold_url
+        ),
+    )
+    .await
+    .map_err(ManagramProcessingError::Internal)?;
     Ok(())
 }
 
-fn process_managram_mirror_command(
+async fn process_managram_mirror_command(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
@@ -211,16 +1196,17 @@ fn process_managram_mirror_command(
         Managram id: {}. From id: {}. Question source: {}. Question id: {}. Force: {}.",
         managram.id, managram.from_id, source, source_id, force
     );
-    let cfg = &config.manifold.managrams;
-    let required_amount = cfg.mirror_cost + cfg.min_amount;
-    if managram.amount < required_amount {
+    // cheap check before fetching the question, the exact price can depend on it
+    check_mirror_payment(config, managram, &source, None)?;
+    if mirror::open_mirror_capacity(db, config, &source)
+        .map_err(ManagramProcessingError::Internal)?
+        == Some(0)
+    {
         return Err(ManagramProcessingError::UserFacing(format!(
-            "Mirror requests should include at least {} mana.",
-            required_amount
+            "The bot has reached its limit of open {} mirrors. Please try again once some have resolved.",
+            source
         )));
     }
-    // TODO: we need to ensure we actually find a mirror if it exists.
-    // I could see this going wrong with Kalshi (case insensitive id input).
     match db::get_any_mirror(db, &source, &source_id)
         .map_err(|e| ManagramProcessingError::Internal(e))?
     {
@@ -243,79 +1229,220 @@ fn process_managram_mirror_command(
         }
         None => {}
     }
-    let mirror = match source {
+    let (mirror, price) = match source {
         QuestionSource::Metaculus => {
-            process_managram_mirror_metaculus(client, db, config, managram, &source_id)?
+            process_managram_mirror_metaculus(client, db, config, managram, &source_id).await?
+        }
+        QuestionSource::Kalshi => {
+            process_managram_mirror_kalshi(client, db, config, managram, &source_id).await?
+        }
+        QuestionSource::Polymarket => {
+            process_managram_mirror_polymarket(client, db, config, managram, &source_id).await?
         }
-        QuestionSource::Kalshi => todo!(),
-        QuestionSource::Polymarket => todo!(),
         QuestionSource::Manual => panic!("Manual market should never appear in mirror request"),
     };
-    db::set_managram_processed(db, &managram.id, true)
+    // Mirror exists now, so make sure a failed response doesn't lead to a refund or retry
+    db::set_managram_state(db, &managram.id, ManagramState::Succeeded, None)
         .map_err(|e| ManagramProcessingError::Internal(e))?;
+    log_if_err!(db::set_mirror_requester(
+        db,
+        mirror.id,
+        &managram.from_id,
+        price
+    ));
     respond_to_managram(
         client,
+        db,
         config,
         managram,
-        ResponseAmount::Minimum,
+        ResponseAmount::Amount(managram.amount - price),
         format!("Created mirror at {}", mirror.manifold_url),
     )
+    .await
     .map_err(|e| ManagramProcessingError::Internal(e))?;
     Ok(())
 }
 
-fn process_managram_mirror_metaculus(
+async fn process_managram_mirror_metaculus(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
     managram: &Managram,
     source_id: &str,
-) -> Result<MirrorRow, ManagramProcessingError> {
+) -> Result<(MirrorRow, f64), ManagramProcessingError> {
     debug!("Metaculus mirror request.");
-    let question = metaculus::get_question(client, source_id, config).map_err(|_| {
-        ManagramProcessingError::UserFacing(format!(
-            "Failed to fetch question with id {} from Metaculus.",
-            source_id
-        ))
-    })?;
+    let question = metaculus::get_question(client, source_id, config)
+        .await
+        .map_err(|_| {
+            ManagramProcessingError::UserFacing(format!(
+                "Failed to fetch question with id {} from Metaculus.",
+                source_id
+            ))
+        })?;
     metaculus::check_question_requirements(&question, &config.metaculus.request_filter)
         .map_err(|e| ManagramProcessingError::UserFacing(e.to_string()))?;
+    let price = check_mirror_payment(
+        config,
+        managram,
+        &QuestionSource::Metaculus,
+        Some(source_question_end_date(&question)?),
+    )?;
     info!(
         "Checks passed. Mirroring metaculus question with id {} (\"{}\") at user request. Managram id: {}. User id: {}",
         question.id, question.title, managram.id, managram.from_id
     );
-    match mirror::mirror_metaculus_question(client, db, config, &question) {
-        Ok(mirror) => Ok(mirror),
+    match mirror::mirror_source_question::<MetaculusSource>(client, db, config, &question).await {
+        Ok(mirror) => Ok((mirror, price)),
         // TODO: maybe split out some cases where we can safely respond
         Err(e) => Err(ManagramProcessingError::Internal(e.into())),
     }
 }
 
-fn respond_to_managram<M: Into<String>>(
+async fn process_managram_mirror_kalshi(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    source_id: &str,
+) -> Result<(MirrorRow, f64), ManagramProcessingError> {
+    debug!("Kalshi mirror request.");
+    let market = kalshi::get_question(client, source_id, config)
+        .await
+        .map_err(|e| match e {
+            KalshiError::OnlySingleMarketsSupported(_) => {
+                ManagramProcessingError::UserFacing(e.to_string())
+            }
+            _ => ManagramProcessingError::UserFacing(format!(
+                "Failed to fetch question with id {} from Kalshi.",
+                source_id
+            )),
+        })?;
+    kalshi::check_market_requirements(&market, &config.kalshi.request_filter)
+        .map_err(|e| ManagramProcessingError::UserFacing(e.to_string()))?;
+    let price = check_mirror_payment(
+        config,
+        managram,
+        &QuestionSource::Kalshi,
+        Some(source_question_end_date(&market)?),
+    )?;
+    info!(
+        "Checks passed. Mirroring kalshi question with id {} (\"{}\") at user request. Managram id: {}. User id: {}",
+        market.id(), market.title(), managram.id, managram.from_id
+    );
+    match mirror::mirror_source_question::<KalshiSource>(client, db, config, &market).await {
+        Ok(mirror) => Ok((mirror, price)),
+        Err(e) => Err(ManagramProcessingError::Internal(e.into())),
+    }
+}
+
+async fn process_managram_mirror_polymarket(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    managram: &Managram,
+    source_id: &str,
+) -> Result<(MirrorRow, f64), ManagramProcessingError> {
+    debug!("Polymarket mirror request.");
+    let market = polymarket::get_question(client, source_id, config)
+        .await
+        .map_err(|e| match e {
+            PolymarketError::NotFound(_) => ManagramProcessingError::UserFacing(e.to_string()),
+            _ => ManagramProcessingError::UserFacing(format!(
+                "Failed to fetch question with id {} from Polymarket.",
+                source_id
+            )),
+        })?;
+    polymarket::check_market_requirements(&market, &config.polymarket.request_filter)
+        .map_err(|e| ManagramProcessingError::UserFacing(e.to_string()))?;
+    let price = check_mirror_payment(
+        config,
+        managram,
+        &QuestionSource::Polymarket,
+        Some(source_question_end_date(&market)?),
+    )?;
+    info!(
+        "Checks passed. Mirroring polymarket question with id {} (\"{}\") at user request. Managram id: {}. User id: {}",
+        market.id(), market.question, managram.id, managram.from_id
+    );
+    match mirror::mirror_source_question::<PolymarketSource>(client, db, config, &market).await {
+        Ok(mirror) => Ok((mirror, price)),
+        Err(e) => Err(ManagramProcessingError::Internal(e.into())),
+    }
+}
+
+/// Check managram covers the price of mirroring a question with given end date,
+/// plus the minimum amount we need to send back. Returns the price.
+fn check_mirror_payment(
+    config: &Settings,
+    managram: &Managram,
+    source: &QuestionSource,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<f64, ManagramProcessingError> {
+    let price = config.mirror_cost(source, end_date);
+    let required_amount = price + config.manifold.managrams.min_amount;
+    if managram.amount < required_amount {
+        return Err(ManagramProcessingError::UserFacing(match end_date {
+            Some(_) => format!(
+                "Mirroring this question costs {} mana. Please send at least {} mana.",
+                price, required_amount
+            ),
+            None => format!(
+                "Mirror requests should include at least {} mana.",
+                required_amount
+            ),
+        }));
+    }
+    Ok(price)
+}
+
+fn source_question_end_date<Q>(source_question: Q) -> Result<DateTime<Utc>, ManagramProcessingError>
+where
+    Q: TryInto<Question, Error = anyhow::Error>,
+{
+    source_question
+        .try_into()
+        .map(|q| q.end_date)
+        .map_err(ManagramProcessingError::Internal)
+}
+
+async fn respond_to_managram<M: Into<String>>(
     client: &Client,
+    db: &rusqlite::Connection,
     config: &Settings,
     managram: &Managram,
     amount: ResponseAmount,
     message: M,
 ) -> Result<()> {
-    let amount = match amount {
-        ResponseAmount::Refund => managram.amount,
-        ResponseAmount::Minimum => config.manifold.managrams.min_amount,
-        ResponseAmount::Amount(amount) => amount,
+    let (amount, category) = match amount {
+        ResponseAmount::Refund => (managram.amount, SpendCategory::Refund),
+        ResponseAmount::Minimum => (
+            config.manifold.managrams.min_amount,
+            SpendCategory::Response,
+        ),
+        ResponseAmount::Amount(amount) => (amount, SpendCategory::Response),
     };
-    manifold::send_managram(
-        client,
-        config,
-        &SendManagramArgs {
+    let payload = ManagramPayload {
+        args: SendManagramArgs {
             amount,
             to_ids: vec![managram.from_id.clone()],
             message: message.into(),
         },
-    )?;
+        category,
+    };
+    let action = db::start_action(db, ActionKind::SendManagram, &managram.id, &payload)?;
+    manifold::send_managram(client, config, &payload.args).await?;
+    db::complete_action(db, action)?;
     info!(
         "Responded to managram with id {} from user with id {}. Request amount: {}. Response amount: {}.",
         managram.id, managram.from_id, managram.amount, amount
     );
+    log_if_err!(db::record_spend(
+        db,
+        payload.category,
+        amount,
+        None,
+        &managram.id
+    ));
     Ok(())
 }
 
@@ -328,6 +1455,7 @@ enum ResponseAmount {
 
 #[derive(Debug, Parser)]
 #[command(disable_help_flag(true))]
+#[command(disable_help_subcommand(true))]
 #[command(no_binary_name(true))]
 struct ManagramArgs {
     #[command(subcommand)]
@@ -340,8 +1468,23 @@ enum ManagramCommands {
     Mirror(MirrorArgs),
     /// Request resolution for a mirror of resolved source
     Resolve(ResolveArgs),
+    /// Update a mirror's close time and description from its source
+    Refresh(RefreshArgs),
+    /// Push a mirror's close time forward while the source is unresolved
+    Extend(ExtendArgs),
+    /// Add the attached mana, minus a fee, to a mirror's liquidity
+    Subsidize(SubsidizeArgs),
+    /// Get notified of new mirrors, optionally only from one source
+    Subscribe(SubscribeArgs),
+    /// Stop notifications of new mirrors
+    Unsubscribe,
+    /// Operator commands, restricted to `managrams.admin_user_ids`
+    #[command(hide = true)]
+    Admin(AdminArgs),
     /// Responds "Pong!", for testing purposes
     Ping,
+    /// Responds with usage and pricing
+    Help,
     /// Anything else
     #[command(external_subcommand)]
     None(Vec<String>),
@@ -354,8 +1497,67 @@ struct ResolveArgs {
     target: MarketIdentifier,
 }
 
+#[derive(Debug, Parser)]
+struct AdminArgs {
+    #[command(subcommand)]
+    command: AdminCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommands {
+    /// Stop auto-mirroring and processing commands from regular users
+    Pause,
+    /// Undo pause
+    Resume,
+    /// Resolve one of our mirrors, skipping confirmation
+    Resolve {
+        /// Market to resolve (url)
+        #[arg(value_parser = MarketIdentifier::parse_arg)]
+        target: MarketIdentifier,
+        /// YES, NO, CANCEL or a percentage
+        resolution: BinaryResolution,
+    },
+    /// Mirror a question without payment or question requirements
+    Mirror {
+        /// Question to mirror (url)
+        #[arg(value_parser = MirrorTarget::parse_arg)]
+        target: MirrorTarget,
+    },
+    /// Respond with bot statistics
+    #[command(alias = "status")]
+    Stats,
+}
+
+#[derive(Debug, Parser)]
+struct ExtendArgs {
+    /// Market to extend (url)
+    #[arg(value_parser = MarketIdentifier::parse_arg)]
+    target: MarketIdentifier,
+}
+
+#[derive(Debug, Parser)]
+struct SubsidizeArgs {
+    /// Market to add liquidity to (url)
+    #[arg(value_parser = MarketIdentifier::parse_arg)]
+    target: MarketIdentifier,
+}
+
+#[derive(Debug, Parser)]
+struct SubscribeArgs {
+    /// Only notify about mirrors from this source
+    #[arg(value_enum, ignore_case = true)]
+    source: Option<QuestionSource>,
+}
+
+#[derive(Debug, Parser)]
+struct RefreshArgs {
+    /// Market to refresh (url)
+    #[arg(value_parser = MarketIdentifier::parse_arg)]
+    target: MarketIdentifier,
+}
+
 #[derive(Debug, Clone)]
-enum MarketIdentifier {
+pub enum MarketIdentifier {
     Id(String),
     Slug(String),
 }
@@ -396,41 +1598,3 @@ struct MirrorArgs {
     #[arg(long = "force")]
     force: bool,
 }
-
-#[derive(Debug, Clone)]
-struct MirrorTarget {
-    source: QuestionSource,
-    source_id: String,
-}
-
-impl MirrorTarget {
-    fn parse_arg(s: &str) -> Result<Self, String> {
-        let generic_error = "Invalid URL";
-        let url: Url = s.parse().map_err(|_| generic_error.to_string())?;
-        match url.host_str() {
-            Some("www.metaculus.com") => {
-                let metaculus_error = "Failed to parse Metaculus question url";
-                let mut path = url.path_segments().ok_or(metaculus_error.to_string())?;
-                if path.next() != Some("questions") {
-                    return Err(metaculus_error.to_string());
-                }
-                // validate and normalize id
-                let id = path
-                    .next()
-                    .ok_or("Missing Metaculus question id".to_string())?
-                    .parse::<u64>()
-                    .map_err(|_| "Metaculus question id must be a positive integer".to_string())?
-                    .to_string();
-                Ok(Self {
-                    source: QuestionSource::Metaculus,
-                    source_id: id.into(),
-                })
-            }
-            Some("kalshi.com") => {
-                Err("Managram mirroring for Kalshi has not been implemented yet.".to_string())
-            }
-            Some(host) => Err(format!("Unrecognized host `{}`", host)),
-            None => Err(generic_error.to_string()),
-        }
-    }
-}
@@ -2,20 +2,23 @@ use std::fmt::{Debug, Display};
 
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
+use futures::Stream;
 use log::{debug, warn};
-use reqwest::{
-    blocking::{Client, RequestBuilder, Response},
-    header::AUTHORIZATION,
-    StatusCode, Url,
-};
+use reqwest::{header::AUTHORIZATION, Client, RequestBuilder, Response, StatusCode, Url};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::value::Value as JsonValue;
 use thiserror::Error;
 
 use crate::{
+    destination::MirrorDestination,
+    http::{self, HttpError},
+    markdown,
+    metaculus::CloseMirrorsAt,
+    mirror::MirrorError,
     settings::Settings,
     types::Question,
     types::{BinaryResolution, QuestionSource},
+    util,
 };
 
 // TODO: migrate from anyhow to this where it makes sense
@@ -30,13 +33,15 @@ pub enum ManifoldError {
     ErrorResponse(StatusCode, ManifoldErrorResponse),
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[error(transparent)]
+    HttpError(#[from] HttpError),
     // #[error(transparent)]
     // Other(#[from] anyhow::Error),
 }
 
 /// Create a new market on Manifold.
 /// Currently only supports simple binary markets.
-pub fn create_market(
+pub async fn create_market(
     client: &Client,
     market: CreateMarketArgs,
     config: &Settings,
@@ -47,10 +52,12 @@ pub fn create_market(
     //     serde_json::to_string(&market).map_err(anyhow::Error::from)?
     // );
     let endpoint = get_api_url(config).join("market/").unwrap();
-    let resp = add_auth(client.post(endpoint), config)
-        .json(&market)
-        .send()?;
-    parse_response(resp)
+    let resp = http::send(
+        config,
+        add_auth(client.post(endpoint), config).json(&market),
+    )
+    .await?;
+    parse_response(resp).await
 }
 
 #[derive(Debug, Serialize)]
@@ -68,11 +75,22 @@ pub struct CreateMarketArgs {
     pub initial_prob: u32,
     /// ids of groups/topics to add to market on creation
     pub group_ids: Vec<String>,
+    /// Unlisted markets don't show up on the home page or in search
+    pub visibility: Visibility,
+    /// Unranked markets don't count towards leagues and profit leaderboards
+    pub is_ranked: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Unlisted,
 }
 
 /// Resolve an existing market.
 /// Currently only supports simple binary markets.
-pub fn resolve_market(
+pub async fn resolve_market(
     client: &Client,
     market_id: &str,
     resolution: ManifoldResolution,
@@ -89,10 +107,81 @@ pub fn resolve_market(
     let endpoint = get_api_url(config)
         .join(&format!("market/{}/resolve/", market_id))
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.post(endpoint), config)
-        .json(&resolution)
-        .send()?;
-    parse_response(resp)
+    let resp = http::send(
+        config,
+        add_auth(client.post(endpoint), config).json(&resolution),
+    )
+    .await?;
+    parse_response(resp).await
+}
+
+/// Update title, description or close time of an existing market.
+/// Undo the resolution of a market
+pub async fn unresolve_market(
+    client: &Client,
+    market_id: &str,
+    config: &Settings,
+) -> Result<(), ManifoldError> {
+    debug!("unresolve_market called with market_id = {}", market_id);
+    let endpoint = get_api_url(config)
+        .join(&format!("market/{}/unresolve/", market_id))
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, add_auth(client.post(endpoint), config)).await?;
+    let _: JsonValue = parse_response(resp).await?;
+    Ok(())
+}
+
+pub async fn update_market(
+    client: &Client,
+    market_id: &str,
+    args: &UpdateMarketArgs,
+    config: &Settings,
+) -> Result<(), ManifoldError> {
+    debug!(
+        "update_market called with market_id = {}, args = {:?}",
+        market_id, args
+    );
+    let endpoint = get_api_url(config)
+        .join(&format!("market/{}/update/", market_id))
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, add_auth(client.post(endpoint), config).json(args)).await?;
+    let _: JsonValue = parse_response(resp).await?;
+    Ok(())
+}
+
+/// Subsidize a market with mana from the bot account
+pub async fn add_liquidity(
+    client: &Client,
+    market_id: &str,
+    amount: f64,
+    config: &Settings,
+) -> Result<(), ManifoldError> {
+    debug!(
+        "add_liquidity called with market_id = {}, amount = {}",
+        market_id, amount
+    );
+    let endpoint = get_api_url(config)
+        .join(&format!("market/{}/add-liquidity/", market_id))
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(
+        config,
+        add_auth(client.post(endpoint), config).json(&serde_json::json!({ "amount": amount })),
+    )
+    .await?;
+    let _: JsonValue = parse_response(resp).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateMarketArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description_markdown: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(with = "chrono::serde::ts_milliseconds_option")]
+    pub close_time: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -105,7 +194,7 @@ pub struct ManifoldResolution {
 }
 
 /// Fetch market info by contract id
-pub fn get_market(
+pub async fn get_market(
     client: &Client,
     market_id: &str,
     config: &Settings,
@@ -114,12 +203,12 @@ pub fn get_market(
     let endpoint = get_api_url(config)
         .join(&format!("market/{}/", market_id))
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.get(endpoint), config).send()?;
-    parse_response(resp)
+    let resp = http::send(config, add_auth(client.get(endpoint), config)).await?;
+    parse_response(resp).await
 }
 
 /// Fetch market info by contract slug
-pub fn get_market_by_slug(
+pub async fn get_market_by_slug(
     client: &Client,
     slug: &str,
     config: &Settings,
@@ -128,12 +217,12 @@ pub fn get_market_by_slug(
     let endpoint = get_api_url(config)
         .join(&format!("slug/{}/", slug))
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.get(endpoint), config).send()?;
-    parse_response(resp)
+    let resp = http::send(config, add_auth(client.get(endpoint), config)).await?;
+    parse_response(resp).await
 }
 
 /// Fetch markets, manual pagination
-pub fn get_markets(
+pub async fn get_markets(
     client: &Client,
     args: &GetMarketsArgs,
     config: &Settings,
@@ -142,40 +231,30 @@ pub fn get_markets(
     let endpoint = get_api_url(config)
         .join("markets/")
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.get(endpoint), config).query(args).send()?;
-    parse_response(resp)
+    let resp = http::send(config, add_auth(client.get(endpoint), config).query(args)).await?;
+    parse_response(resp).await
 }
 
 /// Same as [`get_markets`], but handles pagination
-pub fn get_markets_depaginated(
-    client: &Client,
-    mut args: GetMarketsArgs,
-    config: &Settings,
-) -> Result<Vec<LiteMarket>, ManifoldError> {
-    debug!("get_markets_depaginated called with args = {:?}", args);
-    let mut markets = Vec::new();
-    loop {
-        let mut batch = get_markets(client, &args, config)?;
-        let batch_size = batch.len();
-        debug!("get_markets returned {} items", batch_size);
-        markets.append(&mut batch);
-        if batch_size < args.limit.unwrap_or(500) {
-            break;
-        } else {
-            args.before = Some(
-                markets
-                    .last()
-                    .expect("markets should never be empty here")
-                    .id
-                    .to_owned(),
-            );
+pub fn stream_markets<'a>(
+    client: &'a Client,
+    args: GetMarketsArgs,
+    config: &'a Settings,
+) -> impl Stream<Item = Result<LiteMarket, ManifoldError>> + 'a {
+    debug!("stream_markets called with args = {:?}", args);
+    http::paginate(config, args, move |mut args| async move {
+        let batch = get_markets(client, &args, config).await?;
+        debug!("get_markets returned {} items", batch.len());
+        if batch.len() < args.limit.unwrap_or(500) {
+            return Ok((batch, None));
         }
-    }
-    Ok(markets)
+        args.before = batch.last().map(|m| m.id.to_owned());
+        Ok((batch, Some(args)))
+    })
 }
 
 /// Fetch managrams, reverse-chronological, manual pagination
-pub fn get_managrams(
+pub async fn get_managrams(
     client: &Client,
     args: &GetManagramsArgs,
     config: &Settings,
@@ -184,35 +263,8 @@ pub fn get_managrams(
     let endpoint = get_api_url(config)
         .join("managrams/")
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.get(endpoint), config).query(args).send()?;
-    parse_response(resp)
-}
-
-/// Same as [`get_managrams`], but handles pagination
-pub fn get_managrams_depaginated(
-    client: &Client,
-    mut args: GetManagramsArgs,
-    config: &Settings,
-) -> Result<Vec<Managram>, ManifoldError> {
-    debug!("get_managrams_depaginated called with args = {:?}", args);
-    let mut managrams = Vec::new();
-    loop {
-        let mut batch = get_managrams(client, &args, config)?;
-        let batch_size = batch.len();
-        debug!("get_managrams returned {} items", batch_size);
-        managrams.append(&mut batch);
-        if batch_size < args.limit.unwrap_or(100) {
-            break;
-        } else {
-            args.before = Some(
-                managrams
-                    .last()
-                    .expect("managrams should never be empty here")
-                    .created_time,
-            );
-        }
-    }
-    Ok(managrams)
+    let resp = http::send(config, add_auth(client.get(endpoint), config).query(args)).await?;
+    parse_response(resp).await
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -253,8 +305,120 @@ pub struct GetMarketsArgs {
     pub group_id: Option<String>,
 }
 
+/// Fetch bets on a market or by a user, reverse-chronological
+pub async fn get_bets(
+    client: &Client,
+    args: &GetBetsArgs,
+    config: &Settings,
+) -> Result<Vec<Bet>, ManifoldError> {
+    debug!("get_bets called with args = {:?}", args);
+    let endpoint = get_api_url(config)
+        .join("bets/")
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, client.get(endpoint).query(args)).await?;
+    parse_response(resp).await
+}
+
+/// Stream all bets matching `args`, reverse-chronological, paginated automatically
+pub fn stream_bets<'a>(
+    client: &'a Client,
+    args: GetBetsArgs,
+    config: &'a Settings,
+) -> impl Stream<Item = Result<Bet, ManifoldError>> + 'a {
+    debug!("stream_bets called with args = {:?}", args);
+    http::paginate(config, args, move |mut args| async move {
+        let batch = get_bets(client, &args, config).await?;
+        debug!("get_bets returned {} items", batch.len());
+        if batch.len() < args.limit.unwrap_or(1000) {
+            return Ok((batch, None));
+        }
+        args.before = batch.last().map(|bet| bet.id.to_owned());
+        Ok((batch, Some(args)))
+    })
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBetsArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// server side max 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Only bets placed before this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(with = "chrono::serde::ts_milliseconds_option")]
+    pub before_time: Option<DateTime<Utc>>,
+    /// Only bets placed before the bet with this id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+}
+
+/// Fetch the user the api key belongs to
+pub async fn get_me(client: &Client, config: &Settings) -> Result<User, ManifoldError> {
+    debug!("get_me called");
+    let endpoint = get_api_url(config)
+        .join("me/")
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, add_auth(client.get(endpoint), config)).await?;
+    parse_response(resp).await
+}
+
+/// Fetch the positions of traders on a market
+pub async fn get_positions(
+    client: &Client,
+    market_id: &str,
+    args: &GetPositionsArgs,
+    config: &Settings,
+) -> Result<Vec<ContractMetric>, ManifoldError> {
+    debug!(
+        "get_positions called with market_id = {}, args = {:?}",
+        market_id, args
+    );
+    let endpoint = get_api_url(config)
+        .join(&format!("market/{}/positions/", market_id))
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, client.get(endpoint).query(args)).await?;
+    parse_response(resp).await
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPositionsArgs {
+    /// "profit" or "shares"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+    /// Number of positions from the top of the order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top: Option<usize>,
+}
+
+/// A trader's position on a market
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractMetric {
+    pub user_id: String,
+    pub profit: f64,
+}
+
+/// Fetch a user by id
+pub async fn get_user(
+    client: &Client,
+    user_id: &str,
+    config: &Settings,
+) -> Result<User, ManifoldError> {
+    debug!("get_user called with user_id = {}", user_id);
+    let endpoint = get_api_url(config)
+        .join(&format!("user/by-id/{}/", user_id))
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, client.get(endpoint)).await?;
+    parse_response(resp).await
+}
+
 /// Send a managram
-pub fn send_managram(
+pub async fn send_managram(
     client: &Client,
     config: &Settings,
     args: &SendManagramArgs,
@@ -263,13 +427,122 @@ pub fn send_managram(
     let endpoint = get_api_url(config)
         .join("managram/")
         .expect("endpoint URL should be a valid URL");
-    let resp = add_auth(client.post(endpoint), config).json(args).send()?;
-    let _: JsonValue = parse_response(resp)?;
+    let resp = http::send(config, add_auth(client.post(endpoint), config).json(args)).await?;
+    let _: JsonValue = parse_response(resp).await?;
     Ok(())
 }
 
+/// Fetch comments, manual pagination
+pub async fn get_comments(
+    client: &Client,
+    args: &GetCommentsArgs,
+    config: &Settings,
+) -> Result<Vec<Comment>, ManifoldError> {
+    debug!("get_comments called with args = {:?}", args);
+    let endpoint = get_api_url(config)
+        .join("comments/")
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, add_auth(client.get(endpoint), config).query(args)).await?;
+    parse_response(resp).await
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCommentsArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// server side max and default 5000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+}
+
+/// Post a comment on a market, optionally as a reply in an existing thread
+pub async fn post_comment(
+    client: &Client,
+    args: &PostCommentArgs,
+    config: &Settings,
+) -> Result<Comment, ManifoldError> {
+    debug!("post_comment called with args={:?}", args);
+    let endpoint = get_api_url(config)
+        .join("comment/")
+        .expect("endpoint URL should be a valid URL");
+    let resp = http::send(config, add_auth(client.post(endpoint), config).json(args)).await?;
+    parse_response(resp).await
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
+pub struct PostCommentArgs {
+    pub contract_id: String,
+    pub markdown: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to_comment_id: Option<String>,
+}
+
+/// [`MirrorDestination`] for Manifold, which all mirrors are created on so far
+pub struct ManifoldDestination;
+
+impl MirrorDestination for ManifoldDestination {
+    async fn create_market(
+        client: &Client,
+        destination: Option<&str>,
+        args: CreateMarketArgs,
+        config: &Settings,
+    ) -> Result<LiteMarket, MirrorError> {
+        let config = &config.for_destination(destination)?;
+        Ok(create_market(client, args, config).await?)
+    }
+
+    async fn resolve_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        resolution: &BinaryResolution,
+        config: &Settings,
+    ) -> Result<(), MirrorError> {
+        let config = &config.for_destination(destination)?;
+        resolve_market(client, market_id, resolution.clone().into(), config).await?;
+        Ok(())
+    }
+
+    async fn unresolve_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        config: &Settings,
+    ) -> Result<(), MirrorError> {
+        let config = &config.for_destination(destination)?;
+        Ok(unresolve_market(client, market_id, config).await?)
+    }
+
+    async fn update_market(
+        client: &Client,
+        destination: Option<&str>,
+        market_id: &str,
+        args: &UpdateMarketArgs,
+        config: &Settings,
+    ) -> Result<(), MirrorError> {
+        let config = &config.for_destination(destination)?;
+        Ok(update_market(client, market_id, args, config).await?)
+    }
+
+    async fn post_comment(
+        client: &Client,
+        destination: Option<&str>,
+        args: &PostCommentArgs,
+        config: &Settings,
+    ) -> Result<Comment, MirrorError> {
+        let config = &config.for_destination(destination)?;
+        Ok(post_comment(client, args, config).await?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SendManagramArgs {
     /// Amount of mana to send. Min 10.
     pub amount: f64,
@@ -277,6 +550,31 @@ pub struct SendManagramArgs {
     pub message: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub balance: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bet {
+    pub id: String,
+    pub contract_id: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_time: DateTime<Utc>,
+    /// Market probability after the bet was filled
+    pub prob_after: f64,
+    /// Mana spent, negative for sales and redemptions
+    pub amount: f64,
+    /// Shares bought, negative for sales and redemptions
+    pub shares: f64,
+    /// YES or NO
+    pub outcome: String,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LiteMarket {
@@ -290,6 +588,17 @@ pub struct LiteMarket {
     #[serde(with = "chrono::serde::ts_milliseconds")]
     pub last_updated_time: DateTime<Utc>,
     pub is_resolved: bool,
+    pub volume: f64,
+    /// For new markets, this is the ante we paid
+    pub total_liquidity: Option<f64>,
+    /// Current probability, for binary markets
+    pub probability: Option<f64>,
+    /// YES, NO, MKT or CANCEL, if resolved
+    pub resolution: Option<String>,
+    #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+    pub resolution_time: Option<DateTime<Utc>>,
+    /// Probability the market resolved to, for MKT resolutions
+    pub resolution_probability: Option<f64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -306,6 +615,16 @@ pub struct FullMarket {
     #[serde(with = "chrono::serde::ts_milliseconds")]
     pub last_updated_time: DateTime<Utc>,
     pub is_resolved: bool,
+    pub volume: f64,
+    pub volume_24_hours: Option<f64>,
+    pub total_liquidity: Option<f64>,
+    pub unique_bettor_count: Option<u64>,
+    /// Current probability, for binary markets
+    pub probability: Option<f64>,
+    pub resolution: Option<String>,
+    #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+    pub resolution_time: Option<DateTime<Utc>>,
+    pub resolution_probability: Option<f64>,
     pub description: JsonValue, // TODO: parse this properly?
     pub text_description: String,
 }
@@ -320,6 +639,25 @@ impl Into<LiteMarket> for &FullMarket {
             close_time: self.close_time,
             last_updated_time: self.last_updated_time,
             is_resolved: self.is_resolved,
+            volume: self.volume,
+            total_liquidity: self.total_liquidity,
+            probability: self.probability,
+            resolution: self.resolution.clone(),
+            resolution_time: self.resolution_time,
+            resolution_probability: self.resolution_probability,
+        }
+    }
+}
+
+impl LiteMarket {
+    /// Resolution of a resolved binary market
+    pub fn binary_resolution(&self) -> Option<BinaryResolution> {
+        match self.resolution.as_deref()? {
+            "YES" => Some(BinaryResolution::Yes),
+            "NO" => Some(BinaryResolution::No),
+            "CANCEL" => Some(BinaryResolution::Cancel),
+            "MKT" => self.resolution_probability.map(BinaryResolution::Percent),
+            _ => None,
         }
     }
 }
@@ -350,7 +688,54 @@ impl ManifoldMarket for FullMarket {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub id: String,
+    pub contract_id: String,
+    pub user_id: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_time: DateTime<Utc>,
+    /// Rich text document (TipTap JSON)
+    pub content: Option<JsonValue>,
+    pub reply_to_comment_id: Option<String>,
+}
+
+impl Comment {
+    /// Plain text of comment. Mentions are rendered as `@username`.
+    pub fn text(&self) -> String {
+        fn collect(node: &JsonValue, out: &mut String) {
+            match node.get("type").and_then(JsonValue::as_str) {
+                Some("text") => {
+                    out.push_str(node.get("text").and_then(JsonValue::as_str).unwrap_or(""))
+                }
+                Some("mention") => {
+                    if let Some(label) = node.pointer("/attrs/label").and_then(JsonValue::as_str) {
+                        out.push('@');
+                        out.push_str(label);
+                    }
+                }
+                Some("hardBreak") => out.push('\n'),
+                _ => {}
+            }
+            if let Some(children) = node.get("content").and_then(JsonValue::as_array) {
+                for child in children {
+                    collect(child, out);
+                }
+                if node.get("type").and_then(JsonValue::as_str) == Some("paragraph") {
+                    out.push('\n');
+                }
+            }
+        }
+        let mut text = String::new();
+        if let Some(content) = &self.content {
+            collect(content, &mut text);
+        }
+        text.trim_end().to_string()
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Managram {
     pub id: String,
     /// identifies set of identical managrams sent at once to multiple users
@@ -403,7 +788,7 @@ impl<'de> Deserialize<'de> for Managram {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum TokenType {
     #[serde(rename = "M$")]
@@ -477,9 +862,9 @@ fn add_auth(req: RequestBuilder, config: &Settings) -> RequestBuilder {
 }
 
 /// helper function for parsing both success and error responses
-fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, ManifoldError> {
+async fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, ManifoldError> {
     if resp.status().is_success() {
-        match resp.json() {
+        match resp.json().await {
             Ok(r) => Ok(r),
             Err(_) => Err(ManifoldError::UnexpectedResponseType), // TODO: wrap inner?
         }
@@ -487,6 +872,7 @@ fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, ManifoldErro
         let status = resp.status();
         let error: ManifoldErrorResponse = resp
             .json()
+            .await
             .map_err(|_| ManifoldError::UnexpectedErrorType(status))?;
         Err(ManifoldError::ErrorResponse(status, error))
     }
@@ -499,26 +885,70 @@ pub enum ManifoldOutcomeType {
 }
 
 impl CreateMarketArgs {
-    fn title_from_question(question: &Question, config: &Settings) -> String {
+    pub fn title_from_question(question: &Question, config: &Settings) -> String {
+        let format = config
+            .manifold
+            .template
+            .title_formats
+            .for_source(&question.source);
+        Self::title_from_format(question, format, config)
+    }
+
+    /// Render title from a format with a `{title}` placeholder, truncating the
+    /// middle if it's too long.
+    pub fn title_from_format(question: &Question, format: &str, config: &Settings) -> String {
         let tmpl = &config.manifold.template;
-        let mut title = format!("[{}] {}", question.source, question.question);
-        // TODO: factor out truncation function and use it for description as well
-        if title.len() > tmpl.max_question_length {
+        let title = format.replace("{title}", &question.question);
+        if util::js_len(&title) > tmpl.max_question_length {
             warn!(
                 "Truncating question from {} to {} characters",
-                title.len(),
+                util::js_len(&title),
                 tmpl.max_question_length
             );
-            let suffix_len = tmpl.title_retain_end_characters + 3;
-            let to_remove = title.len() + 3 - tmpl.max_question_length;
-            let cut_start = tmpl.max_question_length - suffix_len;
-            let cut_end = cut_start + to_remove;
-            title.replace_range(cut_start..cut_end, "...");
         }
-        title
+        util::truncate_graphemes(
+            &title,
+            tmpl.max_question_length,
+            &tmpl.title_truncation_marker,
+            tmpl.title_retain_end_characters,
+        )
     }
 
-    fn description_from_question(question: &Question, config: &Settings) -> String {
+    /// Render the description footer from config, filling in placeholders:
+    /// `{version}`, `{mirror_date}`, `{last_checked}`, `{mirror_cost}`,
+    /// `{resolve_cost}` and `{managram_instructions}`.
+    fn render_footer(config: &Settings, mirror_date: DateTime<Utc>) -> String {
+        let cfg = &config.manifold.managrams;
+        let mirror_cost = config.mirror_cost(&QuestionSource::Metaculus, None) + cfg.min_amount;
+        let resolve_cost = cfg.resolve_cost + cfg.min_amount;
+        let managram_instructions = format!(
+            "To request a mirror of another question, send this account a managram of at least \
+            {mirror_cost} mana with the message `mirror <url>`. To request that this market be \
+            resolved after the source resolves, send at least {resolve_cost} mana with the \
+            message `resolve <url of this market>`.",
+        );
+        config
+            .manifold
+            .template
+            .description_footer
+            .replace("{version}", env!("CARGO_PKG_VERSION"))
+            .replace("{mirror_date}", &mirror_date.format("%Y-%m-%d").to_string())
+            .replace(
+                "{last_checked}",
+                &Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+            )
+            .replace("{mirror_cost}", &mirror_cost.to_string())
+            .replace("{resolve_cost}", &resolve_cost.to_string())
+            .replace("{managram_instructions}", &managram_instructions)
+    }
+
+    /// Render the full market description. The source question is assumed to have
+    /// just been fetched, so it's used as the last checked time.
+    pub fn description_from_question(
+        question: &Question,
+        config: &Settings,
+        mirror_date: DateTime<Utc>,
+    ) -> String {
         let tmpl = &config.manifold.template;
         let embed = if let Some(embed_html) = &question.embed_html() {
             format!("\n\n{}", embed_html)
@@ -532,23 +962,93 @@ impl CreateMarketArgs {
             url = question.source_url,
             embed = embed,
         );
+        if let Some(history) = Self::forecast_history_from_question(question, config) {
+            description.push_str(&history);
+        }
         if let Some(criteria) = &question.criteria {
             description.push_str(&format!(
                 "**Resolution criteria**\n\n{criteria}\n\n---\n\n",
-                criteria = criteria
+                criteria = markdown::sanitize(criteria, &question.source_url)
             ))
         }
-        description.push_str(&tmpl.description_footer);
-        if description.len() > tmpl.max_description_length {
+        if let Some(background) = Self::background_from_question(question, config) {
+            description.push_str(&format!(
+                "**Background**\n\n{background}\n\n---\n\n",
+                background = background
+            ))
+        }
+        if let Some(close_time) = Self::source_close_time(question, config) {
+            description.push_str(&format!(
+                "Trading closes {close} UTC, when forecasting on {source} closes. \
+                The question is scheduled to resolve {resolve} UTC.\n\n---\n\n",
+                close = close_time.format("%Y-%m-%d %H:%M"),
+                source = question.source,
+                resolve = question.end_date.format("%Y-%m-%d %H:%M"),
+            ))
+        }
+        description.push_str(&Self::render_footer(config, mirror_date));
+        if util::js_len(&description) > tmpl.max_description_length {
             warn!(
                 "Truncating description from {} to {} characters",
-                description.len(),
+                util::js_len(&description),
                 tmpl.max_description_length
             );
-            description.truncate(tmpl.max_description_length - 3);
-            description.push_str("...");
         }
-        description
+        util::truncate_graphemes(&description, tmpl.max_description_length, "...", 0)
+    }
+
+    /// Sparkline of the source forecast over the last `forecast_history_days`,
+    /// or since the first forecast if that's more recent
+    fn forecast_history_from_question(question: &Question, config: &Settings) -> Option<String> {
+        const SAMPLES: i32 = 30;
+        let days = config.manifold.template.forecast_history_days;
+        if days <= 0 || question.forecast_history.is_empty() {
+            return None;
+        }
+        let start = Utc::now() - Duration::days(days);
+        let step = Duration::days(days) / SAMPLES;
+        // the forecast standing at each sample time
+        let samples: Vec<f64> = (0..=SAMPLES)
+            .filter_map(|i| {
+                let time = start + step * i;
+                question
+                    .forecast_history
+                    .iter()
+                    .take_while(|point| point.time <= time)
+                    .last()
+                    .map(|point| point.probability)
+            })
+            .collect();
+        let (first, last) = (samples.first()?, samples.last()?);
+        if samples.len() < 2 {
+            return None;
+        }
+        Some(format!(
+            "**{source} forecast, last {days} days**\n\n`{sparkline}` {first:.0}% → {last:.0}%\n\n---\n\n",
+            source = question.source,
+            days = days,
+            sparkline = util::sparkline(&samples),
+            first = first * 100.0,
+            last = last * 100.0,
+        ))
+    }
+
+    /// Source background cut to `max_background_length`, with a link to the rest
+    fn background_from_question(question: &Question, config: &Settings) -> Option<String> {
+        let max_length = config.manifold.template.max_background_length;
+        let background = markdown::sanitize(question.background.as_deref()?, &question.source_url);
+        if max_length == 0 {
+            return None;
+        }
+        if util::js_len(&background) <= max_length {
+            return Some(background);
+        }
+        Some(format!(
+            "{}\n\n[Continue reading on {}]({})",
+            util::truncate_graphemes(&background, max_length, "...", 0),
+            question.source,
+            question.source_url
+        ))
     }
 
     pub fn group_ids_from_question(question: &Question, config: &Settings) -> Vec<String> {
@@ -559,26 +1059,80 @@ impl CreateMarketArgs {
             }
             QuestionSource::Kalshi => group_ids.extend(config.kalshi.add_group_ids.iter().cloned()),
             QuestionSource::Polymarket => {
-                todo!()
+                group_ids.extend(config.polymarket.add_group_ids.iter().cloned())
             }
             QuestionSource::Manual => {}
         }
         group_ids
     }
 
+    fn visibility_from_question(question: &Question, config: &Settings) -> Visibility {
+        match question.source {
+            QuestionSource::Metaculus => config.metaculus.visibility.clone(),
+            QuestionSource::Kalshi => config.kalshi.visibility.clone(),
+            QuestionSource::Polymarket => config.polymarket.visibility.clone(),
+            QuestionSource::Manual => Visibility::Public,
+        }
+    }
+
+    fn is_ranked_from_question(question: &Question, config: &Settings) -> bool {
+        match question.source {
+            QuestionSource::Metaculus => config.metaculus.ranked,
+            QuestionSource::Kalshi => config.kalshi.ranked,
+            QuestionSource::Polymarket => config.polymarket.ranked,
+            QuestionSource::Manual => true,
+        }
+    }
+
+    /// Start at the source probability if configured and available, otherwise 50%.
+    fn initial_prob_from_question(question: &Question, config: &Settings) -> u32 {
+        match question.probability {
+            Some(p) if config.manifold.use_source_probability => {
+                (p * 100.0).round().clamp(1.0, 99.0) as u32
+            }
+            _ => 50,
+        }
+    }
+
+    /// Close a day after the source ends, or when forecasting on the source closes
+    /// if that's earlier and the source is configured to. None if that time has passed.
+    pub fn close_time_from_question(
+        question: &Question,
+        config: &Settings,
+    ) -> Option<DateTime<Utc>> {
+        Some(
+            Self::source_close_time(question, config)
+                .unwrap_or(question.end_date + Duration::days(1)),
+        )
+        .filter(|close_time| *close_time > Utc::now())
+    }
+
+    /// Source close time to close the mirror at, if it comes before the end date
+    fn source_close_time(question: &Question, config: &Settings) -> Option<DateTime<Utc>> {
+        let use_close_time = match question.source {
+            QuestionSource::Metaculus => {
+                config.metaculus.close_mirrors_at == CloseMirrorsAt::Earlier
+            }
+            _ => false,
+        };
+        question
+            .close_time
+            .filter(|close_time| use_close_time && *close_time < question.end_date)
+    }
+
     pub fn from_question(config: &Settings, question: &Question) -> Self {
         Self {
             outcome_type: ManifoldOutcomeType::Binary,
             question: Self::title_from_question(question, config),
-            description_markdown: Self::description_from_question(question, config),
-            close_time: if question.end_date > Utc::now() {
-                question.end_date + Duration::days(1)
-            } else {
+            description_markdown: Self::description_from_question(question, config, Utc::now()),
+            close_time: Self::close_time_from_question(question, config).unwrap_or_else(|| {
                 warn!("Source question has end date in the past. Setting close date to a week from now.");
                 Utc::now() + Duration::weeks(1)
-            },
-            initial_prob: 50,
+            }),
+            initial_prob: Self::initial_prob_from_question(question, config),
             group_ids: Self::group_ids_from_question(question, config),
+            visibility: Self::visibility_from_question(question, config),
+            is_ranked: Self::is_ranked_from_question(question, config),
         }
     }
 }
@@ -0,0 +1,203 @@
+//! Cleanup of markdown copied from sources (resolution criteria, backgrounds)
+//! before it goes into Manifold descriptions. Manifold renders neither MathJax
+//! nor footnotes, and links relative to the source lead nowhere.
+
+use regex::{Captures, Regex};
+use reqwest::Url;
+
+/// LaTeX commands shown as the character they stand for, by name
+const LATEX_SYMBOLS: [(&str, &str); 23] = [
+    ("leq", "≤"),
+    ("geq", "≥"),
+    ("le", "≤"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("approx", "≈"),
+    ("times", "×"),
+    ("cdot", "·"),
+    ("pm", "±"),
+    ("infty", "∞"),
+    ("rightarrow", "→"),
+    ("to", "→"),
+    ("leftarrow", "←"),
+    ("in", "∈"),
+    ("sum", "Σ"),
+    ("Delta", "Δ"),
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("mu", "μ"),
+    ("sigma", "σ"),
+    ("pi", "π"),
+    ("left", ""),
+    ("right", ""),
+];
+
+/// Make source markdown render on Manifold: LaTeX becomes inline code with
+/// symbols spelled out, footnotes become plain bracketed numbers, links relative
+/// to `source_url` become absolute, headings become bold lines, and runs of
+/// blank lines are collapsed.
+pub fn sanitize(text: &str, source_url: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let text = Regex::new(r"(?i)<br\s*/?>")
+        .unwrap()
+        .replace_all(&text, "\n");
+    let text = replace_math(&text);
+    let text = replace_footnotes(&text);
+    let text = absolute_links(&text, source_url);
+    let text = Regex::new(r"(?m)^ {0,3}#{1,6}[ \t]+(.+?)[ \t]*#*[ \t]*$")
+        .unwrap()
+        .replace_all(&text, "**$1**");
+    let text = Regex::new(r"[ \t]+\n").unwrap().replace_all(&text, "\n");
+    let text = Regex::new(r"\n{3,}").unwrap().replace_all(&text, "\n\n");
+    text.trim().to_string()
+}
+
+/// Replace `$$…$$`, `\[…\]`, `\(…\)` and `$…$` spans with their rendering. Single
+/// dollar spans only count if they contain LaTeX syntax, dollars are usually money.
+fn replace_math(text: &str) -> String {
+    let math =
+        Regex::new(r"(?s)\$\$(.+?)\$\$|\\\[(.+?)\\\]|\\\((.+?)\\\)|\$([^$\n]*[\\^_{][^$\n]*)\$")
+            .unwrap();
+    math.replace_all(text, |caps: &Captures| {
+        let expression = (1..=4).find_map(|i| caps.get(i)).map_or("", |m| m.as_str());
+        format!("`{}`", render_latex(expression))
+    })
+    .into_owned()
+}
+
+/// Approximate LaTeX with plain text, good enough for the formulas in criteria
+fn render_latex(expression: &str) -> String {
+    let mut text = expression.to_string();
+    let frac = Regex::new(r"\\[dt]?frac\{([^{}]*)\}\{([^{}]*)\}").unwrap();
+    text = frac.replace_all(&text, "$1/$2").into_owned();
+    let wrapper =
+        Regex::new(r"\\(?:text|textbf|textit|mathrm|mathbf|operatorname|mbox)\{([^{}]*)\}")
+            .unwrap();
+    text = wrapper.replace_all(&text, "$1").into_owned();
+    text = Regex::new(r"\\([%$,; ])")
+        .unwrap()
+        .replace_all(&text, |caps: &Captures| match &caps[1] {
+            "%" => "%",
+            "$" => "$",
+            _ => " ",
+        })
+        .into_owned();
+    // unknown commands are shown by name
+    text = Regex::new(r"\\([A-Za-z]+)")
+        .unwrap()
+        .replace_all(&text, |caps: &Captures| {
+            LATEX_SYMBOLS
+                .iter()
+                .find(|(name, _)| *name == &caps[1])
+                .map_or_else(|| caps[1].to_string(), |(_, symbol)| symbol.to_string())
+        })
+        .into_owned();
+    text = text.replace(['{', '}'], "");
+    Regex::new(r"\s+")
+        .unwrap()
+        .replace_all(text.trim(), " ")
+        .into_owned()
+}
+
+/// `[^1]` references become `[1]`, and `[^1]: …` definitions lines starting
+/// with `[1]`, so they aren't read as link references
+fn replace_footnotes(text: &str) -> String {
+    let definitions = Regex::new(r"(?m)^\[\^([^\]\s]+)\]:[ \t]*").unwrap();
+    let text = definitions.replace_all(text, "[$1] ");
+    Regex::new(r"\[\^([^\]\s]+)\]")
+        .unwrap()
+        .replace_all(&text, "[$1]")
+        .into_owned()
+}
+
+/// Prefix links and images starting with `/` with the origin of `source_url`
+fn absolute_links(text: &str, source_url: &str) -> String {
+    let Some(origin) = Url::parse(source_url)
+        .ok()
+        .filter(|url| url.has_host())
+        .map(|url| url.origin().ascii_serialization())
+    else {
+        return text.to_string();
+    };
+    let text = Regex::new(r"\]\((/[^/)\s][^)\s]*|/)\)")
+        .unwrap()
+        .replace_all(text, |caps: &Captures| format!("]({}{})", origin, &caps[1]));
+    Regex::new(r#"\b(href|src)="(/[^/"][^"]*)""#)
+        .unwrap()
+        .replace_all(&text, |caps: &Captures| {
+            format!("{}=\"{}{}\"", &caps[1], origin, &caps[2])
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "https://www.metaculus.com/questions/123/will-x-happen/";
+
+    #[test]
+    fn relative_links_become_absolute() {
+        assert_eq!(
+            sanitize(
+                "See [this](/questions/5/) and [that](https://x.org/a)",
+                SOURCE
+            ),
+            "See [this](https://www.metaculus.com/questions/5/) and [that](https://x.org/a)"
+        );
+        assert_eq!(
+            sanitize("<a href=\"/faq/\">FAQ</a>", SOURCE),
+            "<a href=\"https://www.metaculus.com/faq/\">FAQ</a>"
+        );
+    }
+
+    #[test]
+    fn protocol_relative_links_are_kept() {
+        assert_eq!(
+            sanitize("[cdn](//cdn.example.com/x.png)", SOURCE),
+            "[cdn](//cdn.example.com/x.png)"
+        );
+    }
+
+    #[test]
+    fn latex_is_rendered_as_code() {
+        assert_eq!(
+            sanitize(
+                r"Resolves YES if \(X \geq 5\%\) of $$\frac{a}{b}$$.",
+                SOURCE
+            ),
+            "Resolves YES if `X ≥ 5%` of `a/b`."
+        );
+        assert_eq!(
+            sanitize(r"If $\text{GDP}_{2025} \leq 3$ then", SOURCE),
+            "If `GDP_2025 ≤ 3` then"
+        );
+    }
+
+    #[test]
+    fn dollar_amounts_are_not_math() {
+        assert_eq!(
+            sanitize("Costs between $5 and $10.", SOURCE),
+            "Costs between $5 and $10."
+        );
+    }
+
+    #[test]
+    fn footnotes_become_plain_references() {
+        assert_eq!(
+            sanitize("Claim.[^1]\n\n[^1]: Source.", SOURCE),
+            "Claim.[1]\n\n[1] Source."
+        );
+    }
+
+    #[test]
+    fn markdown_is_normalized() {
+        assert_eq!(
+            sanitize(
+                "## Details ##\r\nline one  \r\n\r\n\r\n\r\nline<br>two\n",
+                SOURCE
+            ),
+            "**Details**\nline one\n\nline\ntwo"
+        );
+    }
+}
@@ -1,67 +1,251 @@
+use std::pin::pin;
+
 use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, TryStreamExt};
 use log::{debug, info, warn};
 use reqwest::{
-    blocking::{Client, RequestBuilder},
-    header::AUTHORIZATION,
+    header::{HeaderValue, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, RequestBuilder, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value as JsonValue;
 use thiserror::Error;
 
+use crate::mirror::MirrorError;
 use crate::settings::{MetaculusQuestionRequirements, Settings};
-use crate::types::{BinaryResolution, Question, QuestionSource};
+use crate::source::MirrorSource;
+use crate::types::{BinaryResolution, ForecastPoint, Question, QuestionSource, RequirementCheck};
+use crate::{
+    db,
+    http::{self, HttpError},
+};
 
-fn list_questions(
-    client: &Client,
+/// What Metaculus mirrors close at (`metaculus.close_mirrors_at`)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseMirrorsAt {
+    /// When forecasting on Metaculus closes, if that's before the question
+    /// resolves. Otherwise like `ResolveTime`.
+    Earlier,
+    /// A day after the scheduled resolve time
+    ResolveTime,
+}
+
+/// Filter questions of a configured project must pass (`metaculus.projects.*.filter`)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectFilter {
+    /// `metaculus.auto_filter`
+    Auto,
+    /// `metaculus.request_filter`
+    Request,
+    /// Every open question of the project
+    #[serde(rename = "none")]
+    Off,
+}
+
+/// Which Metaculus API to talk to (`metaculus.api_version`)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    /// The `api2/questions` endpoints, detecting the schema of each response, so
+    /// mirrors keep syncing if Metaculus switches those endpoints to the new schema
+    Auto,
+    /// The `api2/questions` endpoints and the question schema from before the
+    /// site rewrite
+    Legacy,
+    /// The `api/posts` endpoints of the rewritten site
+    Posts,
+}
+
+/// API url of the question listing, or of a single question
+fn questions_url(config: &Settings, id: Option<u64>) -> String {
+    let path = match config.metaculus.api_version {
+        ApiVersion::Auto | ApiVersion::Legacy => "api2/questions/",
+        ApiVersion::Posts => "api/posts/",
+    };
+    match id {
+        Some(id) => format!("{}{}{}/", config.metaculus.url, path, id),
+        None => format!("{}{}", config.metaculus.url, path),
+    }
+}
+
+/// Parse a question in either schema. Post-rewrite questions are recognized by
+/// their missing `active_state`, unless `metaculus.api_version` pins the schema.
+pub fn parse_question(value: JsonValue, config: &Settings) -> Result<MetaculusQuestion> {
+    let posts_schema = match config.metaculus.api_version {
+        ApiVersion::Auto => value.get("active_state").is_none(),
+        ApiVersion::Legacy => false,
+        ApiVersion::Posts => true,
+    };
+    if posts_schema {
+        let post: Post =
+            serde_json::from_value(value).with_context(|| "failed to parse Metaculus post")?;
+        post.into_question(config)
+    } else {
+        serde_json::from_value(value).with_context(|| "failed to parse Metaculus question")
+    }
+}
+
+/// Questions matching `params`, following pagination
+pub fn stream_questions<'a>(
+    client: &'a Client,
     params: MetaculusListQuestionsParams,
+    config: &'a Settings,
+) -> impl Stream<Item = Result<MetaculusQuestion>> + 'a {
+    debug!("stream_questions called"); // (params: {:?})", params);
+    let first = add_auth(client.get(questions_url(config, None)), config);
+    let first = match config.metaculus.api_version {
+        ApiVersion::Posts => first.query(&params.posts_query()),
+        ApiVersion::Auto | ApiVersion::Legacy => first.query(&params),
+    };
+    // each page links to the next one
+    http::paginate(config, first, move |request| async move {
+        let resp: MetaculusQuestionsResponse = send_waiting_out_rate_limits(config, request)
+            .await?
+            .json()
+            .await?;
+        debug!("Fetched {} metaculus questions", resp.results.len());
+        let questions = resp
+            .results
+            .into_iter()
+            .map(|question| parse_question(question, config))
+            .collect::<Result<Vec<_>>>()?;
+        let next = resp.next.map(|next_url| {
+            debug!("Fetching metaculus questions (next={})", next_url);
+            add_auth(client.get(next_url), config)
+        });
+        Ok((questions, next))
+    })
+}
+
+/// Send a request, sleeping through 429 responses (at least a second at a time) for up to
+/// `metaculus.max_rate_limit_wait_secs` in total, so a long listing resumes where
+/// it was throttled instead of starting over on the next run
+async fn send_waiting_out_rate_limits(
     config: &Settings,
-) -> Result<MetaculusQuestionsResponse> {
-    debug!("list_questions called"); // (params: {:?})", params);
-    Ok(add_auth(
-        client.get("https://www.metaculus.com/api2/questions/"),
+    request: RequestBuilder,
+) -> Result<Response> {
+    let max_wait = std::time::Duration::from_secs(config.metaculus.max_rate_limit_wait_secs);
+    let mut waited = std::time::Duration::ZERO;
+    loop {
+        let attempt = request
+            .try_clone()
+            .ok_or_else(|| anyhow!("Metaculus request can't be repeated"))?;
+        match http::send(config, attempt).await {
+            Err(HttpError::RateLimited { retry_after, .. }) if waited < max_wait => {
+                let wait = retry_after
+                    .unwrap_or(std::time::Duration::from_millis(
+                        config.http.retry_max_delay_ms,
+                    ))
+                    .clamp(std::time::Duration::from_secs(1), max_wait - waited);
+                warn!(
+                    "Metaculus is rate limiting us, resuming in {}s ({}s waited so far)",
+                    wait.as_secs(),
+                    waited.as_secs()
+                );
+                tokio::time::sleep(wait).await;
+                waited += wait;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// Make a minimal authenticated request to check that the API key is accepted
+pub async fn check_auth(client: &Client, config: &Settings) -> Result<()> {
+    http::send(
         config,
+        add_auth(client.get(questions_url(config, None)), config).query(&[("limit", 1)]),
     )
-    .query(&params)
-    .send()?
-    .json()?)
+    .await?
+    .error_for_status()?;
+    Ok(())
 }
 
-/// list_questions but depaginated
-pub fn get_questions(
+pub async fn get_question(
     client: &Client,
-    params: MetaculusListQuestionsParams,
+    id: &str,
     config: &Settings,
-) -> Result<Vec<MetaculusQuestion>> {
-    debug!("get_questions called"); // (params: {:?})", params);
-    let mut questions = Vec::new();
-    let initial_resp = list_questions(client, params, config)?;
-    questions.extend(initial_resp.results.into_iter());
-    let mut next = initial_resp.next;
-    while let Some(next_url) = next {
-        debug!("Fetching metaculus questions (next={})", next_url);
-        let resp: MetaculusQuestionsResponse =
-            add_auth(client.get(next_url), config).send()?.json()?;
-        questions.extend(resp.results.into_iter());
-        next = resp.next;
-    }
-    Ok(questions)
-}
-
-pub fn get_question(client: &Client, id: &str, config: &Settings) -> Result<MetaculusQuestion> {
+) -> Result<MetaculusQuestion> {
     debug!("get_question called (id: {})", id);
     let id: u64 = id
         .parse()
         .with_context(|| "Metaculus question id should be a positive integer")?;
-    Ok(add_auth(
-        client.get(format!("https://www.metaculus.com/api2/questions/{}/", id)),
+    let question = http::send(
         config,
+        add_auth(client.get(questions_url(config, Some(id))), config),
     )
-    .send()?
-    .json()?)
+    .await?
+    .json()
+    .await?;
+    parse_question(question, config)
 }
 
-pub fn get_mirror_candidates(client: &Client, config: &Settings) -> Result<Vec<MetaculusQuestion>> {
+/// get_question for syncing. Responses are cached in the database and reused for
+/// `metaculus.cache_ttl_minutes`, after which they are revalidated with a
+/// conditional GET so unchanged questions aren't downloaded again.
+pub async fn get_question_cached(
+    client: &Client,
+    db: &rusqlite::Connection,
+    id: &str,
+    config: &Settings,
+) -> Result<MetaculusQuestion> {
+    debug!("get_question_cached called (id: {})", id);
+    let question_id: u64 = id
+        .parse()
+        .with_context(|| "Metaculus question id should be a positive integer")?;
+    let cached = db::get_metaculus_cache(db, question_id)?;
+    let mut request = add_auth(client.get(questions_url(config, Some(question_id))), config);
+    if let Some(cached) = &cached {
+        if Utc::now() - cached.fetched_time < Duration::minutes(config.metaculus.cache_ttl_minutes)
+        {
+            debug!("Using cached Metaculus question {}", question_id);
+            return parse_question(serde_json::from_str(&cached.body)?, config);
+        }
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let resp = http::send(config, request).await?;
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (resp.status(), &cached) {
+        debug!("Metaculus question {} not modified", question_id);
+        db::touch_metaculus_cache(db, question_id)?;
+        return parse_question(serde_json::from_str(&cached.body)?, config);
+    }
+    let resp = resp.error_for_status()?;
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+    let body = resp.text().await?;
+    let question = parse_question(serde_json::from_str(&body)?, config)?;
+    db::set_metaculus_cache(
+        db,
+        question_id,
+        etag.as_deref(),
+        last_modified.as_deref(),
+        &body,
+    )?;
+    Ok(question)
+}
+
+/// Questions that passed the auto filter, and those rejected along with the reason
+pub type MirrorCandidates = (
+    Vec<MetaculusQuestion>,
+    Vec<(MetaculusQuestion, MetaculusCheckFailure)>,
+);
+
+/// Fetch questions for auto-mirroring. Returns questions passing the auto filter,
+/// and those that were rejected along with the reason.
+pub async fn get_mirror_candidates(client: &Client, config: &Settings) -> Result<MirrorCandidates> {
     info!("Fetching mirror candidates from Metaculus");
     let requirements = &config.metaculus.auto_filter;
     let mut params = MetaculusListQuestionsParams {
@@ -81,110 +265,180 @@ pub fn get_mirror_candidates(client: &Client, config: &Settings) -> Result<Vec<M
     if requirements.exclude_grouped {
         params.has_group = Some(false);
     }
-    let questions = get_questions(client, params, config)
+    let mut questions = Vec::new();
+    let mut rejected = Vec::new();
+    let mut listed = pin!(stream_questions(client, params, config));
+    while let Some(question) = listed
+        .try_next()
+        .await
         .with_context(|| "failed to fetch questions from metaculus")?
-        .into_iter()
-        .filter(|q| check_question_requirements(q, requirements).is_ok())
-        .collect();
-    Ok(questions)
+    {
+        match check_question_requirements(&question, requirements) {
+            Ok(()) => questions.push(question),
+            Err(reason) => rejected.push((question, reason)),
+        }
+    }
+    Ok((questions, rejected))
 }
 
 pub fn check_question_requirements(
     question: &MetaculusQuestion,
     requirements: &MetaculusQuestionRequirements,
 ) -> Result<(), MetaculusCheckFailure> {
-    // fixed requirements
-    if !question.is_binary() {
-        return Err(MetaculusCheckFailure::NotBinary);
-    }
-    if question.is_conditional() {
-        return Err(MetaculusCheckFailure::Conditional);
-    }
-    if !question.is_forecast() {
-        return Err(MetaculusCheckFailure::NotForecast);
-    }
-    // config requirements
-    if requirements.require_visible_community_prediction && !question.community_prediction_visible()
-    {
-        return Err(MetaculusCheckFailure::NoCommunityPrediction);
-    }
-    if requirements.require_open && question.active_state != ActiveState::Open {
-        return Err(MetaculusCheckFailure::NotOpen);
-    }
-    if requirements.exclude_resolved && question.active_state == ActiveState::Resolved {
-        return Err(MetaculusCheckFailure::Resolved);
-    }
-    if requirements.exclude_grouped && question.is_grouped() {
-        return Err(MetaculusCheckFailure::Grouped);
-    }
-    if let Some(forecasters) = question.number_of_forecasters {
-        if forecasters < requirements.min_forecasters {
-            return Err(MetaculusCheckFailure::NotEnoughForecasters {
-                forecasters: forecasters,
-                threshold: requirements.min_forecasters,
-            });
-        }
-    } else {
+    if question.number_of_forecasters.is_none() {
         warn!(
             "Metaculus question with id {} has a null number_of_forecasters field and will be filtered out",
             question.id
         );
-        return Err(MetaculusCheckFailure::NotEnoughForecasters {
-            forecasters: -1,
-            threshold: requirements.min_forecasters,
-        });
     }
-    if question.votes < requirements.min_votes {
-        return Err(MetaculusCheckFailure::NotEnoughVotes {
-            votes: question.votes,
-            threshold: requirements.min_votes,
-        });
-    }
-    if question.time_to_resolution() < Duration::days(requirements.min_days_to_resolution) {
-        return Err(MetaculusCheckFailure::ResolvesTooSoon {
-            days_remaining: question.time_to_resolution().num_days(),
-            threshold: requirements.min_days_to_resolution,
-        });
-    }
-    if question.time_to_resolution() > Duration::days(requirements.max_days_to_resolution) {
-        return Err(MetaculusCheckFailure::ResolvesTooLate {
-            days_remaining: question.time_to_resolution().num_days(),
-            threshold: requirements.max_days_to_resolution,
-        });
-    }
-    if let Some(last_active) = question.last_activity_time {
-        let days_since_active = (Utc::now() - last_active).num_days();
-        if days_since_active > requirements.max_last_active_days {
-            return Err(MetaculusCheckFailure::NoRecentActivity {
+    RequirementCheck::first_failure(evaluate_question_requirements(question, requirements))
+}
+
+/// Evaluate every requirement, in the order [`check_question_requirements`] applies them
+pub fn evaluate_question_requirements(
+    question: &MetaculusQuestion,
+    requirements: &MetaculusQuestionRequirements,
+) -> Vec<RequirementCheck<MetaculusCheckFailure>> {
+    use MetaculusCheckFailure::*;
+    // missing values are reported as -1 and fail their check
+    let forecasters = question.number_of_forecasters.unwrap_or(-1);
+    let days_since_active = question
+        .last_activity_time
+        .map_or(-1, |last_active| (Utc::now() - last_active).num_days());
+    let days_to_resolution = question.time_to_resolution().num_days();
+    let probability = question.community_prediction_prob();
+    vec![
+        // fixed requirements
+        RequirementCheck::new(
+            "binary",
+            question.is_binary(),
+            true,
+            question.is_binary(),
+            || NotBinary,
+        ),
+        RequirementCheck::new(
+            "unconditional",
+            !question.is_conditional(),
+            true,
+            !question.is_conditional(),
+            || Conditional,
+        ),
+        RequirementCheck::new(
+            "forecast",
+            question.is_forecast(),
+            true,
+            question.is_forecast(),
+            || NotForecast,
+        ),
+        // config requirements
+        RequirementCheck::new(
+            "require_visible_community_prediction",
+            question.community_prediction_visible(),
+            requirements.require_visible_community_prediction,
+            !requirements.require_visible_community_prediction
+                || question.community_prediction_visible(),
+            || NoCommunityPrediction,
+        ),
+        RequirementCheck::new(
+            "require_open",
+            format!("{:?}", question.active_state),
+            requirements.require_open,
+            !requirements.require_open || question.active_state == ActiveState::Open,
+            || NotOpen,
+        ),
+        RequirementCheck::new(
+            "exclude_resolved",
+            format!("{:?}", question.active_state),
+            requirements.exclude_resolved,
+            !requirements.exclude_resolved || question.active_state != ActiveState::Resolved,
+            || Resolved,
+        ),
+        RequirementCheck::new(
+            "exclude_grouped",
+            question.is_grouped(),
+            requirements.exclude_grouped,
+            !requirements.exclude_grouped || !question.is_grouped(),
+            || Grouped,
+        ),
+        RequirementCheck::new(
+            "min_forecasters",
+            forecasters,
+            requirements.min_forecasters,
+            question.number_of_forecasters.is_some() && forecasters >= requirements.min_forecasters,
+            || NotEnoughForecasters {
+                forecasters,
+                threshold: requirements.min_forecasters,
+            },
+        ),
+        RequirementCheck::new(
+            "min_votes",
+            question.votes,
+            requirements.min_votes,
+            question.votes >= requirements.min_votes,
+            || NotEnoughVotes {
+                votes: question.votes,
+                threshold: requirements.min_votes,
+            },
+        ),
+        RequirementCheck::new(
+            "min_days_to_resolution",
+            days_to_resolution,
+            requirements.min_days_to_resolution,
+            question.time_to_resolution() >= Duration::days(requirements.min_days_to_resolution),
+            || ResolvesTooSoon {
+                days_remaining: days_to_resolution,
+                threshold: requirements.min_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_days_to_resolution",
+            days_to_resolution,
+            requirements.max_days_to_resolution,
+            question.time_to_resolution() <= Duration::days(requirements.max_days_to_resolution),
+            || ResolvesTooLate {
+                days_remaining: days_to_resolution,
+                threshold: requirements.max_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_last_active_days",
+            days_since_active,
+            requirements.max_last_active_days,
+            question.last_activity_time.is_some()
+                && days_since_active <= requirements.max_last_active_days,
+            || NoRecentActivity {
                 days_since_active,
                 threshold: requirements.max_last_active_days,
-            });
-        }
-    } else {
-        return Err(MetaculusCheckFailure::NoRecentActivity {
-            days_since_active: -1,
-            threshold: requirements.max_last_active_days,
-        });
-    }
-    if question.age() > Duration::days(requirements.max_age_days) {
-        return Err(MetaculusCheckFailure::TooOld {
-            age_days: question.age().num_days(),
-            threshold: requirements.max_age_days,
-        });
-    }
-    if let Some(p) = question.community_prediction_prob() {
-        if p.max(1.0 - p) > requirements.max_confidence {
-            return Err(MetaculusCheckFailure::TooExtreme {
-                probability: p,
+            },
+        ),
+        RequirementCheck::new(
+            "max_age_days",
+            question.age().num_days(),
+            requirements.max_age_days,
+            question.age() <= Duration::days(requirements.max_age_days),
+            || TooOld {
+                age_days: question.age().num_days(),
+                threshold: requirements.max_age_days,
+            },
+        ),
+        RequirementCheck::new(
+            "max_confidence",
+            probability.map_or("hidden".to_string(), |p| p.to_string()),
+            requirements.max_confidence,
+            probability.is_none_or(|p| p.max(1.0 - p) <= requirements.max_confidence),
+            || TooExtreme {
+                probability: probability.unwrap_or_default(),
                 threshold: requirements.max_confidence,
-            });
-        }
-    }
-    if requirements.exclude_ids.contains(&question.id) {
-        return Err(MetaculusCheckFailure::Banned);
-    }
-
-    Ok(())
+            },
+        ),
+        RequirementCheck::new(
+            "exclude_ids",
+            question.id,
+            format!("{} excluded", requirements.exclude_ids.len()),
+            !requirements.exclude_ids.contains(&question.id),
+            || Banned,
+        ),
+    ]
 }
 
 #[derive(Error, Debug)]
@@ -226,17 +480,18 @@ pub enum MetaculusCheckFailure {
     Banned,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum QuestionStatus {
-    #[serde(rename = "A")]
+    // the aliases are the curation statuses of the post schema
+    #[serde(rename = "A", alias = "approved")]
     Active,
-    #[serde(rename = "T")]
+    #[serde(rename = "T", alias = "draft")]
     Draft,
-    #[serde(rename = "I")]
+    #[serde(rename = "I", alias = "pending")]
     Inactive,
-    #[serde(rename = "R")]
+    #[serde(rename = "R", alias = "rejected")]
     Rejected,
-    #[serde(rename = "D")]
+    #[serde(rename = "D", alias = "deleted")]
     Deleted,
     #[serde(rename = "V")]
     Private,
@@ -270,36 +525,44 @@ impl Default for ForecastType {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ActiveState {
+    // the aliases are the question statuses of the post schema
+    #[serde(alias = "draft")]
     Draft,
+    #[serde(alias = "pending")]
     Pending,
     Deferred,
     Unmoderated,
+    #[serde(alias = "upcoming")]
     Upcoming,
+    #[serde(alias = "open")]
     Open,
+    #[serde(alias = "closed")]
     Closed,
+    #[serde(alias = "resolved")]
     Resolved,
+    #[serde(alias = "pending_resolution")]
     PendingResolution,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PossibilitiesStub {
     #[serde(default)]
     r#type: ForecastType,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CpsFull {
     // q1: Option<f64>,
     q2: Option<f64>,
     // q3: Option<f64>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommunityPredictionStub {
     full: Option<CpsFull>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MetaculusQuestion {
     pub active_state: ActiveState,
     /// API question url
@@ -315,6 +578,9 @@ pub struct MetaculusQuestion {
     pub status: QuestionStatus,
     pub resolution: Option<f64>,
     pub publish_time: DateTime<Utc>,
+    /// when forecasting closes, usually at or before `resolve_time`
+    #[serde(default)]
+    pub close_time: Option<DateTime<Utc>>,
     pub resolve_time: DateTime<Utc>,
     pub possibilities: PossibilitiesStub,
     pub r#type: QuestionType,
@@ -328,6 +594,23 @@ pub struct MetaculusQuestion {
     pub condition: Option<JsonValue>,
     /// only present in /questions/[id] response
     pub resolution_criteria: Option<String>,
+    /// background information, only present in /questions/[id] response
+    #[serde(default)]
+    pub description: Option<String>,
+    /// community prediction over time, only present in /questions/[id] response.
+    /// Left out of stored payloads, it can get long.
+    #[serde(default, skip_serializing)]
+    pub prediction_timeseries: Vec<PredictionPoint>,
+}
+
+/// Entry of [`MetaculusQuestion::prediction_timeseries`]
+#[derive(Deserialize, Debug, Clone)]
+pub struct PredictionPoint {
+    /// unix timestamp
+    pub t: f64,
+    /// a probability for binary questions
+    #[serde(default)]
+    pub community_prediction: Option<JsonValue>,
 }
 
 impl MetaculusQuestion {
@@ -436,16 +719,319 @@ impl TryInto<Question> for &MetaculusQuestion {
                 "{}\n\nFine print and additional background information can be found [on Metaculus]({}).", 
                 criteria, self.full_url()
             )),
+            background: self
+                .description
+                .clone()
+                .filter(|description| !description.trim().is_empty()),
             end_date: self.resolve_time,
+            close_time: self.close_time,
+            probability: self.community_prediction_prob(),
+            source_payload: serde_json::to_value(self).ok(),
+            market_summary: None,
+            forecast_history: self
+                .prediction_timeseries
+                .iter()
+                .filter_map(|point| {
+                    Some(ForecastPoint {
+                        time: DateTime::from_timestamp(point.t as i64, 0)?,
+                        probability: point.community_prediction.as_ref()?.as_f64()?,
+                    })
+                })
+                .collect(),
         })
     }
 }
 
+/// [`MirrorSource`] for Metaculus questions
+pub struct MetaculusSource;
+
+impl MirrorSource for MetaculusSource {
+    type Question = MetaculusQuestion;
+    type Requirements = MetaculusQuestionRequirements;
+    type CheckFailure = MetaculusCheckFailure;
+
+    const SOURCE: QuestionSource = QuestionSource::Metaculus;
+
+    async fn fetch(
+        client: &Client,
+        config: &Settings,
+        id: &str,
+    ) -> Result<MetaculusQuestion, MirrorError> {
+        Ok(get_question(client, id, config).await?)
+    }
+
+    async fn fetch_for_sync(
+        client: &Client,
+        db: &rusqlite::Connection,
+        config: &Settings,
+        id: &str,
+    ) -> Result<MetaculusQuestion, MirrorError> {
+        Ok(get_question_cached(client, db, id, config).await?)
+    }
+
+    async fn candidates(
+        client: &Client,
+        config: &Settings,
+    ) -> Result<MirrorCandidates, MirrorError> {
+        Ok(get_mirror_candidates(client, config).await?)
+    }
+
+    /// Listings leave out the resolution criteria, so fetch them if configured to
+    async fn prepare(
+        client: &Client,
+        config: &Settings,
+        question: &MetaculusQuestion,
+    ) -> Result<MetaculusQuestion, MirrorError> {
+        if config.metaculus.fetch_criteria && question.resolution_criteria.is_none() {
+            debug!("fetching criteria");
+            Ok(get_question(client, &question.id.to_string(), config).await?)
+        } else {
+            Ok(question.clone())
+        }
+    }
+
+    fn check(
+        question: &MetaculusQuestion,
+        requirements: &MetaculusQuestionRequirements,
+    ) -> Vec<RequirementCheck<MetaculusCheckFailure>> {
+        evaluate_question_requirements(question, requirements)
+    }
+
+    fn auto_filter(config: &Settings) -> Option<&MetaculusQuestionRequirements> {
+        Some(&config.metaculus.auto_filter)
+    }
+
+    fn request_filter(config: &Settings) -> &MetaculusQuestionRequirements {
+        &config.metaculus.request_filter
+    }
+
+    fn max_clones_per_day(config: &Settings) -> usize {
+        config.metaculus.max_clones_per_day
+    }
+
+    fn is_resolved(question: &MetaculusQuestion) -> bool {
+        question.is_resolved()
+    }
+
+    fn resolution(question: &MetaculusQuestion) -> Result<Option<BinaryResolution>> {
+        question.get_binary_resolution()
+    }
+
+    fn resolution_state(question: &MetaculusQuestion) -> String {
+        format!(
+            "Metaculus status {:?}, resolution {:?}",
+            question.status, question.resolution
+        )
+    }
+
+    fn probability(question: &MetaculusQuestion) -> Option<f64> {
+        question.community_prediction_prob()
+    }
+
+    fn id(question: &MetaculusQuestion) -> String {
+        question.id.to_string()
+    }
+
+    fn title(question: &MetaculusQuestion) -> String {
+        question.title.clone()
+    }
+
+    fn url(question: &MetaculusQuestion) -> String {
+        question.full_url()
+    }
+
+    fn to_question(question: &MetaculusQuestion) -> Result<Question> {
+        question
+            .try_into()
+            .with_context(|| "failed to convert Metaculus question to common format")
+    }
+}
+
+/// A post of the rewritten site. Posts wrap a question, a group of questions or
+/// a conditional pair; only the parts we use are parsed.
+#[derive(Deserialize, Debug)]
+struct Post {
+    id: i64,
+    title: String,
+    short_title: Option<String>,
+    slug: Option<String>,
+    author_id: i64,
+    author_username: String,
+    curation_status: QuestionStatus,
+    status: Option<ActiveState>,
+    created_at: DateTime<Utc>,
+    published_at: Option<DateTime<Utc>>,
+    edited_at: Option<DateTime<Utc>>,
+    scheduled_close_time: Option<DateTime<Utc>>,
+    scheduled_resolve_time: Option<DateTime<Utc>>,
+    nr_forecasters: Option<i64>,
+    #[serde(default)]
+    forecasts_count: i64,
+    vote: Option<PostVote>,
+    question: Option<PostQuestion>,
+    group_of_questions: Option<PostGroup>,
+    conditional: Option<JsonValue>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PostVote {
+    #[serde(default)]
+    score: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct PostGroup {
+    id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct PostQuestion {
+    r#type: String,
+    status: ActiveState,
+    /// "yes", "no", "ambiguous", "annulled", or the value of non-binary questions
+    resolution: Option<String>,
+    resolution_criteria: Option<String>,
+    description: Option<String>,
+    scheduled_close_time: Option<DateTime<Utc>>,
+    scheduled_resolve_time: DateTime<Utc>,
+    aggregations: Option<Aggregations>,
+}
+
+/// Aggregations replace `community_prediction`
+#[derive(Deserialize, Debug)]
+struct Aggregations {
+    recency_weighted: Option<Aggregation>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Aggregation {
+    latest: Option<AggregateForecast>,
+    #[serde(default)]
+    history: Vec<AggregateForecast>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AggregateForecast {
+    /// unix timestamp
+    start_time: Option<f64>,
+    /// the median for binary questions
+    centers: Option<Vec<f64>>,
+}
+
+impl Post {
+    /// Map the post onto the legacy question schema used throughout the bot. Post
+    /// ids are the ids questions had before the rewrite, so existing mirrors
+    /// still match.
+    fn into_question(self, config: &Settings) -> Result<MetaculusQuestion> {
+        let question = self.question.as_ref();
+        let resolve_time = question
+            .map(|q| q.scheduled_resolve_time)
+            .or(self.scheduled_resolve_time)
+            .ok_or_else(|| anyhow!("Metaculus post {} has no resolve time", self.id))?;
+        let close_time = question
+            .and_then(|q| q.scheduled_close_time)
+            .or(self.scheduled_close_time);
+        let active_state = question
+            .map(|q| q.status.clone())
+            .or(self.status)
+            .ok_or_else(|| anyhow!("Metaculus post {} has no status", self.id))?;
+        let latest = question
+            .and_then(|q| q.aggregations.as_ref())
+            .and_then(|a| a.recency_weighted.as_ref())
+            .and_then(|a| a.latest.as_ref());
+        let community_prediction = latest
+            .and_then(|latest| latest.centers.as_ref())
+            .and_then(|centers| centers.first())
+            .map(|&q2| CommunityPredictionStub {
+                full: Some(CpsFull { q2: Some(q2) }),
+            });
+        let prediction_timeseries = question
+            .and_then(|q| q.aggregations.as_ref())
+            .and_then(|a| a.recency_weighted.as_ref())
+            .map(|a| {
+                a.history
+                    .iter()
+                    .filter_map(|forecast| {
+                        Some(PredictionPoint {
+                            t: forecast.start_time?,
+                            community_prediction: Some(JsonValue::from(
+                                *forecast.centers.as_ref()?.first()?,
+                            )),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let last_forecast_time = latest
+            .and_then(|latest| latest.start_time)
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, 0));
+        // legacy resolutions are numbers, with -1 for ambiguous and -2 for annulled
+        let resolution = question
+            .and_then(|q| q.resolution.as_deref())
+            .and_then(|resolution| match resolution {
+                "yes" => Some(1.0),
+                "no" => Some(0.0),
+                "ambiguous" => Some(-1.0),
+                "annulled" => Some(-2.0),
+                value => value.parse().ok(),
+            });
+        let forecast_type = match question.map(|q| q.r#type.as_str()) {
+            Some("binary") => ForecastType::Binary,
+            Some("numeric" | "date") => ForecastType::Continuous,
+            _ => ForecastType::Unknown,
+        };
+        let r#type = if self.conditional.is_some() {
+            QuestionType::ConditionalGroup
+        } else if self.group_of_questions.is_some() {
+            QuestionType::Group
+        } else if question.is_some() {
+            QuestionType::Forecast
+        } else {
+            QuestionType::Notebook
+        };
+        Ok(MetaculusQuestion {
+            active_state,
+            url: questions_url(config, Some(self.id as u64)),
+            page_url: format!(
+                "/questions/{}/{}",
+                self.id,
+                self.slug.map(|slug| slug + "/").unwrap_or_default()
+            ),
+            id: self.id,
+            author: self.author_id,
+            author_name: self.author_username,
+            title_short: self.short_title.unwrap_or_else(|| self.title.clone()),
+            title: self.title,
+            status: self.curation_status,
+            resolution,
+            publish_time: self.published_at.unwrap_or(self.created_at),
+            close_time,
+            resolve_time,
+            possibilities: PossibilitiesStub {
+                r#type: forecast_type,
+            },
+            r#type,
+            edited_time: self.edited_at,
+            last_activity_time: last_forecast_time.or(self.edited_at),
+            votes: self.vote.map_or(0, |vote| vote.score),
+            community_prediction,
+            number_of_forecasters: self.nr_forecasters,
+            prediction_count: self.forecasts_count,
+            group: self.group_of_questions.map(|group| group.id),
+            condition: self.conditional,
+            description: question.and_then(|q| q.description.clone()),
+            prediction_timeseries,
+            resolution_criteria: self.question.and_then(|q| q.resolution_criteria),
+        })
+    }
+}
+
+/// Either schema, parsed with [`parse_question`]
 #[derive(Deserialize, Debug)]
 pub struct MetaculusQuestionsResponse {
     pub next: Option<String>,
     pub previous: Option<String>,
-    pub results: Vec<MetaculusQuestion>,
+    pub results: Vec<JsonValue>,
 }
 
 #[derive(Serialize, Debug, Default)]
@@ -487,6 +1073,54 @@ pub struct MetaculusListQuestionsParams {
     pub visible_from_project: Option<String>,
 }
 
+impl MetaculusListQuestionsParams {
+    /// The filters as `api/posts` query parameters. Filters it has no equivalent
+    /// for are left out, the requirement checks still apply them to the results.
+    fn posts_query(&self) -> Vec<(&'static str, String)> {
+        let time = |time: &DateTime<Utc>| time.to_rfc3339();
+        [
+            ("author", self.author.map(|a| a.to_string())),
+            ("forecast_type", self.forecast_type.clone()),
+            ("limit", self.limit.map(|l| l.to_string())),
+            ("offset", self.offset.map(|o| o.to_string())),
+            (
+                "order_by",
+                self.order_by.as_ref().map(|order| {
+                    order
+                        .replace("votes", "vote_score")
+                        .replace("publish_time", "published_at")
+                        .replace("resolve_time", "scheduled_resolve_time")
+                        .replace("close_time", "scheduled_close_time")
+                }),
+            ),
+            ("tournaments", self.project.clone()),
+            ("published_at__gt", self.publish_time_gt.as_ref().map(time)),
+            ("published_at__lt", self.publish_time_lt.as_ref().map(time)),
+            (
+                "scheduled_resolve_time__gt",
+                self.resolve_time_gt.as_ref().map(time),
+            ),
+            (
+                "scheduled_resolve_time__lt",
+                self.resolve_time_lt.as_ref().map(time),
+            ),
+            (
+                "scheduled_close_time__gt",
+                self.close_time_gt.as_ref().map(time),
+            ),
+            (
+                "scheduled_close_time__lt",
+                self.close_time_lt.as_ref().map(time),
+            ),
+            ("search", self.search.clone()),
+            ("statuses", self.status.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
 fn add_auth(req: RequestBuilder, config: &Settings) -> RequestBuilder {
     req.header(AUTHORIZATION, format!("Token {}", config.metaculus.api_key))
 }
@@ -1,320 +1,1792 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::pin::pin;
 
-use anyhow::Context;
-use chrono::{Duration, Utc};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use log::{debug, error, info, warn};
 use regex::Regex;
-use reqwest::blocking::Client;
+use reqwest::Client;
+use serde::Deserialize;
 use thiserror::Error;
 
 use crate::{
-    db::{self, MirrorRow},
-    kalshi::{self, KalshiMarket},
+    db::{
+        self, ActionKind, MirrorCommentKind, MirrorRow, NewRejection, PendingResolutionState,
+        SpendCategory,
+    },
+    destination::{Destination, MirrorDestination},
+    kalshi::{self, KalshiSource},
     log_if_err,
-    manifold::{self, CreateMarketArgs, GetMarketsArgs, LiteMarket, ManifoldMarket},
-    metaculus::{self, MetaculusQuestion},
-    settings::Settings,
-    types::{BinaryResolution, Question, QuestionSource},
+    manifold::{
+        self, CreateMarketArgs, GetMarketsArgs, GetPositionsArgs, LiteMarket, ManifoldMarket,
+        PostCommentArgs, SendManagramArgs, UpdateMarketArgs,
+    },
+    metaculus::{
+        self, MetaculusListQuestionsParams, MetaculusQuestion, MetaculusSource, ProjectFilter,
+    },
+    notify::{self, Event},
+    polymarket::{self, PolymarketSource},
+    recovery::{CreateMarketPayload, ManagramPayload, ResolutionPayload},
+    settings::{MetaculusProject, Settings},
+    shutdown, social,
+    source::MirrorSource,
+    types::{BinaryResolution, Question, QuestionSource, RequirementCheck},
 };
 
 // TODO: migrate from anyhow to this where it makes sense
 #[derive(Error, Debug)]
 pub enum MirrorError {
     #[error("Question has already been mirrored at {}", .0.manifold_url)]
-    AlreadyMirrored(MirrorRow),
+    AlreadyMirrored(Box<MirrorRow>),
     #[error(transparent)]
     KalshiError(#[from] kalshi::KalshiError),
     #[error(transparent)]
     ManifoldError(#[from] manifold::ManifoldError),
     #[error(transparent)]
+    PolymarketError(#[from] polymarket::PolymarketError),
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
-/// Attempt to mirror a question to Manifold.
+/// Fetch a source question in common format, and whether it has resolved
+pub async fn fetch_question(
+    client: &Client,
+    config: &Settings,
+    source: QuestionSource,
+    id: &str,
+) -> anyhow::Result<(Question, bool)> {
+    match source {
+        QuestionSource::Metaculus => {
+            fetch_question_from::<MetaculusSource>(client, config, id).await
+        }
+        QuestionSource::Kalshi => fetch_question_from::<KalshiSource>(client, config, id).await,
+        QuestionSource::Polymarket => {
+            fetch_question_from::<PolymarketSource>(client, config, id).await
+        }
+        QuestionSource::Manual => Err(anyhow!("Manual markets are not mirrors")),
+    }
+}
+
+async fn fetch_question_from<S: MirrorSource>(
+    client: &Client,
+    config: &Settings,
+    id: &str,
+) -> anyhow::Result<(Question, bool)> {
+    let source_question = S::fetch(client, config, id)
+        .await
+        .with_context(|| format!("failed to fetch question from {}", S::SOURCE))?;
+    let source_question = S::prepare(client, config, &source_question).await?;
+    Ok((
+        S::to_question(&source_question)?,
+        S::is_resolved(&source_question),
+    ))
+}
+
+/// Attempt to mirror a question to Manifold, with the named `manifold.accounts`
+/// entry or the one configured for the source if `destination` is None.
 /// Will fail if bot already mirrored the question, but does no other checks.
-pub fn mirror_question(
+pub async fn mirror_question(
+    client: &Client,
+    db: &rusqlite::Connection,
+    question: &Question,
+    destination: Option<&str>,
+    config: &Settings,
+) -> Result<MirrorRow, MirrorError> {
+    let args = CreateMarketArgs::from_question(config, question);
+    mirror_question_with_args(client, db, question, destination, args, config).await
+}
+
+/// Like [`mirror_question`], creating the market with `args` instead of the
+/// ones derived from the question
+pub async fn mirror_question_with_args(
     client: &Client,
     db: &rusqlite::Connection,
     question: &Question,
+    destination: Option<&str>,
+    args: CreateMarketArgs,
     config: &Settings,
 ) -> Result<MirrorRow, MirrorError> {
     info!(
         "Mirroring \"{}\" (id: {}) from {}",
         question.question, question.source_id, question.source
     );
+    let destination = destination.or_else(|| config.source_destination(&question.source));
     if let Some(mirror) = db::get_mirror_by_source_id(&db, &question.source, &question.source_id)? {
-        return Err(MirrorError::AlreadyMirrored(mirror));
+        return Err(MirrorError::AlreadyMirrored(Box::new(mirror)));
+    }
+    // an earlier attempt may have created the market but failed to record it
+    let payload = CreateMarketPayload {
+        question: question.clone(),
+        destination: destination.map(str::to_string),
+        title: Some(args.question.clone()),
+    };
+    if let Some(started) = db::start_mirror_creation(db, &payload)? {
+        if let Some(mirror) = adopt_unrecorded_mirror(
+            client,
+            db,
+            config,
+            question,
+            &args.question,
+            destination,
+            started,
+        )
+        .await?
+        {
+            return Ok(mirror);
+        }
+    }
+    let market = Destination::create_market(client, destination, args, config).await?;
+    match market.total_liquidity {
+        Some(cost) => log_if_err!(db::record_spend(
+            db,
+            SpendCategory::MarketCreation,
+            cost,
+            Some(&question.source),
+            &market.id,
+        )),
+        None => warn!(
+            "Created market {} without liquidity info, not recording its cost",
+            market.id
+        ),
+    }
+    let mirror = db::insert_mirror(db, &market, question, Utc::now(), destination, config)?;
+    notify::notify(client, config, Event::MirrorCreated(&mirror)).await;
+    social::announce_mirror(client, db, config, &mirror).await;
+    Ok(mirror)
+}
+
+/// Record the mirror of `question` titled `title` left behind by an attempt that
+/// started at `since` on the `destination` account, if there is one
+pub async fn adopt_unrecorded_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    question: &Question,
+    title: &str,
+    destination: Option<&str>,
+    since: DateTime<Utc>,
+) -> Result<Option<MirrorRow>, MirrorError> {
+    let account = config.for_destination(destination)?;
+    let Some(market) = find_unrecorded_mirror(client, db, &account, question, title, since).await?
+    else {
+        return Ok(None);
+    };
+    warn!(
+        "Adopting market {} created by an earlier attempt to mirror this question",
+        market.id
+    );
+    let mirror = db::insert_mirror(
+        db,
+        &market,
+        question,
+        market.created_time,
+        destination,
+        config,
+    )?;
+    notify::notify(client, config, Event::MirrorCreated(&mirror)).await;
+    social::announce_mirror(client, db, config, &mirror).await;
+    Ok(Some(mirror))
+}
+
+/// Look for a mirror of `question` on the configured account that isn't in the database,
+/// left behind by an attempt that started at `since` and failed after creating
+/// the market. Candidates have the title the mirror was created with, and are
+/// confirmed by their description linking to the source.
+async fn find_unrecorded_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    question: &Question,
+    title: &str,
+    since: DateTime<Utc>,
+) -> Result<Option<LiteMarket>, MirrorError> {
+    info!(
+        "Looking for a mirror of {} question {} left behind by an earlier attempt",
+        question.source, question.source_id
+    );
+    // listed newest first, with some slack for clock differences
+    let cutoff = since - Duration::minutes(5);
+    let mut markets = pin!(manifold::stream_markets(
+        client,
+        GetMarketsArgs {
+            user_id: Some(config.manifold.user_id.clone()),
+            ..Default::default()
+        },
+        config,
+    )
+    .try_take_while(|market| future::ready(Ok(market.created_time >= cutoff))));
+    while let Some(market) = markets.try_next().await? {
+        if market.question != title || db::get_mirror_by_contract_id(db, &market.id)?.is_some() {
+            continue;
+        }
+        let full = manifold::get_market(client, &market.id, config).await?;
+        if full.text_description.contains(&question.source_url) {
+            return Ok(Some(market));
+        }
+    }
+    Ok(None)
+}
+
+/// Attempt to mirror a question as returned by its source.
+/// Does not check configurable question requirements.
+pub async fn mirror_source_question<S: MirrorSource>(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    source_question: &S::Question,
+) -> Result<MirrorRow, MirrorError> {
+    debug!(
+        "Attempting to mirror {} question with id {} (\"{}\")",
+        S::SOURCE,
+        S::id(source_question),
+        S::title(source_question)
+    );
+    let source_question = S::prepare(client, config, source_question).await?;
+    let question = S::to_question(&source_question)?;
+    mirror_question(client, db, &question, None, config).await
+}
+
+/// Operator decision on an auto-mirror candidate
+pub enum CandidateDecision {
+    Mirror,
+    Skip,
+    /// Skip this and all remaining candidates
+    SkipAll,
+}
+
+/// Asks the operator about an auto-mirror candidate, given the question and
+/// the observed values of its auto filter requirements
+pub type ConfirmCandidate<'a> = dyn FnMut(&Question, &[String]) -> CandidateDecision + 'a;
+
+/// Ask the operator about a candidate. Candidates that can't be shown are skipped.
+fn confirm_candidate<E>(
+    confirm: &mut ConfirmCandidate,
+    question: anyhow::Result<Question>,
+    checks: Vec<RequirementCheck<E>>,
+) -> CandidateDecision {
+    let stats: Vec<String> = checks
+        .iter()
+        .map(|check| format!("{}: {}", check.name, check.observed))
+        .collect();
+    match question {
+        Ok(question) => confirm(&question, &stats),
+        Err(e) => {
+            error!("failed to convert candidate for review: {:#}", e);
+            CandidateDecision::Skip
+        }
+    }
+}
+
+/// Outcome of an auto-mirror run
+pub struct AutoMirrorResult {
+    /// Questions that passed filters and weren't mirrored yet
+    pub candidates: usize,
+    pub created: Vec<MirrorRow>,
+}
+
+/// Save candidates rejected by the auto filter so operators can review them.
+/// Failing to record them shouldn't stop the auto-mirror run, so errors are only logged.
+fn record_rejections(
+    db: &rusqlite::Connection,
+    source: QuestionSource,
+    rejections: Vec<NewRejection>,
+) {
+    info!(
+        "{} {} questions rejected by the auto filter",
+        rejections.len(),
+        source
+    );
+    if let Err(e) = db::insert_rejections(db, source, &rejections) {
+        error!("failed to record rejected candidates: {:#}", e);
+    }
+}
+
+/// Automatically pick and mirror questions from a source based on config.
+/// Returns the number of candidates and the mirrors that were created.
+/// If `confirm` is given, only candidates it approves count towards the daily budget.
+pub async fn auto_mirror<S: MirrorSource>(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    dry_run: bool,
+    mut confirm: Option<&mut ConfirmCandidate<'_>>,
+) -> Result<AutoMirrorResult, MirrorError> {
+    let source = S::SOURCE;
+    let auto_filter = S::auto_filter(config)
+        .ok_or_else(|| anyhow!("{} auto-mirroring hasn't been implemented yet", source))?;
+    // TODO: this should be cleaned up in general
+    let existing_clones = db::get_unresolved_mirrors(db, Some(source.clone()))?;
+    let (candidates, rejected) = S::candidates(client, config).await?;
+    record_rejections(
+        db,
+        source.clone(),
+        rejected
+            .iter()
+            .map(|(question, reason)| NewRejection {
+                source_id: S::id(question),
+                title: S::title(question),
+                source_url: S::url(question),
+                reason: reason.to_string(),
+            })
+            .collect(),
+    );
+    let candidates: Vec<S::Question> = candidates
+        .into_iter()
+        .filter(|q| {
+            db::get_any_mirror(db, &source, &S::id(q))
+                .unwrap() // TODO: handle error?
+                .is_none()
+        })
+        .collect();
+    info!(
+        "Obtained {} candidates for cloning from {}",
+        candidates.len(),
+        source
+    );
+    let clone_count_today = existing_clones
+        .iter()
+        .filter(|m| m.clone_date > Utc::now() - Duration::days(1))
+        .count();
+    let max_clones_per_day = S::max_clones_per_day(config);
+    let remaining_budget = max_clones_per_day - clone_count_today.min(max_clones_per_day); // TODO: might want to write a query for this?
+    info!(
+        "Cloned {} {} questions in last 24 hours. Remaining budget: {}",
+        clone_count_today, source, remaining_budget
+    );
+    let remaining_budget = limit_to_open_mirror_capacity(db, config, &source, remaining_budget)?;
+    let candidate_count = candidates.len();
+    let to_clone_count = remaining_budget.min(candidates.len());
+    info!("Attempting to clone top {} candidates", to_clone_count);
+    let mut created = Vec::new();
+    let mut attempted = 0;
+    for source_question in candidates {
+        if shutdown::requested() {
+            break;
+        }
+        if attempted >= to_clone_count {
+            break;
+        }
+        if let Some(confirm) = confirm.as_deref_mut() {
+            match confirm_candidate(
+                confirm,
+                S::to_question(&source_question),
+                S::check(&source_question, auto_filter),
+            ) {
+                CandidateDecision::Mirror => {}
+                CandidateDecision::Skip => continue,
+                CandidateDecision::SkipAll => break,
+            }
+        }
+        attempted += 1;
+        if dry_run {
+            info!(
+                "dry run -> skipping clone of question with id {}, ({}, {})",
+                S::id(&source_question),
+                S::title(&source_question),
+                S::url(&source_question)
+            );
+            continue;
+        }
+        match mirror_source_question::<S>(client, db, config, &source_question)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to mirror question with id {} (\"{}\")",
+                    S::id(&source_question),
+                    S::title(&source_question)
+                )
+            }) {
+            Ok(market) => {
+                info!("Created a mirror:\n{:#?}", market);
+                created.push(market);
+            }
+            Err(e) => error!("{:#}", e),
+        }
+    }
+    Ok(AutoMirrorResult {
+        candidates: candidate_count,
+        created,
+    })
+}
+
+/// Auto-mirror every project in `metaculus.projects`. Failures are logged per
+/// project so one broken project doesn't stop the others.
+pub async fn auto_mirror_projects(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    dry_run: bool,
+    mut confirm: Option<&mut ConfirmCandidate<'_>>,
+) -> AutoMirrorResult {
+    let mut result = AutoMirrorResult {
+        candidates: 0,
+        created: Vec::new(),
+    };
+    let mut names: Vec<&String> = config.metaculus.projects.keys().collect();
+    names.sort();
+    for name in names {
+        if shutdown::requested() {
+            break;
+        }
+        let project = &config.metaculus.projects[name];
+        match auto_mirror_project(client, db, config, project, dry_run, confirm.as_deref_mut())
+            .await
+            .with_context(|| format!("failed to auto-mirror Metaculus project {}", name))
+        {
+            Ok(project_result) => {
+                result.candidates += project_result.candidates;
+                result.created.extend(project_result.created);
+            }
+            Err(e) => error!("{:#}", e),
+        }
+    }
+    result
+}
+
+/// Mirror the open binary questions of a Metaculus project that pass its filter,
+/// titled with the project's header and added to its group. Mirrors are recorded
+/// with the project, for its `max_clones_per_day`.
+pub async fn auto_mirror_project(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    project: &MetaculusProject,
+    dry_run: bool,
+    mut confirm: Option<&mut ConfirmCandidate<'_>>,
+) -> Result<AutoMirrorResult, MirrorError> {
+    let source = QuestionSource::Metaculus;
+    let filter = match project.filter {
+        ProjectFilter::Auto => Some(&config.metaculus.auto_filter),
+        ProjectFilter::Request => Some(&config.metaculus.request_filter),
+        ProjectFilter::Off => None,
+    };
+    let questions: Vec<MetaculusQuestion> = metaculus::stream_questions(
+        client,
+        MetaculusListQuestionsParams {
+            project: Some(project.id.to_string()),
+            r#type: Some(metaculus::QuestionType::Forecast),
+            forecast_type: Some("binary".to_string()),
+            ..Default::default()
+        },
+        config,
+    )
+    .try_collect()
+    .await
+    .with_context(|| format!("failed to fetch questions of project {}", project.id))?;
+    let mut candidates = Vec::new();
+    for question in questions {
+        if question.is_resolved() {
+            continue;
+        }
+        if let Some(Err(reason)) =
+            filter.map(|filter| metaculus::check_question_requirements(&question, filter))
+        {
+            debug!(
+                "Skipping project question {} (\"{}\"): {}",
+                question.id, question.title, reason
+            );
+            continue;
+        }
+        if db::get_mirror_by_source_id(db, &source, &question.id.to_string())?.is_none() {
+            candidates.push(question);
+        }
+    }
+    info!(
+        "Obtained {} candidates for cloning from Metaculus project {}",
+        candidates.len(),
+        project.id
+    );
+    let mut remaining_budget = usize::MAX;
+    if project.max_clones_per_day > 0 {
+        let clone_count_today =
+            db::count_project_mirrors_since(db, project.id, Utc::now() - Duration::days(1))?;
+        remaining_budget =
+            project.max_clones_per_day - clone_count_today.min(project.max_clones_per_day);
+        info!(
+            "Cloned {} questions of project {} in last 24 hours. Remaining budget: {}",
+            clone_count_today, project.id, remaining_budget
+        );
+    }
+    let remaining_budget = limit_to_open_mirror_capacity(db, config, &source, remaining_budget)?;
+    let candidate_count = candidates.len();
+    let mut created = Vec::new();
+    let mut attempted = 0;
+    for question in candidates {
+        if shutdown::requested() || attempted >= remaining_budget {
+            break;
+        }
+        if let Some(confirm) = confirm.as_deref_mut() {
+            let checks = filter
+                .map(|filter| MetaculusSource::check(&question, filter))
+                .unwrap_or_default();
+            match confirm_candidate(confirm, MetaculusSource::to_question(&question), checks) {
+                CandidateDecision::Mirror => {}
+                CandidateDecision::Skip => continue,
+                CandidateDecision::SkipAll => break,
+            }
+        }
+        attempted += 1;
+        if dry_run {
+            info!(
+                "dry run -> skipping clone of project question with id {}, ({})",
+                question.id, question.title
+            );
+            continue;
+        }
+        match mirror_project_question(client, db, config, project, &question)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to mirror project question with id {} (\"{}\")",
+                    question.id, question.title
+                )
+            }) {
+            Ok(mirror) => {
+                info!("Created a mirror:\n{:#?}", mirror);
+                created.push(mirror);
+            }
+            Err(e) => error!("{:#}", e),
+        }
+    }
+    Ok(AutoMirrorResult {
+        candidates: candidate_count,
+        created,
+    })
+}
+
+async fn mirror_project_question(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    project: &MetaculusProject,
+    question: &MetaculusQuestion,
+) -> Result<MirrorRow, MirrorError> {
+    let question = MetaculusSource::prepare(client, config, question).await?;
+    let question = MetaculusSource::to_question(&question)?;
+    let mut args = CreateMarketArgs::from_question(config, &question);
+    args.question = CreateMarketArgs::title_from_format(
+        &question,
+        &format!("[{}] {{title}}", project.header),
+        config,
+    );
+    if !project.group_id.is_empty() {
+        args.group_ids.push(project.group_id.clone());
+    }
+    let mirror = mirror_question_with_args(client, db, &question, None, args, config).await?;
+    db::insert_project_mirror(db, project.id, mirror.id)?;
+    Ok(mirror)
+}
+
+/// How many more mirrors of `source` may be opened under the global and per-source
+/// `max_open_mirrors` limits, or None if neither is set
+pub fn open_mirror_capacity(
+    db: &rusqlite::Connection,
+    config: &Settings,
+    source: &QuestionSource,
+) -> anyhow::Result<Option<usize>> {
+    let source_limit = match source {
+        QuestionSource::Kalshi => config.kalshi.max_open_mirrors,
+        QuestionSource::Metaculus => config.metaculus.max_open_mirrors,
+        QuestionSource::Polymarket => config.polymarket.max_open_mirrors,
+        QuestionSource::Manual => 0,
+    };
+    let mut capacity: Option<usize> = None;
+    for (limit, source) in [
+        (config.manifold.max_open_mirrors, None),
+        (source_limit, Some(source)),
+    ] {
+        if limit > 0 {
+            let remaining = limit.saturating_sub(db::count_open_mirrors(db, source)?);
+            capacity = Some(capacity.map_or(remaining, |c| c.min(remaining)));
+        }
+    }
+    Ok(capacity)
+}
+
+/// Cap an auto-mirror budget at the open mirror capacity
+fn limit_to_open_mirror_capacity(
+    db: &rusqlite::Connection,
+    config: &Settings,
+    source: &QuestionSource,
+    budget: usize,
+) -> anyhow::Result<usize> {
+    match open_mirror_capacity(db, config, source)? {
+        Some(capacity) if capacity < budget => {
+            info!(
+                "Only {} more {} mirrors allowed by max_open_mirrors",
+                capacity, source
+            );
+            Ok(capacity)
+        }
+        _ => Ok(budget),
+    }
+}
+
+/// Resolve mirrored market, unless its volume is high enough that the resolution
+/// should be confirmed by an operator first, or a second look at the source
+/// disagrees (with `sync.confirm_resolutions`).
+/// Returns whether the market was resolved.
+async fn resolve_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    resolution: BinaryResolution,
+    source_state: &str,
+    config: &Settings,
+) -> Result<bool, MirrorError> {
+    if config.sync.confirm_resolutions
+        && !resolution_confirmed(client, mirror, &resolution, config).await?
+    {
+        return Ok(false);
+    }
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    if market.volume >= config.manifold.confirm_resolution_volume {
+        hold_resolution(db, mirror, resolution, market.volume)?;
+        return Ok(false);
+    }
+    apply_resolution(client, db, mirror, resolution, source_state, config).await?;
+    Ok(true)
+}
+
+/// Fetch the source again after `sync.confirm_resolution_delay_secs`, bypassing
+/// caches, and check that it still reports `resolution`. Guards against source
+/// APIs briefly serving bad data.
+async fn resolution_confirmed(
+    client: &Client,
+    mirror: &MirrorRow,
+    resolution: &BinaryResolution,
+    config: &Settings,
+) -> Result<bool, MirrorError> {
+    tokio::time::sleep(std::time::Duration::from_secs(
+        config.sync.confirm_resolution_delay_secs,
+    ))
+    .await;
+    let id = &mirror.source_id;
+    let second = match mirror.source {
+        QuestionSource::Metaculus => {
+            fetch_resolution::<MetaculusSource>(client, config, id).await?
+        }
+        QuestionSource::Kalshi => fetch_resolution::<KalshiSource>(client, config, id).await?,
+        QuestionSource::Polymarket => {
+            fetch_resolution::<PolymarketSource>(client, config, id).await?
+        }
+        QuestionSource::Manual => None,
+    };
+    if second.as_ref() != Some(resolution) {
+        warn!(
+            "Source of mirror with row id {} reported {:?}, but {:?} when fetched again. Not resolving.",
+            mirror.id, resolution, second
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Fetch a question, bypassing caches, and return its resolution
+async fn fetch_resolution<S: MirrorSource>(
+    client: &Client,
+    config: &Settings,
+    id: &str,
+) -> Result<Option<BinaryResolution>, MirrorError> {
+    Ok(S::resolution(&S::fetch(client, config, id).await?)?)
+}
+
+/// Resolve mirror on Manifold and mark it resolved, without any confirmation checks.
+/// `source_state` describes why, for the resolution history.
+pub async fn apply_resolution(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    resolution: BinaryResolution,
+    source_state: &str,
+    config: &Settings,
+) -> Result<(), MirrorError> {
+    let action = db::start_action(
+        db,
+        ActionKind::ResolveMarket,
+        &mirror.manifold_contract_id,
+        &ResolutionPayload {
+            mirror_id: mirror.id,
+            resolution: resolution.clone(),
+        },
+    )?;
+    Destination::resolve_market(
+        client,
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        &resolution,
+        config,
+    )
+    .await?;
+    db::set_mirror_resolution(db, mirror.id, &resolution)?;
+    db::complete_action(db, action)?;
+    log_if_err!(db::insert_resolution_event(
+        db,
+        mirror.id,
+        Some(&resolution),
+        source_state
+    ));
+    notify::notify(
+        client,
+        config,
+        Event::MirrorResolved {
+            mirror,
+            resolution: &resolution,
+        },
+    )
+    .await;
+    Ok(())
+}
+
+/// Undo the resolution of a mirror on Manifold and mark it unresolved.
+/// `source_state` describes why, for the resolution history.
+pub async fn unresolve_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    source_state: &str,
+    config: &Settings,
+) -> Result<(), MirrorError> {
+    Destination::unresolve_market(
+        client,
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        config,
+    )
+    .await?;
+    db::set_mirror_unresolved(db, mirror.id)?;
+    log_if_err!(db::insert_resolution_event(
+        db,
+        mirror.id,
+        None,
+        source_state
+    ));
+    Ok(())
+}
+
+/// Resolve a mirror N/A and retire it, optionally commenting why first
+pub async fn abandon_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    comment: Option<String>,
+    config: &Settings,
+) -> Result<(), MirrorError> {
+    info!(
+        "Abandoning mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    if let Some(comment) = comment {
+        Destination::post_comment(
+            client,
+            mirror.destination.as_deref(),
+            &PostCommentArgs {
+                contract_id: mirror.manifold_contract_id.clone(),
+                markdown: comment,
+                reply_to_comment_id: None,
+            },
+            config,
+        )
+        .await?;
+    }
+    apply_resolution(
+        client,
+        db,
+        mirror,
+        BinaryResolution::Cancel,
+        "abandoned by operator",
+        config,
+    )
+    .await?;
+    db::set_mirror_retired(db, mirror.id)?;
+    Ok(())
+}
+
+/// Record a resolution that needs operator confirmation, if we haven't already.
+fn hold_resolution(
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    resolution: BinaryResolution,
+    volume: f64,
+) -> Result<(), MirrorError> {
+    match db::get_latest_pending_resolution(db, mirror.id)? {
+        Some(row) if row.state == PendingResolutionState::Pending => {
+            if row.resolution != resolution {
+                warn!(
+                    "Source resolution for mirror with row id {} changed from {:?} to {:?} \
+                    while awaiting confirmation. Updating pending resolution with id {}.",
+                    mirror.id, row.resolution, resolution, row.id
+                );
+                db::set_pending_resolution_value(db, row.id, &resolution)?;
+            } else {
+                debug!(
+                    "Resolution for mirror with row id {} has been awaiting confirmation since {} (id {})",
+                    mirror.id, row.created_time, row.id
+                );
+            }
+        }
+        Some(row)
+            if row.state == PendingResolutionState::Rejected && row.resolution == resolution =>
+        {
+            debug!(
+                "Resolution {:?} for mirror with row id {} was rejected by an operator. Skipping.",
+                resolution, mirror.id
+            );
+        }
+        _ => {
+            let row = db::insert_pending_resolution(db, mirror.id, &resolution, volume)?;
+            warn!(
+                "Holding resolution {:?} for mirror with row id {} (\"{}\", volume {:.0}) until \
+                confirmed. Run `approve-resolution {}` or `reject-resolution {}`.",
+                resolution, mirror.id, mirror.question, volume, row.id, row.id
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Apply a resolution that was held for confirmation.
+pub async fn approve_pending_resolution(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    id: i64,
+) -> Result<MirrorRow, MirrorError> {
+    let pending = db::get_pending_resolution(db, id)?
+        .ok_or_else(|| anyhow!("no pending resolution with id {}", id))?;
+    if pending.state != PendingResolutionState::Pending {
+        return Err(anyhow!("resolution with id {} is already {:?}", id, pending.state).into());
+    }
+    let mirror = db::get_mirror_by_id(db, pending.market_id)?
+        .ok_or_else(|| anyhow!("mirror with row id {} not found", pending.market_id))?;
+    if mirror.resolved {
+        return Err(anyhow!("mirror with row id {} is already resolved", mirror.id).into());
+    }
+    info!(
+        "Applying approved resolution {:?} to mirror with row id {} (\"{}\", volume {:.0})",
+        pending.resolution, mirror.id, mirror.question, pending.volume
+    );
+    let source_state = format!(
+        "approved pending resolution {} held at volume {:.0}",
+        id, pending.volume
+    );
+    apply_resolution(
+        client,
+        db,
+        &mirror,
+        pending.resolution,
+        &source_state,
+        config,
+    )
+    .await?;
+    db::set_pending_resolution_state(db, id, PendingResolutionState::Approved)?;
+    Ok(mirror)
+}
+
+/// Discard a resolution that was held for confirmation.
+/// The same resolution will not be held again for this mirror.
+pub fn reject_pending_resolution(db: &rusqlite::Connection, id: i64) -> Result<(), MirrorError> {
+    let pending = db::get_pending_resolution(db, id)?
+        .ok_or_else(|| anyhow!("no pending resolution with id {}", id))?;
+    if pending.state != PendingResolutionState::Pending {
+        return Err(anyhow!("resolution with id {} is already {:?}", id, pending.state).into());
+    }
+    db::set_pending_resolution_state(db, id, PendingResolutionState::Rejected)?;
+    Ok(())
+}
+
+/// Check if source question has resolved and sync resolution to mirror.
+async fn sync_source_mirror<S: MirrorSource>(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<bool, MirrorError> {
+    assert!(mirror.source == S::SOURCE);
+    let source_question = S::fetch_for_sync(client, db, config, &mirror.source_id).await?;
+    db::set_mirror_synced(db, mirror.id, S::probability(&source_question))?;
+    if let Some(resolution) = S::resolution(&source_question)? {
+        info!(
+            "{} question \"{}\" (source id: {}) has resolved {:?}. Syncing.",
+            S::SOURCE,
+            mirror.question,
+            mirror.source_id,
+            resolution
+        );
+        let source_state = S::resolution_state(&source_question);
+        resolve_mirror(client, db, mirror, resolution, &source_state, config).await
+    } else {
+        debug!("Source has not resolved yet");
+        Ok(false)
+    }
+}
+
+/// Check if source resolved and sync resolution to Manifold
+pub async fn sync_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<bool, MirrorError> {
+    debug!(
+        "Syncing resolution for {} question at {}",
+        mirror.source, mirror.source_url
+    );
+    match mirror.source {
+        QuestionSource::Metaculus => {
+            sync_source_mirror::<MetaculusSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Kalshi => {
+            sync_source_mirror::<KalshiSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Polymarket => {
+            sync_source_mirror::<PolymarketSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Manual => Ok(false),
+    }
+}
+
+/// Sync everything about one mirror: state from Manifold, resolution from the
+/// source and, if still open, close time and description drift.
+/// Returns whether the mirror was resolved.
+pub async fn sync_single_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<bool, MirrorError> {
+    info!(
+        "Syncing mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    db::update_mirror_from_market(db, mirror.id, &(&market).into())?;
+    if market.is_resolved || mirror.source == QuestionSource::Manual {
+        return Ok(false);
+    }
+    if sync_mirror(client, db, mirror, config).await? {
+        return Ok(true);
+    }
+    if let Some(close_time) = refresh_mirror(client, db, mirror, config).await? {
+        db::set_mirror_close_time(db, mirror.id, close_time)?;
+    }
+    Ok(false)
+}
+
+/// Fetch the current state of a mirror's source question.
+async fn fetch_source_question(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<Question, MirrorError> {
+    Ok(fetch_source(client, db, mirror, config).await?.question)
+}
+
+/// Current state of a mirror's source question.
+pub struct SourceState {
+    pub question: Question,
+    pub resolution: Option<BinaryResolution>,
+}
+
+impl SourceState {
+    /// Human readable summary of the source's current forecast, if any
+    fn forecast_summary(&self) -> Option<String> {
+        let bid_ask = self
+            .question
+            .market_summary
+            .as_ref()
+            .and_then(|summary| summary.bid_ask);
+        match (&self.question.source, bid_ask, self.question.probability) {
+            (QuestionSource::Kalshi, Some((bid, ask)), _) => Some(format!(
+                "Kalshi yes bid/ask is {:.0}¢/{:.0}¢",
+                bid * 100.0,
+                ask * 100.0
+            )),
+            (QuestionSource::Metaculus, _, Some(p)) => Some(format!(
+                "Metaculus community prediction is {:.0}%",
+                p * 100.0
+            )),
+            (source, _, Some(p)) => Some(format!("{} probability is {:.0}%", source, p * 100.0)),
+            (_, _, None) => None,
+        }
+    }
+}
+
+/// Fetch a mirror's source question along with its resolution, if it has resolved.
+pub async fn fetch_source(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<SourceState, MirrorError> {
+    match mirror.source {
+        QuestionSource::Metaculus => {
+            fetch_source_state::<MetaculusSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Kalshi => {
+            fetch_source_state::<KalshiSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Polymarket => {
+            fetch_source_state::<PolymarketSource>(client, db, mirror, config).await
+        }
+        QuestionSource::Manual => Err(anyhow!("manual markets have no source question").into()),
+    }
+}
+
+async fn fetch_source_state<S: MirrorSource>(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<SourceState, MirrorError> {
+    let source_question = S::fetch_for_sync(client, db, config, &mirror.source_id).await?;
+    Ok(SourceState {
+        question: S::to_question(&source_question)?,
+        resolution: S::resolution(&source_question)?,
+    })
+}
+
+/// Source question for re-rendering a mirror's description, without the forecast
+/// history unless `refresh_forecast_history` is set
+async fn refreshed_source_question(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<Question, MirrorError> {
+    let mut question = fetch_source_question(client, db, mirror, config).await?;
+    if !config.manifold.template.refresh_forecast_history {
+        question.forecast_history.clear();
+    }
+    Ok(question)
+}
+
+/// Re-render a mirror's description from its source, refreshing the dynamic
+/// parts of the footer.
+pub async fn refresh_mirror_description(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<(), MirrorError> {
+    debug!(
+        "Refreshing description for mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let question = refreshed_source_question(client, db, mirror, config).await?;
+    Destination::update_market(
+        client,
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        &UpdateMarketArgs {
+            description_markdown: Some(CreateMarketArgs::description_from_question(
+                &question,
+                config,
+                mirror.clone_date,
+            )),
+            ..Default::default()
+        },
+        config,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Re-fetch a mirror's source and update description and close time to match.
+/// Returns the new close time, if it changed.
+pub async fn refresh_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+) -> Result<Option<DateTime<Utc>>, MirrorError> {
+    info!(
+        "Refreshing mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let question = refreshed_source_question(client, db, mirror, config).await?;
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    // Manifold stores close time with millisecond precision
+    let close_time = CreateMarketArgs::close_time_from_question(&question, config)
+        .filter(|t| t.timestamp_millis() != market.close_time.timestamp_millis());
+    if let Some(close_time) = close_time {
+        info!(
+            "Moving close time of mirror with row id {} from {} to {}",
+            mirror.id, market.close_time, close_time
+        );
+    }
+    Destination::update_market(
+        client,
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        &UpdateMarketArgs {
+            description_markdown: Some(CreateMarketArgs::description_from_question(
+                &question,
+                config,
+                mirror.clone_date,
+            )),
+            close_time,
+            ..Default::default()
+        },
+        config,
+    )
+    .await?;
+    Ok(close_time)
+}
+
+/// Move a mirror's close time, e.g. to close it right away
+pub async fn close_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    close_time: DateTime<Utc>,
+    config: &Settings,
+) -> Result<(), MirrorError> {
+    info!(
+        "Moving close time of mirror with row id {} from {:?} to {}",
+        mirror.id, mirror.close_time, close_time
+    );
+    Destination::update_market(
+        client,
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        &UpdateMarketArgs {
+            close_time: Some(close_time),
+            ..Default::default()
+        },
+        config,
+    )
+    .await?;
+    db::set_mirror_close_time(db, mirror.id, close_time)?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ExtendOutcome {
+    /// Close time was moved to the contained time
+    Extended(DateTime<Utc>),
+    /// Market is already open until the contained time, which is at least as late
+    /// as we would have extended it to
+    AlreadyOpen(DateTime<Utc>),
+    /// Source has resolved, so the mirror should be resolved instead
+    SourceResolved(BinaryResolution),
+}
+
+/// Push a mirror's close time forward while its source is unresolved. Extends to
+/// the source end date if that is later than `extension_days` from now.
+pub async fn extend_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    mirror: &MirrorRow,
+    config: &Settings,
+    extension_days: i64,
+) -> Result<ExtendOutcome, MirrorError> {
+    info!(
+        "Extending close time of mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let SourceState {
+        question,
+        resolution,
+        ..
+    } = fetch_source(client, db, mirror, config).await?;
+    if let Some(resolution) = resolution {
+        return Ok(ExtendOutcome::SourceResolved(resolution));
+    }
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    let close_time =
+        (Utc::now() + Duration::days(extension_days)).max(question.end_date + Duration::days(1));
+    if market.close_time >= close_time {
+        return Ok(ExtendOutcome::AlreadyOpen(market.close_time));
     }
-    let market = manifold::create_market(
+    info!(
+        "Moving close time of mirror with row id {} from {} to {}",
+        mirror.id, market.close_time, close_time
+    );
+    Destination::update_market(
         client,
-        CreateMarketArgs::from_question(config, question),
+        mirror.destination.as_deref(),
+        &mirror.manifold_contract_id,
+        &UpdateMarketArgs {
+            close_time: Some(close_time),
+            ..Default::default()
+        },
         config,
-    )?;
-    Ok(db::insert_mirror(db, &market, &question, config)?)
+    )
+    .await?;
+    Ok(ExtendOutcome::Extended(close_time))
 }
 
-/// Attempt to mirror a Kalshi question.
-/// Does not check configurable question requirements.
-/// Will error if given a multimarket.
-pub fn mirror_kalshi_question(
+/// Fetch trading volume and trader counts of mirrors from Manifold into the
+/// database. Resolved mirrors are fetched once more after resolving, to record
+/// their final activity.
+pub async fn sync_analytics(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
-    kalshi_market: &KalshiMarket,
-) -> Result<MirrorRow, MirrorError> {
-    debug!(
-        "Attempting to mirror kalshi question with id {} (\"{}\")",
-        kalshi_market.id(),
-        kalshi_market.title()
-    );
-    let question: Question = kalshi_market
-        .try_into()
-        .with_context(|| "failed to convert Kalshi question to common format")?;
-    Ok(mirror_question(client, db, &question, config)?)
+) -> Result<(), MirrorError> {
+    let mirrors = db::get_mirrors_for_analytics(db)?;
+    info!("Fetching trading activity of {} mirrors", mirrors.len());
+    let mut fetched = pin!(stream::iter(mirrors)
+        .take_while(|_| future::ready(!shutdown::requested()))
+        .map(|mirror| async move {
+            let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await;
+            (mirror, market)
+        })
+        .buffer_unordered(config.http.max_concurrent_requests.get()));
+    while let Some((mirror, market)) = fetched.next().await {
+        match market {
+            Ok(market) => db::set_mirror_analytics(db, mirror.id, &market)?,
+            Err(e) => error!(
+                "failed to fetch trading activity of mirror with row id {}: {:#}",
+                mirror.id, e
+            ),
+        }
+    }
+    Ok(())
 }
 
-/// Attempt to mirror a metaculus question.
-/// Does not check configurable question requirements.
-pub fn mirror_metaculus_question(
+/// Add liquidity to open mirrors with enough volume and traders, within the
+/// per-market and daily subsidy limits. Uses the activity recorded by
+/// [`sync_analytics`], mirrors it hasn't reached yet are skipped.
+pub async fn top_up_liquidity(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
-    metaculus_question: &MetaculusQuestion,
-) -> Result<MirrorRow, MirrorError> {
-    debug!(
-        "Attempting to mirror metaculus question with id {} (\"{}\")",
-        metaculus_question.id, metaculus_question.title
-    );
-    let metaculus_question =
-        if config.metaculus.fetch_criteria && metaculus_question.resolution_criteria.is_none() {
-            debug!("fetching criteria");
-            metaculus::get_question(client, &metaculus_question.id.to_string(), config)?
-        } else {
-            metaculus_question.to_owned()
+) -> Result<(), MirrorError> {
+    let cfg = &config.manifold.subsidy;
+    if !cfg.enabled {
+        debug!("Liquidity top-ups are disabled");
+        return Ok(());
+    }
+    info!("Topping up liquidity of active mirrors");
+    let spent_today = db::get_total_spend(
+        db,
+        SpendCategory::Subsidy,
+        Some(Utc::now() - Duration::days(1)),
+        None,
+    )?;
+    let mut remaining_budget = cfg.daily_budget - spent_today;
+    for mirror in db::get_unresolved_mirrors(db, None)? {
+        if shutdown::requested() {
+            break;
+        }
+        if remaining_budget < cfg.amount {
+            info!(
+                "Daily subsidy budget exhausted ({} of {} spent)",
+                cfg.daily_budget - remaining_budget,
+                cfg.daily_budget
+            );
+            break;
+        }
+        if mirror.source == QuestionSource::Manual {
+            continue;
+        }
+        let (Some(volume), Some(traders)) = (mirror.volume, mirror.unique_bettors) else {
+            debug!(
+                "No trading activity recorded for mirror with row id {}, skipping liquidity check",
+                mirror.id
+            );
+            continue;
         };
-    let question: Question = (&metaculus_question)
-        .try_into()
-        .with_context(|| "failed to convert Metaculus question to common format")?;
-    Ok(mirror_question(client, db, &question, config)?)
+        if mirror
+            .close_time
+            .is_some_and(|close_time| close_time < Utc::now())
+            || volume < cfg.min_volume
+            || (traders as u64) < cfg.min_traders
+        {
+            continue;
+        }
+        let subsidized = db::get_total_spend(
+            db,
+            SpendCategory::Subsidy,
+            None,
+            Some(&mirror.manifold_contract_id),
+        )?;
+        if subsidized + cfg.amount > cfg.max_per_market {
+            continue;
+        }
+        info!(
+            "Adding {} liquidity to mirror with row id {} (\"{}\", volume {:.0}, {} traders)",
+            cfg.amount, mirror.id, mirror.question, volume, traders
+        );
+        manifold::add_liquidity(client, &mirror.manifold_contract_id, cfg.amount, config).await?;
+        db::record_spend(
+            db,
+            SpendCategory::Subsidy,
+            cfg.amount,
+            Some(&mirror.source),
+            &mirror.manifold_contract_id,
+        )?;
+        remaining_budget -= cfg.amount;
+    }
+    Ok(())
 }
 
-/// Automatically pick and mirror Kalshi questions based on config.
-pub fn auto_mirror_kalshi(
+/// Profits on Manifold settle shortly after resolution, rewards wait this long
+const REWARD_DELAY_HOURS: i64 = 1;
+/// Mirrors that resolved longer ago than this aren't rewarded, e.g. when rewards
+/// are first enabled
+const REWARD_WINDOW_DAYS: i64 = 7;
+
+/// Send a managram to the most profitable traders on recently resolved mirrors,
+/// within the daily reward budget. Each mirror is rewarded once.
+pub async fn reward_top_traders(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
-    dry_run: bool,
 ) -> Result<(), MirrorError> {
-    // TODO: this should be cleaned up in general
-    let existing_clones = db::get_unresolved_mirrors(db, Some(QuestionSource::Kalshi))?;
-    let candidates: Vec<KalshiMarket> = kalshi::get_mirror_candidates(client, config)?
-        .into_iter()
-        .filter(|q| {
-            db::get_any_mirror(db, &QuestionSource::Kalshi, &q.id())
-                .unwrap() // TODO: handle error?
-                .is_none()
-        })
-        .collect();
-    info!(
-        "Obtained {} candidates for cloning from Kalshi",
-        candidates.len()
-    );
-    let clone_count_today = existing_clones
-        .iter()
-        .filter(|m| m.clone_date > Utc::now() - Duration::days(1))
-        .count();
-    let remaining_budget =
-        config.kalshi.max_clones_per_day - clone_count_today.min(config.kalshi.max_clones_per_day); // TODO: might want to write a query for this?
-    info!(
-        "Cloned {} kalshi questions in last 24 hours. Remaining budget: {}",
-        clone_count_today, remaining_budget
-    );
-    let to_clone_count = remaining_budget.min(candidates.len());
-    info!("Attempting to clone top {} candidates", to_clone_count);
-    for kalshi_question in candidates.into_iter().take(to_clone_count) {
-        if dry_run {
+    let cfg = &config.manifold.rewards;
+    if !cfg.enabled {
+        debug!("Top trader rewards are disabled");
+        return Ok(());
+    }
+    info!("Rewarding top traders on resolved mirrors");
+    let now = Utc::now();
+    let spent_today = db::get_total_spend(
+        db,
+        SpendCategory::Reward,
+        Some(now - Duration::days(1)),
+        None,
+    )?;
+    let mut remaining_budget = cfg.daily_budget - spent_today;
+    // the bot's own accounts may trade on mirrors too
+    let own_ids: Vec<&str> = config.own_user_ids().collect();
+    let mirrors = db::get_mirrors_to_reward(
+        db,
+        now - Duration::days(REWARD_WINDOW_DAYS),
+        now - Duration::hours(REWARD_DELAY_HOURS),
+    )?;
+    for mirror in mirrors {
+        if shutdown::requested() {
+            break;
+        }
+        let args = GetPositionsArgs {
+            order: Some("profit".to_string()),
+            top: Some(cfg.top_traders + own_ids.len()),
+        };
+        let positions = match manifold::get_positions(
+            client,
+            &mirror.manifold_contract_id,
+            &args,
+            config,
+        )
+        .await
+        {
+            Ok(positions) => positions,
+            Err(e) => {
+                error!(
+                    "failed to fetch positions on mirror with row id {} for rewards: {:#}",
+                    mirror.id, e
+                );
+                continue;
+            }
+        };
+        let winners: Vec<String> = positions
+            .into_iter()
+            .filter(|position| {
+                position.profit >= cfg.min_profit && !own_ids.contains(&position.user_id.as_str())
+            })
+            .take(cfg.top_traders)
+            .map(|position| position.user_id)
+            .collect();
+        let cost = cfg.amount * winners.len() as f64;
+        if cost > remaining_budget {
             info!(
-                "dry run -> skipping clone of question with id {}, ({}, {})",
-                kalshi_question.id(),
-                kalshi_question.title(),
-                kalshi_question.full_url()
+                "Daily reward budget exhausted ({} of {} spent)",
+                cfg.daily_budget - remaining_budget,
+                cfg.daily_budget
             );
-            continue;
+            break;
         }
-        match mirror_kalshi_question(client, db, config, &kalshi_question).with_context(|| {
-            format!(
-                "failed to mirror question with id {} (\"{}\")",
-                kalshi_question.id(),
-                kalshi_question.title()
-            )
-        }) {
-            Ok(market) => {
-                info!("Created a mirror:\n{:#?}", market);
+        if !winners.is_empty() {
+            info!(
+                "Rewarding {} top trader(s) on mirror with row id {} (\"{}\")",
+                winners.len(),
+                mirror.id,
+                mirror.question
+            );
+            let payload = ManagramPayload {
+                args: SendManagramArgs {
+                    amount: cfg.amount,
+                    to_ids: winners,
+                    message: format!(
+                        "Congratulations on being one of the top traders on {}! \
+                        Thanks for trading on our mirrors.",
+                        mirror.manifold_url
+                    ),
+                },
+                category: SpendCategory::Reward,
+            };
+            let action = db::start_action(
+                db,
+                ActionKind::SendManagram,
+                &mirror.manifold_contract_id,
+                &payload,
+            )?;
+            if let Err(e) = manifold::send_managram(client, config, &payload.args).await {
+                // recovery sends the reward if it didn't go through, rewarding this
+                // mirror again could pay twice
+                db::set_mirror_rewarded(db, mirror.id)?;
+                return Err(e.into());
             }
-            Err(e) => error!("{:#}", e),
+            db::complete_action(db, action)?;
+            log_if_err!(db::record_spend(
+                db,
+                SpendCategory::Reward,
+                cost,
+                Some(&mirror.source),
+                &mirror.manifold_contract_id
+            ));
+            remaining_budget -= cost;
         }
+        db::set_mirror_rewarded(db, mirror.id)?;
     }
     Ok(())
 }
 
-/// Automatically pick and mirror Metaculus questions based on config.
-pub fn auto_mirror_metaculus(
+/// Comment on mirrors that are about to close with the source's resolution date
+/// and current probability. Each mirror gets at most one reminder.
+pub async fn post_close_reminders(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
-    dry_run: bool,
 ) -> Result<(), MirrorError> {
-    // TODO: this should be cleaned up in general
-    let existing_clones = db::get_unresolved_mirrors(db, Some(QuestionSource::Metaculus))?;
-    let candidates: Vec<MetaculusQuestion> = metaculus::get_mirror_candidates(client, config)?
-        .into_iter()
-        .filter(|q| {
-            db::get_any_mirror(db, &QuestionSource::Metaculus, &q.id.to_string())
-                .unwrap() // TODO: handle error?
-                .is_none()
-        })
-        .collect();
-    info!(
-        "Obtained {} candidates for cloning from Metaculus",
-        candidates.len()
-    );
-    let clone_count_today = existing_clones
-        .iter()
-        .filter(|m| m.clone_date > Utc::now() - Duration::days(1))
-        .count();
-    let remaining_budget = config.metaculus.max_clones_per_day
-        - clone_count_today.min(config.metaculus.max_clones_per_day); // TODO: might want to write a query for this?
-    info!(
-        "Cloned {} metaculus questions in last 24 hours. Remaining budget: {}",
-        clone_count_today, remaining_budget
-    );
-    let to_clone_count = remaining_budget.min(candidates.len());
-    info!("Attempting to clone top {} candidates", to_clone_count);
-    for metaculus_question in candidates.into_iter().take(to_clone_count) {
-        if dry_run {
-            info!(
-                "dry run -> skipping clone of question with id {}, ({}, {})",
-                metaculus_question.id,
-                metaculus_question.title,
-                metaculus_question.full_url()
-            );
-            continue;
+    let cfg = &config.manifold.reminders;
+    if !cfg.enabled {
+        debug!("Close reminders are disabled");
+        return Ok(());
+    }
+    info!("Posting close reminders on mirrors");
+    let horizon = Utc::now() + Duration::days(cfg.days_before_close);
+    for mirror in db::get_unresolved_mirrors(db, None)? {
+        if shutdown::requested() {
+            break;
         }
-        match mirror_metaculus_question(client, db, config, &metaculus_question).with_context(
-            || {
-                format!(
-                    "failed to mirror question with id {} (\"{}\")",
-                    metaculus_question.id, metaculus_question.title
-                )
-            },
-        ) {
-            Ok(market) => {
-                info!("Created a mirror:\n{:#?}", market);
-            }
-            Err(e) => error!("{:#}", e),
+        if mirror.source == QuestionSource::Manual
+            || db::get_last_mirror_comment_time(db, mirror.id, MirrorCommentKind::CloseReminder)?
+                .is_some()
+        {
+            continue;
         }
+        log_if_err!(post_close_reminder(client, db, &mirror, config, horizon)
+            .await
+            .with_context(|| format!(
+                "failed to post close reminder on mirror with row id {}",
+                mirror.id
+            )));
     }
     Ok(())
 }
 
-/// Resolve mirrored market.
-fn resolve_mirror(
+async fn post_close_reminder(
     client: &Client,
     db: &rusqlite::Connection,
     mirror: &MirrorRow,
-    resolution: BinaryResolution,
     config: &Settings,
+    horizon: DateTime<Utc>,
 ) -> Result<(), MirrorError> {
-    manifold::resolve_market(
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    if market.close_time < Utc::now() || market.close_time > horizon {
+        return Ok(());
+    }
+    let source = fetch_source(client, db, mirror, config).await?;
+    if source.resolution.is_some() {
+        // resolution sync will take care of it
+        return Ok(());
+    }
+    let mut message = format!(
+        "This market closes on {}. The source question resolves on {}",
+        market.close_time.format("%Y-%m-%d"),
+        source.question.end_date.format("%Y-%m-%d")
+    );
+    match source.question.probability {
+        Some(p) => message.push_str(&format!(
+            "; current source probability is {:.0}%.",
+            p * 100.0
+        )),
+        None => message.push('.'),
+    }
+    info!(
+        "Posting close reminder on mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let comment = Destination::post_comment(
         client,
-        &mirror.manifold_contract_id,
-        resolution.try_into().map_err(anyhow::Error::from)?,
+        mirror.destination.as_deref(),
+        &PostCommentArgs {
+            contract_id: mirror.manifold_contract_id.clone(),
+            markdown: message,
+            reply_to_comment_id: None,
+        },
         config,
-    )?;
-    db::set_mirror_resolved(db, mirror.id, true)?;
+    )
+    .await?;
+    db::insert_mirror_comment(db, mirror.id, MirrorCommentKind::CloseReminder, &comment.id)?;
     Ok(())
 }
 
-/// Check if Kalshi question has resolved and sync resolution to mirror.
-fn sync_kalshi_mirror(
+/// Comment on open mirrors with the source's current forecast next to the Manifold
+/// probability, at most once per configured interval per mirror.
+pub async fn post_probability_updates(
     client: &Client,
     db: &rusqlite::Connection,
-    mirror: &MirrorRow,
     config: &Settings,
-) -> Result<bool, MirrorError> {
-    assert!(mirror.source == QuestionSource::Kalshi);
-    let kalshi_question = kalshi::get_question(client, &mirror.source_id, config)?;
-    if let Some(resolution) = kalshi_question.get_binary_resolution()? {
-        info!(
-            "Kalshi question \"{}\" (source id: {}) has resolved {:?}. Syncing.",
-            mirror.question, mirror.source_id, resolution
-        );
-        resolve_mirror(client, db, &mirror, resolution, config)?;
-        Ok(true)
-    } else {
-        debug!("Source has not resolved yet");
-        Ok(false)
+) -> Result<(), MirrorError> {
+    let cfg = &config.manifold.probability_updates;
+    if !cfg.enabled {
+        debug!("Probability update comments are disabled");
+        return Ok(());
+    }
+    info!("Posting probability updates on mirrors");
+    let cutoff = Utc::now() - Duration::days(cfg.interval_days);
+    for mirror in db::get_unresolved_mirrors(db, None)? {
+        if shutdown::requested() {
+            break;
+        }
+        if mirror.source == QuestionSource::Manual {
+            continue;
+        }
+        // Count from mirror creation so new mirrors don't get an update right away
+        let last_posted =
+            db::get_last_mirror_comment_time(db, mirror.id, MirrorCommentKind::ProbabilityUpdate)?
+                .unwrap_or(mirror.clone_date);
+        if last_posted > cutoff {
+            continue;
+        }
+        log_if_err!(post_probability_update(client, db, &mirror, config)
+            .await
+            .with_context(|| format!(
+                "failed to post probability update on mirror with row id {}",
+                mirror.id
+            )));
     }
+    Ok(())
 }
 
-/// Check if Metaculus question has resolved and sync resolution to mirror.
-fn sync_metaculus_mirror(
+async fn post_probability_update(
     client: &Client,
     db: &rusqlite::Connection,
     mirror: &MirrorRow,
     config: &Settings,
-) -> Result<bool, MirrorError> {
-    assert!(mirror.source == QuestionSource::Metaculus);
-    let metaculus_question = metaculus::get_question(client, &mirror.source_id, config)?;
-    if let Some(resolution) = metaculus_question.get_binary_resolution()? {
-        info!(
-            "Metaculus question \"{}\" (source id: {}) has resolved {:?}. Syncing.",
-            mirror.question, mirror.source_id, resolution
-        );
-        resolve_mirror(client, db, &mirror, resolution, config)?;
-        Ok(true)
-    } else {
-        debug!("Source has not resolved yet");
-        Ok(false)
+) -> Result<(), MirrorError> {
+    let market = manifold::get_market(client, &mirror.manifold_contract_id, config).await?;
+    if market.close_time < Utc::now() {
+        return Ok(());
+    }
+    let source = fetch_source(client, db, mirror, config).await?;
+    if source.resolution.is_some() {
+        return Ok(());
+    }
+    let summary = match source.forecast_summary() {
+        Some(summary) => summary,
+        None => {
+            debug!(
+                "No source forecast available for mirror with row id {}",
+                mirror.id
+            );
+            return Ok(());
+        }
+    };
+    let mut message = format!("Source update: {}", summary);
+    match market.probability {
+        Some(p) => message.push_str(&format!(", versus {:.0}% here.", p * 100.0)),
+        None => message.push('.'),
     }
+    info!(
+        "Posting probability update on mirror with row id {} (\"{}\")",
+        mirror.id, mirror.question
+    );
+    let comment = Destination::post_comment(
+        client,
+        mirror.destination.as_deref(),
+        &PostCommentArgs {
+            contract_id: mirror.manifold_contract_id.clone(),
+            markdown: message,
+            reply_to_comment_id: None,
+        },
+        config,
+    )
+    .await?;
+    db::insert_mirror_comment(
+        db,
+        mirror.id,
+        MirrorCommentKind::ProbabilityUpdate,
+        &comment.id,
+    )?;
+    Ok(())
 }
 
-/// Check if source resolved and sync resolution to Manifold
-pub fn sync_mirror(
+/// Refresh descriptions of all unresolved mirrors
+pub async fn sync_descriptions(
     client: &Client,
     db: &rusqlite::Connection,
-    mirror: &MirrorRow,
     config: &Settings,
-) -> Result<bool, MirrorError> {
-    debug!(
-        "Syncing resolution for {} question at {}",
-        mirror.source, mirror.source_url
-    );
-    Ok(match mirror.source {
-        crate::types::QuestionSource::Metaculus => {
-            sync_metaculus_mirror(client, db, &mirror, config)?
+) -> Result<(), MirrorError> {
+    info!("Refreshing mirror descriptions");
+    for row in db::get_unresolved_mirrors(db, None)?
+        .iter()
+        .filter(|r| r.source != QuestionSource::Manual)
+    {
+        if shutdown::requested() {
+            break;
         }
-        crate::types::QuestionSource::Kalshi => sync_kalshi_mirror(client, db, &mirror, config)?,
-        crate::types::QuestionSource::Polymarket => todo!(),
-        crate::types::QuestionSource::Manual => false,
-    })
+        log_if_err!(refresh_mirror_description(client, db, row, config)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to refresh description for market with row id {}",
+                    row.id
+                )
+            }));
+    }
+    Ok(())
+}
+
+/// How many mirrors of a source `sync_resolutions_to_manifold` works on at once
+fn max_concurrent_syncs(config: &Settings, source: &QuestionSource) -> usize {
+    match source {
+        QuestionSource::Kalshi => config.kalshi.max_concurrent_syncs,
+        QuestionSource::Metaculus => config.metaculus.max_concurrent_syncs,
+        QuestionSource::Polymarket => config.polymarket.max_concurrent_syncs,
+        // no requests to make
+        QuestionSource::Manual => config.http.max_concurrent_requests,
+    }
+    .get()
 }
 
-/// Resolve any mirrored markets where the source has resolved
-pub fn sync_resolutions_to_manifold(
+/// When to check the source of an unresolved mirror next. Mirrors near or past
+/// their end date are checked every `sync.near_end_check_interval_hours`, others
+/// every `sync.check_interval_hours`, but no later than when they get near it.
+fn next_check_at(config: &Settings, mirror: &MirrorRow) -> DateTime<Utc> {
+    let now = Utc::now();
+    let near_end_check = now + Duration::hours(config.sync.near_end_check_interval_hours);
+    match mirror.close_time {
+        Some(close_time) => {
+            let near_end = close_time - Duration::days(config.sync.near_end_days);
+            (now + Duration::hours(config.sync.check_interval_hours))
+                .min(near_end)
+                .max(near_end_check)
+        }
+        None => near_end_check,
+    }
+}
+
+/// Resolve any mirrored markets where the source has resolved. Only mirrors due
+/// according to their schedule are checked, unless `ignore_schedule` is set.
+pub async fn sync_resolutions_to_manifold(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
     source: Option<QuestionSource>,
-) -> Result<(), MirrorError> {
+    ignore_schedule: bool,
+) -> Result<usize, MirrorError> {
     info!("Syncing resolutions to Manifold (source = {:?})", source);
-    for row in db::get_unresolved_mirrors(&db, source)? {
-        log_if_err!(sync_mirror(client, db, &row, config).with_context(|| {
-            format!(
-                "failed to sync resolution for market with row id {}",
-                row.id
+    let mut resolved = 0;
+    let reopen_sources = match &source {
+        Some(source) => vec![source.clone()],
+        None => vec![
+            QuestionSource::Kalshi,
+            QuestionSource::Metaculus,
+            QuestionSource::Polymarket,
+        ],
+    };
+    let rows = if ignore_schedule {
+        db::get_unresolved_mirrors(db, source)?
+    } else {
+        db::get_due_mirrors(db, source)?
+    };
+    info!("{} mirrors due for a resolution check", rows.len());
+    let mut by_source: HashMap<QuestionSource, Vec<MirrorRow>> = HashMap::new();
+    for row in rows {
+        by_source.entry(row.source.clone()).or_default().push(row);
+    }
+    // sources are synced side by side, each with its own concurrency limit
+    let mut results = stream::select_all(by_source.into_iter().map(|(source, rows)| {
+        stream::iter(rows.into_iter().take_while(|_| !shutdown::requested()))
+            .map(|row| async move {
+                let resolved = sync_mirror(client, db, &row, config)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to sync resolution for market with row id {}",
+                            row.id
+                        )
+                    })?;
+                // failed checks keep their schedule, so they are retried next time
+                if !resolved {
+                    db::set_mirror_next_check(db, row.id, next_check_at(config, &row))?;
+                }
+                Ok::<_, anyhow::Error>(resolved)
+            })
+            .buffer_unordered(max_concurrent_syncs(config, &source))
+    }));
+    while let Some(result) = results.next().await {
+        match result {
+            Ok(true) => resolved += 1,
+            Ok(false) => {}
+            Err(e) => error!("{:?}", e),
+        }
+    }
+    for source in reopen_sources {
+        log_if_err!(check_reopened_sources(client, db, config, &source).await);
+    }
+    Ok(resolved)
+}
+
+/// Check recently resolved mirrors for sources that opened again, e.g. after an
+/// admin correction. Those are flagged and reported to the operator, and
+/// unresolved if `sync.unresolve_reopened` is set.
+async fn check_reopened_sources(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    source: &QuestionSource,
+) -> anyhow::Result<()> {
+    let since = Utc::now() - Duration::days(config.sync.reopen_check_days);
+    let rows = db::get_recently_resolved_mirrors(db, source, since)?;
+    debug!(
+        "Checking {} recently resolved {} mirrors for reopened sources",
+        rows.len(),
+        source
+    );
+    let mut results = stream::iter(rows.into_iter().take_while(|_| !shutdown::requested()))
+        .map(|mirror| async move {
+            let state = fetch_source(client, db, &mirror, config)
+                .await
+                .with_context(|| {
+                    format!("failed to check source of mirror with row id {}", mirror.id)
+                })?;
+            if state.resolution.is_some() {
+                return Ok(());
+            }
+            warn!(
+                "Source of mirror with row id {} (\"{}\") is open again, was resolved {:?}",
+                mirror.id, mirror.question, mirror.resolution
+            );
+            db::set_mirror_reopened(db, mirror.id)?;
+            let unresolved = config.sync.unresolve_reopened;
+            if unresolved {
+                unresolve_mirror(client, db, &mirror, "source opened again", config).await?;
+            }
+            notify::notify(
+                client,
+                config,
+                Event::SourceReopened {
+                    mirror: &mirror,
+                    unresolved,
+                },
             )
-        }));
+            .await;
+            Ok::<_, anyhow::Error>(())
+        })
+        .buffer_unordered(max_concurrent_syncs(config, source));
+    while let Some(result) = results.next().await {
+        log_if_err!(result);
     }
     Ok(())
 }
@@ -331,30 +1803,101 @@ pub fn register_manual_market(
         source_id: market.id.clone(),
         question: market.question.clone(),
         criteria: None,
+        background: None,
         end_date: market.close_time.clone(),
+        close_time: None,
+        probability: market.probability,
+        source_payload: None,
+        market_summary: None,
+        forecast_history: Vec::new(),
     };
-    db::insert_mirror(db, market, &question, config)?;
+    db::insert_mirror(db, market, &question, Utc::now(), None, config)?;
     Ok(())
 }
 
+/// Mirror record as written by `export --table mirrors`
+#[derive(Debug, Deserialize)]
+pub struct ImportedMirror {
+    pub clone_date: DateTime<Utc>,
+    pub manifold_contract_id: String,
+    pub source: String,
+    pub source_id: String,
+    pub source_url: String,
+    pub question: String,
+    pub source_payload: Option<String>,
+    /// missing from exports made before mirrors could be routed to other accounts
+    #[serde(default)]
+    pub destination: Option<String>,
+}
+
+/// Add an exported mirror record back to the db after checking that the market exists
+/// on Manifold and belongs to us. Resolution state is taken from Manifold.
+/// Returns None if the mirror is already in the db.
+pub async fn import_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    row: &ImportedMirror,
+) -> Result<Option<MirrorRow>, MirrorError> {
+    if db::get_mirror_by_contract_id(db, &row.manifold_contract_id)?.is_some() {
+        return Ok(None);
+    }
+    let source = QuestionSource::from_str(&row.source, true)
+        .map_err(|e| anyhow!("invalid source {:?}: {}", row.source, e))?;
+    let destination = row.destination.as_deref().filter(|d| !d.is_empty());
+    let market = manifold::get_market(client, &row.manifold_contract_id, config).await?;
+    if market.creator_id != config.for_destination(destination)?.manifold.user_id {
+        return Err(anyhow!(
+            "market with id {} was not created by the bot account",
+            market.id
+        )
+        .into());
+    }
+    let question = Question {
+        source,
+        source_url: row.source_url.clone(),
+        source_id: row.source_id.clone(),
+        question: row.question.clone(),
+        criteria: None,
+        background: None,
+        end_date: market.close_time,
+        close_time: None,
+        probability: market.probability,
+        source_payload: row
+            .source_payload
+            .as_deref()
+            .filter(|payload| !payload.is_empty())
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|e| anyhow!("invalid source payload: {}", e))?,
+        market_summary: None,
+        forecast_history: Vec::new(),
+    };
+    let market: LiteMarket = (&market).into();
+    let mirror = db::insert_mirror(db, &market, &question, row.clone_date, destination, config)?;
+    if market.is_resolved {
+        db::update_mirror_from_market(db, mirror.id, &market)?;
+        return Ok(db::get_mirror_by_id(db, mirror.id)?);
+    }
+    Ok(Some(mirror))
+}
+
 /// Register unknown markets on our Manifold account as manually created
-pub fn register_existing_manual_markets(
+pub async fn register_existing_manual_markets(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
 ) -> Result<(), MirrorError> {
-    info!("Fetching markets from Manifold.");
-    let markets = manifold::get_markets_depaginated(
+    info!("Registering unknown markets on Manifold as manually created.");
+    let mut markets = pin!(manifold::stream_markets(
         client,
         GetMarketsArgs {
             user_id: Some(config.manifold.user_id.clone()),
             ..Default::default()
         },
         config,
-    )?;
-
-    info!("Registering unknown markets as manually created.");
-    for market in markets {
+    ));
+    while let Some(market) = markets.try_next().await? {
         if db::get_mirror_by_contract_id(db, &market.id)?.is_none() {
             info!(
                 "Registering market with id {} (\"{}\")",
@@ -367,7 +1910,7 @@ pub fn register_existing_manual_markets(
 }
 
 /// Ensure database state matches Manifold for mirrored questions
-pub fn sync_manifold_to_db(
+pub async fn sync_manifold_to_db(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
@@ -375,25 +1918,30 @@ pub fn sync_manifold_to_db(
     info!("Syncing Manifold state to database.");
 
     info!("Fetching markets from Manifold.");
-    let markets = manifold::get_markets_depaginated(
-        client,
-        GetMarketsArgs {
-            user_id: Some(config.manifold.user_id.clone()),
-            ..Default::default()
-        },
-        config,
-    )?;
+    // collected, since all of them are needed to find ghost markets
+    let mut markets: Vec<LiteMarket> = Vec::new();
+    for user_id in config.own_user_ids() {
+        let account_markets: Vec<LiteMarket> = manifold::stream_markets(
+            client,
+            GetMarketsArgs {
+                user_id: Some(user_id.to_string()),
+                ..Default::default()
+            },
+            config,
+        )
+        .try_collect()
+        .await?;
+        markets.extend(account_markets);
+    }
 
     info!("Syncing to database.");
+    let mut mirrors: HashMap<String, MirrorRow> = db::get_mirrors(db)?
+        .into_iter()
+        .map(|row| (row.manifold_contract_id.clone(), row))
+        .collect();
     for market in markets.iter() {
-        if let Some(row) = db::get_mirror_by_contract_id(db, &market.id)? {
-            if row.resolved != market.is_resolved {
-                info!(
-                    "Updating resolution state ({} -> {}) for mirror with row id {} (\"{}\")",
-                    row.resolved, market.is_resolved, row.id, row.question
-                );
-                db::set_mirror_resolved(db, row.id, market.is_resolved)?;
-            }
+        if let Some(row) = mirrors.remove(&market.id) {
+            sync_mirror_row_from_market(db, &row, market)?;
         } else {
             warn!(
                 "Manifold market with id {} (\"{}\") missing from database.",
@@ -401,20 +1949,61 @@ pub fn sync_manifold_to_db(
             );
         }
     }
+    // Mirrors missing from the listing, e.g. because it was cut short by
+    // http.max_paginated_items, are fetched one by one. Those that can't be are
+    // ghosts.
     // TODO: if we want to resolve markets not owned by our account, we'll need to sync those too
-    info!("Checking for ghost markets in database.");
-    let market_ids: HashSet<String> = markets.iter().map(|m| m.id.clone()).collect();
-    for row in db::get_mirrors(db)?
-        .into_iter()
-        .filter(|r| !market_ids.contains(&r.manifold_contract_id))
-    {
-        warn!("Database contains reference to manifold market with id {} (\"{}\"), which does not exist or is not owned by us.", row.manifold_contract_id, row.question);
+    info!(
+        "Checking {} mirrors missing from the listing for ghost markets.",
+        mirrors.len()
+    );
+    let fetched: Vec<_> = stream::iter(mirrors.into_values())
+        .map(|row| async move {
+            let market = manifold::get_market(client, &row.manifold_contract_id, config).await;
+            (row, market)
+        })
+        .buffer_unordered(config.http.max_concurrent_requests.get())
+        .collect()
+        .await;
+    for (row, market) in fetched {
+        let owner = match config.for_destination(row.destination.as_deref()) {
+            Ok(account) => account.manifold.user_id.clone(),
+            Err(e) => {
+                warn!(
+                    "Can't check owner of mirror with row id {}: {:#}",
+                    row.id, e
+                );
+                continue;
+            }
+        };
+        match market {
+            Ok(market) if market.creator_id == owner => {
+                sync_mirror_row_from_market(db, &row, &(&market).into())?;
+            }
+            Ok(_) => warn!("Database contains reference to manifold market with id {} (\"{}\"), which is not owned by us.", row.manifold_contract_id, row.question),
+            Err(e) => warn!("Database contains reference to manifold market with id {} (\"{}\"), which could not be fetched: {:#}", row.manifold_contract_id, row.question, e),
+        }
+    }
+    Ok(())
+}
+
+fn sync_mirror_row_from_market(
+    db: &rusqlite::Connection,
+    row: &MirrorRow,
+    market: &LiteMarket,
+) -> Result<(), MirrorError> {
+    if row.resolved != market.is_resolved {
+        info!(
+            "Updating resolution state ({} -> {}) for mirror with row id {} (\"{}\")",
+            row.resolved, market.is_resolved, row.id, row.question
+        );
     }
+    db::update_mirror_from_market(db, row.id, market)?;
     Ok(())
 }
 
 /// Look for mirrors created by others and sync to db.
-pub fn sync_third_party_mirrors(
+pub async fn sync_third_party_mirrors(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
@@ -430,6 +2019,7 @@ pub fn sync_third_party_mirrors(
             &*group_id,
             &metaculus_link_regex,
         )
+        .await
         .with_context(|| {
             format!(
                 "failed to sync third party Metaculus mirrors from group with id {}",
@@ -441,31 +2031,30 @@ pub fn sync_third_party_mirrors(
 }
 
 /// Look for Metaculus mirrors created by others in group and sync to db.
-fn sync_third_party_metaculus_mirrors_from_group(
+async fn sync_third_party_metaculus_mirrors_from_group(
     client: &Client,
     db: &rusqlite::Connection,
     config: &Settings,
     group_id: &str,
     pattern: &Regex,
 ) -> Result<(), MirrorError> {
-    for market in manifold::get_markets_depaginated(
+    let mut markets = pin!(manifold::stream_markets(
         client,
         GetMarketsArgs {
             group_id: Some(group_id.to_owned()),
             ..Default::default()
         },
         config,
-    )?
-    .iter()
-    .filter(|m| !m.is_resolved)
-    {
+    )
+    .try_filter(|m| future::ready(!m.is_resolved)));
+    while let Some(market) = markets.try_next().await? {
         if db::get_third_party_mirror_by_contract_id(db, &market.id)?.is_some() {
             continue;
         }
         if db::get_mirror_by_contract_id(db, &market.id)?.is_some() {
             continue;
         }
-        match manifold::get_market(client, &market.id, config) {
+        match manifold::get_market(client, &market.id, config).await {
             Ok(market) => {
                 let description = market.description.to_string();
                 if let Some(caps) = pattern.captures(&description) {
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::debug;
+use reqwest::Client;
+use serde_json::{json, Value as JsonValue};
+use sha2::Sha256;
+
+use crate::{
+    db::{ManagramState, MirrorRow},
+    http, log_if_err,
+    manifold::Managram,
+    settings::{NotificationEvents, Settings},
+    telegram,
+    types::BinaryResolution,
+    util,
+};
+
+/// Discord rejects webhook messages longer than this
+const MAX_DISCORD_CHARS: usize = 2000;
+/// Header carrying the hex HMAC-SHA256 of outbound webhook bodies
+const SIGNATURE_HEADER: &str = "X-Mirror-Bot-Signature";
+
+/// Something the operator may want to hear about
+pub enum Event<'a> {
+    MirrorCreated(&'a MirrorRow),
+    MirrorResolved {
+        mirror: &'a MirrorRow,
+        resolution: &'a BinaryResolution,
+    },
+    /// A managram command failed for good, either refunded or out of retries
+    ManagramFailed {
+        managram: &'a Managram,
+        error: &'a str,
+    },
+    /// Any attempt at processing a managram, including retries
+    ManagramProcessed {
+        managram: &'a Managram,
+        attempt: i64,
+        outcome: &'a ManagramState,
+        error: Option<&'a str>,
+    },
+    /// A sync run logged errors
+    SyncFailed {
+        run_id: i64,
+        errors: usize,
+    },
+    /// The source of a resolved mirror is open again
+    SourceReopened {
+        mirror: &'a MirrorRow,
+        unresolved: bool,
+    },
+}
+
+impl Event<'_> {
+    fn enabled(&self, events: &NotificationEvents) -> bool {
+        match self {
+            Event::MirrorCreated(_) => events.mirror_created,
+            Event::MirrorResolved { .. } => events.mirror_resolved,
+            Event::ManagramFailed { .. } => events.managram_failed,
+            Event::ManagramProcessed { .. } => events.managram_processed,
+            Event::SyncFailed { .. } => events.sync_failed,
+            Event::SourceReopened { .. } => events.source_reopened,
+        }
+    }
+
+    /// Event type in webhook payloads
+    fn name(&self) -> &'static str {
+        match self {
+            Event::MirrorCreated(_) => "mirror_created",
+            Event::MirrorResolved { .. } => "mirror_resolved",
+            Event::ManagramFailed { .. } => "managram_failed",
+            Event::ManagramProcessed { .. } => "managram_processed",
+            Event::SyncFailed { .. } => "sync_failed",
+            Event::SourceReopened { .. } => "source_reopened",
+        }
+    }
+
+    fn data(&self) -> JsonValue {
+        match self {
+            Event::MirrorCreated(mirror) => json!({ "mirror": mirror }),
+            Event::MirrorResolved { mirror, resolution } => {
+                json!({ "mirror": mirror, "resolution": resolution })
+            }
+            Event::ManagramFailed { managram, error } => {
+                json!({ "managram": managram, "error": error })
+            }
+            Event::ManagramProcessed {
+                managram,
+                attempt,
+                outcome,
+                error,
+            } => json!({
+                "managram": managram,
+                "attempt": attempt,
+                "outcome": outcome,
+                "error": error,
+            }),
+            Event::SyncFailed { run_id, errors } => {
+                json!({ "run_id": run_id, "errors": errors })
+            }
+            Event::SourceReopened { mirror, unresolved } => {
+                json!({ "mirror": mirror, "unresolved": unresolved })
+            }
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Event::MirrorCreated(mirror) => format!(
+                "Mirrored {} question \"{}\": {}",
+                mirror.source, mirror.question, mirror.manifold_url
+            ),
+            Event::MirrorResolved { mirror, resolution } => format!(
+                "Resolved \"{}\" {:?}: {}",
+                mirror.question, resolution, mirror.manifold_url
+            ),
+            Event::ManagramFailed { managram, error } => format!(
+                "Managram {} from {} ({} mana, \"{}\") failed: {}",
+                managram.id, managram.from_id, managram.amount, managram.message, error
+            ),
+            Event::ManagramProcessed {
+                managram,
+                attempt,
+                outcome,
+                error,
+            } => format!(
+                "Managram {} from {} attempt {}: {:?}{}",
+                managram.id,
+                managram.from_id,
+                attempt,
+                outcome,
+                error.map(|e| format!(" ({})", e)).unwrap_or_default()
+            ),
+            Event::SyncFailed { run_id, errors } => {
+                format!("Sync run #{} logged {} error(s)", run_id, errors)
+            }
+            Event::SourceReopened { mirror, unresolved } => format!(
+                "{} question of \"{}\" is open again after the mirror resolved {:?}{}: {}",
+                mirror.source,
+                mirror.question,
+                mirror.resolution,
+                if *unresolved {
+                    ", unresolved the mirror"
+                } else {
+                    ""
+                },
+                mirror.manifold_url
+            ),
+        }
+    }
+}
+
+/// Send a notification to every configured channel that wants this event.
+/// Failures are logged, notifications never fail the operation they report on.
+pub async fn notify(client: &Client, config: &Settings, event: Event<'_>) {
+    let discord = &config.notifications.discord;
+    if !discord.webhook_url.is_empty() && event.enabled(&discord.events) {
+        log_if_err!(
+            post_discord(client, config, &discord.webhook_url, &event.message())
+                .await
+                .with_context(|| "failed to send Discord notification")
+        );
+    }
+    let telegram = &config.notifications.telegram;
+    if !telegram.bot_token.is_empty() && event.enabled(&telegram.events) {
+        log_if_err!(telegram::send_message(client, config, &event.message())
+            .await
+            .with_context(|| "failed to send Telegram notification"));
+    }
+    let webhooks = &config.notifications.webhooks;
+    if !webhooks.urls.is_empty() && event.enabled(&webhooks.events) {
+        let body = json!({
+            "event": event.name(),
+            "timestamp": Utc::now(),
+            "data": event.data(),
+        })
+        .to_string();
+        for url in &webhooks.urls {
+            log_if_err!(post_webhook(client, config, url, &webhooks.secret, &body)
+                .await
+                .with_context(|| format!("failed to send webhook to {}", url)));
+        }
+    }
+}
+
+async fn post_discord(
+    client: &Client,
+    config: &Settings,
+    webhook_url: &str,
+    message: &str,
+) -> Result<()> {
+    debug!("Posting Discord notification: {}", message);
+    let request = client
+        .post(webhook_url)
+        .json(&json!({ "content": util::truncate(message, MAX_DISCORD_CHARS) }));
+    http::send(config, request).await?.error_for_status()?;
+    Ok(())
+}
+
+/// POST a JSON payload, signed with HMAC-SHA256 if a secret is configured
+async fn post_webhook(
+    client: &Client,
+    config: &Settings,
+    url: &str,
+    secret: &str,
+    body: &str,
+) -> Result<()> {
+    debug!("Posting webhook to {}: {}", url, body);
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    if !secret.is_empty() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .with_context(|| "invalid webhook secret")?;
+        mac.update(body.as_bytes());
+        request = request.header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        );
+    }
+    http::send(config, request).await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,428 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use reqwest::StatusCode;
+use reqwest::{Client, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::http::{self, HttpError};
+use crate::mirror::MirrorError;
+use crate::settings::{PolymarketQuestionRequirements, Settings};
+use crate::source::{Candidates, MirrorSource};
+use crate::types::{BinaryResolution, MarketSummary, Question, QuestionSource, RequirementCheck};
+
+/// Fetch a market by slug
+pub async fn get_question(
+    client: &Client,
+    slug: &str,
+    config: &Settings,
+) -> Result<PolymarketMarket, PolymarketError> {
+    debug!("polymarket::get_question called (slug: {})", slug);
+    if !slug
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(PolymarketError::IllegalSlugCharacters(slug.to_string()));
+    }
+    let resp = http::send(
+        config,
+        client
+            .get(format!("{}markets", config.polymarket.api_url))
+            .query(&[("slug", slug)]),
+    )
+    .await?;
+    let markets: Vec<PolymarketMarket> = parse_response(resp).await?;
+    markets
+        .into_iter()
+        .next()
+        .ok_or_else(|| PolymarketError::NotFound(slug.to_string()))
+}
+
+pub fn check_market_requirements(
+    market: &PolymarketMarket,
+    requirements: &PolymarketQuestionRequirements,
+) -> Result<(), PolymarketCheckFailure> {
+    RequirementCheck::first_failure(evaluate_market_requirements(market, requirements))
+}
+
+/// Evaluate every requirement, in the order [`check_market_requirements`] applies them
+pub fn evaluate_market_requirements(
+    market: &PolymarketMarket,
+    requirements: &PolymarketQuestionRequirements,
+) -> Vec<RequirementCheck<PolymarketCheckFailure>> {
+    use PolymarketCheckFailure::*;
+    // without an end date, the resolution time checks pass and NoEndDate fails instead
+    let time_to_resolution = market.time_to_resolution();
+    let days_to_resolution = time_to_resolution.map_or(-1, |t| t.num_days());
+    let probability = market.yes_price();
+    vec![
+        // fixed requirements
+        RequirementCheck::new(
+            "binary",
+            market.is_binary(),
+            true,
+            market.is_binary(),
+            || NotBinary,
+        ),
+        RequirementCheck::new(
+            "end date",
+            market
+                .end_date
+                .map_or("none".to_string(), |d| d.to_string()),
+            "required",
+            market.end_date.is_some(),
+            || NoEndDate,
+        ),
+        // config requirements
+        RequirementCheck::new(
+            "require_open",
+            market.is_open(),
+            requirements.require_open,
+            !requirements.require_open || market.is_open(),
+            || NotOpen,
+        ),
+        RequirementCheck::new(
+            "exclude_resolved",
+            market.is_resolved(),
+            requirements.exclude_resolved,
+            !requirements.exclude_resolved || !market.is_resolved(),
+            || Resolved,
+        ),
+        RequirementCheck::new(
+            "min_volume",
+            market.volume(),
+            requirements.min_volume,
+            market.volume() >= requirements.min_volume,
+            || NotEnoughVolume {
+                volume: market.volume(),
+                threshold: requirements.min_volume,
+            },
+        ),
+        RequirementCheck::new(
+            "min_liquidity",
+            market.liquidity(),
+            requirements.min_liquidity,
+            market.liquidity() >= requirements.min_liquidity,
+            || NotEnoughLiquidity {
+                liquidity: market.liquidity(),
+                threshold: requirements.min_liquidity,
+            },
+        ),
+        RequirementCheck::new(
+            "min_days_to_resolution",
+            days_to_resolution,
+            requirements.min_days_to_resolution,
+            time_to_resolution
+                .is_none_or(|t| t >= Duration::days(requirements.min_days_to_resolution)),
+            || ResolvesTooSoon {
+                days_remaining: days_to_resolution,
+                threshold: requirements.min_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_days_to_resolution",
+            days_to_resolution,
+            requirements.max_days_to_resolution,
+            time_to_resolution
+                .is_none_or(|t| t <= Duration::days(requirements.max_days_to_resolution)),
+            || ResolvesTooLate {
+                days_remaining: days_to_resolution,
+                threshold: requirements.max_days_to_resolution,
+            },
+        ),
+        RequirementCheck::new(
+            "max_confidence",
+            probability.map_or("unknown".to_string(), |p| p.to_string()),
+            requirements.max_confidence,
+            probability.is_none_or(|p| p.max(1.0 - p) <= requirements.max_confidence),
+            || TooExtreme {
+                probability: probability.unwrap_or_default(),
+                threshold: requirements.max_confidence,
+            },
+        ),
+        RequirementCheck::new(
+            "exclude_ids",
+            &market.slug,
+            format!("{} excluded", requirements.exclude_ids.len()),
+            !requirements.exclude_ids.contains(&market.slug),
+            || Banned,
+        ),
+    ]
+}
+
+/// helper function for parsing both success and error responses
+async fn parse_response<T: DeserializeOwned>(resp: Response) -> Result<T, PolymarketError> {
+    if resp.status().is_success() {
+        resp.json()
+            .await
+            .map_err(|_| PolymarketError::UnexpectedResponseType)
+    } else {
+        Err(PolymarketError::ErrorResponse(resp.status()))
+    }
+}
+
+impl PolymarketMarket {
+    pub fn id(&self) -> &str {
+        &self.slug
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.outcomes.len() == 2
+            && self.outcomes[0].eq_ignore_ascii_case("yes")
+            && self.outcomes[1].eq_ignore_ascii_case("no")
+            && self.outcome_prices.len() == 2
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.active && !self.closed
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.closed && self.settled_yes_price().is_some()
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.volume_num.unwrap_or(0.0)
+    }
+
+    pub fn liquidity(&self) -> f64 {
+        self.liquidity_num.unwrap_or(0.0)
+    }
+
+    pub fn time_to_resolution(&self) -> Option<Duration> {
+        self.end_date.map(|end_date| end_date - Utc::now())
+    }
+
+    /// Current price of YES, if market is binary
+    pub fn yes_price(&self) -> Option<f64> {
+        if self.is_binary() {
+            Some(self.outcome_prices[0])
+        } else {
+            None
+        }
+    }
+
+    /// Once a market settles, prices snap to 0 or 1 (or 0.5 for 50-50 resolutions)
+    fn settled_yes_price(&self) -> Option<f64> {
+        self.yes_price()
+            .filter(|p| *p == 0.0 || *p == 0.5 || *p == 1.0)
+    }
+
+    pub fn full_url(&self) -> String {
+        format!("https://polymarket.com/market/{}", self.slug)
+    }
+
+    pub fn get_binary_resolution(&self) -> Result<Option<BinaryResolution>> {
+        if !self.closed {
+            return Ok(None);
+        }
+        if !self.is_binary() {
+            return Err(anyhow!("Polymarket market is not binary"));
+        }
+        Ok(match self.settled_yes_price() {
+            Some(1.0) => Some(BinaryResolution::Yes),
+            Some(0.0) => Some(BinaryResolution::No),
+            Some(p) => Some(BinaryResolution::Percent(p)),
+            // closed, but not settled yet
+            None => None,
+        })
+    }
+}
+
+impl TryInto<Question> for &PolymarketMarket {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<Question> {
+        if !self.is_binary() {
+            return Err(anyhow!("non-binary questions are not supported yet"));
+        }
+        Ok(Question {
+            source: QuestionSource::Polymarket,
+            source_url: self.full_url(),
+            source_id: self.id().to_string(),
+            question: self.question.clone(),
+            criteria: self.description.clone(),
+            background: None,
+            end_date: self
+                .end_date
+                .ok_or_else(|| anyhow!("Polymarket market has no end date"))?,
+            close_time: None,
+            probability: self.yes_price(),
+            source_payload: serde_json::to_value(self).ok(),
+            market_summary: Some(MarketSummary {
+                price: self.yes_price(),
+                bid_ask: self.best_bid.zip(self.best_ask),
+                volume: self.volume_num,
+                related_url: None,
+                resting_contracts: None,
+            }),
+            forecast_history: Vec::new(),
+        })
+    }
+}
+
+/// [`MirrorSource`] for Polymarket markets. These are only mirrored on request.
+pub struct PolymarketSource;
+
+impl MirrorSource for PolymarketSource {
+    type Question = PolymarketMarket;
+    type Requirements = PolymarketQuestionRequirements;
+    type CheckFailure = PolymarketCheckFailure;
+
+    const SOURCE: QuestionSource = QuestionSource::Polymarket;
+
+    async fn fetch(
+        client: &Client,
+        config: &Settings,
+        id: &str,
+    ) -> Result<PolymarketMarket, MirrorError> {
+        Ok(get_question(client, id, config).await?)
+    }
+
+    async fn candidates(
+        _client: &Client,
+        _config: &Settings,
+    ) -> Result<Candidates<Self>, MirrorError> {
+        Err(anyhow!("Polymarket auto-mirroring hasn't been implemented yet").into())
+    }
+
+    fn check(
+        market: &PolymarketMarket,
+        requirements: &PolymarketQuestionRequirements,
+    ) -> Vec<RequirementCheck<PolymarketCheckFailure>> {
+        evaluate_market_requirements(market, requirements)
+    }
+
+    fn auto_filter(_config: &Settings) -> Option<&PolymarketQuestionRequirements> {
+        None
+    }
+
+    fn request_filter(config: &Settings) -> &PolymarketQuestionRequirements {
+        &config.polymarket.request_filter
+    }
+
+    fn max_clones_per_day(_config: &Settings) -> usize {
+        0
+    }
+
+    fn is_resolved(market: &PolymarketMarket) -> bool {
+        market.is_resolved()
+    }
+
+    fn resolution(market: &PolymarketMarket) -> Result<Option<BinaryResolution>> {
+        market.get_binary_resolution()
+    }
+
+    fn resolution_state(market: &PolymarketMarket) -> String {
+        format!("Polymarket outcome prices {:?}", market.outcome_prices)
+    }
+
+    fn probability(market: &PolymarketMarket) -> Option<f64> {
+        market.yes_price()
+    }
+
+    fn id(market: &PolymarketMarket) -> String {
+        market.id().to_string()
+    }
+
+    fn title(market: &PolymarketMarket) -> String {
+        market.question.clone()
+    }
+
+    fn url(market: &PolymarketMarket) -> String {
+        market.full_url()
+    }
+
+    fn to_question(market: &PolymarketMarket) -> Result<Question> {
+        market
+            .try_into()
+            .with_context(|| "failed to convert Polymarket question to common format")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PolymarketMarket {
+    pub question: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub active: bool,
+    pub closed: bool,
+    /// Outcome names, e.g. ["Yes", "No"]
+    #[serde(default, deserialize_with = "deserialize_json_string")]
+    pub outcomes: Vec<String>,
+    /// Current price for each outcome, in the same order as outcomes
+    #[serde(default, deserialize_with = "deserialize_json_string_prices")]
+    pub outcome_prices: Vec<f64>,
+    pub volume_num: Option<f64>,
+    pub liquidity_num: Option<f64>,
+    /// Best bid and ask for YES
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+}
+
+/// The gamma api encodes some arrays as JSON inside a string
+fn deserialize_json_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    match s {
+        Some(s) => serde_json::from_str(&s).map_err(serde::de::Error::custom),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Same as [`deserialize_json_string`], but the inner strings are numbers
+fn deserialize_json_string_prices<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_json_string(deserializer)?
+        .iter()
+        .map(|p| p.parse::<f64>().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum PolymarketCheckFailure {
+    #[error("not a binary question")]
+    NotBinary,
+    #[error("question has no end date")]
+    NoEndDate,
+    #[error("question is not open")]
+    NotOpen,
+    #[error("question has {volume} volume, and the minimum is {threshold}")]
+    NotEnoughVolume { volume: f64, threshold: f64 },
+    #[error("question has {liquidity} liquidity, and the minimum is {threshold}")]
+    NotEnoughLiquidity { liquidity: f64, threshold: f64 },
+    #[error("question resolves in {days_remaining} days, and the minimum is {threshold}")]
+    ResolvesTooSoon { days_remaining: i64, threshold: i64 },
+    #[error("question resolves in {days_remaining} days, and the maximum is {threshold}")]
+    ResolvesTooLate { days_remaining: i64, threshold: i64 },
+    #[error("market price suggests a probability of {probability}, and the maximum confidence is {threshold}")]
+    TooExtreme { probability: f64, threshold: f64 },
+    #[error("question has already resolved")]
+    Resolved,
+    #[error("question is banned in config")]
+    Banned,
+}
+
+#[derive(Error, Debug)]
+pub enum PolymarketError {
+    #[error("failed to parse success response from Polymarket")]
+    UnexpectedResponseType,
+    #[error("error response ({}) from Polymarket", .0)]
+    ErrorResponse(StatusCode),
+    #[error("no Polymarket market found with slug \"{}\"", .0)]
+    NotFound(String),
+    #[error("Only lowercase alphanumeric characters and \"-\" are allowed in market slugs (\"{}\" given)", .0)]
+    IllegalSlugCharacters(String),
+    #[error(transparent)]
+    ReqwestError(#[from] reqwest::Error),
+    #[error(transparent)]
+    HttpError(#[from] HttpError),
+}
@@ -0,0 +1,338 @@
+//! Recording of API traffic to disk, and replaying it in place of the APIs, so a
+//! failure seen in production can be reproduced offline. Enabled by
+//! `http.record_dir` and `http.replay_dir`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use reqwest::{Request, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+
+use crate::http::HttpError;
+
+/// A request and the response to it, one per file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exchange {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    method: String,
+    url: String,
+    body: Option<String>,
+}
+
+/// Paths of endpoints that exchange credentials. Neither their request body nor
+/// the secrets in their response are written to disk.
+const AUTH_PATHS: &[&str] = &["/login"];
+
+/// Response fields of auth endpoints that hold session tokens
+const SECRET_FIELDS: &[&str] = &["token", "access_token", "refresh_token"];
+
+/// Headers carrying credentials, left out of recordings
+const SECRET_HEADERS: &[&str] = &[
+    "set-cookie",
+    "cookie",
+    "authorization",
+    "proxy-authorization",
+];
+
+/// Stands in for secrets removed from recordings
+const REDACTED: &str = "REDACTED";
+
+impl RecordedRequest {
+    /// Capture `request` for a recording, with secrets removed: the Telegram bot
+    /// token in the url and the body of auth endpoints. The same is done to
+    /// requests being replayed, so they still match their recordings.
+    pub fn new(request: &Request) -> Self {
+        let mut url = request.url().clone();
+        let path = url
+            .path()
+            .split('/')
+            .map(|segment| match segment.strip_prefix("bot") {
+                Some(token) if token.contains(':') => format!("bot{}", REDACTED),
+                _ => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        url.set_path(&path);
+        let body = if is_auth_endpoint(url.path()) {
+            None
+        } else {
+            request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+        };
+        RecordedRequest {
+            method: request.method().to_string(),
+            url: url.to_string(),
+            body,
+        }
+    }
+
+    /// Url without the query, which often holds timestamps that differ between runs
+    fn endpoint(&self) -> &str {
+        self.url.split('?').next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// Distinguishes the recordings of this run from earlier ones in the same directory
+static RUN: OnceLock<i64> = OnceLock::new();
+/// Recordings made by this run so far, keeps files in the order responses arrived
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Write `request` and `response` to `dir`, returning an equivalent response.
+/// Failing to write is logged, the run goes on without the recording.
+pub async fn record(
+    dir: &str,
+    request: RecordedRequest,
+    response: Response,
+) -> Result<Response, HttpError> {
+    let status = response.status();
+    let url = response.url().clone();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = response.bytes().await?;
+    let response = RecordedResponse {
+        status: status.as_u16(),
+        headers: headers
+            .iter()
+            .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+            .cloned()
+            .collect(),
+        body: recorded_body(&request, &body),
+    };
+    let exchange = Exchange { request, response };
+    if let Err(e) = write_exchange(Path::new(dir), &exchange) {
+        warn!(
+            "Failed to record {} {}: {:#}",
+            exchange.request.method,
+            url.host_str().unwrap_or_default(),
+            e
+        );
+    }
+    let mut builder = ::http::Response::builder().status(status).url(url);
+    for (name, value) in &headers {
+        builder = builder.header(name, value);
+    }
+    to_response(builder, body)
+}
+
+fn is_auth_endpoint(path: &str) -> bool {
+    AUTH_PATHS.iter().any(|auth_path| path.ends_with(auth_path))
+}
+
+/// Response body as written to disk, with the session tokens handed out by auth
+/// endpoints replaced so a replayed login still parses
+fn recorded_body(request: &RecordedRequest, body: &[u8]) -> String {
+    if is_auth_endpoint(request.endpoint()) {
+        if let Ok(serde_json::Value::Object(mut fields)) = serde_json::from_slice(body) {
+            for field in SECRET_FIELDS {
+                if let Some(value) = fields.get_mut(*field) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                }
+            }
+            return serde_json::Value::Object(fields).to_string();
+        }
+        return String::new();
+    }
+    String::from_utf8_lossy(body).into_owned()
+}
+
+fn write_exchange(dir: &Path, exchange: &Exchange) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let host = reqwest::Url::parse(&exchange.request.url)?
+        .host_str()
+        .unwrap_or_default()
+        .to_string();
+    let name = format!(
+        "{}-{:05}-{}-{}.json",
+        RUN.get_or_init(|| Utc::now().timestamp_millis()),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed),
+        exchange.request.method,
+        host
+    );
+    fs::write(dir.join(&name), serde_json::to_string_pretty(exchange)?)
+        .with_context(|| format!("failed to write {}", name))
+}
+
+/// Recordings by directory, loaded on first use. Exchanges are removed as they
+/// are replayed, except for the last one of each request.
+static REPLAYS: OnceLock<Mutex<HashMap<String, Vec<Exchange>>>> = OnceLock::new();
+
+/// Answer `request` with the response recorded for it in `dir`. Requests made
+/// several times get their recorded responses in order, the last one repeating.
+/// A request whose exact url and body weren't recorded gets a response to the
+/// same endpoint with a different query, if there is one.
+pub fn replay(dir: &str, request: &Request) -> Result<Response, HttpError> {
+    let request = RecordedRequest::new(request);
+    let mut replays = REPLAYS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if !replays.contains_key(dir) {
+        let exchanges =
+            load_exchanges(Path::new(dir)).map_err(|e| HttpError::Replay(format!("{:#}", e)))?;
+        info!(
+            "Replaying {} recorded responses from {}",
+            exchanges.len(),
+            dir
+        );
+        replays.insert(dir.to_string(), exchanges);
+    }
+    let exchanges = replays.get_mut(dir).expect("recordings were just loaded");
+    let exchange = take_matching(exchanges, |recorded| {
+        recorded.method == request.method
+            && recorded.url == request.url
+            && recorded.body == request.body
+    })
+    .or_else(|| {
+        take_matching(exchanges, |recorded| {
+            recorded.method == request.method && recorded.endpoint() == request.endpoint()
+        })
+    })
+    .ok_or_else(|| {
+        HttpError::Replay(format!(
+            "no recorded response to {} {}",
+            request.method,
+            request.endpoint()
+        ))
+    })?;
+    let mut builder = ::http::Response::builder().status(exchange.response.status);
+    if let Ok(url) = reqwest::Url::parse(&exchange.request.url) {
+        builder = builder.url(url);
+    }
+    for (name, value) in &exchange.response.headers {
+        builder = builder.header(name, value);
+    }
+    to_response(builder, exchange.response.body)
+}
+
+fn load_exchanges(dir: &Path) -> Result<Vec<Exchange>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let contents = fs::read_to_string(path)?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("invalid recording {}", path.display()))
+        })
+        .collect()
+}
+
+fn take_matching(
+    exchanges: &mut Vec<Exchange>,
+    matches: impl Fn(&RecordedRequest) -> bool,
+) -> Option<Exchange> {
+    let mut found = exchanges
+        .iter()
+        .enumerate()
+        .filter(|(_, exchange)| matches(&exchange.request))
+        .map(|(i, _)| i);
+    let first = found.next()?;
+    if found.next().is_some() {
+        Some(exchanges.remove(first))
+    } else {
+        Some(exchanges[first].clone())
+    }
+}
+
+fn to_response(
+    builder: ::http::response::Builder,
+    body: impl Into<reqwest::Body>,
+) -> Result<Response, HttpError> {
+    builder
+        .body(body)
+        .map(Response::from)
+        .map_err(|e| HttpError::Replay(format!("invalid recorded response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn recorded_login_holds_no_secrets() {
+        let dir = std::env::temp_dir().join(format!(
+            "mirror_bot-login-recording-{}",
+            Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        let request = reqwest::Client::new()
+            .post("https://api.example.com/trade-api/v2/login")
+            .json(&serde_json::json!({"email": "bot@example.com", "password": "hunter2"}))
+            .build()
+            .unwrap();
+        let response = ::http::Response::builder()
+            .status(200)
+            .header("set-cookie", "session=cookie-secret")
+            .body(r#"{"member_id": "member", "token": "token-secret"}"#)
+            .map(Response::from)
+            .unwrap();
+
+        let response = record(
+            &dir.display().to_string(),
+            RecordedRequest::new(&request),
+            response,
+        )
+        .await
+        .unwrap();
+
+        let recordings = load_exchanges(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(recordings.len(), 1);
+        let written = serde_json::to_string(&recordings[0]).unwrap();
+        for secret in [
+            "hunter2",
+            "bot@example.com",
+            "token-secret",
+            "cookie-secret",
+        ] {
+            assert!(!written.contains(secret), "{} was recorded", secret);
+        }
+        assert!(response.headers().contains_key("set-cookie"));
+        assert!(response.text().await.unwrap().contains("token-secret"));
+    }
+
+    #[test]
+    fn telegram_bot_token_is_not_recorded() {
+        let request = reqwest::Client::new()
+            .post("https://api.telegram.org/bot123:secret-token/sendMessage")
+            .build()
+            .unwrap();
+
+        let recorded = RecordedRequest::new(&request);
+
+        assert_eq!(
+            recorded.url,
+            "https://api.telegram.org/botREDACTED/sendMessage"
+        );
+    }
+}
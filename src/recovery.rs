@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{self, ActionKind, PendingActionRow, SpendCategory},
+    log_if_err,
+    manifold::{self, CreateMarketArgs, GetManagramsArgs, LiteMarket, SendManagramArgs},
+    mirror,
+    settings::Settings,
+    shutdown,
+    types::{BinaryResolution, Question},
+};
+
+/// Incomplete actions younger than this may belong to a run that is still going
+const RECOVERY_DELAY_MINUTES: i64 = 10;
+
+/// Payload of [`ActionKind::CreateMarket`] actions. Payloads recorded before the
+/// destination was included are just the question.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateMarketPayload {
+    #[serde(flatten)]
+    pub question: Question,
+    /// `manifold.accounts` entry the market is created with, None for the main account
+    #[serde(default)]
+    pub destination: Option<String>,
+    /// Title the market is created with, None in payloads recorded before it was
+    /// included, whose markets have the title derived from the question
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Payload of [`ActionKind::ResolveMarket`] actions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolutionPayload {
+    pub mirror_id: i64,
+    pub resolution: BinaryResolution,
+}
+
+/// Payload of [`ActionKind::SendManagram`] actions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManagramPayload {
+    pub args: SendManagramArgs,
+    pub category: SpendCategory,
+}
+
+/// Reconcile actions that were started but never completed against Manifold.
+/// Effects that happened are recorded in the database, managrams that were
+/// never sent are sent. Failures are logged and retried on the next pass.
+pub async fn recover_pending_actions(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    let cutoff = Utc::now() - Duration::minutes(RECOVERY_DELAY_MINUTES);
+    let actions = db::get_incomplete_actions(db, cutoff)?;
+    if !actions.is_empty() {
+        warn!("Recovering {} interrupted action(s)", actions.len());
+    }
+    for action in actions {
+        if shutdown::requested() {
+            break;
+        }
+        log_if_err!(recover_action(client, db, config, &action)
+            .await
+            .with_context(|| format!(
+                "failed to recover {} action {} on {}",
+                action.kind, action.id, action.subject
+            )));
+    }
+    Ok(())
+}
+
+async fn recover_action(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    action: &PendingActionRow,
+) -> Result<()> {
+    match action.kind {
+        ActionKind::CreateMarket => {
+            let CreateMarketPayload {
+                question,
+                destination,
+                title,
+            } = serde_json::from_str(&action.payload)?;
+            if db::get_mirror_by_source_id(db, &question.source, &question.source_id)?.is_none() {
+                let title = title
+                    .unwrap_or_else(|| CreateMarketArgs::title_from_question(&question, config));
+                let adopted = mirror::adopt_unrecorded_mirror(
+                    client,
+                    db,
+                    config,
+                    &question,
+                    &title,
+                    destination.as_deref(),
+                    action.created_time,
+                )
+                .await?;
+                if adopted.is_none() {
+                    info!(
+                        "Interrupted attempt to mirror {} question {} didn't create a market",
+                        question.source, question.source_id
+                    );
+                }
+            }
+        }
+        ActionKind::ResolveMarket => {
+            let payload: ResolutionPayload = serde_json::from_str(&action.payload)?;
+            let mirror = db::get_mirror_by_id(db, payload.mirror_id)?
+                .ok_or_else(|| anyhow!("mirror with row id {} not found", payload.mirror_id))?;
+            let market = manifold::get_market(client, &action.subject, config).await?;
+            if !market.is_resolved {
+                info!(
+                    "Interrupted resolution of mirror {} wasn't applied, leaving it to the next sync",
+                    mirror.id
+                );
+            } else if mirror.resolution.is_none() {
+                let market: LiteMarket = (&market).into();
+                let resolution = market.binary_resolution().unwrap_or(payload.resolution);
+                warn!(
+                    "Mirror {} was resolved {:?} by an interrupted run, recording it",
+                    mirror.id, resolution
+                );
+                db::set_mirror_resolution(db, mirror.id, &resolution)?;
+                log_if_err!(db::insert_resolution_event(
+                    db,
+                    mirror.id,
+                    Some(&resolution),
+                    "recovered after an interrupted resolution"
+                ));
+            }
+        }
+        ActionKind::SendManagram => {
+            let payload: ManagramPayload = serde_json::from_str(&action.payload)?;
+            let args = &payload.args;
+            let to_id = args
+                .to_ids
+                .first()
+                .ok_or_else(|| anyhow!("managram without recipients"))?;
+            // with some slack for clock differences
+            let sent = manifold::get_managrams(
+                client,
+                &GetManagramsArgs {
+                    from_id: Some(config.manifold.user_id.clone()),
+                    to_id: Some(to_id.clone()),
+                    after: Some(action.created_time - Duration::minutes(5)),
+                    ..Default::default()
+                },
+                config,
+            )
+            .await?
+            .iter()
+            .any(|managram| managram.amount == args.amount && managram.message == args.message);
+            if sent {
+                info!(
+                    "Response to managram {} was sent by an interrupted run, recording it",
+                    action.subject
+                );
+            } else {
+                warn!(
+                    "Response to managram {} was never sent, sending it now",
+                    action.subject
+                );
+                manifold::send_managram(client, config, args).await?;
+            }
+            log_if_err!(db::record_spend(
+                db,
+                payload.category,
+                args.amount * args.to_ids.len() as f64,
+                None,
+                &action.subject
+            ));
+        }
+    }
+    db::complete_action(db, action.id)
+}
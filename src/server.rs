@@ -0,0 +1,205 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+    args::SyncArgs, commands, dashboard, db, db::ManagramState, settings::Settings, shutdown,
+};
+
+struct AppState {
+    config: Settings,
+    /// Set while a sync triggered through the API is running
+    syncing: AtomicBool,
+}
+
+/// Serve the HTTP API until the process is stopped.
+pub async fn serve(config: Settings, bind_address: Option<String>) -> Result<()> {
+    let addr: SocketAddr = bind_address
+        .as_deref()
+        .unwrap_or(&config.server.bind_address)
+        .parse()
+        .with_context(|| "invalid bind address")?;
+    if config.server.api_token.is_empty() {
+        info!("No server.api_token configured, API requests are not authenticated");
+    }
+    let state = Arc::new(AppState {
+        config,
+        syncing: AtomicBool::new(false),
+    });
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/mirrors", get(list_mirrors))
+        .route("/runs", get(list_runs))
+        .route("/managrams/pending", get(list_pending_managrams))
+        .route("/sync", post(trigger_sync))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state.clone());
+    info!("Serving API on {}", addr);
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown::wait())
+        .await?;
+    // a sync started through the API stops after its current item, let it finish
+    while state.syncing.load(Ordering::SeqCst) {
+        info!("Waiting for the running sync to finish");
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+    info!("Server stopped");
+    Ok(())
+}
+
+/// Error response for failed requests. Details are logged rather than returned.
+struct ApiError(anyhow::Error);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        error!("API request failed: {:#}", self.0);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "internal error, see bot logs" })),
+        )
+            .into_response()
+    }
+}
+
+async fn require_token<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let token = &state.config.server.api_token;
+    if token.is_empty() {
+        return next.run(request).await;
+    }
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| value == token);
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "missing or invalid bearer token" })),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Run a blocking database query on the blocking thread pool.
+async fn with_db<T, F>(state: Arc<AppState>, f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&rusqlite::Connection) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&db::open(&state.config)?))
+        .await
+        .map_err(anyhow::Error::from)?
+        .map_err(ApiError)
+}
+
+async fn dashboard(State(state): State<Arc<AppState>>) -> Result<Html<String>, ApiError> {
+    Ok(Html(with_db(state, dashboard::render).await?))
+}
+
+#[derive(Deserialize)]
+struct MirrorsQuery {
+    #[serde(default)]
+    resolved: bool,
+}
+
+async fn list_mirrors(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MirrorsQuery>,
+) -> Result<Json<JsonValue>, ApiError> {
+    let mirrors = with_db(state, move |db| {
+        if query.resolved {
+            db::get_resolved_mirrors(db, None)
+        } else {
+            db::get_unresolved_mirrors(db, None)
+        }
+    })
+    .await?;
+    Ok(Json(json!(mirrors)))
+}
+
+#[derive(Deserialize)]
+struct RunsQuery {
+    limit: Option<usize>,
+}
+
+async fn list_runs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RunsQuery>,
+) -> Result<Json<JsonValue>, ApiError> {
+    let runs = with_db(state, move |db| db::get_runs(db, query.limit.unwrap_or(20))).await?;
+    Ok(Json(json!(runs)))
+}
+
+/// Managrams that haven't finished processing, by state
+async fn list_pending_managrams(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<JsonValue>, ApiError> {
+    let managrams = with_db(state, |db| {
+        Ok(json!({
+            "new": db::get_managrams_by_state(db, ManagramState::New)?,
+            "started": db::get_managrams_by_state(db, ManagramState::Started)?,
+            "failed": db::get_managrams_by_state(db, ManagramState::Failed)?,
+        }))
+    })
+    .await?;
+    Ok(Json(managrams))
+}
+
+/// Start a sync in the background. Takes the same targets as the `sync` command
+/// as query parameters, e.g. `/sync?kalshi=true&managrams=true`, and syncs
+/// everything if none are given.
+async fn trigger_sync(
+    State(state): State<Arc<AppState>>,
+    Query(mut args): Query<SyncArgs>,
+) -> (StatusCode, Json<JsonValue>) {
+    if state.syncing.swap(true, Ordering::SeqCst) {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "a sync is already running" })),
+        );
+    }
+    if !args.any_target() {
+        args.all = true;
+    }
+    info!("Sync requested through API: {:?}", args);
+    // the sync holds a database connection across awaits, so it can't move between
+    // worker threads and runs on its own blocking thread instead
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = handle.block_on(commands::sync(&state.config, args)) {
+            error!("API triggered sync failed: {:#}", e);
+        }
+        state.syncing.store(false, Ordering::SeqCst);
+    });
+    (StatusCode::ACCEPTED, Json(json!({ "status": "started" })))
+}
@@ -0,0 +1,65 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+
+use log::{error, warn};
+use tokio::{signal, sync::Notify};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+fn notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Whether a shutdown was requested. Loops over mirrors, managrams etc. check
+/// this before starting on the next item.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Resolves once a shutdown is requested
+pub async fn wait() {
+    loop {
+        let notified = notify().notified();
+        if requested() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Watch for SIGINT / SIGTERM for the rest of the process. The first signal asks
+/// long-running work to stop after the mirror or managram it is working on, so
+/// nothing is left half done. A second one exits immediately.
+pub fn listen() {
+    tokio::spawn(async {
+        loop {
+            if let Err(e) = next_signal().await {
+                error!("Failed to listen for shutdown signals: {:#}", e);
+                return;
+            }
+            if REQUESTED.swap(true, Ordering::SeqCst) {
+                error!("Shutdown requested again, exiting immediately");
+                std::process::exit(130);
+            }
+            warn!("Shutdown requested, finishing the current operation. Signal again to exit now.");
+            notify().notify_waiters();
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn next_signal() -> std::io::Result<()> {
+    let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> std::io::Result<()> {
+    signal::ctrl_c().await
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use log::{debug, info};
+use reqwest::Client;
+use serde_json::json;
+
+use crate::{db, db::MirrorRow, http, log_if_err, settings::Settings, util};
+
+/// Account new mirrors are announced on
+#[derive(Debug, Clone, Copy)]
+enum Platform {
+    Mastodon,
+    X,
+}
+
+impl Platform {
+    /// Name stored with posts in the database
+    fn name(self) -> &'static str {
+        match self {
+            Platform::Mastodon => "mastodon",
+            Platform::X => "x",
+        }
+    }
+
+    /// Longest post the platform accepts
+    fn max_chars(self) -> usize {
+        match self {
+            Platform::Mastodon => 500,
+            Platform::X => 280,
+        }
+    }
+}
+
+/// Announce a new mirror on every configured account, unless that account has
+/// reached `social.max_posts_per_day`. Failures are logged.
+pub async fn announce_mirror(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    mirror: &MirrorRow,
+) {
+    let social = &config.social;
+    for (platform, enabled) in [
+        (Platform::Mastodon, !social.mastodon.access_token.is_empty()),
+        (Platform::X, !social.x.access_token.is_empty()),
+    ] {
+        if enabled {
+            log_if_err!(announce_on(client, db, config, platform, mirror)
+                .await
+                .with_context(|| format!("failed to announce mirror on {}", platform.name())));
+        }
+    }
+}
+
+async fn announce_on(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    platform: Platform,
+    mirror: &MirrorRow,
+) -> Result<()> {
+    let posted = db::count_social_posts_since(db, platform.name(), Utc::now() - Duration::days(1))?;
+    if posted >= config.social.max_posts_per_day {
+        info!(
+            "Not announcing mirror {} on {}, already posted {} times today",
+            mirror.id,
+            platform.name(),
+            posted
+        );
+        return Ok(());
+    }
+    let text = render_post(&config.social.template, mirror, platform.max_chars());
+    debug!(
+        "Announcing mirror {} on {}: {}",
+        mirror.id,
+        platform.name(),
+        text
+    );
+    match platform {
+        Platform::Mastodon => {
+            let mastodon = &config.social.mastodon;
+            let request = client
+                .post(format!("{}api/v1/statuses", mastodon.instance_url))
+                .bearer_auth(&mastodon.access_token)
+                .json(&json!({ "status": text }));
+            http::send(config, request).await?.error_for_status()?;
+        }
+        Platform::X => {
+            let x = &config.social.x;
+            let request = client
+                .post(format!("{}tweets", x.api_url))
+                .bearer_auth(&x.access_token)
+                .json(&json!({ "text": text }));
+            http::send(config, request).await?.error_for_status()?;
+        }
+    }
+    db::insert_social_post(db, mirror.id, platform.name())?;
+    Ok(())
+}
+
+/// Fill in the post template, shortening the title so the post fits in `max_chars`.
+/// Placeholders: `{title}`, `{url}`, `{source}`, `{source_url}`.
+fn render_post(template: &str, mirror: &MirrorRow, max_chars: usize) -> String {
+    let render = |title: &str| {
+        template
+            .replace("{source_url}", &mirror.source_url)
+            .replace("{source}", &mirror.source.to_string())
+            .replace("{url}", &mirror.manifold_url)
+            .replace("{title}", title)
+    };
+    let available = max_chars.saturating_sub(render("").chars().count());
+    render(&util::truncate(&mirror.question, available))
+}
@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use reqwest::Client;
+
+use crate::{
+    mirror::MirrorError,
+    settings::Settings,
+    types::{BinaryResolution, Question, QuestionSource, RequirementCheck},
+};
+
+/// Questions that passed the auto filter, and those rejected along with the reason
+pub type Candidates<S> = (
+    Vec<<S as MirrorSource>::Question>,
+    Vec<(
+        <S as MirrorSource>::Question,
+        <S as MirrorSource>::CheckFailure,
+    )>,
+);
+
+/// A site we mirror questions from. Each source module implements this once, and
+/// mirroring, auto-mirroring and resolution syncing in `mirror.rs` are generic over it.
+pub trait MirrorSource {
+    /// Question as returned by the source API
+    type Question: Clone;
+    /// Filter settings, `auto_filter` and `request_filter` in the source's config section
+    type Requirements;
+    /// Reason a question fails a filter requirement
+    type CheckFailure: Display;
+
+    const SOURCE: QuestionSource;
+
+    /// Fetch a question by id, bypassing any cache
+    async fn fetch(
+        client: &Client,
+        config: &Settings,
+        id: &str,
+    ) -> Result<Self::Question, MirrorError>;
+
+    /// Fetch a question to check it for resolution. Sources with a response
+    /// cache use it here.
+    async fn fetch_for_sync(
+        client: &Client,
+        _db: &rusqlite::Connection,
+        config: &Settings,
+        id: &str,
+    ) -> Result<Self::Question, MirrorError> {
+        Self::fetch(client, config, id).await
+    }
+
+    /// Fetch questions for auto-mirroring
+    async fn candidates(
+        client: &Client,
+        config: &Settings,
+    ) -> Result<Candidates<Self>, MirrorError>;
+
+    /// Fill in anything the question needs before it is mirrored
+    async fn prepare(
+        _client: &Client,
+        _config: &Settings,
+        question: &Self::Question,
+    ) -> Result<Self::Question, MirrorError> {
+        Ok(question.clone())
+    }
+
+    /// Evaluate each filter requirement on a question
+    fn check(
+        question: &Self::Question,
+        requirements: &Self::Requirements,
+    ) -> Vec<RequirementCheck<Self::CheckFailure>>;
+
+    /// Auto filter, or None if the source isn't auto-mirrored
+    fn auto_filter(config: &Settings) -> Option<&Self::Requirements>;
+
+    fn request_filter(config: &Settings) -> &Self::Requirements;
+
+    fn max_clones_per_day(config: &Settings) -> usize;
+
+    fn is_resolved(question: &Self::Question) -> bool;
+
+    /// Resolution of the question, if it has resolved
+    fn resolution(question: &Self::Question) -> anyhow::Result<Option<BinaryResolution>>;
+
+    /// Raw resolution state, recorded in the resolution history
+    fn resolution_state(question: &Self::Question) -> String;
+
+    /// Current forecast, if the source has one
+    fn probability(question: &Self::Question) -> Option<f64>;
+
+    fn id(question: &Self::Question) -> String;
+
+    fn title(question: &Self::Question) -> String;
+
+    fn url(question: &Self::Question) -> String;
+
+    /// Convert to the common format
+    fn to_question(question: &Self::Question) -> anyhow::Result<Question>;
+}
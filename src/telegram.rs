@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+    db, http, log_if_err,
+    managrams::{self, ManagramProcessingError},
+    settings::Settings,
+    util,
+};
+
+/// Telegram rejects messages longer than this
+const MAX_MESSAGE_CHARS: usize = 4096;
+
+#[derive(Debug, Deserialize)]
+struct TelegramResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+/// Call a Bot API method
+async fn call<T: DeserializeOwned>(
+    client: &Client,
+    config: &Settings,
+    method: &str,
+    body: JsonValue,
+) -> Result<T> {
+    let telegram = &config.notifications.telegram;
+    // the url contains the bot token, so keep it out of errors
+    let request = client
+        .post(format!(
+            "{}bot{}/{}",
+            telegram.api_url, telegram.bot_token, method
+        ))
+        .json(&body);
+    let response: TelegramResponse<T> = http::send(config, request)
+        .await
+        .map_err(|e| e.without_url())?
+        .json()
+        .await
+        .map_err(|e| e.without_url())?;
+    match response {
+        TelegramResponse {
+            ok: true,
+            result: Some(result),
+            ..
+        } => Ok(result),
+        TelegramResponse { description, .. } => Err(anyhow!(
+            "Telegram {} failed: {}",
+            method,
+            description.unwrap_or_else(|| "no description".to_string())
+        )),
+    }
+}
+
+/// Send a message to the operator chat
+pub async fn send_message(client: &Client, config: &Settings, text: &str) -> Result<()> {
+    debug!("Sending Telegram message: {}", text);
+    call::<JsonValue>(
+        client,
+        config,
+        "sendMessage",
+        json!({
+            "chat_id": config.notifications.telegram.chat_id,
+            "text": util::truncate(text, MAX_MESSAGE_CHARS),
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Answer operator commands sent to the bot since the last call. Messages from
+/// chats other than `notifications.telegram.chat_id` are ignored.
+pub async fn process_commands(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+) -> Result<()> {
+    let telegram = &config.notifications.telegram;
+    if telegram.bot_token.is_empty() {
+        debug!("No Telegram bot token configured, skipping Telegram commands");
+        return Ok(());
+    }
+    info!("Processing Telegram commands");
+    let updates: Vec<Update> = call(
+        client,
+        config,
+        "getUpdates",
+        json!({
+            "offset": db::get_telegram_offset(db)?,
+            "allowed_updates": ["message"],
+        }),
+    )
+    .await?;
+    for update in updates {
+        // acknowledge first so a command that keeps failing isn't run forever
+        db::set_telegram_offset(db, update.update_id + 1)?;
+        let Some(Message {
+            chat,
+            text: Some(text),
+        }) = update.message
+        else {
+            continue;
+        };
+        if chat.id != telegram.chat_id {
+            warn!(
+                "Ignoring Telegram message from chat {}, which is not the operator chat",
+                chat.id
+            );
+            continue;
+        }
+        info!("Telegram command received: {}", text);
+        let response = run_command(client, db, config, &text).await;
+        log_if_err!(send_message(client, config, &response).await);
+    }
+    Ok(())
+}
+
+/// Run a message like `/status` or `/mirror <url>` as an admin command
+async fn run_command(
+    client: &Client,
+    db: &rusqlite::Connection,
+    config: &Settings,
+    text: &str,
+) -> String {
+    let text = text.trim().trim_start_matches('/');
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    // in group chats commands may be addressed to the bot as /command@botname
+    let command = command.split('@').next().unwrap_or(command);
+    let command = match managrams::parse_admin_command(&format!("{} {}", command, args)) {
+        Ok(command) => command,
+        Err(usage) => return usage,
+    };
+    match managrams::run_admin_command(client, db, config, command, "Telegram operator command")
+        .await
+    {
+        Ok(response) => response,
+        Err(ManagramProcessingError::UserFacing(message)) => message,
+        Err(ManagramProcessingError::Internal(e)) => {
+            error!("Telegram command \"{}\" failed: {:#}", text, e);
+            "Command failed, see bot logs.".to_string()
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Fakes of the Kalshi, Metaculus, Polymarket and Manifold APIs and a database fixture, for
+//! tests that run whole flows in process. Enabled by the `test-support` feature.
+
+use chrono::{Duration, Utc};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{method, path, path_regex, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{db, metaculus::ApiVersion, settings::Settings};
+
+/// Bot account on the fake Manifold
+pub const BOT_USER_ID: &str = "bot-user";
+
+/// A fake server per API
+pub struct FakeApis {
+    pub kalshi: MockServer,
+    pub metaculus: MockServer,
+    pub polymarket: MockServer,
+    pub manifold: MockServer,
+}
+
+impl FakeApis {
+    pub async fn start() -> Self {
+        FakeApis {
+            kalshi: MockServer::start().await,
+            metaculus: MockServer::start().await,
+            polymarket: MockServer::start().await,
+            manifold: MockServer::start().await,
+        }
+    }
+
+    /// Settings from config.toml pointed at the fakes
+    pub fn settings(&self) -> Settings {
+        let mut config = Settings::new().expect("config.toml should be valid");
+        config.kalshi.api_url = format!("{}/trade-api/v2/", self.kalshi.uri());
+        config.kalshi.email = String::new();
+        config.kalshi.api_key_id = String::new();
+        config.metaculus.url = format!("{}/", self.metaculus.uri());
+        config.metaculus.api_version = ApiVersion::Posts;
+        config.polymarket.api_url = format!("{}/", self.polymarket.uri());
+        config.manifold.api_url = format!("{}/v0/", self.manifold.uri());
+        config.manifold.user_id = BOT_USER_ID.to_string();
+        config
+    }
+
+    /// Serve a Kalshi market as a single market event, both when listed and by ticker
+    pub async fn kalshi_market(&self, market: JsonValue) {
+        let ticker = market["ticker"]
+            .as_str()
+            .expect("market should have a ticker");
+        let event = json!({
+            "series_ticker": "SER",
+            "event_ticker": ticker,
+            "markets": [market],
+        });
+        Mock::given(method("GET"))
+            .and(path("/trade-api/v2/events"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "events": [event],
+                "cursor": "",
+            })))
+            .mount(&self.kalshi)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/trade-api/v2/events/{}", ticker)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "event": {"series_ticker": "SER", "event_ticker": ticker},
+                "markets": [market],
+            })))
+            .mount(&self.kalshi)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/trade-api/v2/series/SER"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "series": {"settlement_sources": [{"name": "BLS", "url": "https://bls.gov"}]},
+            })))
+            .mount(&self.kalshi)
+            .await;
+    }
+
+    /// Accept member logins, and serve `orderbook` for any market
+    pub async fn kalshi_member_api(&self, orderbook: JsonValue) {
+        Mock::given(method("POST"))
+            .and(path("/trade-api/v2/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "member_id": "member",
+                "token": "token",
+            })))
+            .mount(&self.kalshi)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/trade-api/v2/markets/[^/]+/orderbook$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "orderbook": orderbook,
+            })))
+            .mount(&self.kalshi)
+            .await;
+    }
+
+    /// Reject the next request to `path` as unauthorized
+    pub async fn kalshi_rejects_once(&self, request_path: &str) {
+        Mock::given(method("GET"))
+            .and(path(request_path))
+            .respond_with(ResponseTemplate::new(401))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&self.kalshi)
+            .await;
+    }
+
+    /// Number of requests the fake Kalshi received at `path`
+    pub async fn kalshi_request_count(&self, path: &str) -> usize {
+        self.kalshi
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|request| request.url.path() == path)
+            .count()
+    }
+
+    /// Serve a Metaculus post by id
+    pub async fn metaculus_post(&self, post: JsonValue) {
+        let id = post["id"].as_u64().expect("post should have an id");
+        Mock::given(method("GET"))
+            .and(path(format!("/api/posts/{}/", id)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(post))
+            .mount(&self.metaculus)
+            .await;
+    }
+
+    /// Serve a Polymarket market when looked up by its slug
+    pub async fn polymarket_market(&self, market: JsonValue) {
+        let slug = market["slug"]
+            .as_str()
+            .expect("market should have a slug")
+            .to_string();
+        Mock::given(method("GET"))
+            .and(path("/markets"))
+            .and(query_param("slug", slug))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([market])))
+            .mount(&self.polymarket)
+            .await;
+    }
+
+    /// Serve `posts` as the question listing, whatever the filters
+    pub async fn metaculus_listing(&self, posts: Vec<JsonValue>) {
+        Mock::given(method("GET"))
+            .and(path("/api/posts/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": posts,
+                "next": null,
+            })))
+            .mount(&self.metaculus)
+            .await;
+    }
+
+    /// Answer the next `times` requests to the question listing with 429
+    pub async fn metaculus_throttles(&self, times: u64) {
+        Mock::given(method("GET"))
+            .and(path("/api/posts/"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(times)
+            .with_priority(1)
+            .mount(&self.metaculus)
+            .await;
+    }
+
+    /// Accept market creation, returning `market`
+    pub async fn manifold_creates(&self, market: JsonValue) {
+        Mock::given(method("POST"))
+            .and(path("/v0/market/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(market))
+            .mount(&self.manifold)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v0/markets/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .mount(&self.manifold)
+            .await;
+    }
+
+    /// Serve a market by id
+    pub async fn manifold_market(&self, market: JsonValue) {
+        let id = market["id"].as_str().expect("market should have an id");
+        Mock::given(method("GET"))
+            .and(path(format!("/v0/market/{}/", id)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(market))
+            .mount(&self.manifold)
+            .await;
+    }
+
+    /// Accept resolutions of any market
+    pub async fn manifold_resolves(&self) {
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/v0/market/[^/]+/resolve/$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"message": "ok"})))
+            .mount(&self.manifold)
+            .await;
+    }
+
+    /// Serve `managrams` to the bot, and accept managrams from it
+    pub async fn manifold_managrams(&self, managrams: JsonValue) {
+        Mock::given(method("GET"))
+            .and(path("/v0/managrams/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(managrams))
+            .mount(&self.manifold)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v0/managram/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"message": "ok"})))
+            .mount(&self.manifold)
+            .await;
+    }
+
+    /// Reject the next managram sent by the bot
+    pub async fn manifold_managram_fails_once(&self) {
+        Mock::given(method("POST"))
+            .and(path("/v0/managram/"))
+            .respond_with(ResponseTemplate::new(400))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&self.manifold)
+            .await;
+    }
+
+    /// Bodies of requests the fake Manifold received at `path`
+    pub async fn manifold_requests(&self, path: &str) -> Vec<JsonValue> {
+        self.manifold
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.url.path() == path)
+            .map(|request| serde_json::from_slice(&request.body).unwrap_or_default())
+            .collect()
+    }
+}
+
+/// Empty database with the current schema
+pub fn memory_db() -> rusqlite::Connection {
+    let conn = rusqlite::Connection::open_in_memory().expect("in-memory database should open");
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    conn.pragma_update(None, "user_version", db::SCHEMA_VERSION)
+        .unwrap();
+    db::init_tables(&conn).expect("tables should be created");
+    conn
+}
+
+/// Open Kalshi market passing the default auto filter
+pub fn kalshi_market(ticker: &str) -> JsonValue {
+    json!({
+        "ticker": ticker,
+        "title": "Will it happen?",
+        "status": "active",
+        "open_time": (Utc::now() - Duration::days(10)).to_rfc3339(),
+        "expiration_time": (Utc::now() + Duration::days(60)).to_rfc3339(),
+        "result": "",
+        "yes_bid": 40,
+        "yes_ask": 44,
+        "last_price": 42,
+        "volume": 1000,
+        "volume_24h": 50,
+        "open_interest": 300,
+        "liquidity": 50000,
+        "rules_primary": "If it happens, resolves Yes.",
+        "rules_secondary": "",
+    })
+}
+
+/// Kalshi market settled with `result` ("yes" or "no")
+pub fn settled_kalshi_market(ticker: &str, result: &str) -> JsonValue {
+    let mut market = kalshi_market(ticker);
+    market["status"] = json!("settled");
+    market["result"] = json!(result);
+    market
+}
+
+/// Open binary Polymarket market passing the default request filter
+pub fn polymarket_market(slug: &str) -> JsonValue {
+    json!({
+        "question": "Will it happen?",
+        "slug": slug,
+        "description": "Resolves YES if it happens.",
+        "endDate": (Utc::now() + Duration::days(60)).to_rfc3339(),
+        "active": true,
+        "closed": false,
+        "outcomes": "[\"Yes\", \"No\"]",
+        "outcomePrices": "[\"0.3\", \"0.7\"]",
+        "volumeNum": 50000.0,
+        "liquidityNum": 10000.0,
+        "bestBid": 0.29,
+        "bestAsk": 0.31,
+    })
+}
+
+/// Open binary Metaculus question passing the default request filter
+pub fn metaculus_post(id: u64) -> JsonValue {
+    let resolve_time = (Utc::now() + Duration::days(120)).to_rfc3339();
+    json!({
+        "id": id,
+        "title": "Will X happen?",
+        "short_title": "X",
+        "slug": "will-x-happen",
+        "author_id": 5,
+        "author_username": "author",
+        "curation_status": "approved",
+        "status": "open",
+        "created_at": (Utc::now() - Duration::days(30)).to_rfc3339(),
+        "published_at": (Utc::now() - Duration::days(29)).to_rfc3339(),
+        "edited_at": null,
+        "scheduled_resolve_time": resolve_time,
+        "nr_forecasters": 80,
+        "forecasts_count": 200,
+        "vote": {"score": 30},
+        "question": {
+            "id": id + 1000,
+            "type": "binary",
+            "status": "open",
+            "resolution": null,
+            "resolution_criteria": "Resolves YES if X happens.",
+            "description": "X has been discussed for years.",
+            "scheduled_close_time": resolve_time,
+            "scheduled_resolve_time": resolve_time,
+            "aggregations": {"recency_weighted": {
+                "latest": {
+                    "start_time": (Utc::now() - Duration::days(1)).timestamp() as f64,
+                    "centers": [0.37],
+                },
+                "history": [
+                    {
+                        "start_time": (Utc::now() - Duration::days(40)).timestamp() as f64,
+                        "centers": [0.3],
+                    },
+                    {
+                        "start_time": (Utc::now() - Duration::days(1)).timestamp() as f64,
+                        "centers": [0.37],
+                    },
+                ],
+            }},
+        },
+    })
+}
+
+/// Manifold market as returned by the API
+pub fn manifold_market(id: &str, question: &str) -> JsonValue {
+    let now = Utc::now().timestamp_millis();
+    json!({
+        "id": id,
+        "creatorId": BOT_USER_ID,
+        "question": question,
+        "slug": id,
+        "url": format!("https://manifold.markets/mirrorbot/{}", id),
+        "createdTime": now,
+        "closeTime": now + Duration::days(60).num_milliseconds(),
+        "lastUpdatedTime": now,
+        "isResolved": false,
+        "volume": 100.0,
+        "totalLiquidity": 100.0,
+        "probability": 0.42,
+        "description": {},
+        "textDescription": "",
+    })
+}
+
+/// Managram sent to the bot
+pub fn managram(id: &str, from_id: &str, amount: f64, message: &str) -> JsonValue {
+    json!({
+        "id": id,
+        "fromId": from_id,
+        "toId": BOT_USER_ID,
+        "createdTime": Utc::now().timestamp_millis(),
+        "token": "M$",
+        "amount": amount,
+        "data": {"groupId": format!("group-{}", id), "message": message},
+    })
+}
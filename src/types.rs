@@ -1,21 +1,101 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use chrono::{DateTime, Utc};
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 /// Intermediate type for questions from all sources
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Question {
     pub source: QuestionSource,
     pub source_url: String,
     pub source_id: String,
     pub question: String,
     pub criteria: Option<String>,
+    /// Background information or description of the question, for sources
+    /// that keep it apart from the resolution criteria
+    #[serde(default)]
+    pub background: Option<String>,
     pub end_date: DateTime<Utc>,
+    /// When forecasting on the source closes, for sources that report it apart
+    /// from `end_date`
+    #[serde(default)]
+    pub close_time: Option<DateTime<Utc>>,
+    /// Current probability of YES on the source, if available.
+    /// For Kalshi this is the midpoint of the best bid and ask.
+    pub probability: Option<f64>,
+    /// Source question as fetched from the source API, stored with new mirrors
+    pub source_payload: Option<JsonValue>,
+    /// Trading state of the source, for sources that are markets
+    pub market_summary: Option<MarketSummary>,
+    /// Past forecasts on the source, oldest first, for sources that publish them
+    #[serde(default)]
+    pub forecast_history: Vec<ForecastPoint>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Probability of YES on the source from `time` until the next point
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ForecastPoint {
+    pub time: DateTime<Utc>,
+    pub probability: f64,
+}
+
+/// Snapshot of a source market's trading state, shown in mirror descriptions
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MarketSummary {
+    /// Current price of yes, as a probability
+    pub price: Option<f64>,
+    /// Best yes bid and ask, as probabilities
+    pub bid_ask: Option<(f64, f64)>,
+    /// Traded volume in dollars
+    pub volume: Option<f64>,
+    /// Page listing related markets, e.g. the Kalshi series
+    pub related_url: Option<String>,
+    /// Contracts resting on yes and on no bids, for sources whose full
+    /// orderbook we can see
+    #[serde(default)]
+    pub resting_contracts: Option<(i64, i64)>,
+}
+
+/// Outcome of a single filter requirement, so filter decisions can be explained
+#[derive(Debug)]
+pub struct RequirementCheck<E> {
+    /// Config key of the requirement, or a description for fixed requirements
+    pub name: &'static str,
+    /// Value observed on the question
+    pub observed: String,
+    /// Configured limit
+    pub threshold: String,
+    pub result: Result<(), E>,
+}
+
+impl<E> RequirementCheck<E> {
+    pub fn new(
+        name: &'static str,
+        observed: impl Display,
+        threshold: impl Display,
+        passed: bool,
+        failure: impl FnOnce() -> E,
+    ) -> Self {
+        RequirementCheck {
+            name,
+            observed: observed.to_string(),
+            threshold: threshold.to_string(),
+            result: if passed { Ok(()) } else { Err(failure()) },
+        }
+    }
+
+    /// First failure among the checks, if any
+    pub fn first_failure(checks: Vec<Self>) -> Result<(), E> {
+        checks
+            .into_iter()
+            .find_map(|check| check.result.err())
+            .map_or(Ok(()), Err)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum BinaryResolution {
     Yes,
     No,
@@ -23,7 +103,7 @@ pub enum BinaryResolution {
     Cancel,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, ValueEnum, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, ValueEnum, PartialEq, Eq, Hash)]
 pub enum QuestionSource {
     Kalshi,
     Metaculus,
@@ -32,7 +112,53 @@ pub enum QuestionSource {
     Manual,
 }
 
+impl QuestionSource {
+    /// Canonical form of a question id from this source, so lookups don't miss a
+    /// mirror because of how the id was typed. Kalshi tickers are case insensitive
+    /// and kept uppercase, like their API returns them.
+    pub fn normalize_id(&self, id: &str) -> String {
+        match self {
+            QuestionSource::Kalshi => id.trim().to_uppercase(),
+            _ => id.trim().to_string(),
+        }
+    }
+}
+
+impl MarketSummary {
+    /// One line summary, e.g. "**On Kalshi as of the last check:** yes bid 45¢ / ask 48¢"
+    pub fn to_markdown(&self, source: &QuestionSource) -> String {
+        let mut parts = Vec::new();
+        if let Some(price) = self.price {
+            parts.push(format!("yes at {:.0}¢", price * 100.0));
+        }
+        if let Some((bid, ask)) = self.bid_ask {
+            parts.push(format!(
+                "yes bid {:.0}¢ / ask {:.0}¢",
+                bid * 100.0,
+                ask * 100.0
+            ));
+        }
+        if let Some(volume) = self.volume {
+            parts.push(format!("${:.0} traded", volume));
+        }
+        if let Some((yes, no)) = self.resting_contracts {
+            parts.push(format!("{} yes / {} no contracts bid", yes, no));
+        }
+        let mut summary = format!(
+            "**On {} as of the last check:** {}",
+            source,
+            parts.join(", ")
+        );
+        if let Some(url) = &self.related_url {
+            summary.push_str(&format!(". [Related markets]({})", url));
+        }
+        summary
+    }
+}
+
 impl Question {
+    /// Block shown below the source link in mirror descriptions. Either an
+    /// iframe or a markdown summary of the source market.
     pub fn embed_html(&self) -> Option<String> {
         match self.source {
             QuestionSource::Metaculus => {
@@ -42,8 +168,10 @@ impl Question {
                     self.source_id
                 ))
             }
-            QuestionSource::Kalshi => None,
-            QuestionSource::Polymarket => None,
+            QuestionSource::Kalshi | QuestionSource::Polymarket => self
+                .market_summary
+                .as_ref()
+                .map(|summary| summary.to_markdown(&self.source)),
             QuestionSource::Manual => None,
         }
     }
@@ -60,3 +188,161 @@ impl Display for QuestionSource {
         Ok(())
     }
 }
+
+impl FromStr for BinaryResolution {
+    type Err = String;
+
+    /// Parses YES, NO, CANCEL or NA (case insensitive) or a percentage like 37, 37% or p=37
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "YES" => Ok(BinaryResolution::Yes),
+            "NO" => Ok(BinaryResolution::No),
+            "CANCEL" | "N/A" | "NA" => Ok(BinaryResolution::Cancel),
+            other => match other
+                .trim_start_matches("P=")
+                .trim_end_matches('%')
+                .parse::<f64>()
+            {
+                Ok(p) if (0.0..=100.0).contains(&p) => Ok(BinaryResolution::Percent(p / 100.0)),
+                _ => Err(format!(
+                    "invalid resolution \"{}\", expected YES, NO, CANCEL or a percentage",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// One of our mirrors, given either as its row id or its Manifold url
+#[derive(Debug, Clone)]
+pub enum MirrorRef {
+    Id(i64),
+    Slug(String),
+}
+
+impl FromStr for MirrorRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(MirrorRef::Id(id));
+        }
+        let url: reqwest::Url = s
+            .parse()
+            .map_err(|_| format!("expected a mirror row id or Manifold url, got \"{}\"", s))?;
+        let slug = url
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+            .ok_or_else(|| format!("missing market slug in \"{}\"", s))?;
+        if !slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            return Err(format!("invalid market slug \"{}\"", slug));
+        }
+        Ok(MirrorRef::Slug(slug.to_string()))
+    }
+}
+
+impl Display for MirrorRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorRef::Id(id) => write!(f, "with row id {}", id),
+            MirrorRef::Slug(slug) => write!(f, "with slug {}", slug),
+        }
+    }
+}
+
+/// Source question identified by its url
+#[derive(Debug, Clone)]
+pub struct MirrorTarget {
+    pub source: QuestionSource,
+    pub source_id: String,
+}
+
+impl MirrorTarget {
+    pub fn parse_arg(s: &str) -> Result<Self, String> {
+        let generic_error = "Invalid URL";
+        let url: reqwest::Url = s.parse().map_err(|_| generic_error.to_string())?;
+        match url.host_str() {
+            Some("www.metaculus.com") => {
+                let metaculus_error = "Failed to parse Metaculus question url";
+                let mut path = url.path_segments().ok_or(metaculus_error.to_string())?;
+                if path.next() != Some("questions") {
+                    return Err(metaculus_error.to_string());
+                }
+                // validate and normalize id
+                let id = path
+                    .next()
+                    .ok_or("Missing Metaculus question id".to_string())?
+                    .parse::<u64>()
+                    .map_err(|_| "Metaculus question id must be a positive integer".to_string())?
+                    .to_string();
+                Ok(Self {
+                    source: QuestionSource::Metaculus,
+                    source_id: id,
+                })
+            }
+            Some("kalshi.com") => {
+                // Market links look like kalshi.com/markets/<series>/<slug>#<ticker>, or
+                // kalshi.com/markets/<series>/<slug>/<ticker> depending on where they were
+                // copied from. Links to the series alone don't identify a single market.
+                let kalshi_error = "Failed to parse Kalshi market url";
+                let mut path = url.path_segments().ok_or(kalshi_error.to_string())?;
+                if path.next() != Some("markets") {
+                    return Err(kalshi_error.to_string());
+                }
+                let segments: Vec<&str> = path.filter(|s| !s.is_empty()).collect();
+                let ticker = match url.fragment().filter(|f| !f.is_empty()) {
+                    Some(fragment) => fragment,
+                    None if segments.len() >= 3 => segments[segments.len() - 1],
+                    None => {
+                        return Err("Missing Kalshi market ticker. Link to a specific market \
+                            rather than a series."
+                            .to_string())
+                    }
+                };
+                // validate and normalize ticker
+                if !ticker
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                {
+                    return Err("Invalid Kalshi market ticker".to_string());
+                }
+                Ok(Self {
+                    source: QuestionSource::Kalshi,
+                    source_id: QuestionSource::Kalshi.normalize_id(ticker),
+                })
+            }
+            Some("polymarket.com") => {
+                // Market links look like polymarket.com/event/<event-slug>/<market-slug>,
+                // or polymarket.com/event/<slug> for events with a single market.
+                // polymarket.com/market/<market-slug> redirects to the former.
+                let polymarket_error = "Failed to parse Polymarket market url";
+                let path: Vec<&str> = url
+                    .path_segments()
+                    .ok_or(polymarket_error.to_string())?
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                let slug = match path.as_slice() {
+                    ["event", _event_slug, market_slug] => market_slug,
+                    ["event", slug] | ["market", slug] => slug,
+                    _ => return Err(polymarket_error.to_string()),
+                };
+                // validate slug
+                if !slug
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                {
+                    return Err("Invalid Polymarket market slug".to_string());
+                }
+                Ok(Self {
+                    source: QuestionSource::Polymarket,
+                    source_id: slug.to_string(),
+                })
+            }
+            Some(host) => Err(format!("Unrecognized host `{}`", host)),
+            None => Err(generic_error.to_string()),
+        }
+    }
+}
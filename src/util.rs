@@ -1,3 +1,98 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::Duration;
+use tracing_subscriber::{layer::Context, Layer};
+use unicode_segmentation::UnicodeSegmentation;
+
+static ERRORS_LOGGED: AtomicUsize = AtomicUsize::new(0);
+
+/// Tracing layer counting logged errors, so runs can report how many went wrong.
+pub struct ErrorCounter;
+
+impl<S: tracing::Subscriber> Layer<S> for ErrorCounter {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() == tracing::Level::ERROR {
+            ERRORS_LOGGED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Number of errors logged so far by this process
+pub fn errors_logged() -> usize {
+    ERRORS_LOGGED.load(Ordering::Relaxed)
+}
+
+/// Parse a duration like `30m`, `12h`, `7d` or `2w`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.len() - s.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = s.split_at(split);
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("expected a number followed by a unit, got \"{}\"", s))?;
+    match unit {
+        "m" => Ok(Duration::minutes(count)),
+        "h" => Ok(Duration::hours(count)),
+        "d" => Ok(Duration::days(count)),
+        "w" => Ok(Duration::weeks(count)),
+        _ => Err(format!(
+            "unknown unit \"{}\", expected one of m, h, d, w",
+            unit
+        )),
+    }
+}
+
+/// Shorten `s` to at most `max_chars` characters, ending in "..." if anything was cut
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut truncated: String = s.chars().take(max_chars.saturating_sub(3)).collect();
+    truncated.push_str("...");
+    truncated
+}
+
+/// Length of `s` as JavaScript counts it (UTF-16 code units), which is what
+/// Manifold's length limits are checked against. Most emoji count as two.
+pub fn js_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Shorten `s` to at most `max_len` (see [`js_len`]) by replacing part of it with
+/// `marker`. Up to `keep_end` of the end is kept, so with 0 the end is cut off.
+/// Cuts only fall between graphemes, so emoji, CJK and combining characters are
+/// never split and the result may come out a little shorter than `max_len`.
+pub fn truncate_graphemes(s: &str, max_len: usize, marker: &str, keep_end: usize) -> String {
+    if js_len(s) <= max_len {
+        return s.to_string();
+    }
+    let graphemes: Vec<&str> = s.graphemes(true).collect();
+    let fit = |budget: usize, graphemes: &mut dyn Iterator<Item = &&str>| {
+        let mut len = 0;
+        graphemes
+            .take_while(|g| {
+                len += js_len(g);
+                len <= budget
+            })
+            .count()
+    };
+    let tail = fit(keep_end, &mut graphemes.iter().rev());
+    let tail_len: usize = graphemes[graphemes.len() - tail..]
+        .iter()
+        .map(|g| js_len(g))
+        .sum();
+    let head = fit(
+        max_len.saturating_sub(js_len(marker) + tail_len),
+        &mut graphemes.iter(),
+    );
+    [
+        &graphemes[..head].concat(),
+        marker,
+        &graphemes[graphemes.len() - tail..].concat(),
+    ]
+    .concat()
+}
+
 /// If contained Result is Err variant, log error
 #[macro_export]
 macro_rules! log_if_err {
@@ -7,3 +102,108 @@ macro_rules! log_if_err {
         }
     };
 }
+
+/// Split a message into arguments like a shell would. Arguments are separated by
+/// whitespace unless it is inside single or double quotes. Outside single quotes,
+/// a backslash escapes the next character.
+pub fn tokenize(message: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => current.get_or_insert_with(String::new).push(c),
+            (_, '\\') => match chars.next() {
+                Some(escaped) => current.get_or_insert_with(String::new).push(escaped),
+                None => return Err("Message ends with an unfinished escape (\\)".to_string()),
+            },
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                // quotes can produce an empty argument
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => tokens.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("Message has an unclosed quote ({})", q));
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+/// Render probabilities as a line of block characters, from ▁ for 0 to █ for 1
+pub fn sparkline(probabilities: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    probabilities
+        .iter()
+        .map(|p| BLOCKS[(p.clamp(0.0, 1.0) * 7.0).round() as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_strings_are_unchanged() {
+        assert_eq!(
+            truncate_graphemes("Will it rain?", 20, "...", 0),
+            "Will it rain?"
+        );
+        assert_eq!(
+            truncate_graphemes("東京で雨が降るか", 8, "...", 0),
+            "東京で雨が降るか"
+        );
+    }
+
+    #[test]
+    fn cuts_the_end() {
+        assert_eq!(truncate_graphemes("abcdefghij", 8, "...", 0), "abcde...");
+    }
+
+    #[test]
+    fn cuts_the_middle() {
+        assert_eq!(truncate_graphemes("abcdefghij", 8, "..", 3), "abc..hij");
+    }
+
+    #[test]
+    fn never_splits_multibyte_characters() {
+        // used to panic when the byte offset fell inside the em dash
+        let title = "Will GDP grow — according to the BEA — by more than 3%?";
+        let truncated = truncate_graphemes(title, 20, "...", 0);
+        assert_eq!(truncated, "Will GDP grow — a...");
+        assert_eq!(js_len(&truncated), 20);
+    }
+
+    #[test]
+    fn counts_cjk_as_single_units() {
+        let truncated = truncate_graphemes("東京で雨が降るかどうか", 8, "…", 2);
+        assert_eq!(truncated, "東京で雨が…うか");
+        assert_eq!(js_len(&truncated), 8);
+    }
+
+    #[test]
+    fn keeps_emoji_whole() {
+        // 🌧 is two UTF-16 units, 👩‍🔬 is one grapheme of five
+        assert_eq!(truncate_graphemes("rain 🌧🌧🌧", 8, "...", 0), "rain ...");
+        assert_eq!(truncate_graphemes("a👩‍🔬bcdef", 6, "..", 0), "a..");
+        assert_eq!(truncate_graphemes("abcdef👩‍🔬", 8, "..", 5), "a..👩‍🔬");
+        assert_eq!(truncate_graphemes("abcdefg👩‍🔬", 8, "..", 4), "abcdef..");
+    }
+
+    #[test]
+    fn marker_longer_than_limit() {
+        assert_eq!(truncate_graphemes("abcdef", 2, "...", 0), "...");
+    }
+
+    #[test]
+    fn sparkline_spans_the_probability_range() {
+        assert_eq!(sparkline(&[0.0, 0.5, 1.0, 1.2]), "▁▅██");
+        assert_eq!(sparkline(&[]), "");
+    }
+}